@@ -488,6 +488,7 @@ pub fn aggregate(input: TokenStream) -> TokenStream {
     let expanded = quote! {
         impl sourced_rust::Aggregate for #agg_name {
             type ReplayError = String;
+            const TYPE_NAME: &'static str = stringify!(#agg_name);
 
             fn entity(&self) -> &sourced_rust::Entity {
                 &self.#entity_field
@@ -1007,6 +1008,7 @@ pub fn sourced(attr: TokenStream, item: TokenStream) -> TokenStream {
     let aggregate_impl = quote! {
         impl sourced_rust::Aggregate for #struct_name {
             type ReplayError = String;
+            const TYPE_NAME: &'static str = stringify!(#struct_name);
 
             fn entity(&self) -> &sourced_rust::Entity {
                 &self.#entity_field
@@ -1065,6 +1067,9 @@ pub fn sourced(attr: TokenStream, item: TokenStream) -> TokenStream {
 ///   If omitted, defaults to snake_case struct name + "s".
 /// - `#[readmodel(id)]` marks the field used as the unique identifier.
 ///   If omitted, defaults to a field named `id`.
+/// - `#[readmodel(index = "...")]` on the struct (repeatable) or
+///   `#[readmodel(index)]` on a field declares an indexed field, listed in
+///   `ReadModel::INDEXES`.
 #[proc_macro_derive(ReadModel, attributes(readmodel))]
 pub fn derive_read_model(input: TokenStream) -> TokenStream {
     read_model::derive_read_model(input)
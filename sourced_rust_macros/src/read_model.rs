@@ -12,9 +12,13 @@ pub fn derive_read_model(input: TokenStream) -> TokenStream {
     // Extract the field marked with #[readmodel(id)] or default to "id"
     let id_field = extract_id_field(&input);
 
+    // Extract #[readmodel(index = "...")] and fields marked #[readmodel(index)]
+    let indexes = extract_indexes(&input);
+
     let expanded = quote! {
         impl sourced_rust::ReadModel for #name {
             const COLLECTION: &'static str = #collection;
+            const INDEXES: &'static [&'static str] = &[#(#indexes),*];
 
             fn id(&self) -> &str {
                 &self.#id_field
@@ -36,6 +40,8 @@ fn extract_collection(input: &DeriveInput) -> String {
             if meta.path.is_ident("collection") {
                 let value: LitStr = meta.value()?.parse()?;
                 collection = Some(value.value());
+            } else if meta.path.is_ident("index") {
+                let _: LitStr = meta.value()?.parse()?;
             }
             Ok(())
         });
@@ -84,6 +90,49 @@ fn extract_id_field(input: &DeriveInput) -> syn::Ident {
     panic!("ReadModel derive: no field marked with #[readmodel(id)] and no field named `id`");
 }
 
+fn extract_indexes(input: &DeriveInput) -> Vec<String> {
+    let mut indexes = Vec::new();
+
+    for attr in &input.attrs {
+        if !attr.path().is_ident("readmodel") {
+            continue;
+        }
+        let _ = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("index") {
+                let value: LitStr = meta.value()?.parse()?;
+                indexes.push(value.value());
+            } else if meta.path.is_ident("collection") {
+                let _: LitStr = meta.value()?.parse()?;
+            }
+            Ok(())
+        });
+    }
+
+    if let Data::Struct(data_struct) = &input.data {
+        if let Fields::Named(fields) = &data_struct.fields {
+            for field in &fields.named {
+                for attr in &field.attrs {
+                    if !attr.path().is_ident("readmodel") {
+                        continue;
+                    }
+                    let mut is_index = false;
+                    let _ = attr.parse_nested_meta(|meta| {
+                        if meta.path.is_ident("index") {
+                            is_index = true;
+                        }
+                        Ok(())
+                    });
+                    if is_index {
+                        indexes.push(field.ident.as_ref().unwrap().to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    indexes
+}
+
 fn to_snake_case(s: &str) -> String {
     let mut result = String::new();
     for (i, ch) in s.chars().enumerate() {
@@ -1,14 +1,30 @@
+use std::borrow::Cow;
 use std::fmt;
 use std::marker::PhantomData;
 
 use crate::entity::{Entity, EventRecord, EventUpcaster, upcast_events};
-use crate::repository::{Commit, Find, Get, Repository, RepositoryError};
+use crate::repository::{
+    Commit, ExpectedVersion, Find, FindByIndex, FindPage, Get, Page, PageRequest, Pages,
+    Repository, RepositoryError,
+};
+#[cfg(feature = "async")]
+use crate::repository::{AsyncCommit, AsyncFind, AsyncGet};
 use crate::snapshot::{SnapshotAggregateRepository, SnapshotStore, Snapshottable};
 
+use super::locked::Locked;
+use super::namespace::Namespace;
+use super::retry::{RetryPolicy, Updated};
+
 /// Trait for domain aggregates that can be event-sourced.
 pub trait Aggregate: Sized + Default {
     type ReplayError: fmt::Display;
 
+    /// Name of the aggregate type, used as the stream namespace by
+    /// [`AggregateRepository::namespaced`]. `#[sourced]`, `aggregate!` and
+    /// `impl_aggregate!` set it to the type's name. Hand-written impls only
+    /// need it for namespacing; it is empty by default.
+    const TYPE_NAME: &'static str = "";
+
     fn new_empty() -> Self {
         Self::default()
     }
@@ -21,14 +37,52 @@ pub trait Aggregate: Sized + Default {
     fn upcasters() -> &'static [EventUpcaster] { &[] }
 }
 
+/// The type name `stringify!` gives for a type path: its last segment,
+/// without generic arguments (`"Order"` for `"crate :: orders :: Order"`).
+#[doc(hidden)]
+pub const fn type_name_of_path(path: &'static str) -> &'static str {
+    let bytes = path.as_bytes();
+    let mut end = 0;
+    while end < bytes.len() && bytes[end] != b'<' {
+        end += 1;
+    }
+    let mut start = 0;
+    let mut i = 0;
+    while i + 1 < end {
+        if bytes[i] == b':' && bytes[i + 1] == b':' {
+            start = i + 2;
+        }
+        i += 1;
+    }
+    while start < end && bytes[start] == b' ' {
+        start += 1;
+    }
+    while end > start && bytes[end - 1] == b' ' {
+        end -= 1;
+    }
+    match std::str::from_utf8(bytes.split_at(end).0.split_at(start).1) {
+        Ok(name) => name,
+        Err(_) => path,
+    }
+}
+
+/// Implement [`Aggregate`] for a type with an `Entity` field and a replay
+/// method. `TYPE_NAME` is the type's name without its module path, or the
+/// given `name`:
+///
+/// ```ignore
+/// impl_aggregate!(Order, entity, replay);
+/// impl_aggregate!(orders::Order, entity, replay, OrderError, name = "Order");
+/// ```
 #[macro_export]
 macro_rules! impl_aggregate {
-    ($ty:ty, $entity:ident, $replay:ident) => {
-        $crate::impl_aggregate!($ty, $entity, $replay, String);
+    ($ty:ty, $entity:ident, $replay:ident, name = $name:expr) => {
+        $crate::impl_aggregate!($ty, $entity, $replay, String, name = $name);
     };
-    ($ty:ty, $entity:ident, $replay:ident, $err:ty) => {
+    ($ty:ty, $entity:ident, $replay:ident, $err:ty, name = $name:expr) => {
         impl $crate::Aggregate for $ty {
             type ReplayError = $err;
+            const TYPE_NAME: &'static str = $name;
 
             fn entity(&self) -> &$crate::Entity {
                 &self.$entity
@@ -46,6 +100,18 @@ macro_rules! impl_aggregate {
             }
         }
     };
+    ($ty:ty, $entity:ident, $replay:ident) => {
+        $crate::impl_aggregate!($ty, $entity, $replay, String);
+    };
+    ($ty:ty, $entity:ident, $replay:ident, $err:ty) => {
+        $crate::impl_aggregate!(
+            $ty,
+            $entity,
+            $replay,
+            $err,
+            name = $crate::aggregate::type_name_of_path(stringify!($ty))
+        );
+    };
 }
 
 // Note: The `aggregate!` macro is now provided as a proc-macro from sourced_rust_macros.
@@ -233,6 +299,7 @@ impl<T> AggregateBuilder for T {}
 /// A repository wrapper that provides typed access to a specific aggregate type.
 pub struct AggregateRepository<R, A> {
     repo: R,
+    namespace: Namespace,
     _marker: PhantomData<A>,
 }
 
@@ -240,6 +307,7 @@ impl<R, A> AggregateRepository<R, A> {
     pub fn new(repo: R) -> Self {
         AggregateRepository {
             repo,
+            namespace: Namespace::default(),
             _marker: PhantomData,
         }
     }
@@ -251,6 +319,61 @@ impl<R, A> AggregateRepository<R, A> {
     pub fn repo_mut(&mut self) -> &mut R {
         &mut self.repo
     }
+
+    /// The stream ID of aggregate `id` in the underlying store.
+    pub fn stream_id<'a>(&self, id: &'a str) -> Cow<'a, str> {
+        self.namespace.stream_id(id)
+    }
+
+    pub(crate) fn namespace(&self) -> Namespace {
+        self.namespace
+    }
+
+    /// Commit aggregate entities, switching them to their stream IDs for
+    /// the duration of the commit.
+    fn commit_entities(
+        &self,
+        entities: &mut [&mut Entity],
+        commit: impl FnOnce(&mut [&mut Entity]) -> Result<(), RepositoryError>,
+    ) -> Result<(), RepositoryError> {
+        for entity in entities.iter_mut() {
+            self.namespace.enter(entity);
+        }
+        let result = commit(entities);
+        for entity in entities.iter_mut() {
+            self.namespace.leave(entity);
+        }
+        result
+    }
+
+    fn hydrate_all(&self, entities: Vec<Entity>) -> Result<Vec<A>, RepositoryError>
+    where
+        A: Aggregate,
+    {
+        let mut aggregates = Vec::with_capacity(entities.len());
+        for entity in entities {
+            aggregates.push(hydrate::<A>(self.namespace.load(entity))?);
+        }
+        Ok(aggregates)
+    }
+}
+
+impl<R, A: Aggregate> AggregateRepository<R, A> {
+    /// Store this aggregate type's streams as `"{TYPE_NAME}:{id}"`, so that
+    /// aggregates of different types can share IDs in one store, and
+    /// `find`/`count` only load streams of this type.
+    ///
+    /// Aggregate IDs are unchanged; only the keys in the store are prefixed.
+    /// Every repository reading or writing these aggregates must be namespaced.
+    ///
+    /// # Panics
+    ///
+    /// If the aggregate's `TYPE_NAME` is empty (the default for hand-written
+    /// `Aggregate` impls).
+    pub fn namespaced(mut self) -> Self {
+        self.namespace = Namespace::of(A::TYPE_NAME);
+        self
+    }
 }
 
 impl<R, A> AggregateRepository<R, A>
@@ -259,11 +382,11 @@ where
     A: Aggregate,
 {
     pub fn get(&self, id: &str) -> Result<Option<A>, RepositoryError> {
-        let entity = self.repo.get(id)?;
+        let entity = self.repo.get(self.namespace.stream_id(id).as_ref())?;
         let Some(entity) = entity else {
             return Ok(None);
         };
-        Ok(Some(hydrate::<A>(entity)?))
+        Ok(Some(hydrate::<A>(self.namespace.load(entity))?))
     }
 }
 
@@ -273,12 +396,10 @@ where
     A: Aggregate,
 {
     pub fn get_all(&self, ids: &[&str]) -> Result<Vec<A>, RepositoryError> {
-        let entities = self.repo.get(ids)?;
-        let mut aggregates = Vec::with_capacity(entities.len());
-        for entity in entities {
-            aggregates.push(hydrate::<A>(entity)?);
-        }
-        Ok(aggregates)
+        let stream_ids = self.namespace.stream_ids(ids);
+        let stream_ids: Vec<&str> = stream_ids.iter().map(|id| id.as_ref()).collect();
+        let entities = self.repo.get(&stream_ids[..])?;
+        self.hydrate_all(entities)
     }
 }
 
@@ -288,7 +409,22 @@ where
     A: Aggregate,
 {
     pub fn commit(&self, aggregate: &mut A) -> Result<(), RepositoryError> {
-        self.repo.commit(aggregate.entity_mut())
+        self.commit_entities(&mut [aggregate.entity_mut()], |entities| {
+            self.repo.commit(entities)
+        })
+    }
+
+    /// Commit with an explicit [`ExpectedVersion`], e.g. `NoStream` for unique
+    /// creation. The expectation applies to this attempt only.
+    pub fn commit_expecting(
+        &self,
+        aggregate: &mut A,
+        expected: ExpectedVersion,
+    ) -> Result<(), RepositoryError> {
+        aggregate.entity_mut().expect_version(expected);
+        let result = self.commit(aggregate);
+        aggregate.entity_mut().clear_expected_version();
+        result
     }
 
     pub fn commit_all(&self, aggregates: &mut [&mut A]) -> Result<(), RepositoryError> {
@@ -296,7 +432,72 @@ where
             .iter_mut()
             .map(|agg| (*agg).entity_mut())
             .collect();
-        self.repo.commit(&mut entities[..])
+        self.commit_entities(&mut entities, |entities| self.repo.commit(entities))
+    }
+
+    /// Commit an aggregate together with other entities (e.g. outbox
+    /// messages), which keep their own IDs.
+    pub(crate) fn commit_with(
+        &self,
+        aggregate: &mut A,
+        others: &mut [&mut Entity],
+    ) -> Result<(), RepositoryError> {
+        self.namespace.enter(aggregate.entity_mut());
+        let mut entities: Vec<&mut Entity> = vec![aggregate.entity_mut()];
+        entities.extend(others.iter_mut().map(|e| &mut **e));
+        let result = self.repo.commit(&mut entities[..]);
+        self.namespace.leave(aggregate.entity_mut());
+        result
+    }
+}
+
+impl<R, A> AggregateRepository<R, A>
+where
+    R: Get + Commit,
+    A: Aggregate,
+{
+    /// Load an aggregate, run `command` on it and commit, retrying with the
+    /// default [`RetryPolicy`] on [`RepositoryError::ConcurrentWrite`].
+    ///
+    /// `command` is re-run against a freshly loaded aggregate on every
+    /// attempt, so it must not have side effects outside the aggregate.
+    /// Returns `None` if the aggregate does not exist; once the attempts run
+    /// out, it fails with [`RepositoryError::RetriesExhausted`] holding the
+    /// attempt count and the last conflict. On a locking repository the
+    /// stream is released after every attempt that does not commit.
+    ///
+    /// ```ignore
+    /// let updated = todos.update("todo-1", |todo| {
+    ///     todo.complete();
+    ///     Ok::<_, RepositoryError>(())
+    /// })?;
+    /// ```
+    pub fn update<T, E, F>(&self, id: &str, command: F) -> Result<Option<Updated<A, T>>, E>
+    where
+        F: FnMut(&mut A) -> Result<T, E>,
+        E: From<RepositoryError>,
+    {
+        self.update_with(id, &RetryPolicy::default(), command)
+    }
+
+    /// [`update`](Self::update) with an explicit retry policy.
+    pub fn update_with<T, E, F>(
+        &self,
+        id: &str,
+        policy: &RetryPolicy,
+        command: F,
+    ) -> Result<Option<Updated<A, T>>, E>
+    where
+        F: FnMut(&mut A) -> Result<T, E>,
+        E: From<RepositoryError>,
+    {
+        let stream_id = self.namespace.stream_id(id);
+        policy.run(
+            || self.get(id),
+            |aggregate| self.commit(aggregate),
+            || self.repo.release(&[&stream_id]),
+            command,
+        )
     }
 }
 
@@ -310,10 +511,11 @@ where
     where
         F: Fn(&A) -> bool,
     {
-        let entities = self.repo.find(|_| true)?;
+        let namespace = self.namespace;
+        let entities = self.repo.find(|e| namespace.contains(e.id()))?;
         let mut results = Vec::new();
         for entity in entities {
-            let agg = hydrate::<A>(entity)?;
+            let agg = hydrate::<A>(namespace.load(entity))?;
             if predicate(&agg) {
                 results.push(agg);
             }
@@ -326,9 +528,10 @@ where
     where
         F: Fn(&A) -> bool,
     {
-        let entities = self.repo.find(|_| true)?;
+        let namespace = self.namespace;
+        let entities = self.repo.find(|e| namespace.contains(e.id()))?;
         for entity in entities {
-            let agg = hydrate::<A>(entity)?;
+            let agg = hydrate::<A>(namespace.load(entity))?;
             if predicate(&agg) {
                 return Ok(Some(agg));
             }
@@ -353,6 +556,222 @@ where
     }
 }
 
+impl<R, A> AggregateRepository<R, A>
+where
+    R: FindPage,
+    A: Aggregate,
+{
+    /// Up to `request.limit()` aggregates matching a predicate, in ID order,
+    /// starting after the request's cursor. Streams past the end of the page
+    /// are not loaded.
+    ///
+    /// The predicate is checked inside the repository's page scan, so a
+    /// locking repository only keeps the returned aggregates locked.
+    pub fn find_page<F>(
+        &self,
+        predicate: F,
+        request: &PageRequest,
+    ) -> Result<Page<A>, RepositoryError>
+    where
+        F: Fn(&A) -> bool,
+    {
+        let namespace = self.namespace;
+        let mut streams = PageRequest::new(request.limit());
+        if let Some(after) = request.after() {
+            streams = streams.with_after(namespace.stream_id(after));
+        }
+
+        // A stream that fails to replay is kept so that `hydrate` below
+        // reports the error.
+        let matches = |entity: &Entity| {
+            namespace.contains(entity.id())
+                && hydrate::<A>(namespace.load(entity.clone())).map_or(true, |agg| predicate(&agg))
+        };
+        let entities = self.repo.find_page(matches, &streams)?;
+
+        let mut items = Vec::with_capacity(entities.items.len());
+        for entity in entities.items {
+            items.push(hydrate::<A>(namespace.load(entity))?);
+        }
+        let next_cursor = entities
+            .next_cursor
+            .and(items.last())
+            .map(|agg| agg.entity().id().to_string());
+        Ok(Page { items, next_cursor })
+    }
+
+    /// Lazily iterate over every aggregate matching a predicate, loading
+    /// `batch_size` aggregates per page. On a locking repository, the caller
+    /// must unlock every aggregate it is given.
+    pub fn find_iter<'a, F>(
+        &'a self,
+        predicate: F,
+        batch_size: usize,
+    ) -> Pages<'a, A, RepositoryError>
+    where
+        F: Fn(&A) -> bool + 'a,
+    {
+        Pages::new(batch_size, move |request| self.find_page(&predicate, request))
+    }
+}
+
+impl<R, A> AggregateRepository<R, A>
+where
+    R: FindByIndex + Get,
+    A: Aggregate,
+{
+    /// Load every aggregate in a [`SecondaryIndex`](crate::SecondaryIndex),
+    /// without scanning the other streams. In a namespaced repository, streams
+    /// of other aggregate types in the index are skipped.
+    pub fn find_in(&self, index: &str) -> Result<Vec<A>, RepositoryError> {
+        let ids = self.repo.ids_in(index)?;
+        self.load_ids(&ids)
+    }
+
+    /// Load the aggregates filed under `key` in a keyed
+    /// [`SecondaryIndex`](crate::SecondaryIndex).
+    pub fn find_by(&self, index: &str, key: &str) -> Result<Vec<A>, RepositoryError> {
+        let ids = self.repo.ids_by(index, key)?;
+        self.load_ids(&ids)
+    }
+
+    /// Count the aggregates in an index. Nothing is loaded.
+    pub fn count_in(&self, index: &str) -> Result<usize, RepositoryError> {
+        let ids = self.repo.ids_in(index)?;
+        Ok(ids.iter().filter(|id| self.namespace.contains(id)).count())
+    }
+
+    /// Count the aggregates under `key` in an index. Nothing is loaded.
+    pub fn count_by(&self, index: &str, key: &str) -> Result<usize, RepositoryError> {
+        let ids = self.repo.ids_by(index, key)?;
+        Ok(ids.iter().filter(|id| self.namespace.contains(id)).count())
+    }
+
+    /// Load aggregates by stream ID.
+    fn load_ids(&self, stream_ids: &[String]) -> Result<Vec<A>, RepositoryError> {
+        let stream_ids: Vec<&str> = stream_ids
+            .iter()
+            .map(String::as_str)
+            .filter(|id| self.namespace.contains(id))
+            .collect();
+        let entities = self.repo.get(&stream_ids[..])?;
+        self.hydrate_all(entities)
+    }
+}
+
+// ============================================================================
+// Async counterparts (requires the `async` feature)
+// ============================================================================
+
+#[cfg(feature = "async")]
+impl<R, A> AggregateRepository<R, A>
+where
+    R: AsyncGet,
+    A: Aggregate,
+{
+    /// Async counterpart of [`get`](Self::get).
+    pub async fn get_async(&self, id: &str) -> Result<Option<A>, RepositoryError> {
+        let stream_id = self.namespace.stream_id(id);
+        let entity = self.repo.get_async(stream_id.as_ref()).await?;
+        let Some(entity) = entity else {
+            return Ok(None);
+        };
+        Ok(Some(hydrate::<A>(self.namespace.load(entity))?))
+    }
+
+    /// Async counterpart of [`get_all`](Self::get_all).
+    pub async fn get_all_async(&self, ids: &[&str]) -> Result<Vec<A>, RepositoryError> {
+        let stream_ids = self.namespace.stream_ids(ids);
+        let stream_ids: Vec<&str> = stream_ids.iter().map(|id| id.as_ref()).collect();
+        let entities = self.repo.get_async(&stream_ids[..]).await?;
+        self.hydrate_all(entities)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R, A> AggregateRepository<R, A>
+where
+    R: AsyncCommit,
+    A: Aggregate,
+{
+    /// Async counterpart of [`commit`](Self::commit).
+    pub async fn commit_async(&self, aggregate: &mut A) -> Result<(), RepositoryError> {
+        self.commit_all_async(&mut [aggregate]).await
+    }
+
+    /// Async counterpart of [`commit_all`](Self::commit_all).
+    pub async fn commit_all_async(&self, aggregates: &mut [&mut A]) -> Result<(), RepositoryError> {
+        let mut entities: Vec<&mut Entity> = aggregates
+            .iter_mut()
+            .map(|agg| (*agg).entity_mut())
+            .collect();
+        for entity in entities.iter_mut() {
+            self.namespace.enter(entity);
+        }
+        let result = self.repo.commit_async(&mut entities[..]).await;
+        for entity in entities.iter_mut() {
+            self.namespace.leave(entity);
+        }
+        result
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R, A> AggregateRepository<R, A>
+where
+    R: AsyncFind,
+    A: Aggregate,
+{
+    /// Async counterpart of [`find`](Self::find).
+    pub async fn find_async<F>(&self, predicate: F) -> Result<Vec<A>, RepositoryError>
+    where
+        F: Fn(&A) -> bool,
+    {
+        let namespace = self.namespace;
+        let entities = self.repo.find_async(move |e| namespace.contains(e.id())).await?;
+        let mut results = Vec::new();
+        for entity in entities {
+            let agg = hydrate::<A>(namespace.load(entity))?;
+            if predicate(&agg) {
+                results.push(agg);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Async counterpart of [`find_one`](Self::find_one).
+    pub async fn find_one_async<F>(&self, predicate: F) -> Result<Option<A>, RepositoryError>
+    where
+        F: Fn(&A) -> bool,
+    {
+        let namespace = self.namespace;
+        let entities = self.repo.find_async(move |e| namespace.contains(e.id())).await?;
+        for entity in entities {
+            let agg = hydrate::<A>(namespace.load(entity))?;
+            if predicate(&agg) {
+                return Ok(Some(agg));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Async counterpart of [`exists`](Self::exists).
+    pub async fn exists_async<F>(&self, predicate: F) -> Result<bool, RepositoryError>
+    where
+        F: Fn(&A) -> bool,
+    {
+        Ok(self.find_one_async(predicate).await?.is_some())
+    }
+
+    /// Async counterpart of [`count`](Self::count).
+    pub async fn count_async<F>(&self, predicate: F) -> Result<usize, RepositoryError>
+    where
+        F: Fn(&A) -> bool,
+    {
+        Ok(self.find_async(predicate).await?.len())
+    }
+}
+
 // Re-export from queued module for backward compatibility
 pub use crate::queued_repo::{GetAllWithOpts, GetWithOpts, ReadOpts, UnlockableRepository};
 
@@ -362,7 +781,32 @@ where
     A: Aggregate,
 {
     pub fn abort(&self, aggregate: &A) -> Result<(), RepositoryError> {
-        self.repo.unlock(aggregate.entity().id())
+        self.repo.unlock(&self.namespace.stream_id(aggregate.entity().id()))
+    }
+}
+
+impl<R, A> AggregateRepository<R, A>
+where
+    R: Get + UnlockableRepository,
+    A: Aggregate,
+{
+    /// Like [`get`](Self::get) on a locking repository, but the lock is held
+    /// by the returned [`Locked`] guard and released when it is dropped
+    /// unless the aggregate is committed. A missing aggregate is unlocked
+    /// right away.
+    pub fn get_locked(&self, id: &str) -> Result<Option<Locked<'_, R, A>>, RepositoryError> {
+        let stream_id = self.namespace.stream_id(id).into_owned();
+        let Some(entity) = self.repo.get(stream_id.as_str())? else {
+            self.repo.unlock(&stream_id)?;
+            return Ok(None);
+        };
+        match hydrate::<A>(self.namespace.load(entity)) {
+            Ok(aggregate) => Ok(Some(Locked::new(self, stream_id, aggregate))),
+            Err(err) => {
+                self.repo.unlock(&stream_id)?;
+                Err(err)
+            }
+        }
     }
 }
 
@@ -384,11 +828,11 @@ where
 {
     /// Get an aggregate with options (e.g., to skip locking).
     pub fn get_with(&self, id: &str, opts: ReadOpts) -> Result<Option<A>, RepositoryError> {
-        let entity = self.repo.get_with(id, opts)?;
+        let entity = self.repo.get_with(&self.namespace.stream_id(id), opts)?;
         let Some(entity) = entity else {
             return Ok(None);
         };
-        Ok(Some(hydrate::<A>(entity)?))
+        Ok(Some(hydrate::<A>(self.namespace.load(entity))?))
     }
 
     /// Non-locking read (alias for get_with no_lock).
@@ -404,12 +848,10 @@ where
 {
     /// Get all aggregates with options (e.g., to skip locking).
     pub fn get_all_with(&self, ids: &[&str], opts: ReadOpts) -> Result<Vec<A>, RepositoryError> {
-        let entities = self.repo.get_all_with(ids, opts)?;
-        let mut aggregates = Vec::with_capacity(entities.len());
-        for entity in entities {
-            aggregates.push(hydrate::<A>(entity)?);
-        }
-        Ok(aggregates)
+        let stream_ids = self.namespace.stream_ids(ids);
+        let stream_ids: Vec<&str> = stream_ids.iter().map(|id| id.as_ref()).collect();
+        let entities = self.repo.get_all_with(&stream_ids, opts)?;
+        self.hydrate_all(entities)
     }
 
     /// Non-locking read (alias for get_all_with no_lock).
@@ -0,0 +1,77 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::queued_repo::UnlockableRepository;
+use crate::repository::{Commit, RepositoryError};
+
+use super::aggregate::{Aggregate, AggregateRepository};
+
+/// An aggregate read with [`get_locked`](AggregateRepository::get_locked),
+/// holding its stream's lock.
+///
+/// The lock is released when the guard is dropped, unless the aggregate was
+/// committed (which releases it) or taken with [`into_inner`](Self::into_inner).
+/// A panic or early `?` return in a handler therefore cannot leak the lock.
+pub struct Locked<'a, R: UnlockableRepository, A> {
+    repo: &'a AggregateRepository<R, A>,
+    stream_id: String,
+    aggregate: Option<A>,
+}
+
+impl<'a, R: UnlockableRepository, A> Locked<'a, R, A> {
+    pub(crate) fn new(
+        repo: &'a AggregateRepository<R, A>,
+        stream_id: String,
+        aggregate: A,
+    ) -> Self {
+        Self {
+            repo,
+            stream_id,
+            aggregate: Some(aggregate),
+        }
+    }
+
+    /// Release the lock now, discarding any uncommitted changes.
+    pub fn abort(mut self) -> Result<(), RepositoryError> {
+        self.aggregate = None;
+        self.repo.repo().unlock(&self.stream_id)
+    }
+
+    /// Take the aggregate, keeping the lock. It is then released by a later
+    /// `commit`, `abort` or `unlock`, as with `get`.
+    pub fn into_inner(mut self) -> A {
+        self.aggregate.take().expect("aggregate is only taken on consume")
+    }
+}
+
+impl<R: UnlockableRepository + Commit, A: Aggregate> Locked<'_, R, A> {
+    /// Commit the aggregate and return it. If the commit fails, the guard is
+    /// dropped and the lock released.
+    pub fn commit(mut self) -> Result<A, RepositoryError> {
+        let aggregate = self.aggregate.as_mut().expect("aggregate is only taken on consume");
+        self.repo.commit(aggregate)?;
+        Ok(self.aggregate.take().expect("aggregate is only taken on consume"))
+    }
+}
+
+impl<R: UnlockableRepository, A> Deref for Locked<'_, R, A> {
+    type Target = A;
+
+    fn deref(&self) -> &A {
+        self.aggregate.as_ref().expect("aggregate is only taken on consume")
+    }
+}
+
+impl<R: UnlockableRepository, A> DerefMut for Locked<'_, R, A> {
+    fn deref_mut(&mut self) -> &mut A {
+        self.aggregate.as_mut().expect("aggregate is only taken on consume")
+    }
+}
+
+impl<R: UnlockableRepository, A> Drop for Locked<'_, R, A> {
+    fn drop(&mut self) {
+        // `None` after a commit or `into_inner`, which own the lock from then on.
+        if self.aggregate.is_some() {
+            let _ = self.repo.repo().unlock(&self.stream_id);
+        }
+    }
+}
@@ -1,7 +1,17 @@
 mod aggregate;
+mod locked;
+mod namespace;
+mod retry;
+mod unit_of_work;
 
 pub use aggregate::{
     hydrate, Aggregate, AggregateBuilder, AggregateRepository, CommitAggregate, CountAggregate,
     ExistsAggregate, FindAggregate, FindOneAggregate, GetAggregate, GetAllAggregates,
     GetAllWithOpts, GetWithOpts, ReadOpts, RepositoryExt, UnlockableRepository,
 };
+#[doc(hidden)]
+pub use aggregate::type_name_of_path;
+pub use locked::Locked;
+pub(crate) use namespace::Namespace;
+pub use retry::{RetryPolicy, Updated};
+pub use unit_of_work::{AggregateRef, UnitOfWork};
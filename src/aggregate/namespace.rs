@@ -0,0 +1,96 @@
+use std::borrow::Cow;
+
+use crate::entity::Entity;
+
+/// Maps aggregate IDs to stream IDs for a namespaced
+/// [`AggregateRepository`](super::AggregateRepository).
+///
+/// In a namespace the stream of aggregate `id` is `"{TYPE_NAME}:{id}"`.
+/// Without one, stream IDs and aggregate IDs are the same.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Namespace(Option<&'static str>);
+
+impl Namespace {
+    /// # Panics
+    ///
+    /// If `type_name` is empty, i.e. the aggregate did not set
+    /// [`Aggregate::TYPE_NAME`](super::Aggregate::TYPE_NAME).
+    pub fn of(type_name: &'static str) -> Self {
+        assert!(
+            !type_name.is_empty(),
+            "namespaced streams need Aggregate::TYPE_NAME to be set"
+        );
+        Namespace(Some(type_name))
+    }
+
+    pub fn stream_id<'a>(&self, id: &'a str) -> Cow<'a, str> {
+        match self.0 {
+            Some(name) => Cow::Owned(format!("{}:{}", name, id)),
+            None => Cow::Borrowed(id),
+        }
+    }
+
+    pub fn stream_ids<'a>(&self, ids: &[&'a str]) -> Vec<Cow<'a, str>> {
+        ids.iter().map(|id| self.stream_id(id)).collect()
+    }
+
+    /// The aggregate ID of a stream in this namespace, or `None` if the
+    /// stream belongs to another one.
+    pub fn aggregate_id<'a>(&self, stream_id: &'a str) -> Option<&'a str> {
+        match self.0 {
+            Some(name) => stream_id.strip_prefix(name)?.strip_prefix(':'),
+            None => Some(stream_id),
+        }
+    }
+
+    pub fn contains(&self, stream_id: &str) -> bool {
+        self.aggregate_id(stream_id).is_some()
+    }
+
+    /// Switch a loaded entity from its stream ID to its aggregate ID.
+    pub fn load(&self, mut entity: Entity) -> Entity {
+        self.leave(&mut entity);
+        entity
+    }
+
+    /// Switch an aggregate's entity to its stream ID before committing.
+    pub fn enter(&self, entity: &mut Entity) {
+        if self.0.is_some() {
+            let stream_id = self.stream_id(entity.id()).into_owned();
+            entity.set_id(stream_id);
+        }
+    }
+
+    /// Undo [`enter`](Self::enter).
+    pub fn leave(&self, entity: &mut Entity) {
+        if self.0.is_some() {
+            if let Some(id) = self.aggregate_id(entity.id()).map(str::to_string) {
+                entity.set_id(id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_ids_to_streams_and_back() {
+        let orders = Namespace::of("Order");
+        assert_eq!(orders.stream_id("42"), "Order:42");
+        assert_eq!(orders.aggregate_id("Order:42"), Some("42"));
+        assert_eq!(orders.aggregate_id("OrderLine:42"), None);
+        assert!(!orders.contains("outbox:1"));
+
+        let mut entity = Entity::with_id("42");
+        orders.enter(&mut entity);
+        assert_eq!(entity.id(), "Order:42");
+        orders.leave(&mut entity);
+        assert_eq!(entity.id(), "42");
+
+        let none = Namespace::default();
+        assert_eq!(none.stream_id("42"), "42");
+        assert!(none.contains("outbox:1"));
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::thread;
+use std::time::Duration;
+
+use crate::repository::RepositoryError;
+
+/// How [`AggregateRepository::update`](super::AggregateRepository::update)
+/// retries a command after a [`RepositoryError::ConcurrentWrite`].
+///
+/// The delay before retry `n` is `initial_backoff * 2^(n-1)`, capped at
+/// `max_backoff`. With jitter enabled the delay is scaled by a random factor
+/// in `[0.5, 1.0]` so competing writers do not retry in lockstep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    /// Three attempts, 10ms initial backoff capped at 1s, with jitter.
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy making at most `max_attempts` attempts (at least one).
+    pub fn new(max_attempts: u32) -> Self {
+        Self::default().with_max_attempts(max_attempts)
+    }
+
+    /// A policy that gives up on the first conflict.
+    pub fn never() -> Self {
+        Self::new(1)
+    }
+
+    /// Set the maximum number of attempts, including the first one.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Set the delay before the first retry and the cap for later ones.
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// Retry immediately, without sleeping.
+    pub fn without_backoff(self) -> Self {
+        self.with_backoff(Duration::ZERO, Duration::ZERO)
+    }
+
+    /// Enable or disable jitter.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// The delay before retrying after failed attempt number `attempt` (1-based).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self
+            .initial_backoff
+            .saturating_mul(1 << exponent)
+            .min(self.max_backoff);
+        if !self.jitter || delay.is_zero() {
+            return delay;
+        }
+        let random = RandomState::new().hash_one(attempt);
+        let factor = 0.5 + (random % 1000) as f64 / 2000.0;
+        delay.mul_f64(factor)
+    }
+
+    /// Load, mutate and commit until the commit does not conflict or the
+    /// attempts run out, which fails with
+    /// [`RepositoryError::RetriesExhausted`]. Returns `None` if `load` finds
+    /// nothing.
+    ///
+    /// `release` gives up the stream after every attempt that did not
+    /// commit, so that a locking repository can load it again. It is not
+    /// called when `load` fails to take the lock.
+    pub(crate) fn run<A, T, E, L, C, U, F>(
+        &self,
+        mut load: L,
+        mut commit: C,
+        mut release: U,
+        mut command: F,
+    ) -> Result<Option<Updated<A, T>>, E>
+    where
+        L: FnMut() -> Result<Option<A>, RepositoryError>,
+        C: FnMut(&mut A) -> Result<(), RepositoryError>,
+        U: FnMut() -> Result<(), RepositoryError>,
+        F: FnMut(&mut A) -> Result<T, E>,
+        E: From<RepositoryError>,
+    {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let mut aggregate = match load() {
+                Ok(Some(aggregate)) => aggregate,
+                Ok(None) => {
+                    release()?;
+                    return Ok(None);
+                }
+                Err(err @ RepositoryError::Lock(_)) => return Err(err.into()),
+                Err(err) => {
+                    let _ = release();
+                    return Err(err.into());
+                }
+            };
+            let value = match command(&mut aggregate) {
+                Ok(value) => value,
+                Err(err) => {
+                    let _ = release();
+                    return Err(err);
+                }
+            };
+            match commit(&mut aggregate) {
+                Ok(()) => {
+                    return Ok(Some(Updated {
+                        aggregate,
+                        value,
+                        attempts,
+                    }))
+                }
+                Err(RepositoryError::ConcurrentWrite { .. }) if attempts < self.max_attempts => {
+                    release()?;
+                    thread::sleep(self.backoff(attempts));
+                }
+                Err(err @ RepositoryError::ConcurrentWrite { .. }) => {
+                    let _ = release();
+                    return Err(RepositoryError::RetriesExhausted {
+                        attempts,
+                        last: Box::new(err),
+                    }
+                    .into());
+                }
+                Err(err) => {
+                    let _ = release();
+                    return Err(err.into());
+                }
+            }
+        }
+    }
+}
+
+/// The result of a successful
+/// [`AggregateRepository::update`](super::AggregateRepository::update).
+#[derive(Debug)]
+pub struct Updated<A, T = ()> {
+    /// The aggregate as committed.
+    pub aggregate: A,
+    /// The value returned by the command closure on the successful attempt.
+    pub value: T,
+    /// Number of attempts made, including the successful one.
+    pub attempts: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conflict() -> RepositoryError {
+        RepositoryError::ConcurrentWrite {
+            id: "a".into(),
+            expected: 1,
+            actual: 2,
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy::new(10)
+            .with_backoff(Duration::from_millis(10), Duration::from_millis(50))
+            .with_jitter(false);
+        let delays: Vec<u128> = (1..=4).map(|n| policy.backoff(n).as_millis()).collect();
+        assert_eq!(delays, vec![10, 20, 40, 50]);
+
+        let jittered = RetryPolicy::default().backoff(2);
+        assert!(jittered >= Duration::from_millis(10) && jittered <= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn run_retries_conflicts_and_counts_attempts() {
+        let policy = RetryPolicy::new(3).without_backoff();
+        let mut conflicts = 2;
+        let mut releases = 0;
+        let updated = policy
+            .run(
+                || Ok(Some(0)),
+                |_| {
+                    if conflicts > 0 {
+                        conflicts -= 1;
+                        return Err(conflict());
+                    }
+                    Ok(())
+                },
+                || {
+                    releases += 1;
+                    Ok(())
+                },
+                |n: &mut i32| {
+                    *n += 1;
+                    Ok::<_, RepositoryError>("done")
+                },
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.attempts, 3);
+        assert_eq!(updated.value, "done");
+        assert_eq!(releases, 2);
+    }
+
+    #[test]
+    fn run_gives_up_after_max_attempts() {
+        let mut calls = 0;
+        let err = RetryPolicy::new(2)
+            .without_backoff()
+            .run(|| Ok(Some(())), |_| Err(conflict()), || Ok(()), |_| {
+                calls += 1;
+                Ok::<_, RepositoryError>(())
+            })
+            .unwrap_err();
+        assert_eq!(
+            err,
+            RepositoryError::RetriesExhausted {
+                attempts: 2,
+                last: Box::new(conflict()),
+            }
+        );
+        assert_eq!(calls, 2);
+    }
+}
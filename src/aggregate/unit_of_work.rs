@@ -0,0 +1,444 @@
+//! UnitOfWork - Load aggregates of several types and commit them together.
+//!
+//! ## Example
+//!
+//! ```ignore
+//! let mut uow = UnitOfWork::new(&repo);
+//! uow.load(&[
+//!     AggregateRef::of::<Order>("order-1"),
+//!     AggregateRef::of::<Inventory>("WIDGET-001"),
+//! ])?;
+//!
+//! uow.get_mut::<Inventory>("WIDGET-001").unwrap().reserve("order-1".into(), 5);
+//! uow.get_mut::<Order>("order-1").unwrap().mark_inventory_reserved();
+//! uow.add(payment);
+//!
+//! // One commit, one concurrency check for every tracked aggregate
+//! uow.commit()?;
+//! ```
+
+use std::any::{Any, TypeId};
+
+use crate::entity::Entity;
+use crate::repository::{Commit, Get, RepositoryError};
+
+use super::aggregate::{hydrate, Aggregate};
+use super::namespace::Namespace;
+use super::UnlockableRepository;
+
+/// A type-erased tracked aggregate.
+trait Tracked: Any {
+    fn entity_mut(&mut self) -> &mut Entity;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<A: Aggregate + 'static> Tracked for A {
+    fn entity_mut(&mut self) -> &mut Entity {
+        Aggregate::entity_mut(self)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+fn hydrate_tracked<A: Aggregate + 'static>(
+    entity: Entity,
+) -> Result<Box<dyn Tracked>, RepositoryError> {
+    Ok(Box::new(hydrate::<A>(entity)?))
+}
+
+/// An aggregate to [`load`](UnitOfWork::load): its type and ID.
+pub struct AggregateRef {
+    type_id: TypeId,
+    type_name: &'static str,
+    id: String,
+    hydrate: fn(Entity) -> Result<Box<dyn Tracked>, RepositoryError>,
+}
+
+impl AggregateRef {
+    pub fn of<A: Aggregate + 'static>(id: impl Into<String>) -> Self {
+        Self {
+            type_id: TypeId::of::<A>(),
+            type_name: A::TYPE_NAME,
+            id: id.into(),
+            hydrate: hydrate_tracked::<A>,
+        }
+    }
+}
+
+struct Entry {
+    type_id: TypeId,
+    type_name: &'static str,
+    id: String,
+    aggregate: Box<dyn Tracked>,
+}
+
+/// Tracks every aggregate loaded through it, whatever its type, and commits
+/// them all in one repository commit.
+///
+/// The commit checks every tracked aggregate, changed or not, against the
+/// store, so either all of their events are appended or none are. Loading
+/// through a [`QueuedRepository`](crate::QueuedRepository) locks the streams
+/// until the unit of work is committed or aborted, including the streams of
+/// missing IDs. [`load`](Self::load) locks one batch in stream ID order, so
+/// units of work that load their overlapping aggregates in a single `load`
+/// cannot deadlock; later `load` and [`get`](Self::get) calls lock in call
+/// order. A unit of work dropped without a successful commit releases every
+/// stream it locked.
+pub struct UnitOfWork<'r, R: Commit> {
+    repo: &'r R,
+    namespaced: bool,
+    entries: Vec<Entry>,
+    requested: Vec<String>,
+}
+
+impl<'r, R: Commit> UnitOfWork<'r, R> {
+    pub fn new(repo: &'r R) -> Self {
+        Self {
+            repo,
+            namespaced: false,
+            entries: Vec::new(),
+            requested: Vec::new(),
+        }
+    }
+
+    /// Store each aggregate type in its own namespace, as a
+    /// [`namespaced`](super::AggregateRepository::namespaced) aggregate
+    /// repository would.
+    pub fn namespaced(mut self) -> Self {
+        self.namespaced = true;
+        self
+    }
+
+    fn namespace(&self, type_name: &'static str) -> Namespace {
+        if self.namespaced {
+            Namespace::of(type_name)
+        } else {
+            Namespace::default()
+        }
+    }
+
+    fn position(&self, type_id: TypeId, id: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.type_id == type_id && entry.id == id)
+    }
+
+    fn stream_id(&self, type_name: &'static str, id: &str) -> String {
+        self.namespace(type_name).stream_id(id).into_owned()
+    }
+
+    /// Whether an aggregate is tracked.
+    pub fn contains<A: Aggregate + 'static>(&self, id: &str) -> bool {
+        self.position(TypeId::of::<A>(), id).is_some()
+    }
+
+    /// A tracked aggregate.
+    pub fn get_mut<A: Aggregate + 'static>(&mut self, id: &str) -> Option<&mut A> {
+        let index = self.position(TypeId::of::<A>(), id)?;
+        self.entries[index].aggregate.as_any_mut().downcast_mut::<A>()
+    }
+
+    /// Track a new aggregate, replacing a tracked one of the same type and ID.
+    pub fn add<A: Aggregate + 'static>(&mut self, mut aggregate: A) -> &mut A {
+        let id = aggregate.entity_mut().id().to_string();
+        let entry = Entry {
+            type_id: TypeId::of::<A>(),
+            type_name: A::TYPE_NAME,
+            id: id.clone(),
+            aggregate: Box::new(aggregate),
+        };
+        let index = match self.position(entry.type_id, &id) {
+            Some(index) => {
+                self.entries[index] = entry;
+                index
+            }
+            None => {
+                self.entries.push(entry);
+                self.entries.len() - 1
+            }
+        };
+        self.entries[index]
+            .aggregate
+            .as_any_mut()
+            .downcast_mut::<A>()
+            .expect("entry was just stored with this type")
+    }
+
+    /// Stop tracking an aggregate and return it.
+    pub fn take<A: Aggregate + 'static>(&mut self, id: &str) -> Option<A> {
+        let index = self.position(TypeId::of::<A>(), id)?;
+        let entry = self.entries.remove(index);
+        entry.aggregate.into_any().downcast::<A>().ok().map(|a| *a)
+    }
+}
+
+impl<R: Get + Commit> UnitOfWork<'_, R> {
+    /// Load and track several aggregates, possibly of different types, with
+    /// one repository read. Already tracked aggregates are kept as they are,
+    /// and aggregates that do not exist are skipped. A stream is read once
+    /// however often it is requested; requesting it as a different type than
+    /// it is tracked or requested as fails with
+    /// [`RepositoryError::StreamTypeConflict`] before anything is read.
+    pub fn load(&mut self, refs: &[AggregateRef]) -> Result<(), RepositoryError> {
+        let mut pending: Vec<(&AggregateRef, String)> = Vec::new();
+        for r in refs {
+            let stream_id = self.stream_id(r.type_name, &r.id);
+            let tracked = self
+                .entries
+                .iter()
+                .find(|entry| self.stream_id(entry.type_name, &entry.id) == stream_id)
+                .map(|entry| (entry.type_id, entry.type_name))
+                .or_else(|| {
+                    pending
+                        .iter()
+                        .find(|(_, id)| *id == stream_id)
+                        .map(|(p, _)| (p.type_id, p.type_name))
+                });
+            match tracked {
+                Some((type_id, _)) if type_id == r.type_id => continue,
+                Some((_, tracked)) => {
+                    return Err(RepositoryError::StreamTypeConflict {
+                        stream_id,
+                        tracked,
+                        requested: r.type_name,
+                    })
+                }
+                // Requested before and missing (or taken): already locked
+                None if self.requested.contains(&stream_id) => continue,
+                None => pending.push((r, stream_id)),
+            }
+        }
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let stream_ids: Vec<&str> = pending.iter().map(|(_, id)| id.as_str()).collect();
+        self.requested.extend(stream_ids.iter().map(|id| id.to_string()));
+        let entities = self.repo.get(&stream_ids[..])?;
+
+        for entity in entities {
+            let Some((r, _)) = pending.iter().find(|(_, id)| id == entity.id()) else {
+                continue;
+            };
+            let entity = self.namespace(r.type_name).load(entity);
+            self.entries.push(Entry {
+                type_id: r.type_id,
+                type_name: r.type_name,
+                id: r.id.clone(),
+                aggregate: (r.hydrate)(entity)?,
+            });
+        }
+        Ok(())
+    }
+
+    /// A tracked aggregate, loading and tracking it first if needed.
+    pub fn get<A: Aggregate + 'static>(
+        &mut self,
+        id: &str,
+    ) -> Result<Option<&mut A>, RepositoryError> {
+        if !self.contains::<A>(id) {
+            self.load(&[AggregateRef::of::<A>(id)])?;
+        }
+        Ok(self.get_mut::<A>(id))
+    }
+}
+
+impl<R: Commit> UnitOfWork<'_, R> {
+    /// Commit every tracked aggregate at once. Aggregates stay tracked, so
+    /// they can be changed and committed again. Once the commit succeeds,
+    /// streams that were loaded but are no longer tracked (missing IDs and
+    /// aggregates removed with [`take`](Self::take)) are released too, and
+    /// the unit of work holds no locks until it loads again.
+    pub fn commit(&mut self) -> Result<(), RepositoryError> {
+        let namespaces: Vec<Namespace> = self
+            .entries
+            .iter()
+            .map(|entry| self.namespace(entry.type_name))
+            .collect();
+        let mut entities: Vec<&mut Entity> = self
+            .entries
+            .iter_mut()
+            .map(|entry| entry.aggregate.entity_mut())
+            .collect();
+
+        for (entity, namespace) in entities.iter_mut().zip(&namespaces) {
+            namespace.enter(entity);
+        }
+        let result = self.repo.commit(&mut entities[..]);
+        for (entity, namespace) in entities.iter_mut().zip(&namespaces) {
+            namespace.leave(entity);
+        }
+        result?;
+
+        let tracked: Vec<String> = self
+            .entries
+            .iter()
+            .zip(&namespaces)
+            .map(|(entry, namespace)| namespace.stream_id(&entry.id).into_owned())
+            .collect();
+        // The repository commit released the tracked streams
+        let requested = std::mem::take(&mut self.requested);
+        let untracked: Vec<&str> = requested
+            .iter()
+            .map(String::as_str)
+            .filter(|stream_id| !tracked.iter().any(|id| id == stream_id))
+            .collect();
+        self.repo.release(&untracked)
+    }
+}
+
+impl<R: Commit + UnlockableRepository> UnitOfWork<'_, R> {
+    /// Give up on the unit of work, releasing the lock of every stream it
+    /// loaded or tried to load.
+    pub fn abort(mut self) -> Result<(), RepositoryError> {
+        for stream_id in std::mem::take(&mut self.requested) {
+            self.repo.unlock(&stream_id)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: Commit> Drop for UnitOfWork<'_, R> {
+    fn drop(&mut self) {
+        if !self.requested.is_empty() {
+            let requested: Vec<&str> = self.requested.iter().map(String::as_str).collect();
+            let _ = self.repo.release(&requested);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{impl_aggregate, EventRecord, HashMapRepository};
+
+    #[derive(Default)]
+    struct Counter {
+        entity: Entity,
+        value: u32,
+    }
+
+    impl Counter {
+        fn create(id: &str) -> Self {
+            let mut counter = Counter::default();
+            counter.entity.set_id(id);
+            counter.entity.digest_empty("Created");
+            counter
+        }
+
+        fn increment(&mut self) {
+            self.value += 1;
+            self.entity.digest_empty("Incremented");
+        }
+
+        fn replay(&mut self, event: &EventRecord) -> Result<(), String> {
+            if event.event_name == "Incremented" {
+                self.value += 1;
+            }
+            Ok(())
+        }
+    }
+
+    impl_aggregate!(Counter, entity, replay);
+
+    #[derive(Default)]
+    struct Tally {
+        entity: Entity,
+    }
+
+    impl Tally {
+        fn replay(&mut self, _event: &EventRecord) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    impl_aggregate!(Tally, entity, replay);
+
+    #[test]
+    fn tracks_aggregates_of_different_types_by_type_and_id() {
+        let repo = HashMapRepository::new();
+        let mut uow = UnitOfWork::new(&repo).namespaced();
+        uow.add(Counter::create("a"));
+        let mut tally = Tally::default();
+        tally.entity.set_id("a");
+        tally.entity.digest_empty("Opened");
+        uow.add(tally);
+        uow.commit().unwrap();
+
+        let mut uow = UnitOfWork::new(&repo).namespaced();
+        uow.load(&[AggregateRef::of::<Counter>("a"), AggregateRef::of::<Tally>("a")])
+            .unwrap();
+        assert!(uow.contains::<Tally>("a"));
+        uow.get_mut::<Counter>("a").unwrap().increment();
+        uow.commit().unwrap();
+
+        assert!(uow.get::<Counter>("missing").unwrap().is_none());
+        let counter = uow.take::<Counter>("a").unwrap();
+        assert_eq!(counter.value, 1);
+        assert_eq!(counter.entity.id(), "a");
+        assert_eq!(repo.get("Counter:a").unwrap().unwrap().version(), 2);
+    }
+
+    #[test]
+    fn a_stale_aggregate_fails_the_whole_commit() {
+        let repo = HashMapRepository::new();
+        let mut setup = UnitOfWork::new(&repo);
+        setup.add(Counter::create("a"));
+        setup.add(Counter::create("b"));
+        setup.commit().unwrap();
+
+        let mut uow = UnitOfWork::new(&repo);
+        uow.get::<Counter>("a").unwrap().unwrap().increment();
+        uow.get::<Counter>("b").unwrap().unwrap().increment();
+
+        // Someone else changes "b" (which this unit of work read) meanwhile
+        let mut other = UnitOfWork::new(&repo);
+        other.get::<Counter>("b").unwrap().unwrap().increment();
+        other.commit().unwrap();
+
+        let err = uow.commit().unwrap_err();
+        assert!(matches!(err, RepositoryError::ConcurrentWrite { .. }));
+        assert_eq!(repo.get("a").unwrap().unwrap().version(), 1);
+    }
+
+    #[test]
+    fn a_stream_is_tracked_once_and_as_one_type() {
+        let repo = HashMapRepository::new();
+        let mut setup = UnitOfWork::new(&repo);
+        setup.add(Counter::create("a"));
+        setup.commit().unwrap();
+
+        let mut uow = UnitOfWork::new(&repo);
+        uow.load(&[AggregateRef::of::<Counter>("a"), AggregateRef::of::<Counter>("a")])
+            .unwrap();
+        uow.get_mut::<Counter>("a").unwrap().increment();
+        uow.commit().unwrap();
+        assert_eq!(uow.entries.len(), 1);
+        assert_eq!(repo.get("a").unwrap().unwrap().version(), 2);
+
+        // Without namespaces, Counter "a" and Tally "a" are the same stream
+        let err = uow
+            .load(&[AggregateRef::of::<Tally>("a"), AggregateRef::of::<Tally>("b")])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            RepositoryError::StreamTypeConflict {
+                stream_id: "a".into(),
+                tracked: "Counter",
+                requested: "Tally",
+            }
+        );
+        assert!(!uow.contains::<Tally>("b"));
+        let err = UnitOfWork::new(&repo)
+            .load(&[AggregateRef::of::<Tally>("b"), AggregateRef::of::<Counter>("b")])
+            .unwrap_err();
+        assert!(matches!(err, RepositoryError::StreamTypeConflict { .. }));
+    }
+}
@@ -15,25 +15,29 @@
 //!     .commit(&mut game)?;
 //! ```
 
-use crate::aggregate::Aggregate;
+mod write_set;
+
+pub use write_set::{AtomicCommit, ModelChange, ModelWrite, WriteSet};
+#[cfg(feature = "async")]
+pub use write_set::AsyncAtomicCommit;
+pub(crate) use write_set::check_model_versions;
+
+use crate::aggregate::{Aggregate, Namespace};
 use crate::entity::Entity;
-use crate::read_model::{ReadModel, ReadModelStore};
+use crate::read_model::ReadModel;
 use crate::outbox::OutboxMessage;
-use crate::repository::{Commit, RepositoryError};
-
-/// A queued read model save (type-erased).
-struct QueuedModel {
-    /// Storage key: "COLLECTION:id"
-    key: String,
-    /// JSON-serialized bytes
-    bytes: Vec<u8>,
-}
+use crate::repository::{ExpectedVersion, RepositoryError};
 
 /// Builder for chaining multiple items into a single atomic commit.
+///
+/// Everything is applied as one [`WriteSet`] by the store's [`AtomicCommit`]
+/// implementation: either the events and every read model are stored, or
+/// none of them are.
 pub struct CommitBuilder<'a, R> {
     repo: &'a R,
     entities: Vec<Entity>,
-    models: Vec<QueuedModel>,
+    models: Vec<ModelWrite>,
+    namespaced: bool,
 }
 
 impl<'a, R> CommitBuilder<'a, R> {
@@ -42,6 +46,23 @@ impl<'a, R> CommitBuilder<'a, R> {
             repo,
             entities: vec![],
             models: vec![],
+            namespaced: false,
+        }
+    }
+
+    /// Commit the primary aggregate to its namespaced stream, as a
+    /// [`namespaced`](crate::AggregateRepository::namespaced) aggregate
+    /// repository would.
+    pub fn namespaced(mut self) -> Self {
+        self.namespaced = true;
+        self
+    }
+
+    fn namespace<A: Aggregate>(&self) -> Namespace {
+        if self.namespaced {
+            Namespace::of(A::TYPE_NAME)
+        } else {
+            Namespace::default()
         }
     }
 
@@ -49,7 +70,24 @@ impl<'a, R> CommitBuilder<'a, R> {
     pub fn readmodel<M: ReadModel>(mut self, model: &M) -> Self {
         let key = format!("{}:{}", M::COLLECTION, model.id());
         let bytes = serde_json::to_vec(model).expect("read model serialization should not fail");
-        self.models.push(QueuedModel { key, bytes });
+        self.models.push(ModelWrite::upsert(key, bytes));
+        self
+    }
+
+    /// Add a read model that must still be at `expected_version` (`0`: must
+    /// not exist yet). Otherwise the whole commit fails with
+    /// [`RepositoryError::ReadModelConflict`].
+    pub fn readmodel_expecting<M: ReadModel>(mut self, model: &M, expected_version: u64) -> Self {
+        let key = format!("{}:{}", M::COLLECTION, model.id());
+        let bytes = serde_json::to_vec(model).expect("read model serialization should not fail");
+        self.models.push(ModelWrite::upsert(key, bytes).expecting(expected_version));
+        self
+    }
+
+    /// Delete a read model as part of the commit. Deleting a missing model is
+    /// not an error.
+    pub fn delete_readmodel<M: ReadModel>(mut self, id: &str) -> Self {
+        self.models.push(ModelWrite::delete(format!("{}:{}", M::COLLECTION, id)));
         self
     }
 
@@ -62,20 +100,34 @@ impl<'a, R> CommitBuilder<'a, R> {
     /// Commit all items plus the primary aggregate.
     pub fn commit<A: Aggregate>(mut self, aggregate: &mut A) -> Result<(), RepositoryError>
     where
-        R: Commit + ReadModelStore,
+        R: AtomicCommit,
     {
-        // Commit entities (outbox messages + aggregate)
+        // Commit entities (outbox messages + aggregate) and queued read models
+        let namespace = self.namespace::<A>();
+        namespace.enter(aggregate.entity_mut());
         let mut entity_refs: Vec<&mut Entity> = self.entities.iter_mut().collect();
         entity_refs.push(aggregate.entity_mut());
-        self.repo.commit(&mut entity_refs[..])?;
-
-        // Write queued read models
-        for queued in self.models {
-            self.repo
-                .upsert_raw(&queued.key, queued.bytes)?;
-        }
+        let result = self.repo.commit_write_set(WriteSet::new(entity_refs, self.models));
+        namespace.leave(aggregate.entity_mut());
+        result
+    }
 
-        Ok(())
+    /// Like [`commit`](Self::commit), checking the aggregate's stream against
+    /// `expected`. Outbox messages and other entities keep their own checks.
+    /// The expectation applies to this attempt only, so after a failure (e.g.
+    /// a read model conflict) the aggregate is back to the default check.
+    pub fn commit_expecting<A: Aggregate>(
+        self,
+        aggregate: &mut A,
+        expected: ExpectedVersion,
+    ) -> Result<(), RepositoryError>
+    where
+        R: AtomicCommit,
+    {
+        aggregate.entity_mut().expect_version(expected);
+        let result = self.commit(aggregate);
+        aggregate.entity_mut().clear_expected_version();
+        result
     }
 
     /// Commit multiple entities atomically (along with any queued read models and outbox).
@@ -90,61 +142,96 @@ impl<'a, R> CommitBuilder<'a, R> {
         entities: &mut [&mut Entity],
     ) -> Result<(), RepositoryError>
     where
-        R: Commit + ReadModelStore,
+        R: AtomicCommit,
     {
         let mut entity_refs: Vec<&mut Entity> = self.entities.iter_mut().collect();
         for e in entities.iter_mut() {
             entity_refs.push(e);
         }
-        self.repo.commit(&mut entity_refs[..])?;
-
-        for queued in self.models {
-            self.repo.upsert_raw(&queued.key, queued.bytes)?;
-        }
-
-        Ok(())
+        self.repo.commit_write_set(WriteSet::new(entity_refs, self.models))
     }
 
     /// Commit without a primary aggregate.
     pub fn commit_all(mut self) -> Result<(), RepositoryError>
     where
-        R: Commit + ReadModelStore,
+        R: AtomicCommit,
     {
-        // Commit entities if any
-        if !self.entities.is_empty() {
-            let mut entity_refs: Vec<&mut Entity> = self.entities.iter_mut().collect();
-            self.repo.commit(&mut entity_refs[..])?;
-        }
+        let entity_refs: Vec<&mut Entity> = self.entities.iter_mut().collect();
+        self.repo.commit_write_set(WriteSet::new(entity_refs, self.models))
+    }
+}
 
-        // Write queued read models
-        for queued in self.models {
-            self.repo
-                .upsert_raw(&queued.key, queued.bytes)?;
+#[cfg(feature = "async")]
+impl<'a, R> CommitBuilder<'a, R>
+where
+    R: AsyncAtomicCommit,
+{
+    /// Async counterpart of [`commit`](Self::commit).
+    pub async fn commit_async<A: Aggregate>(mut self, aggregate: &mut A) -> Result<(), RepositoryError> {
+        let namespace = self.namespace::<A>();
+        namespace.enter(aggregate.entity_mut());
+        let mut entity_refs: Vec<&mut Entity> = self.entities.iter_mut().collect();
+        entity_refs.push(aggregate.entity_mut());
+        let writes = WriteSet::new(entity_refs, self.models);
+        let result = self.repo.commit_write_set_async(writes).await;
+        namespace.leave(aggregate.entity_mut());
+        result
+    }
+
+    /// Async counterpart of [`commit_many`](Self::commit_many).
+    pub async fn commit_many_async(
+        mut self,
+        entities: &mut [&mut Entity],
+    ) -> Result<(), RepositoryError> {
+        let mut entity_refs: Vec<&mut Entity> = self.entities.iter_mut().collect();
+        for e in entities.iter_mut() {
+            entity_refs.push(e);
         }
+        let writes = WriteSet::new(entity_refs, self.models);
+        self.repo.commit_write_set_async(writes).await
+    }
 
-        Ok(())
+    /// Async counterpart of [`commit_all`](Self::commit_all).
+    pub async fn commit_all_async(mut self) -> Result<(), RepositoryError> {
+        let entity_refs: Vec<&mut Entity> = self.entities.iter_mut().collect();
+        let writes = WriteSet::new(entity_refs, self.models);
+        self.repo.commit_write_set_async(writes).await
     }
 }
 
 /// Extension trait to start a commit builder chain from a read model or outbox.
-pub trait CommitBuilderExt: Commit + ReadModelStore + Sized {
+pub trait CommitBuilderExt: AtomicCommit + Sized {
     /// Start a commit builder chain with a read model.
     fn readmodel<M: ReadModel>(&self, model: &M) -> CommitBuilder<'_, Self> {
         CommitBuilder::new(self).readmodel(model)
     }
 
+    /// Start a commit builder chain with a version-checked read model.
+    fn readmodel_expecting<M: ReadModel>(
+        &self,
+        model: &M,
+        expected_version: u64,
+    ) -> CommitBuilder<'_, Self> {
+        CommitBuilder::new(self).readmodel_expecting(model, expected_version)
+    }
+
+    /// Start a commit builder chain with a read model deletion.
+    fn delete_readmodel<M: ReadModel>(&self, id: &str) -> CommitBuilder<'_, Self> {
+        CommitBuilder::new(self).delete_readmodel::<M>(id)
+    }
+
     /// Start a commit builder chain with an outbox message.
     fn outbox(&self, msg: OutboxMessage) -> CommitBuilder<'_, Self> {
         CommitBuilder::new(self).outbox(msg)
     }
 }
 
-impl<R: Commit + ReadModelStore> CommitBuilderExt for R {}
+impl<R: AtomicCommit> CommitBuilderExt for R {}
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{impl_aggregate, Entity, EventRecord, Get, HashMapRepository};
+    use crate::{impl_aggregate, Commit, Entity, EventRecord, Get, HashMapRepository, ReadAll};
     use crate::read_model::ReadModelsExt;
     use serde::{Deserialize, Serialize};
 
@@ -333,4 +420,149 @@ mod tests {
         let e2 = repo.get("agg-2").unwrap();
         assert!(e2.is_some());
     }
+
+    #[test]
+    fn failed_commit_writes_nothing() {
+        let repo = HashMapRepository::new();
+
+        let mut first = TestAggregate::default();
+        first.touch();
+        repo.commit(&mut first.entity).unwrap();
+
+        let view = TestView {
+            id: "1".into(),
+            counter: 42,
+        };
+        let outbox = OutboxMessage::create("msg-1", "TestEvent", b"{}".to_vec());
+        let mut agg = TestAggregate::default();
+        agg.touch();
+
+        let result = repo
+            .readmodel(&view)
+            .outbox(outbox)
+            .commit_expecting(&mut agg, ExpectedVersion::NoStream);
+        assert!(matches!(result, Err(RepositoryError::WrongExpectedVersion { .. })));
+
+        // Neither the outbox message nor the read model were written
+        assert_eq!(repo.read_all(0, 100).unwrap().len(), 1);
+        assert!(repo.read_models::<TestView>().get("1").unwrap().is_none());
+        assert_eq!(agg.entity.new_events().len(), 1);
+    }
+
+    #[test]
+    fn deletes_and_version_checks_commit_together() {
+        let repo = HashMapRepository::new();
+        let view = TestView {
+            id: "1".into(),
+            counter: 1,
+        };
+        let old = TestView {
+            id: "old".into(),
+            counter: 0,
+        };
+        repo.readmodel(&view).readmodel(&old).commit_all().unwrap();
+
+        // A stale expected version fails the commit: nothing is deleted or appended
+        let mut agg = TestAggregate::default();
+        agg.touch();
+        let result = repo
+            .readmodel_expecting(&view, 2)
+            .delete_readmodel::<TestView>("old")
+            .commit(&mut agg);
+        assert!(matches!(
+            result,
+            Err(RepositoryError::ReadModelConflict(
+                crate::ReadModelError::ConcurrencyConflict { expected: 2, actual: 1, .. }
+            ))
+        ));
+        assert!(repo.read_models::<TestView>().get("old").unwrap().is_some());
+        assert!(repo.get("agg-1").unwrap().is_none());
+
+        repo.readmodel_expecting(&view, 1)
+            .delete_readmodel::<TestView>("old")
+            .delete_readmodel::<TestView>("missing")
+            .commit(&mut agg)
+            .unwrap();
+        assert_eq!(repo.read_models::<TestView>().get("1").unwrap().unwrap().version, 2);
+        assert!(repo.read_models::<TestView>().get("old").unwrap().is_none());
+        assert!(repo.get("agg-1").unwrap().is_some());
+
+        // Expecting version 0 requires the model not to exist yet
+        let fresh = TestView {
+            id: "2".into(),
+            counter: 2,
+        };
+        repo.readmodel_expecting(&fresh, 0).commit_all().unwrap();
+        assert!(repo.readmodel_expecting(&fresh, 0).commit_all().is_err());
+    }
+
+    /// Writes to one key are checked in order, each against the version the
+    /// earlier writes of the set leave.
+    fn check_repeated_model_writes<R: AtomicCommit>(repo: &R) {
+        let view = TestView {
+            id: "1".into(),
+            counter: 1,
+        };
+        let result = repo
+            .readmodel_expecting(&view, 0)
+            .readmodel_expecting(&view, 0)
+            .commit_all();
+        assert!(matches!(
+            result,
+            Err(RepositoryError::ReadModelConflict(
+                crate::ReadModelError::ConcurrencyConflict { expected: 0, actual: 1, .. }
+            ))
+        ));
+        assert!(repo.get_model::<TestView>("1").unwrap().is_none());
+
+        repo.readmodel_expecting(&view, 0)
+            .readmodel_expecting(&view, 1)
+            .delete_readmodel::<TestView>("1")
+            .readmodel_expecting(&view, 0)
+            .commit_all()
+            .unwrap();
+        assert_eq!(repo.get_model::<TestView>("1").unwrap().unwrap().version, 1);
+    }
+
+    #[test]
+    fn repeated_model_writes_are_checked_in_order() {
+        check_repeated_model_writes(&HashMapRepository::new());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_checks_repeated_model_writes_in_order() {
+        check_repeated_model_writes(&crate::SqliteRepository::in_memory().unwrap());
+    }
+
+    #[test]
+    fn failed_commit_expecting_does_not_disable_the_next_check() {
+        let repo = HashMapRepository::new();
+        let mut agg = TestAggregate::default();
+        agg.touch();
+        repo.commit(&mut agg.entity).unwrap();
+
+        let mut stale = TestAggregate::default();
+        stale.entity = repo.get("agg-1").unwrap().unwrap();
+        agg.touch();
+        repo.commit(&mut agg.entity).unwrap();
+
+        // Fails on the read model, not the stream, which `Any` would accept
+        let view = TestView {
+            id: "1".into(),
+            counter: 1,
+        };
+        stale.touch();
+        let result = repo
+            .readmodel_expecting(&view, 5)
+            .commit_expecting(&mut stale, ExpectedVersion::Any);
+        assert!(matches!(result, Err(RepositoryError::ReadModelConflict(_))));
+
+        // A plain commit checks the stream against the loaded version again
+        let result = repo.readmodel(&view).commit(&mut stale);
+        assert!(matches!(
+            result,
+            Err(RepositoryError::ConcurrentWrite { expected: 1, actual: 2, .. })
+        ));
+    }
 }
@@ -0,0 +1,136 @@
+//! WriteSet - the writes of one atomic commit, and the stores that apply them.
+
+use std::collections::{HashMap, HashSet};
+#[cfg(feature = "async")]
+use std::future::Future;
+
+use crate::entity::Entity;
+use crate::read_model::{ReadModelError, ReadModelStore};
+#[cfg(feature = "async")]
+use crate::repository::AsyncCommit;
+use crate::repository::{Commit, RepositoryError};
+
+/// What a [`ModelWrite`] does to the stored model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModelChange {
+    /// Store the JSON-serialized model, creating or replacing it.
+    Upsert(Vec<u8>),
+    /// Remove the model. Deleting a missing model is not an error.
+    Delete,
+}
+
+/// A pre-serialized read model write.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelWrite {
+    /// Storage key: `"COLLECTION:id"`.
+    pub key: String,
+    pub change: ModelChange,
+    /// The version the stored model must be at, `0` meaning it must not
+    /// exist. Checked before anything in the write set is applied, against
+    /// the version left by earlier writes of the set to the same key.
+    pub expected_version: Option<u64>,
+}
+
+impl ModelWrite {
+    pub fn upsert(key: impl Into<String>, bytes: Vec<u8>) -> Self {
+        Self {
+            key: key.into(),
+            change: ModelChange::Upsert(bytes),
+            expected_version: None,
+        }
+    }
+
+    pub fn delete(key: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            change: ModelChange::Delete,
+            expected_version: None,
+        }
+    }
+
+    /// Require the stored model to be at `version`.
+    pub fn expecting(mut self, version: u64) -> Self {
+        self.expected_version = Some(version);
+        self
+    }
+
+    /// Check the expected version against the stored one (`None` if the
+    /// model does not exist), failing with a `ConcurrencyConflict`.
+    pub fn check_version(&self, actual: Option<u64>) -> Result<(), RepositoryError> {
+        let Some(expected) = self.expected_version else {
+            return Ok(());
+        };
+        let actual = actual.unwrap_or(0);
+        if expected == actual {
+            return Ok(());
+        }
+        let (collection, id) = self.key.split_once(':').unwrap_or((&self.key, ""));
+        Err(RepositoryError::ReadModelConflict(ReadModelError::ConcurrencyConflict {
+            collection: collection.to_string(),
+            id: id.to_string(),
+            expected,
+            actual,
+        }))
+    }
+}
+
+/// Check the expected versions of `models` in order, as if each earlier
+/// write of the set had been applied: an upsert bumps the version (or
+/// creates the model at version 1), a delete removes the model. `stored`
+/// looks up a key's version before the write set.
+pub(crate) fn check_model_versions(
+    models: &[ModelWrite],
+    mut stored: impl FnMut(&str) -> Result<Option<u64>, RepositoryError>,
+) -> Result<(), RepositoryError> {
+    let checked: HashSet<&str> = models
+        .iter()
+        .filter(|model| model.expected_version.is_some())
+        .map(|model| model.key.as_str())
+        .collect();
+    let mut versions: HashMap<&str, Option<u64>> = HashMap::new();
+    for model in models.iter().filter(|model| checked.contains(model.key.as_str())) {
+        let version = match versions.get(model.key.as_str()) {
+            Some(version) => *version,
+            None => stored(&model.key)?,
+        };
+        model.check_version(version)?;
+        let next = match model.change {
+            ModelChange::Upsert(_) => Some(version.map_or(1, |version| version + 1)),
+            ModelChange::Delete => None,
+        };
+        versions.insert(&model.key, next);
+    }
+    Ok(())
+}
+
+/// Everything one [`CommitBuilder`](super::CommitBuilder) commit writes:
+/// entities with new events, and read models.
+pub struct WriteSet<'a> {
+    pub entities: Vec<&'a mut Entity>,
+    pub models: Vec<ModelWrite>,
+}
+
+impl<'a> WriteSet<'a> {
+    pub fn new(entities: Vec<&'a mut Entity>, models: Vec<ModelWrite>) -> Self {
+        Self { entities, models }
+    }
+}
+
+/// A store that applies a [`WriteSet`] all-or-nothing.
+///
+/// If any check fails (an entity's or read model's expected version, a
+/// poisoned lock, a database error), nothing in the write set is stored and no entity is marked
+/// committed. `HashMapRepository` applies the write set under its event and
+/// read model locks; `SqliteRepository` in one SQL transaction.
+pub trait AtomicCommit: Commit + ReadModelStore {
+    fn commit_write_set(&self, writes: WriteSet<'_>) -> Result<(), RepositoryError>;
+}
+
+/// Async counterpart of [`AtomicCommit`].
+#[cfg(feature = "async")]
+pub trait AsyncAtomicCommit: AsyncCommit + ReadModelStore {
+    fn commit_write_set_async(
+        &self,
+        writes: WriteSet<'_>,
+    ) -> impl Future<Output = Result<(), RepositoryError>> + Send;
+}
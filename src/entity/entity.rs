@@ -5,11 +5,16 @@ use std::time::SystemTime;
 use serde::{Deserialize, Serialize};
 
 use super::EventRecord;
+use crate::repository::ExpectedVersion;
 
 #[derive(Serialize, Deserialize)]
 pub struct Entity {
     id: String,
     version: u64,
+    /// Version before the first loaded event. Non-zero when only the tail of
+    /// the stream was loaded (see [`Entity::load_history_after`]).
+    #[serde(default)]
+    base_version: u64,
     events: Vec<EventRecord>,
     #[serde(skip, default)]
     replaying: bool,
@@ -22,6 +27,10 @@ pub struct Entity {
     /// command methods to attach correlation IDs, user context, etc.
     #[serde(skip, default)]
     metadata: HashMap<String, String>,
+    /// Expectation checked by the next commit. Transient; cleared once
+    /// committed, or by the `commit_expecting` helpers after their attempt.
+    #[serde(skip, default)]
+    expected_version: Option<ExpectedVersion>,
 }
 
 impl Default for Entity {
@@ -29,12 +38,14 @@ impl Default for Entity {
         Entity {
             id: String::new(),
             version: 0,
+            base_version: 0,
             events: Vec::new(),
             replaying: false,
             snapshot_version: 0,
             committed_version: 0,
             timestamp: SystemTime::now(),
             metadata: HashMap::new(),
+            expected_version: None,
         }
     }
 }
@@ -44,12 +55,14 @@ impl fmt::Debug for Entity {
         f.debug_struct("Entity")
             .field("id", &self.id)
             .field("version", &self.version)
+            .field("base_version", &self.base_version)
             .field("events", &self.events)
             .field("replaying", &self.replaying)
             .field("snapshot_version", &self.snapshot_version)
             .field("committed_version", &self.committed_version)
             .field("timestamp", &self.timestamp)
             .field("metadata", &self.metadata)
+            .field("expected_version", &self.expected_version)
             .finish()
     }
 }
@@ -59,12 +72,14 @@ impl Clone for Entity {
         Entity {
             id: self.id.clone(),
             version: self.version,
+            base_version: self.base_version,
             events: self.events.clone(),
             replaying: self.replaying,
             snapshot_version: self.snapshot_version,
             committed_version: self.committed_version,
             timestamp: self.timestamp,
             metadata: self.metadata.clone(),
+            expected_version: self.expected_version,
         }
     }
 }
@@ -121,18 +136,73 @@ impl Entity {
         self.committed_version
     }
 
+    /// Version before the first event in [`events`](Self::events); zero unless
+    /// the entity was loaded from a version with [`load_history_after`](Self::load_history_after).
+    pub fn base_version(&self) -> u64 {
+        self.base_version
+    }
+
     pub fn events(&self) -> &[EventRecord] {
         &self.events
     }
 
     /// Returns events added since the entity was loaded (not yet persisted).
     pub fn new_events(&self) -> &[EventRecord] {
-        &self.events[self.committed_version as usize..]
+        &self.events[(self.committed_version - self.base_version) as usize..]
+    }
+
+    /// Assign consecutive global positions, starting at `first_position`, to the
+    /// uncommitted events. Called by repositories while committing, before
+    /// `mark_committed`.
+    pub fn assign_positions(&mut self, first_position: u64) {
+        let committed = (self.committed_version - self.base_version) as usize;
+        for (i, event) in self.events[committed..].iter_mut().enumerate() {
+            event.position = first_position + i as u64;
+        }
     }
 
     /// Mark all current events as committed. Called by repository after successful commit.
     pub fn mark_committed(&mut self) {
         self.committed_version = self.version;
+        self.expected_version = None;
+    }
+
+    /// Set what the next commit expects of the stored stream, instead of the
+    /// default check against `committed_version()`.
+    ///
+    /// The expectation stays set until a commit succeeds. If the commit fails,
+    /// call [`clear_expected_version`](Self::clear_expected_version) before
+    /// committing again, or use the `commit_expecting` helpers, which clear it
+    /// after every attempt.
+    pub fn expect_version(&mut self, expected: ExpectedVersion) {
+        self.expected_version = Some(expected);
+    }
+
+    /// Go back to the default check against `committed_version()`.
+    pub fn clear_expected_version(&mut self) {
+        self.expected_version = None;
+    }
+
+    pub fn expected_version(&self) -> Option<ExpectedVersion> {
+        self.expected_version
+    }
+
+    /// Move the uncommitted events so they follow `stored_version`. Called by
+    /// repositories after an [`ExpectedVersion`] check passed for a stream
+    /// that is not at `committed_version()`; the already committed events are
+    /// dropped, as with [`load_history_after`](Self::load_history_after).
+    pub fn rebase(&mut self, stored_version: u64) {
+        if stored_version == self.committed_version {
+            return;
+        }
+        let committed = (self.committed_version - self.base_version) as usize;
+        self.events.drain(..committed);
+        for (i, event) in self.events.iter_mut().enumerate() {
+            event.sequence = stored_version + 1 + i as u64;
+        }
+        self.base_version = stored_version;
+        self.committed_version = stored_version;
+        self.version = stored_version + self.events.len() as u64;
     }
 
     /// Set metadata that will be attached to every subsequent event.
@@ -177,13 +247,13 @@ impl Entity {
         }
 
         let bytes = bitcode::serialize(payload).expect("failed to serialize payload");
-        let sequence = self.events.len() as u64 + 1;
+        let sequence = self.version + 1;
         let mut record = EventRecord::new(name, bytes, sequence);
         if !self.metadata.is_empty() {
             record.metadata = self.metadata.clone();
         }
         self.events.push(record);
-        self.version = self.events.len() as u64;
+        self.version = sequence;
         self.timestamp = SystemTime::now();
     }
 
@@ -194,13 +264,13 @@ impl Entity {
         }
 
         let bytes = bitcode::serialize(payload).expect("failed to serialize payload");
-        let sequence = self.events.len() as u64 + 1;
+        let sequence = self.version + 1;
         let mut record = EventRecord::new_versioned(name, bytes, sequence, version);
         if !self.metadata.is_empty() {
             record.metadata = self.metadata.clone();
         }
         self.events.push(record);
-        self.version = self.events.len() as u64;
+        self.version = sequence;
         self.timestamp = SystemTime::now();
     }
 
@@ -210,8 +280,16 @@ impl Entity {
     }
 
     pub fn load_from_history(&mut self, history: Vec<EventRecord>) {
+        self.load_history_after(0, history);
+    }
+
+    /// Load only the tail of a stream: `history` holds the events after
+    /// `version`. The entity's version is absolute, so it can be committed as
+    /// usual; `events()` just starts later.
+    pub fn load_history_after(&mut self, version: u64, history: Vec<EventRecord>) {
+        self.base_version = version;
         self.events = history;
-        self.version = self.events.len() as u64;
+        self.version = version + self.events.len() as u64;
         self.committed_version = self.version;
     }
 
@@ -243,6 +321,7 @@ impl Entity {
         self.events.clear();
         let record = EventRecord::new("Snapshot", payload, 1);
         self.events.push(record);
+        self.base_version = 0;
         self.version = 1;
         self.timestamp = SystemTime::now();
     }
@@ -387,6 +466,46 @@ mod tests {
         assert_eq!(entity.new_events()[0].event_name, "e3");
     }
 
+    #[test]
+    fn load_history_after_keeps_absolute_versions() {
+        let mut source = Entity::new();
+        for name in ["e1", "e2", "e3"] {
+            source.digest(name, &"x");
+        }
+
+        let mut entity = Entity::new();
+        entity.load_history_after(2, source.events()[2..].to_vec());
+        assert_eq!(entity.base_version(), 2);
+        assert_eq!(entity.version(), 3);
+        assert_eq!(entity.committed_version(), 3);
+        assert!(entity.new_events().is_empty());
+
+        entity.digest("e4", &"y");
+        entity.assign_positions(9);
+        assert_eq!(entity.new_events().len(), 1);
+        assert_eq!(entity.new_events()[0].sequence, 4);
+        assert_eq!(entity.new_events()[0].position, 9);
+        assert_eq!(entity.version(), 4);
+        assert_eq!(entity.events().len(), 2);
+    }
+
+    #[test]
+    fn rebase_renumbers_new_events_onto_stored_version() {
+        let mut entity = Entity::with_id("e1");
+        entity.digest("e1", &"a");
+        entity.mark_committed();
+        entity.digest("e2", &"b");
+        entity.digest("e3", &"c");
+
+        entity.rebase(4);
+        assert_eq!(entity.base_version(), 4);
+        assert_eq!(entity.committed_version(), 4);
+        assert_eq!(entity.version(), 6);
+        let sequences: Vec<u64> = entity.new_events().iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, vec![5, 6]);
+        assert_eq!(entity.events().len(), 2);
+    }
+
     #[test]
     fn mark_committed_resets_new_events() {
         let mut entity = Entity::new();
@@ -402,6 +521,21 @@ mod tests {
         assert_eq!(entity.events().len(), 2);
     }
 
+    #[test]
+    fn assign_positions_only_touches_new_events() {
+        let mut entity = Entity::new();
+        entity.digest("e1", &"a");
+        entity.assign_positions(10);
+        entity.mark_committed();
+
+        entity.digest("e2", &"b");
+        entity.digest("e3", &"c");
+        entity.assign_positions(20);
+
+        let positions: Vec<u64> = entity.events().iter().map(|e| e.position).collect();
+        assert_eq!(positions, vec![10, 20, 21]);
+    }
+
     #[test]
     fn digest_propagates_metadata_to_event_record() {
         let mut entity = Entity::new();
@@ -19,6 +19,7 @@ impl std::error::Error for PayloadError {}
 
 fn default_event_version() -> u64 { 1 }
 fn is_version_one(v: &u64) -> bool { *v == 1 }
+fn is_zero(v: &u64) -> bool { *v == 0 }
 
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
 pub struct EventRecord {
@@ -31,6 +32,10 @@ pub struct EventRecord {
     pub timestamp: SystemTime,
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub metadata: HashMap<String, String>,
+    /// Global position in the repository's `$all` log, assigned on commit.
+    /// Strictly increasing across all streams; 0 means not yet committed.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub position: u64,
 }
 
 mod payload_serde {
@@ -62,6 +67,7 @@ impl EventRecord {
             sequence,
             timestamp: SystemTime::now(),
             metadata: HashMap::new(),
+            position: 0,
         }
     }
 
@@ -74,6 +80,7 @@ impl EventRecord {
             sequence,
             timestamp: SystemTime::now(),
             metadata: HashMap::new(),
+            position: 0,
         }
     }
 
@@ -91,6 +98,7 @@ impl EventRecord {
             sequence,
             timestamp: SystemTime::now(),
             metadata,
+            position: 0,
         }
     }
 
@@ -208,6 +216,19 @@ mod tests {
         assert!(json.contains("key"));
     }
 
+    #[test]
+    fn position_skipped_until_committed() {
+        let mut record = EventRecord::new("test_event", vec![], 1);
+        assert_eq!(record.position, 0);
+        let json = serde_json::to_string(&record).unwrap();
+        assert!(!json.contains("position"));
+
+        record.position = 7;
+        let json = serde_json::to_string(&record).unwrap();
+        let deserialized: EventRecord = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized.position, 7);
+    }
+
     #[test]
     fn deserialize_without_metadata_field() {
         // Simulates loading old events that were serialized before metadata existed
@@ -0,0 +1,42 @@
+//! Subscription checkpoints, stored as one JSON map next to the segments.
+//!
+//! The file is replaced atomically (write to a temporary file, fsync, rename),
+//! so a crash leaves either the old or the new checkpoints, never a mix.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+use crate::repository::RepositoryError;
+
+use super::segment;
+
+const CHECKPOINTS_FILE: &str = "checkpoints.json";
+const CHECKPOINTS_TMP: &str = "checkpoints.json.tmp";
+
+/// Load all checkpoints in `dir` (empty if none were ever saved).
+pub(super) fn load(dir: &Path) -> Result<HashMap<String, u64>, RepositoryError> {
+    match fs::read(dir.join(CHECKPOINTS_FILE)) {
+        Ok(bytes) => {
+            serde_json::from_slice(&bytes).map_err(|e| RepositoryError::Storage(e.to_string()))
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Durably replace the checkpoints in `dir`.
+pub(super) fn save(dir: &Path, checkpoints: &HashMap<String, u64>) -> Result<(), RepositoryError> {
+    let bytes =
+        serde_json::to_vec(checkpoints).map_err(|e| RepositoryError::Storage(e.to_string()))?;
+
+    let tmp = dir.join(CHECKPOINTS_TMP);
+    let mut file = File::create(&tmp)?;
+    file.write_all(&bytes)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp, dir.join(CHECKPOINTS_FILE))?;
+    segment::sync_dir(dir)
+}
@@ -0,0 +1,7 @@
+mod checkpoints;
+mod repository;
+mod segment;
+mod store;
+
+pub use repository::FileRepository;
+pub use store::DEFAULT_MAX_SEGMENT_BYTES;
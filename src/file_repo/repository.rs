@@ -0,0 +1,491 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::entity::{Committable, Entity, EventRecord};
+use crate::repository::{
+    Commit, Count, Exists, Find, FindOne, FindPage, GetMany, GetOne, GetOneFrom, Page, PageRequest,
+    ReadAll, RepositoryError, StoredEvent,
+};
+
+use crate::subscription::CheckpointStore;
+
+use super::checkpoints;
+use super::store::SegmentStore;
+
+/// Durable, append-only event store backed by segment files on disk.
+///
+/// Each commit is written as a single checksummed record and fsynced before
+/// `commit` returns, so a commit is either fully durable or absent. Segments
+/// roll over once they reach [`DEFAULT_MAX_SEGMENT_BYTES`](super::DEFAULT_MAX_SEGMENT_BYTES)
+/// (configurable via [`with_max_segment_bytes`](Self::with_max_segment_bytes)).
+///
+/// An in-memory index of stream versions and record locations is rebuilt when
+/// the store is opened. A torn record at the end of the last segment (from a
+/// crash mid-write) is truncated during that scan.
+///
+/// Subscription checkpoints are kept in `checkpoints.json` in the same
+/// directory.
+///
+/// Cheap to clone: clones share the same files and index. A directory can
+/// only be opened once at a time: [`open`](Self::open) holds an OS lock on a
+/// `LOCK` file in it, and fails while another process (or another `open` in
+/// this one) holds it.
+#[derive(Clone)]
+pub struct FileRepository {
+    dir: PathBuf,
+    store: Arc<RwLock<SegmentStore>>,
+    checkpoints: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl FileRepository {
+    /// Open the event store in `dir`, creating the directory if needed. Fails
+    /// with `Storage` if the directory is already open.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, RepositoryError> {
+        let dir = dir.as_ref().to_path_buf();
+        let store = SegmentStore::open(&dir)?;
+        let checkpoints = checkpoints::load(&dir)?;
+        Ok(FileRepository {
+            dir,
+            store: Arc::new(RwLock::new(store)),
+            checkpoints: Arc::new(Mutex::new(checkpoints)),
+        })
+    }
+
+    /// Set the size at which the active segment is rolled over.
+    pub fn with_max_segment_bytes(self, bytes: u64) -> Self {
+        if let Ok(mut store) = self.store.write() {
+            store.set_max_segment_bytes(bytes);
+        }
+        self
+    }
+
+    /// The directory holding the segment files.
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    pub(crate) fn store(&self) -> &RwLock<SegmentStore> {
+        self.store.as_ref()
+    }
+
+    fn load_all(&self) -> Result<Vec<Entity>, RepositoryError> {
+        let store = self
+            .store
+            .read()
+            .map_err(|_| RepositoryError::LockPoisoned("read"))?;
+
+        Ok(store
+            .read_streams()?
+            .into_iter()
+            .map(|(id, events)| to_entity(id, events))
+            .collect())
+    }
+}
+
+fn to_entity(id: impl Into<String>, events: Vec<EventRecord>) -> Entity {
+    let mut entity = Entity::with_id(id);
+    entity.load_from_history(events);
+    entity
+}
+
+impl GetOne for FileRepository {
+    fn get_one(&self, id: &str) -> Result<Option<Entity>, RepositoryError> {
+        let store = self
+            .store
+            .read()
+            .map_err(|_| RepositoryError::LockPoisoned("read"))?;
+
+        Ok(store.read_stream(id)?.map(|events| to_entity(id, events)))
+    }
+}
+
+impl GetOneFrom for FileRepository {
+    fn get_one_from(&self, id: &str, from_version: u64) -> Result<Option<Entity>, RepositoryError> {
+        let store = self
+            .store
+            .read()
+            .map_err(|_| RepositoryError::LockPoisoned("read"))?;
+
+        Ok(store.read_stream_from(id, from_version)?.map(|(from, events)| {
+            let mut entity = Entity::with_id(id);
+            entity.load_history_after(from, events);
+            entity
+        }))
+    }
+}
+
+impl GetMany for FileRepository {
+    fn get_many(&self, ids: &[&str]) -> Result<Vec<Entity>, RepositoryError> {
+        let mut entities = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(entity) = self.get_one(id)? {
+                entities.push(entity);
+            }
+        }
+        Ok(entities)
+    }
+}
+
+impl Find for FileRepository {
+    fn find<F>(&self, predicate: F) -> Result<Vec<Entity>, RepositoryError>
+    where
+        F: Fn(&Entity) -> bool,
+    {
+        Ok(self.load_all()?.into_iter().filter(|e| predicate(e)).collect())
+    }
+}
+
+impl FindPage for FileRepository {
+    fn find_page<F>(
+        &self,
+        predicate: F,
+        request: &PageRequest,
+    ) -> Result<Page<Entity>, RepositoryError>
+    where
+        F: Fn(&Entity) -> bool,
+    {
+        let store = self
+            .store
+            .read()
+            .map_err(|_| RepositoryError::LockPoisoned("read"))?;
+
+        let mut ids: Vec<&str> = store.stream_ids().filter(|id| request.includes(id)).collect();
+        ids.sort_unstable();
+        let candidates = ids.into_iter().map(|id| {
+            Ok(to_entity(id, store.read_stream(id)?.unwrap_or_default()))
+        });
+        Page::fill(request, candidates, predicate, |e| e.id().to_string())
+    }
+}
+
+impl FindOne for FileRepository {
+    fn find_one<F>(&self, predicate: F) -> Result<Option<Entity>, RepositoryError>
+    where
+        F: Fn(&Entity) -> bool,
+    {
+        Ok(self.load_all()?.into_iter().find(|e| predicate(e)))
+    }
+}
+
+impl Exists for FileRepository {
+    fn exists<F>(&self, predicate: F) -> Result<bool, RepositoryError>
+    where
+        F: Fn(&Entity) -> bool,
+    {
+        Ok(self.load_all()?.iter().any(predicate))
+    }
+}
+
+impl Count for FileRepository {
+    fn count<F>(&self, predicate: F) -> Result<usize, RepositoryError>
+    where
+        F: Fn(&Entity) -> bool,
+    {
+        Ok(self.load_all()?.iter().filter(|e| predicate(e)).count())
+    }
+}
+
+impl Commit for FileRepository {
+    fn commit<C: Committable + ?Sized>(&self, committable: &mut C) -> Result<(), RepositoryError> {
+        let mut store = self
+            .store
+            .write()
+            .map_err(|_| RepositoryError::LockPoisoned("write"))?;
+
+        store.append(committable.entities_mut())
+    }
+}
+
+impl ReadAll for FileRepository {
+    fn read_all(&self, from_position: u64, limit: usize) -> Result<Vec<StoredEvent>, RepositoryError> {
+        let store = self
+            .store
+            .read()
+            .map_err(|_| RepositoryError::LockPoisoned("read"))?;
+
+        store.read_all(from_position, limit)
+    }
+
+    fn last_position(&self) -> Result<u64, RepositoryError> {
+        let store = self
+            .store
+            .read()
+            .map_err(|_| RepositoryError::LockPoisoned("read"))?;
+
+        Ok(store.last_position())
+    }
+}
+
+impl CheckpointStore for FileRepository {
+    fn load_checkpoint(&self, name: &str) -> Result<Option<u64>, RepositoryError> {
+        let checkpoints = self
+            .checkpoints
+            .lock()
+            .map_err(|_| RepositoryError::LockPoisoned("checkpoint read"))?;
+        Ok(checkpoints.get(name).copied())
+    }
+
+    fn save_checkpoint(&self, name: &str, position: u64) -> Result<(), RepositoryError> {
+        let mut checkpoints = self
+            .checkpoints
+            .lock()
+            .map_err(|_| RepositoryError::LockPoisoned("checkpoint write"))?;
+
+        let mut updated = checkpoints.clone();
+        updated.insert(name.to_string(), position);
+        checkpoints::save(&self.dir, &updated)?;
+        *checkpoints = updated;
+        Ok(())
+    }
+
+    fn delete_checkpoint(&self, name: &str) -> Result<bool, RepositoryError> {
+        let mut checkpoints = self
+            .checkpoints
+            .lock()
+            .map_err(|_| RepositoryError::LockPoisoned("checkpoint write"))?;
+        if !checkpoints.contains_key(name) {
+            return Ok(false);
+        }
+
+        let mut updated = checkpoints.clone();
+        updated.remove(name);
+        checkpoints::save(&self.dir, &updated)?;
+        *checkpoints = updated;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::Get;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "sourced_rust_file_repo_{}_{}_{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn segment_files(dir: &Path) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn open_creates_directory() {
+        let dir = temp_dir("open");
+        let repo = FileRepository::open(&dir).unwrap();
+        assert!(dir.is_dir());
+        assert_eq!(repo.path(), dir.as_path());
+        assert_eq!(segment_files(&dir).len(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn events_survive_reopen() {
+        let dir = temp_dir("reopen");
+        {
+            let repo = FileRepository::open(&dir).unwrap();
+            let mut entity = Entity::with_id("e1");
+            entity.digest("Created", &"v1");
+            repo.commit(&mut entity).unwrap();
+            entity.digest("Updated", &"v2");
+            repo.commit(&mut entity).unwrap();
+        }
+
+        let repo = FileRepository::open(&dir).unwrap();
+        let loaded = repo.get("e1").unwrap().unwrap();
+        assert_eq!(loaded.version(), 2);
+        assert_eq!(loaded.events()[0].event_name, "Created");
+        assert_eq!(loaded.events()[1].event_name, "Updated");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn concurrent_write_is_rejected() {
+        let dir = temp_dir("conflict");
+        let repo = FileRepository::open(&dir).unwrap();
+
+        let mut entity = Entity::with_id("e1");
+        entity.digest("Created", &"v1");
+        repo.commit(&mut entity).unwrap();
+
+        let mut a = repo.get_one("e1").unwrap().unwrap();
+        let mut b = repo.get_one("e1").unwrap().unwrap();
+        a.digest("A", &"a");
+        b.digest("B", &"b");
+        repo.commit(&mut a).unwrap();
+
+        let err = repo.commit(&mut b).unwrap_err();
+        assert_eq!(
+            err,
+            RepositoryError::ConcurrentWrite {
+                id: "e1".to_string(),
+                expected: 1,
+                actual: 2,
+            }
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn torn_tail_is_truncated_on_open() {
+        let dir = temp_dir("torn");
+        {
+            let repo = FileRepository::open(&dir).unwrap();
+            let mut entity = Entity::with_id("e1");
+            entity.digest("Created", &"v1");
+            repo.commit(&mut entity).unwrap();
+        }
+
+        let segment = segment_files(&dir).pop().unwrap();
+        let valid_len = fs::metadata(&segment).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+        file.write_all(&[42, 0, 0, 0, 1, 2, 3]).unwrap();
+        drop(file);
+
+        let repo = FileRepository::open(&dir).unwrap();
+        assert_eq!(fs::metadata(&segment).unwrap().len(), valid_len);
+
+        let mut entity = repo.get_one("e1").unwrap().unwrap();
+        assert_eq!(entity.version(), 1);
+        entity.digest("Updated", &"v2");
+        repo.commit(&mut entity).unwrap();
+        drop(repo);
+
+        let reopened = FileRepository::open(&dir).unwrap();
+        assert_eq!(reopened.get_one("e1").unwrap().unwrap().version(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn segments_roll_over() {
+        let dir = temp_dir("roll");
+        {
+            let repo = FileRepository::open(&dir).unwrap().with_max_segment_bytes(256);
+            for i in 0..10 {
+                let mut entity = Entity::with_id(format!("e{}", i));
+                entity.digest("Created", &i);
+                repo.commit(&mut entity).unwrap();
+            }
+        }
+        assert!(segment_files(&dir).len() > 1);
+
+        let repo = FileRepository::open(&dir).unwrap();
+        assert_eq!(repo.count(|_| true).unwrap(), 10);
+        assert_eq!(repo.get_one("e7").unwrap().unwrap().version(), 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn positions_continue_after_reopen() {
+        let dir = temp_dir("positions");
+        {
+            let repo = FileRepository::open(&dir).unwrap().with_max_segment_bytes(256);
+            for i in 0..6 {
+                let mut entity = Entity::with_id(format!("e{}", i % 2));
+                if i >= 2 {
+                    entity = repo.get_one(entity.id()).unwrap().unwrap();
+                }
+                entity.digest("Touched", &i);
+                repo.commit(&mut entity).unwrap();
+            }
+        }
+        assert!(segment_files(&dir).len() > 1);
+
+        let repo = FileRepository::open(&dir).unwrap();
+        assert_eq!(repo.last_position().unwrap(), 6);
+
+        let tail = repo.read_all(3, 10).unwrap();
+        let positions: Vec<u64> = tail.iter().map(|e| e.position()).collect();
+        assert_eq!(positions, vec![4, 5, 6]);
+        assert_eq!(tail[0].stream_id, "e1");
+
+        let mut entity = repo.get_one("e0").unwrap().unwrap();
+        entity.digest("Touched", &6);
+        repo.commit(&mut entity).unwrap();
+        assert_eq!(entity.events().last().unwrap().position, 7);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn get_one_from_skips_older_records() {
+        let dir = temp_dir("ranged");
+        let repo = FileRepository::open(&dir).unwrap().with_max_segment_bytes(256);
+        let mut entity = Entity::with_id("e1");
+        for i in 0..5 {
+            entity.digest("Touched", &i);
+            repo.commit(&mut entity).unwrap();
+        }
+        assert!(segment_files(&dir).len() > 1);
+
+        let tail = repo.get_one_from("e1", 3).unwrap().unwrap();
+        let sequences: Vec<u64> = tail.events().iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, vec![4, 5]);
+        assert_eq!(tail.version(), 5);
+        assert_eq!(tail.base_version(), 3);
+        assert_eq!(repo.get_one_from("e1", 0).unwrap().unwrap().events().len(), 5);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn exact_expected_version_appends_to_indexed_stream() {
+        use crate::repository::ExpectedVersion;
+
+        let dir = temp_dir("expected");
+        let repo = FileRepository::open(&dir).unwrap();
+        let mut entity = Entity::with_id("e1");
+        entity.digest("Created", &1);
+        repo.commit(&mut entity).unwrap();
+
+        let mut command = Entity::with_id("e1");
+        command.digest("Touched", &2);
+        assert!(repo.commit_expecting(&mut command, ExpectedVersion::NoStream).is_err());
+        repo.commit_expecting(&mut command, ExpectedVersion::Exact(1)).unwrap();
+
+        let tail = repo.get_one_from("e1", 1).unwrap().unwrap();
+        assert_eq!(tail.events()[0].sequence, 2);
+        assert_eq!(tail.events()[0].event_name, "Touched");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_sealed_segment_is_an_error() {
+        let dir = temp_dir("corrupt");
+        {
+            let repo = FileRepository::open(&dir).unwrap().with_max_segment_bytes(64);
+            for i in 0..3 {
+                let mut entity = Entity::with_id(format!("e{}", i));
+                entity.digest("Created", &i);
+                repo.commit(&mut entity).unwrap();
+            }
+        }
+
+        let first = segment_files(&dir).remove(0);
+        let mut bytes = fs::read(&first).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        fs::write(&first, bytes).unwrap();
+
+        assert!(matches!(
+            FileRepository::open(&dir),
+            Err(RepositoryError::Storage(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
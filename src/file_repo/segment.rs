@@ -0,0 +1,206 @@
+//! On-disk segment format.
+//!
+//! A segment is a flat sequence of records, one per commit:
+//!
+//! ```text
+//! [len: u32 LE][crc32: u32 LE][payload: len bytes]
+//! ```
+//!
+//! The payload is a JSON-encoded [`Batch`]. A record whose header or payload is
+//! incomplete, or whose checksum does not match, marks the end of the valid data
+//! in that segment.
+
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::entity::EventRecord;
+use crate::repository::RepositoryError;
+
+pub(super) const HEADER_LEN: usize = 8;
+
+const SEGMENT_PREFIX: &str = "segment-";
+const SEGMENT_SUFFIX: &str = ".log";
+
+/// Events appended to a single stream by one commit.
+#[derive(Debug, Serialize, Deserialize)]
+pub(super) struct StreamAppend {
+    pub id: String,
+    pub events: Vec<EventRecord>,
+}
+
+/// Everything written by one commit. Stored as a single record so a commit is
+/// either fully present on disk or not at all.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(super) struct Batch {
+    pub streams: Vec<StreamAppend>,
+}
+
+impl Batch {
+    pub fn encode(&self) -> Result<Vec<u8>, RepositoryError> {
+        serde_json::to_vec(self).map_err(|e| RepositoryError::Storage(e.to_string()))
+    }
+
+    pub fn decode(payload: &[u8]) -> Result<Self, RepositoryError> {
+        serde_json::from_slice(payload).map_err(|e| RepositoryError::Storage(e.to_string()))
+    }
+
+    /// Position of the last event in the batch, if it holds any events.
+    pub fn last_position(&self) -> Option<u64> {
+        self.streams
+            .iter()
+            .flat_map(|s| s.events.iter())
+            .map(|e| e.position)
+            .max()
+    }
+}
+
+pub(super) fn segment_path(dir: &Path, segment: u32) -> PathBuf {
+    dir.join(format!("{}{:08}{}", SEGMENT_PREFIX, segment, SEGMENT_SUFFIX))
+}
+
+/// List the segment numbers present in `dir`, in ascending order.
+pub(super) fn list_segments(dir: &Path) -> Result<Vec<u32>, RepositoryError> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let Some(name) = name.to_str() else { continue };
+        let number = name
+            .strip_prefix(SEGMENT_PREFIX)
+            .and_then(|rest| rest.strip_suffix(SEGMENT_SUFFIX))
+            .and_then(|n| n.parse::<u32>().ok());
+        if let Some(number) = number {
+            segments.push(number);
+        }
+    }
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+/// Frame a payload as a record (header + payload).
+pub(super) fn encode_record(payload: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(HEADER_LEN + payload.len());
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(&crc32(payload).to_le_bytes());
+    record.extend_from_slice(payload);
+    record
+}
+
+/// Decode the record at the start of `buf`, returning its payload.
+///
+/// Returns `None` for a torn or corrupt record. Empty payloads are never
+/// written, so a zero length is treated as corruption too (zero-filled tails
+/// are a common crash artifact).
+fn decode_record(buf: &[u8]) -> Option<&[u8]> {
+    if buf.len() < HEADER_LEN {
+        return None;
+    }
+    let len = u32::from_le_bytes(buf[0..4].try_into().unwrap()) as usize;
+    let crc = u32::from_le_bytes(buf[4..8].try_into().unwrap());
+    if len == 0 {
+        return None;
+    }
+    let payload = buf.get(HEADER_LEN..HEADER_LEN + len)?;
+    (crc32(payload) == crc).then_some(payload)
+}
+
+/// Walk the records of a segment, calling `f` with each record's offset and payload.
+///
+/// Returns the length of the valid prefix. Anything after it is a torn or
+/// corrupt tail.
+pub(super) fn scan<F>(path: &Path, mut f: F) -> Result<u64, RepositoryError>
+where
+    F: FnMut(u64, &[u8]) -> Result<(), RepositoryError>,
+{
+    let bytes = fs::read(path)?;
+    let mut offset = 0;
+    while let Some(payload) = decode_record(&bytes[offset..]) {
+        f(offset as u64, payload)?;
+        offset += HEADER_LEN + payload.len();
+    }
+    Ok(offset as u64)
+}
+
+/// Read the payload of the record at `offset`.
+pub(super) fn read_record(path: &Path, offset: u64) -> Result<Vec<u8>, RepositoryError> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut header = [0u8; HEADER_LEN];
+    file.read_exact(&mut header)?;
+    let len = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+    let crc = u32::from_le_bytes(header[4..8].try_into().unwrap());
+
+    let mut payload = vec![0u8; len];
+    file.read_exact(&mut payload)?;
+    if crc32(&payload) != crc {
+        return Err(RepositoryError::Storage(format!(
+            "checksum mismatch in {} at offset {}",
+            path.display(),
+            offset
+        )));
+    }
+    Ok(payload)
+}
+
+/// Flush directory metadata so newly created segment files survive a crash.
+pub(super) fn sync_dir(dir: &Path) -> Result<(), RepositoryError> {
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// CRC-32 (IEEE 802.3), bitwise.
+pub(super) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn record_round_trip() {
+        let record = encode_record(b"hello");
+        assert_eq!(record.len(), HEADER_LEN + 5);
+        assert_eq!(decode_record(&record), Some(&b"hello"[..]));
+    }
+
+    #[test]
+    fn torn_record_is_rejected() {
+        let record = encode_record(b"hello");
+        assert_eq!(decode_record(&record[..HEADER_LEN + 2]), None);
+        assert_eq!(decode_record(&record[..3]), None);
+    }
+
+    #[test]
+    fn corrupt_record_is_rejected() {
+        let mut record = encode_record(b"hello");
+        let last = record.len() - 1;
+        record[last] ^= 0xff;
+        assert_eq!(decode_record(&record), None);
+    }
+
+    #[test]
+    fn zero_filled_tail_is_rejected() {
+        assert_eq!(decode_record(&[0u8; 32]), None);
+    }
+}
@@ -0,0 +1,336 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::entity::{Entity, EventRecord};
+use crate::repository::{check_expected_version, RepositoryError, StoredEvent};
+
+use super::segment::{self, Batch, StreamAppend};
+
+/// The file in a store directory holding the OS lock of the process that has
+/// the store open.
+const LOCK_FILE: &str = "LOCK";
+
+/// Default size at which the active segment is rolled over (64 MiB).
+pub const DEFAULT_MAX_SEGMENT_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Position of a commit record on disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Location {
+    segment: u32,
+    offset: u64,
+}
+
+/// Index entry for a single stream.
+#[derive(Debug, Default)]
+struct StreamIndex {
+    version: u64,
+    /// `(stream version after record, location)` for every record holding
+    /// events of this stream. Used to seek ranged reads.
+    records: Vec<(u64, Location)>,
+}
+
+impl StreamIndex {
+    fn append(&mut self, events: u64, location: Location) {
+        self.version += events;
+        match self.records.last_mut() {
+            Some((version, last)) if *last == location => *version = self.version,
+            _ => self.records.push((self.version, location)),
+        }
+    }
+}
+
+/// Append-only segment files plus an in-memory index of stream versions and
+/// record locations.
+///
+/// The index is rebuilt from the segments on open, so the segments are the
+/// only source of truth on disk.
+pub(crate) struct SegmentStore {
+    dir: PathBuf,
+    index: HashMap<String, StreamIndex>,
+    /// `(last position in record, location)` for every record holding events,
+    /// in commit order. Used to seek `read_all` to its starting record.
+    positions: Vec<(u64, Location)>,
+    last_position: u64,
+    segments: Vec<u32>,
+    active: File,
+    active_segment: u32,
+    active_len: u64,
+    max_segment_bytes: u64,
+    /// Holds an exclusive OS lock on the directory's `LOCK` file while open.
+    _lock: File,
+}
+
+/// Take the exclusive lock on `dir`'s `LOCK` file, released when the
+/// returned file is closed.
+fn lock_dir(dir: &Path) -> Result<File, RepositoryError> {
+    let path = dir.join(LOCK_FILE);
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => Err(RepositoryError::Storage(format!(
+            "{} is already open (locked by {})",
+            dir.display(),
+            path.display()
+        ))),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+impl SegmentStore {
+    /// Open (or create) a store in `dir`, rebuilding the index and truncating a
+    /// torn record at the end of the last segment.
+    ///
+    /// Fails if another `SegmentStore` (in this or another process) has the
+    /// directory open.
+    pub fn open(dir: &Path) -> Result<Self, RepositoryError> {
+        fs::create_dir_all(dir)?;
+        let lock = lock_dir(dir)?;
+
+        let mut segments = segment::list_segments(dir)?;
+        if segments.is_empty() {
+            File::create(segment::segment_path(dir, 0))?.sync_all()?;
+            segment::sync_dir(dir)?;
+            segments.push(0);
+        }
+
+        let mut index: HashMap<String, StreamIndex> = HashMap::new();
+        let mut positions = Vec::new();
+        let mut last_position = 0;
+        let mut valid_len = 0;
+        let last = *segments.last().unwrap();
+
+        for &number in &segments {
+            let path = segment::segment_path(dir, number);
+            valid_len = segment::scan(&path, |offset, payload| {
+                let location = Location { segment: number, offset };
+                let batch = Batch::decode(payload)?;
+                if let Some(last) = batch.last_position() {
+                    last_position = last;
+                    positions.push((last, location));
+                }
+                for append in batch.streams {
+                    let stream: &mut StreamIndex = index.entry(append.id).or_default();
+                    stream.append(append.events.len() as u64, location);
+                }
+                Ok(())
+            })?;
+
+            let file_len = fs::metadata(&path)?.len();
+            if valid_len < file_len && number != last {
+                return Err(RepositoryError::Storage(format!(
+                    "corrupt record in {} at offset {}",
+                    path.display(),
+                    valid_len
+                )));
+            }
+        }
+
+        let active = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(segment::segment_path(dir, last))?;
+        if active.metadata()?.len() > valid_len {
+            // Torn write from a crash: drop the partial record.
+            active.set_len(valid_len)?;
+            active.sync_all()?;
+        }
+
+        Ok(SegmentStore {
+            dir: dir.to_path_buf(),
+            index,
+            positions,
+            last_position,
+            segments,
+            active,
+            active_segment: last,
+            active_len: valid_len,
+            max_segment_bytes: DEFAULT_MAX_SEGMENT_BYTES,
+            _lock: lock,
+        })
+    }
+
+    pub fn set_max_segment_bytes(&mut self, bytes: u64) {
+        self.max_segment_bytes = bytes;
+    }
+
+    /// Load every event of one stream, or `None` if the stream does not exist.
+    pub fn read_stream(&self, id: &str) -> Result<Option<Vec<EventRecord>>, RepositoryError> {
+        Ok(self.read_stream_from(id, 0)?.map(|(_, events)| events))
+    }
+
+    /// Load the events of one stream after `from_version` (clamped to the
+    /// stream's version), skipping records that hold only older events.
+    /// Returns the clamped version with the events.
+    pub fn read_stream_from(
+        &self,
+        id: &str,
+        from_version: u64,
+    ) -> Result<Option<(u64, Vec<EventRecord>)>, RepositoryError> {
+        let Some(stream) = self.index.get(id) else {
+            return Ok(None);
+        };
+        let from = from_version.min(stream.version);
+        let start = stream.records.partition_point(|(version, _)| *version <= from);
+
+        let mut events = Vec::with_capacity((stream.version - from) as usize);
+        for (_, location) in &stream.records[start..] {
+            let path = segment::segment_path(&self.dir, location.segment);
+            let payload = segment::read_record(&path, location.offset)?;
+            for append in Batch::decode(&payload)?.streams {
+                if append.id == id {
+                    events.extend(append.events.into_iter().filter(|e| e.sequence > from));
+                }
+            }
+        }
+        Ok(Some((from, events)))
+    }
+
+    /// IDs of every stream, in no particular order.
+    pub fn stream_ids(&self) -> impl Iterator<Item = &str> {
+        self.index.keys().map(String::as_str)
+    }
+
+    /// Load every stream in a single sequential pass over the segments.
+    pub fn read_streams(&self) -> Result<HashMap<String, Vec<EventRecord>>, RepositoryError> {
+        let mut streams: HashMap<String, Vec<EventRecord>> = HashMap::new();
+        for &number in &self.segments {
+            let path = segment::segment_path(&self.dir, number);
+            segment::scan(&path, |_, payload| {
+                for append in Batch::decode(payload)?.streams {
+                    streams.entry(append.id).or_default().extend(append.events);
+                }
+                Ok(())
+            })?;
+        }
+        Ok(streams)
+    }
+
+    pub fn last_position(&self) -> u64 {
+        self.last_position
+    }
+
+    /// Read up to `limit` events with a position greater than `from_position`.
+    pub fn read_all(&self, from_position: u64, limit: usize) -> Result<Vec<StoredEvent>, RepositoryError> {
+        let start = self.positions.partition_point(|(last, _)| *last <= from_position);
+
+        let mut events = Vec::new();
+        for (_, location) in &self.positions[start..] {
+            if events.len() >= limit {
+                break;
+            }
+            let path = segment::segment_path(&self.dir, location.segment);
+            let payload = segment::read_record(&path, location.offset)?;
+            for append in Batch::decode(&payload)?.streams {
+                for event in append.events {
+                    if event.position > from_position && events.len() < limit {
+                        events.push(StoredEvent {
+                            stream_id: append.id.clone(),
+                            event,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(events)
+    }
+
+    /// Validate and durably append the new events of `entities` as one record.
+    ///
+    /// Either every entity is written and marked committed, or none are.
+    pub fn append(&mut self, mut entities: Vec<&mut Entity>) -> Result<(), RepositoryError> {
+        // Phase 1: Validate (optimistic concurrency / expected version check)
+        let mut stored = Vec::with_capacity(entities.len());
+        for entity in &entities {
+            let actual = self.index.get(entity.id()).map(|s| s.version);
+            check_expected_version(entity, actual)?;
+            stored.push(actual.unwrap_or(0));
+        }
+
+        // Phase 2: Assign global positions, write one record and fsync. New
+        // streams are written even without events so they exist after a restart.
+        let mut next_position = self.last_position + 1;
+        for (entity, stored) in entities.iter_mut().zip(stored) {
+            entity.rebase(stored);
+            entity.assign_positions(next_position);
+            next_position += entity.new_events().len() as u64;
+        }
+
+        let mut batch = Batch::default();
+        for entity in &entities {
+            if !entity.new_events().is_empty() || !self.index.contains_key(entity.id()) {
+                batch.streams.push(StreamAppend {
+                    id: entity.id().to_string(),
+                    events: entity.new_events().to_vec(),
+                });
+            }
+        }
+
+        if !batch.streams.is_empty() {
+            let location = self.write_record(&batch.encode()?)?;
+            if let Some(last) = batch.last_position() {
+                self.last_position = last;
+                self.positions.push((last, location));
+            }
+            for append in &batch.streams {
+                let stream = self.index.entry(append.id.clone()).or_default();
+                stream.append(append.events.len() as u64, location);
+            }
+        }
+
+        // Phase 3: Mark committed
+        for entity in entities {
+            entity.mark_committed();
+        }
+
+        Ok(())
+    }
+
+    fn write_record(&mut self, payload: &[u8]) -> Result<Location, RepositoryError> {
+        let record = segment::encode_record(payload);
+        if self.active_len > 0 && self.active_len + record.len() as u64 > self.max_segment_bytes {
+            self.roll_segment()?;
+        }
+
+        let offset = self.active_len;
+        let written = self
+            .active
+            .seek(SeekFrom::Start(offset))
+            .and_then(|_| self.active.write_all(&record))
+            .and_then(|_| self.active.sync_data());
+        if let Err(err) = written {
+            // Best effort: drop the partial record now. If this fails too, the
+            // next write overwrites it and recovery truncates anything left over.
+            let _ = self.active.set_len(offset);
+            return Err(err.into());
+        }
+
+        self.active_len += record.len() as u64;
+        Ok(Location {
+            segment: self.active_segment,
+            offset,
+        })
+    }
+
+    fn roll_segment(&mut self) -> Result<(), RepositoryError> {
+        let next = self.active_segment + 1;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(segment::segment_path(&self.dir, next))?;
+        file.sync_all()?;
+        segment::sync_dir(&self.dir)?;
+
+        self.segments.push(next);
+        self.active = file;
+        self.active_segment = next;
+        self.active_len = 0;
+        Ok(())
+    }
+}
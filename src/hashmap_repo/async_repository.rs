@@ -0,0 +1,128 @@
+//! Async trait implementations for `HashMapRepository`.
+//!
+//! Storage is in-memory, so every operation completes immediately and is
+//! returned as a ready future.
+
+use std::future::{ready, Future};
+
+use crate::commit_builder::{AsyncAtomicCommit, AtomicCommit, WriteSet};
+use crate::entity::{Committable, Entity};
+use crate::repository::{
+    AsyncCommit, AsyncCount, AsyncExists, AsyncFind, AsyncFindOne, AsyncGetMany, AsyncGetOne,
+    AsyncGetOneFrom, Commit, Count, Exists, Find, FindOne, GetMany, GetOne, GetOneFrom,
+    RepositoryError,
+};
+use crate::snapshot::{AsyncSnapshotStore, SnapshotRecord, SnapshotStore};
+
+use super::HashMapRepository;
+
+impl AsyncGetOne for HashMapRepository {
+    fn get_one_async(
+        &self,
+        id: &str,
+    ) -> impl Future<Output = Result<Option<Entity>, RepositoryError>> + Send {
+        ready(self.get_one(id))
+    }
+}
+
+impl AsyncGetOneFrom for HashMapRepository {
+    fn get_one_from_async(
+        &self,
+        id: &str,
+        from_version: u64,
+    ) -> impl Future<Output = Result<Option<Entity>, RepositoryError>> + Send {
+        ready(self.get_one_from(id, from_version))
+    }
+}
+
+impl AsyncGetMany for HashMapRepository {
+    fn get_many_async(
+        &self,
+        ids: &[&str],
+    ) -> impl Future<Output = Result<Vec<Entity>, RepositoryError>> + Send {
+        ready(self.get_many(ids))
+    }
+}
+
+impl AsyncFind for HashMapRepository {
+    fn find_async<F>(
+        &self,
+        predicate: F,
+    ) -> impl Future<Output = Result<Vec<Entity>, RepositoryError>> + Send
+    where
+        F: Fn(&Entity) -> bool + Send,
+    {
+        ready(self.find(predicate))
+    }
+}
+
+impl AsyncFindOne for HashMapRepository {
+    fn find_one_async<F>(
+        &self,
+        predicate: F,
+    ) -> impl Future<Output = Result<Option<Entity>, RepositoryError>> + Send
+    where
+        F: Fn(&Entity) -> bool + Send,
+    {
+        ready(self.find_one(predicate))
+    }
+}
+
+impl AsyncExists for HashMapRepository {
+    fn exists_async<F>(&self, predicate: F) -> impl Future<Output = Result<bool, RepositoryError>> + Send
+    where
+        F: Fn(&Entity) -> bool + Send,
+    {
+        ready(self.exists(predicate))
+    }
+}
+
+impl AsyncCount for HashMapRepository {
+    fn count_async<F>(&self, predicate: F) -> impl Future<Output = Result<usize, RepositoryError>> + Send
+    where
+        F: Fn(&Entity) -> bool + Send,
+    {
+        ready(self.count(predicate))
+    }
+}
+
+impl AsyncCommit for HashMapRepository {
+    fn commit_async<C: Committable + ?Sized + Send>(
+        &self,
+        committable: &mut C,
+    ) -> impl Future<Output = Result<(), RepositoryError>> + Send {
+        ready(self.commit(committable))
+    }
+}
+
+impl AsyncAtomicCommit for HashMapRepository {
+    fn commit_write_set_async(
+        &self,
+        writes: WriteSet<'_>,
+    ) -> impl Future<Output = Result<(), RepositoryError>> + Send {
+        ready(self.commit_write_set(writes))
+    }
+}
+
+impl AsyncSnapshotStore for HashMapRepository {
+    fn get_snapshot_async(
+        &self,
+        id: &str,
+    ) -> impl Future<Output = Result<Option<SnapshotRecord>, RepositoryError>> + Send {
+        ready(self.get_snapshot(id))
+    }
+
+    fn save_snapshot_async(
+        &self,
+        record: SnapshotRecord,
+    ) -> impl Future<Output = Result<(), RepositoryError>> + Send {
+        ready(self.save_snapshot(record))
+    }
+
+    fn delete_snapshot_async(
+        &self,
+        id: &str,
+    ) -> impl Future<Output = Result<bool, RepositoryError>> + Send {
+        ready(self.delete_snapshot(id))
+    }
+}
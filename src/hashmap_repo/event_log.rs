@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+
+use crate::entity::{Entity, EventRecord};
+use crate::repository::{Indexes, SecondaryIndex, StoredEvent};
+
+/// Streams keyed by ID, plus the global log that orders every event across them.
+#[derive(Default)]
+pub(crate) struct EventLog {
+    streams: HashMap<String, Vec<EventRecord>>,
+    /// `(stream_id, index within stream)` for each event in commit order.
+    /// The event at `log[i]` has position `i + 1`.
+    log: Vec<(String, usize)>,
+    indexes: Indexes,
+}
+
+impl EventLog {
+    pub fn streams(&self) -> &HashMap<String, Vec<EventRecord>> {
+        &self.streams
+    }
+
+    pub fn stream(&self, id: &str) -> Option<&Vec<EventRecord>> {
+        self.streams.get(id)
+    }
+
+    /// Version of a stream, or `None` if it does not exist.
+    pub fn stream_version(&self, id: &str) -> Option<u64> {
+        self.streams.get(id).map(|v| v.len() as u64)
+    }
+
+    pub fn indexes(&self) -> &Indexes {
+        &self.indexes
+    }
+
+    /// Declare a secondary index, filling it from the existing streams.
+    pub fn add_index(&mut self, index: SecondaryIndex) {
+        let streams = self.streams.iter().map(|(id, events)| (id.as_str(), &events[..]));
+        self.indexes.add(index, streams);
+    }
+
+    pub fn last_position(&self) -> u64 {
+        self.log.len() as u64
+    }
+
+    /// Assign positions to the entity's new events and append them to its
+    /// stream, updating the secondary indexes. Creates the stream if needed.
+    /// Does not mark the entity committed.
+    pub fn append(&mut self, entity: &mut Entity) {
+        entity.assign_positions(self.last_position() + 1);
+
+        let stored = self.streams.entry(entity.id().to_string()).or_default();
+        for event in entity.new_events() {
+            self.log.push((entity.id().to_string(), stored.len()));
+            stored.push(event.clone());
+        }
+        self.indexes.record(entity.id(), entity.new_events());
+    }
+
+    pub fn read_all(&self, from_position: u64, limit: usize) -> Vec<StoredEvent> {
+        let start = (from_position as usize).min(self.log.len());
+        self.log[start..]
+            .iter()
+            .take(limit)
+            .map(|(id, index)| StoredEvent {
+                stream_id: id.clone(),
+                event: self.streams[id][*index].clone(),
+            })
+            .collect()
+    }
+}
@@ -1,3 +1,6 @@
+#[cfg(feature = "async")]
+mod async_repository;
+mod event_log;
 mod repository;
 
 pub use repository::HashMapRepository;
@@ -1,12 +1,20 @@
-use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
-use crate::entity::{Committable, Entity, EventRecord};
-use crate::read_model::{InMemoryReadModelStore, ReadModel, ReadModelError, ReadModelStore, Versioned};
+use crate::commit_builder::{
+    check_model_versions, AtomicCommit, ModelChange, WriteSet,
+};
+use crate::entity::{Committable, Entity};
+use crate::read_model::{
+    InMemoryReadModelStore, Query, ReadModel, ReadModelError, ReadModelStore, Versioned,
+};
 use crate::repository::{
-    Commit, Count, Exists, Find, FindOne, GetMany, GetOne, RepositoryError,
+    check_expected_version, Commit, Count, Exists, Find, FindByIndex, FindOne, FindPage, GetMany,
+    GetOne, GetOneFrom, Page, PageRequest, ReadAll, RepositoryError, SecondaryIndex, StoredEvent,
 };
 use crate::snapshot::{InMemorySnapshotStore, SnapshotRecord, SnapshotStore};
+use crate::subscription::{CheckpointStore, InMemoryCheckpointStore};
+
+use super::event_log::EventLog;
 
 /// In-memory repository implementation using HashMap.
 ///
@@ -15,9 +23,10 @@ use crate::snapshot::{InMemorySnapshotStore, SnapshotRecord, SnapshotStore};
 /// Also includes an embedded `InMemoryReadModelStore` for read model storage.
 #[derive(Clone)]
 pub struct HashMapRepository {
-    event_store: Arc<RwLock<HashMap<String, Vec<EventRecord>>>>,
+    event_store: Arc<RwLock<EventLog>>,
     model_store: InMemoryReadModelStore,
     snapshot_store: InMemorySnapshotStore,
+    checkpoint_store: InMemoryCheckpointStore,
 }
 
 impl Default for HashMapRepository {
@@ -30,13 +39,24 @@ impl HashMapRepository {
     /// Create a new empty repository.
     pub fn new() -> Self {
         HashMapRepository {
-            event_store: Arc::new(RwLock::new(HashMap::new())),
+            event_store: Arc::new(RwLock::new(EventLog::default())),
             model_store: InMemoryReadModelStore::new(),
             snapshot_store: InMemorySnapshotStore::new(),
+            checkpoint_store: InMemoryCheckpointStore::new(),
         }
     }
 
-    pub(crate) fn event_store(&self) -> &RwLock<HashMap<String, Vec<EventRecord>>> {
+    /// Declare a [`SecondaryIndex`], maintained on every commit and queried
+    /// through [`FindByIndex`]. Existing streams are indexed immediately.
+    pub fn with_index(self, index: SecondaryIndex) -> Result<Self, RepositoryError> {
+        self.event_store
+            .write()
+            .map_err(|_| RepositoryError::LockPoisoned("write"))?
+            .add_index(index);
+        Ok(self)
+    }
+
+    pub(crate) fn event_store(&self) -> &RwLock<EventLog> {
         self.event_store.as_ref()
     }
 
@@ -49,6 +69,11 @@ impl HashMapRepository {
     pub fn snapshot_store(&self) -> &InMemorySnapshotStore {
         &self.snapshot_store
     }
+
+    /// Access the embedded subscription checkpoint store directly.
+    pub fn checkpoint_store(&self) -> &InMemoryCheckpointStore {
+        &self.checkpoint_store
+    }
 }
 
 impl GetOne for HashMapRepository {
@@ -58,7 +83,7 @@ impl GetOne for HashMapRepository {
             .read()
             .map_err(|_| RepositoryError::LockPoisoned("read"))?;
 
-        if let Some(events) = storage.get(id) {
+        if let Some(events) = storage.stream(id) {
             let mut entity = Entity::new();
             entity.set_id(id);
             entity.load_from_history(events.clone());
@@ -69,6 +94,25 @@ impl GetOne for HashMapRepository {
     }
 }
 
+impl GetOneFrom for HashMapRepository {
+    fn get_one_from(&self, id: &str, from_version: u64) -> Result<Option<Entity>, RepositoryError> {
+        let storage = self
+            .event_store
+            .read()
+            .map_err(|_| RepositoryError::LockPoisoned("read"))?;
+
+        if let Some(events) = storage.stream(id) {
+            let from = from_version.min(events.len() as u64);
+            let mut entity = Entity::new();
+            entity.set_id(id);
+            entity.load_history_after(from, events[from as usize..].to_vec());
+            Ok(Some(entity))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 impl GetMany for HashMapRepository {
     fn get_many(&self, ids: &[&str]) -> Result<Vec<Entity>, RepositoryError> {
         let mut entities = Vec::with_capacity(ids.len());
@@ -92,7 +136,7 @@ impl Find for HashMapRepository {
             .map_err(|_| RepositoryError::LockPoisoned("read"))?;
 
         let mut results = Vec::new();
-        for (id, events) in storage.iter() {
+        for (id, events) in storage.streams().iter() {
             let mut entity = Entity::new();
             entity.set_id(id);
             entity.load_from_history(events.clone());
@@ -104,6 +148,35 @@ impl Find for HashMapRepository {
     }
 }
 
+impl FindPage for HashMapRepository {
+    fn find_page<F>(
+        &self,
+        predicate: F,
+        request: &PageRequest,
+    ) -> Result<Page<Entity>, RepositoryError>
+    where
+        F: Fn(&Entity) -> bool,
+    {
+        let storage = self
+            .event_store
+            .read()
+            .map_err(|_| RepositoryError::LockPoisoned("read"))?;
+
+        let mut ids: Vec<&String> = storage
+            .streams()
+            .keys()
+            .filter(|id| request.includes(id))
+            .collect();
+        ids.sort();
+        let candidates = ids.into_iter().map(|id| {
+            let mut entity = Entity::with_id(id.as_str());
+            entity.load_from_history(storage.streams()[id].clone());
+            Ok(entity)
+        });
+        Page::fill(request, candidates, predicate, |e| e.id().to_string())
+    }
+}
+
 impl FindOne for HashMapRepository {
     fn find_one<F>(&self, predicate: F) -> Result<Option<Entity>, RepositoryError>
     where
@@ -114,7 +187,7 @@ impl FindOne for HashMapRepository {
             .read()
             .map_err(|_| RepositoryError::LockPoisoned("read"))?;
 
-        for (id, events) in storage.iter() {
+        for (id, events) in storage.streams().iter() {
             let mut entity = Entity::new();
             entity.set_id(id);
             entity.load_from_history(events.clone());
@@ -136,7 +209,7 @@ impl Exists for HashMapRepository {
             .read()
             .map_err(|_| RepositoryError::LockPoisoned("read"))?;
 
-        for (id, events) in storage.iter() {
+        for (id, events) in storage.streams().iter() {
             let mut entity = Entity::new();
             entity.set_id(id);
             entity.load_from_history(events.clone());
@@ -159,7 +232,7 @@ impl Count for HashMapRepository {
             .map_err(|_| RepositoryError::LockPoisoned("read"))?;
 
         let mut count = 0;
-        for (id, events) in storage.iter() {
+        for (id, events) in storage.streams().iter() {
             let mut entity = Entity::new();
             entity.set_id(id);
             entity.load_from_history(events.clone());
@@ -180,27 +253,101 @@ impl Commit for HashMapRepository {
             .write()
             .map_err(|_| RepositoryError::LockPoisoned("write"))?;
 
-        // Phase 1: Validate (optimistic concurrency check)
-        for entity in &entities {
-            let stored_len = storage.get(entity.id()).map(|v| v.len() as u64).unwrap_or(0);
-            if stored_len != entity.committed_version() {
-                return Err(RepositoryError::ConcurrentWrite {
-                    id: entity.id().to_string(),
-                    expected: entity.committed_version(),
-                    actual: stored_len,
-                });
+        let stored = validate(&storage, &entities)?;
+        append(&mut storage, entities, stored);
+        Ok(())
+    }
+}
+
+impl AtomicCommit for HashMapRepository {
+    /// Holds the event log and read model locks (in that order) for the whole
+    /// write set. Every check runs before anything is written, so a failed
+    /// commit leaves both untouched.
+    fn commit_write_set(&self, writes: WriteSet<'_>) -> Result<(), RepositoryError> {
+        let WriteSet { entities, models } = writes;
+
+        let mut storage = self
+            .event_store
+            .write()
+            .map_err(|_| RepositoryError::LockPoisoned("write"))?;
+        let mut model_writer = self.model_store.raw_writer()?;
+
+        let stored = validate(&storage, &entities)?;
+        check_model_versions(&models, |key| Ok(model_writer.version(key)))?;
+
+        append(&mut storage, entities, stored);
+        for model in models {
+            match model.change {
+                ModelChange::Upsert(bytes) => {
+                    model_writer.upsert(&model.key, bytes);
+                }
+                ModelChange::Delete => {
+                    model_writer.delete(&model.key);
+                }
             }
         }
+        Ok(())
+    }
+}
 
-        // Phase 2: Append new events and mark committed
-        for entity in entities {
-            let new_events = entity.new_events().to_vec();
-            let stored = storage.entry(entity.id().to_string()).or_insert_with(Vec::new);
-            stored.extend(new_events);
-            entity.mark_committed();
-        }
+/// Phase 1: Validate (optimistic concurrency / expected version check).
+/// Returns each entity's stored version.
+fn validate(storage: &EventLog, entities: &[&mut Entity]) -> Result<Vec<u64>, RepositoryError> {
+    let mut stored = Vec::with_capacity(entities.len());
+    for entity in entities {
+        let actual = storage.stream_version(entity.id());
+        check_expected_version(entity, actual)?;
+        stored.push(actual.unwrap_or(0));
+    }
+    Ok(stored)
+}
 
-        Ok(())
+/// Phase 2: Append new events (assigning global positions) and mark committed.
+fn append(storage: &mut EventLog, entities: Vec<&mut Entity>, stored: Vec<u64>) {
+    for (entity, stored) in entities.into_iter().zip(stored) {
+        entity.rebase(stored);
+        storage.append(entity);
+        entity.mark_committed();
+    }
+}
+
+impl FindByIndex for HashMapRepository {
+    fn ids_in(&self, index: &str) -> Result<Vec<String>, RepositoryError> {
+        let storage = self
+            .event_store
+            .read()
+            .map_err(|_| RepositoryError::LockPoisoned("read"))?;
+
+        storage.indexes().ids_in(index)
+    }
+
+    fn ids_by(&self, index: &str, key: &str) -> Result<Vec<String>, RepositoryError> {
+        let storage = self
+            .event_store
+            .read()
+            .map_err(|_| RepositoryError::LockPoisoned("read"))?;
+
+        storage.indexes().ids_by(index, key)
+    }
+}
+
+impl ReadAll for HashMapRepository {
+    fn read_all(&self, from_position: u64, limit: usize) -> Result<Vec<StoredEvent>, RepositoryError> {
+        let storage = self
+            .event_store
+            .read()
+            .map_err(|_| RepositoryError::LockPoisoned("read"))?;
+
+        Ok(storage.read_all(from_position, limit))
+    }
+
+    fn last_position(&self) -> Result<u64, RepositoryError> {
+        let storage = self
+            .event_store
+            .read()
+            .map_err(|_| RepositoryError::LockPoisoned("read"))?;
+
+        Ok(storage.last_position())
     }
 }
 
@@ -236,6 +383,29 @@ impl ReadModelStore for HashMapRepository {
         self.model_store.find_models(predicate)
     }
 
+    fn find_models_page<M: ReadModel>(
+        &self,
+        predicate: &dyn Fn(&M) -> bool,
+        request: &PageRequest,
+    ) -> Result<Page<Versioned<M>>, ReadModelError> {
+        self.model_store.find_models_page(predicate, request)
+    }
+
+    fn query_models<M: ReadModel>(
+        &self,
+        query: &Query,
+    ) -> Result<Vec<Versioned<M>>, ReadModelError> {
+        self.model_store.query_models(query)
+    }
+
+    fn find_models_by<M: ReadModel>(
+        &self,
+        field: &str,
+        value: &serde_json::Value,
+    ) -> Result<Vec<Versioned<M>>, ReadModelError> {
+        self.model_store.find_models_by(field, value)
+    }
+
     fn find_one_model<M: ReadModel>(
         &self,
         predicate: &dyn Fn(&M) -> bool,
@@ -246,6 +416,26 @@ impl ReadModelStore for HashMapRepository {
     fn upsert_raw(&self, key: &str, bytes: Vec<u8>) -> Result<(), ReadModelError> {
         self.model_store.upsert_raw(key, bytes)
     }
+
+    fn upsert_raw_many(&self, models: Vec<(String, Vec<u8>)>) -> Result<(), ReadModelError> {
+        self.model_store.upsert_raw_many(models)
+    }
+
+    fn get_raw(&self, key: &str) -> Result<Option<Vec<u8>>, ReadModelError> {
+        self.model_store.get_raw(key)
+    }
+
+    fn delete_collection(&self, collection: &str) -> Result<usize, ReadModelError> {
+        self.model_store.delete_collection(collection)
+    }
+
+    fn swap_collections(
+        &self,
+        swaps: &[(&str, &str)],
+        models: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), ReadModelError> {
+        self.model_store.swap_collections(swaps, models)
+    }
 }
 
 impl SnapshotStore for HashMapRepository {
@@ -262,6 +452,20 @@ impl SnapshotStore for HashMapRepository {
     }
 }
 
+impl CheckpointStore for HashMapRepository {
+    fn load_checkpoint(&self, name: &str) -> Result<Option<u64>, RepositoryError> {
+        self.checkpoint_store.load_checkpoint(name)
+    }
+
+    fn save_checkpoint(&self, name: &str, position: u64) -> Result<(), RepositoryError> {
+        self.checkpoint_store.save_checkpoint(name, position)
+    }
+
+    fn delete_checkpoint(&self, name: &str) -> Result<bool, RepositoryError> {
+        self.checkpoint_store.delete_checkpoint(name)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -270,7 +474,7 @@ mod tests {
     #[test]
     fn new() {
         let repo = HashMapRepository::new();
-        assert!(repo.event_store.read().unwrap().is_empty());
+        assert!(repo.event_store.read().unwrap().streams().is_empty());
     }
 
     #[test]
@@ -396,6 +600,86 @@ mod tests {
         assert_eq!(repo.count(|e| e.id().starts_with("order-")).unwrap(), 0);
     }
 
+    #[test]
+    fn indexes_are_updated_by_commit() {
+        let repo = HashMapRepository::new()
+            .with_index(SecondaryIndex::key("owner", |event| event.decode::<String>().ok()))
+            .unwrap();
+        let mut entity = Entity::with_id("a");
+        entity.digest("Assigned", &"alice");
+        repo.commit(&mut entity).unwrap();
+        assert_eq!(repo.ids_by("owner", "alice").unwrap(), vec!["a"]);
+
+        entity.digest("Assigned", &"bob");
+        repo.commit(&mut entity).unwrap();
+        assert!(repo.ids_by("owner", "alice").unwrap().is_empty());
+        assert_eq!(repo.ids_in("owner").unwrap(), vec!["a"]);
+    }
+
+    #[test]
+    fn with_index_reports_a_poisoned_event_store() {
+        let repo = HashMapRepository::new();
+        let poisoner = repo.clone();
+        let _ = std::thread::spawn(move || {
+            let _guard = poisoner.event_store().write().unwrap();
+            panic!("poison the event store");
+        })
+        .join();
+
+        let result = repo.with_index(SecondaryIndex::prefix("all", ""));
+        assert!(matches!(result, Err(RepositoryError::LockPoisoned("write"))));
+    }
+
+    #[test]
+    fn get_one_from_loads_only_the_tail() {
+        let repo = HashMapRepository::new();
+        let mut entity = Entity::with_id("a");
+        for i in 0..3 {
+            entity.digest("Touched", &i);
+        }
+        repo.commit(&mut entity).unwrap();
+
+        let mut tail = repo.get_one_from("a", 2).unwrap().unwrap();
+        assert_eq!(tail.version(), 3);
+        assert_eq!(tail.events().len(), 1);
+        assert_eq!(tail.events()[0].sequence, 3);
+
+        tail.digest("Touched", &3);
+        repo.commit(&mut tail).unwrap();
+        assert_eq!(repo.get_one("a").unwrap().unwrap().version(), 4);
+
+        let clamped = repo.get_one_from("a", 10).unwrap().unwrap();
+        assert_eq!((clamped.base_version(), clamped.events().len()), (4, 0));
+        assert!(repo.get_one_from("missing", 0).unwrap().is_none());
+    }
+
+    #[test]
+    fn read_all_orders_events_across_streams() {
+        let repo = HashMapRepository::new();
+
+        let mut a = Entity::with_id("a");
+        a.digest("Created", &"a");
+        let mut b = Entity::with_id("b");
+        b.digest("Created", &"b");
+        b.digest("Updated", &"b");
+        repo.commit(&mut [&mut a, &mut b]).unwrap();
+
+        a.digest("Updated", &"a");
+        repo.commit(&mut a).unwrap();
+
+        let all = repo.read_all(0, 100).unwrap();
+        let order: Vec<(&str, u64)> = all
+            .iter()
+            .map(|e| (e.stream_id.as_str(), e.position()))
+            .collect();
+        assert_eq!(order, vec![("a", 1), ("b", 2), ("b", 3), ("a", 4)]);
+        assert_eq!(repo.last_position().unwrap(), 4);
+
+        assert_eq!(repo.read_all(2, 1).unwrap()[0].position(), 3);
+        assert!(repo.read_all(4, 10).unwrap().is_empty());
+        assert_eq!(repo.get_one("a").unwrap().unwrap().events()[1].position, 4);
+    }
+
     #[test]
     fn exists_and_count_on_empty_repo() {
         let repo = HashMapRepository::new();
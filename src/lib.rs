@@ -11,34 +11,59 @@ pub mod emitter;
 pub mod bus;
 pub mod microsvc;
 mod commit_builder;
+mod file_repo;
 mod hashmap_repo;
 pub mod lock;
+pub mod projection;
 pub mod read_model;
 mod outbox;
 mod outbox_worker;
 pub mod queued_repo;
 pub mod snapshot;
+pub mod subscription;
+#[cfg(feature = "sqlite")]
+mod sqlite_repo;
 
 // Re-export entity types at crate root for convenience
 pub use entity::{Committable, Entity, Event, EventRecord, EventUpcaster, LocalEvent, PayloadError, upcast_events};
 
 // Re-export repository traits at crate root for convenience
 pub use repository::{
-    Commit, Count, Exists, Find, FindOne, Get, GetMany, GetOne, Gettable, Repository,
-    RepositoryError,
+    Commit, Count, Exists, ExpectedVersion, Find, FindByIndex, FindOne, FindPage, Get, GetMany,
+    GetOne, GetOneFrom, Gettable, Page, PageRequest, Pages, ReadAll, Repository, RepositoryError,
+    SecondaryIndex, StoredEvent,
+};
+
+// Async repository traits (requires "async" feature)
+#[cfg(feature = "async")]
+pub use repository::{
+    AsyncCommit, AsyncCount, AsyncExists, AsyncFind, AsyncFindOne, AsyncGet, AsyncGetMany,
+    AsyncGetOne, AsyncGetOneFrom, AsyncGettable, AsyncRepository,
 };
 
 // Re-export aggregate types at crate root for convenience
 pub use aggregate::{
-    hydrate, Aggregate, AggregateBuilder, AggregateRepository, CommitAggregate, CountAggregate,
-    ExistsAggregate, FindAggregate, FindOneAggregate, GetAggregate, GetAllAggregates, RepositoryExt,
-    UnlockableRepository,
+    hydrate, Aggregate, AggregateBuilder, AggregateRef, AggregateRepository, CommitAggregate,
+    CountAggregate, ExistsAggregate, FindAggregate, FindOneAggregate, GetAggregate,
+    GetAllAggregates, Locked, RepositoryExt, RetryPolicy, UnitOfWork, UnlockableRepository,
+    Updated,
 };
 
 pub use hashmap_repo::HashMapRepository;
 
+// Durable file-backed event store
+pub use file_repo::{FileRepository, DEFAULT_MAX_SEGMENT_BYTES};
+
+// Embedded SQLite backend (requires "sqlite" feature)
+#[cfg(feature = "sqlite")]
+pub use sqlite_repo::SqliteRepository;
+
 // Re-export lock traits and types at crate root for convenience
-pub use lock::{InMemoryLock, InMemoryLockManager, Lock, LockError, LockManager};
+pub use lock::{
+    FileLeaseStore, FileLock, FileLockManager, InMemoryLeaseStore, InMemoryLock,
+    InMemoryLockManager, KeyStats, Lease, LeaseLock, LeaseLockManager, LeaseStore, Lock,
+    LockError, LockHolder, LockManager, LockStats, LockWaitReport,
+};
 
 // Outbox: commit concerns (atomic aggregate + outbox commit)
 pub use outbox::{
@@ -82,12 +107,16 @@ pub use queued_repo::{
 
 // Read models: projections and read-optimized views
 pub use read_model::{
-    InMemoryReadModelStore, QueuedReadModelStore, ReadModel, ReadModelError, ReadModelStore,
-    ReadModelsExt, Versioned,
+    InMemoryReadModelStore, LockedModel, Query, QueuedReadModelStore, ReadModel, ReadModelError,
+    ReadModelStore, ReadModelsExt, SortOrder, Versioned,
 };
 
 // CommitBuilder: atomic commits of read models, outbox, and aggregates
-pub use commit_builder::{CommitBuilder, CommitBuilderExt};
+pub use commit_builder::{
+    AtomicCommit, CommitBuilder, CommitBuilderExt, ModelChange, ModelWrite, WriteSet,
+};
+#[cfg(feature = "async")]
+pub use commit_builder::AsyncAtomicCommit;
 
 // Snapshot: periodic aggregate snapshots for fast hydration
 pub use snapshot::{
@@ -95,6 +124,21 @@ pub use snapshot::{
     SnapshotRecord, SnapshotStore,
 };
 
+#[cfg(feature = "async")]
+pub use snapshot::AsyncSnapshotStore;
+
+// Subscriptions: checkpointed catch-up + live consumers of the global log
+pub use subscription::{
+    CheckpointStore, InMemoryCheckpointStore, Pollable, StartFrom, Subscription,
+    SubscriptionError, SubscriptionHandler, SubscriptionStats, SubscriptionThread,
+};
+
+// Projections: event-driven read model builders
+pub use projection::{
+    EventProjection, Projection, ProjectionCheckpoint, ProjectionContext, ProjectionError,
+    ProjectionRebuild, ProjectionRunner, RebuildProgress,
+};
+
 // Re-export the EventEmitter from the event_emitter_rs crate (requires "emitter" feature)
 #[cfg(feature = "emitter")]
 pub use event_emitter_rs::EventEmitter;
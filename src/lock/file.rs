@@ -0,0 +1,482 @@
+//! File locks - OS advisory file locks, for locking across processes on a host.
+//!
+//! Each key is guarded by a lock file in the manager's directory: one of a
+//! fixed set of stripe files, or optionally one file per key. The OS lock on
+//! the file keeps other processes out; an in-process lock per key serializes
+//! this process's threads, which share the file lock of a stripe.
+//!
+//! Lock files are never deleted, since removing a file another process is
+//! about to lock would let two processes hold the "same" lock. The
+//! in-process locks of idle keys are dropped, as `InMemoryLockManager` does.
+
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{InMemoryLock, Lock, LockError, LockManager};
+
+/// How long to wait for a lock file.
+#[derive(Clone, Copy)]
+enum Wait {
+    Block,
+    Try,
+    Until(Instant),
+}
+
+/// One lock file, held by this process while any of its keys is locked.
+struct LockFile {
+    path: PathBuf,
+    /// How many keys of this process hold the file, and the open file
+    /// carrying the OS lock while that is more than zero.
+    held: Mutex<(usize, Option<File>)>,
+}
+
+impl LockFile {
+    fn open(&self) -> Result<File, LockError> {
+        OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&self.path)
+            .map_err(|e| LockError::AcquireFailed(format!("{}: {}", self.path.display(), e)))
+    }
+
+    /// Take the file lock for one more key. `Ok(false)` if another process
+    /// holds it and `wait` ran out.
+    ///
+    /// The OS lock is only ever tried, and waiting happens with `held`
+    /// released, so threads entering other keys of the stripe are not held up.
+    fn enter(&self, wait: Wait) -> Result<bool, LockError> {
+        let mut backoff = Duration::from_millis(1);
+        loop {
+            let mut held = self
+                .held
+                .lock()
+                .map_err(|_| LockError::Poisoned("lock file state poisoned".into()))?;
+            if held.0 > 0 {
+                held.0 += 1;
+                return Ok(true);
+            }
+
+            let file = self.open()?;
+            match file.try_lock() {
+                Ok(()) => {
+                    *held = (1, Some(file));
+                    return Ok(true);
+                }
+                Err(TryLockError::WouldBlock) => {}
+                Err(TryLockError::Error(e)) => {
+                    return Err(LockError::AcquireFailed(format!(
+                        "{}: {}",
+                        self.path.display(),
+                        e
+                    )))
+                }
+            }
+            drop(held);
+
+            let pause = match wait {
+                Wait::Block => backoff,
+                Wait::Try => return Ok(false),
+                Wait::Until(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Ok(false);
+                    }
+                    backoff.min(deadline - now)
+                }
+            };
+            thread::sleep(pause);
+            backoff = (backoff * 2).min(Duration::from_millis(50));
+        }
+    }
+
+    /// Give up the file lock for one key, releasing the OS lock with the last.
+    fn leave(&self) -> Result<(), LockError> {
+        let mut held = self
+            .held
+            .lock()
+            .map_err(|_| LockError::Poisoned("lock file state poisoned".into()))?;
+        held.0 = held.0.saturating_sub(1);
+        if held.0 == 0 {
+            if let Some(file) = held.1.take() {
+                file.unlock().map_err(|e| {
+                    LockError::ReleaseFailed(format!("{}: {}", self.path.display(), e))
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A lock on one key, held by this process's thread and, through its lock
+/// file, by this process.
+pub struct FileLock {
+    local: InMemoryLock,
+    file: Arc<LockFile>,
+    held: AtomicBool,
+}
+
+impl FileLock {
+    /// The lock file guarding this key.
+    pub fn path(&self) -> &Path {
+        &self.file.path
+    }
+
+    /// Take the file lock once the in-process lock is held, giving the
+    /// in-process lock back if that fails.
+    fn enter(&self, wait: Wait) -> Result<bool, LockError> {
+        match self.file.enter(wait) {
+            Ok(true) => {
+                self.held.store(true, Ordering::SeqCst);
+                Ok(true)
+            }
+            Ok(false) => {
+                self.local.unlock()?;
+                Ok(false)
+            }
+            Err(e) => {
+                let _ = self.local.unlock();
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Lock for FileLock {
+    fn lock(&self) -> Result<(), LockError> {
+        self.local.lock()?;
+        self.enter(Wait::Block).map(|_| ())
+    }
+
+    fn try_lock(&self) -> Result<bool, LockError> {
+        if !self.local.try_lock()? {
+            return Ok(false);
+        }
+        self.enter(Wait::Try)
+    }
+
+    fn lock_timeout(&self, timeout: Duration) -> Result<(), LockError> {
+        let deadline = Instant::now() + timeout;
+        self.local.lock_timeout(timeout)?;
+        if self.enter(Wait::Until(deadline))? {
+            Ok(())
+        } else {
+            Err(LockError::AcquireFailed(format!("timed out after {:?}", timeout)))
+        }
+    }
+
+    fn unlock(&self) -> Result<(), LockError> {
+        if self.held.swap(false, Ordering::SeqCst) {
+            let left = self.file.leave();
+            self.local.unlock()?;
+            left?;
+        }
+        Ok(())
+    }
+}
+
+/// FNV-1a, stable across processes and builds, unlike `DefaultHasher`.
+fn stripe_of(key: &str, stripes: usize) -> usize {
+    let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    (hash % stripes as u64) as usize
+}
+
+/// The lock file name for a key: the key with every byte outside
+/// `[A-Za-z0-9._-]` percent-encoded.
+fn file_name(key: &str) -> String {
+    let mut name = String::with_capacity(key.len() + 5);
+    for byte in key.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'_' | b'-' => name.push(byte as char),
+            _ => name.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    name.push_str(".lock");
+    name
+}
+
+/// The number of stripe files of a new `FileLockManager`.
+const DEFAULT_STRIPES: usize = 1024;
+
+/// The table size below which `get_lock` does not sweep.
+const SWEEP_FLOOR: usize = 1024;
+
+struct FileLockTable {
+    locks: HashMap<String, Arc<FileLock>>,
+    /// `get_lock` sweeps once the table reaches this size.
+    next_sweep: usize,
+}
+
+/// A `LockManager` backed by OS advisory file locks in a directory, so
+/// pessimistic locking works across processes on one host.
+///
+/// Every process must use the same directory and the same striping. Keys
+/// share 1024 stripe files by default ([`with_stripes`](Self::with_stripes)
+/// changes the count), so processes contend for unrelated keys that share a
+/// stripe; keys sharing a stripe never block each other within one process.
+/// [`with_file_per_key`](Self::with_file_per_key) gives each key its own
+/// file instead, leaving a file behind for every key ever locked.
+///
+/// The in-process lock of a key is dropped once it is idle: `get_lock`
+/// sweeps whenever its table has doubled since the last sweep (and holds at
+/// least 1024 keys), evicting the keys that are not held and that no one
+/// outside the manager has a reference to.
+///
+/// The locks only serialize writers; the store behind them must itself be
+/// safe to open from several processes, such as a `SqliteRepository` file
+/// (with the `sqlite` feature). A `FileLockManager` does not make a
+/// [`FileRepository`](crate::FileRepository) shareable: its directory can
+/// only be open in one process at a time.
+///
+/// ```ignore
+/// let locks = FileLockManager::open("/var/lib/orders/locks")?;
+/// let repo = SqliteRepository::open("/var/lib/orders/events.db")?
+///     .queued_with(locks)
+///     .aggregate::<Order>();
+/// ```
+pub struct FileLockManager {
+    dir: PathBuf,
+    /// `None` for one file per key.
+    stripes: Option<usize>,
+    locks: Mutex<FileLockTable>,
+    files: Mutex<HashMap<String, Arc<LockFile>>>,
+}
+
+impl FileLockManager {
+    /// Keep lock files in `dir`, creating it if needed.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, LockError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|e| LockError::Other(e.to_string()))?;
+        Ok(FileLockManager {
+            dir,
+            stripes: Some(DEFAULT_STRIPES),
+            locks: Mutex::new(FileLockTable {
+                locks: HashMap::new(),
+                next_sweep: SWEEP_FLOOR,
+            }),
+            files: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Share `stripes` lock files between all keys.
+    ///
+    /// # Panics
+    ///
+    /// If `stripes` is zero.
+    pub fn with_stripes(mut self, stripes: usize) -> Self {
+        assert!(stripes > 0, "a FileLockManager needs at least one stripe");
+        self.stripes = Some(stripes);
+        self
+    }
+
+    /// Give each key its own lock file, so keys never contend across
+    /// processes. The directory gains a file for every key ever locked.
+    pub fn with_file_per_key(mut self) -> Self {
+        self.stripes = None;
+        self
+    }
+
+    /// The directory holding the lock files.
+    pub fn path(&self) -> &Path {
+        &self.dir
+    }
+
+    fn lock_file(&self, key: &str) -> Result<Arc<LockFile>, LockError> {
+        let name = match self.stripes {
+            Some(stripes) => format!("stripe-{}.lock", stripe_of(key, stripes)),
+            None => file_name(key),
+        };
+        let mut files = self
+            .files
+            .lock()
+            .map_err(|_| LockError::Poisoned("lock file map poisoned".into()))?;
+        Ok(files
+            .entry(name)
+            .or_insert_with_key(|name| {
+                Arc::new(LockFile {
+                    path: self.dir.join(name),
+                    held: Mutex::new((0, None)),
+                })
+            })
+            .clone())
+    }
+
+    /// Drop the locks that are not held and only the table refers to, then
+    /// the lock files none of the remaining locks use. The table is locked
+    /// throughout, so an evicted lock cannot be handed out meanwhile.
+    fn sweep(&self, table: &mut FileLockTable) -> Result<(), LockError> {
+        table
+            .locks
+            .retain(|_, lock| Arc::strong_count(lock) > 1 || lock.held.load(Ordering::SeqCst));
+        table.next_sweep = (table.locks.len() * 2).max(SWEEP_FLOOR);
+        self.files
+            .lock()
+            .map_err(|_| LockError::Poisoned("lock file map poisoned".into()))?
+            .retain(|_, file| Arc::strong_count(file) > 1);
+        Ok(())
+    }
+}
+
+impl LockManager for FileLockManager {
+    type Lock = FileLock;
+
+    fn get_lock(&self, id: &str) -> Result<Arc<FileLock>, LockError> {
+        let mut table = self
+            .locks
+            .lock()
+            .map_err(|_| LockError::Poisoned("lock manager map poisoned".into()))?;
+        if let Some(lock) = table.locks.get(id) {
+            return Ok(Arc::clone(lock));
+        }
+        if table.locks.len() >= table.next_sweep {
+            self.sweep(&mut table)?;
+        }
+        let lock = Arc::new(FileLock {
+            local: InMemoryLock::new(),
+            file: self.lock_file(id)?,
+            held: AtomicBool::new(false),
+        });
+        table.locks.insert(id.to_string(), Arc::clone(&lock));
+        Ok(lock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "sourced_rust_file_locks_{}_{}_{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn keys_map_to_safe_file_names() {
+        assert_eq!(file_name("Order:42"), "Order%3A42.lock");
+        assert_eq!(file_name("../x y"), "..%2Fx%20y.lock");
+        assert_eq!(stripe_of("Order:42", 8), stripe_of("Order:42", 8));
+    }
+
+    #[test]
+    fn managers_on_one_directory_exclude_each_other() {
+        let dir = temp_dir("contend");
+        fs::create_dir_all(&dir).unwrap();
+        let counter = dir.join("counter");
+        fs::write(&counter, "0").unwrap();
+
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let (dir, counter) = (dir.clone(), counter.clone());
+                thread::spawn(move || {
+                    // Each worker has its own manager and file handles, as a process would
+                    let manager = FileLockManager::open(&dir).unwrap();
+                    let lock = manager.get_lock("counter").unwrap();
+                    for _ in 0..10 {
+                        lock.lock().unwrap();
+                        let value: u32 = fs::read_to_string(&counter).unwrap().parse().unwrap();
+                        fs::write(&counter, (value + 1).to_string()).unwrap();
+                        lock.unlock().unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&counter).unwrap(), "40");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn striped_keys_only_contend_across_managers() {
+        let dir = temp_dir("stripes");
+        let a = FileLockManager::open(&dir).unwrap().with_stripes(1);
+        let b = FileLockManager::open(&dir).unwrap().with_stripes(1);
+
+        let (x, y) = (a.get_lock("x").unwrap(), a.get_lock("y").unwrap());
+        assert_eq!(x.path(), y.path());
+        assert!(x.try_lock().unwrap());
+        assert!(y.try_lock().unwrap());
+        assert!(!x.try_lock().unwrap());
+
+        let other = b.get_lock("z").unwrap();
+        assert!(!other.try_lock().unwrap());
+        let err = other.lock_timeout(Duration::from_millis(20)).unwrap_err();
+        assert!(matches!(err, LockError::AcquireFailed(_)));
+
+        x.unlock().unwrap();
+        assert!(!other.try_lock().unwrap());
+        y.unlock().unwrap();
+        assert!(other.try_lock().unwrap());
+        other.unlock().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn idle_keys_are_evicted_and_held_ones_kept() {
+        let dir = temp_dir("sweep");
+        let manager = FileLockManager::open(&dir).unwrap().with_stripes(8);
+        manager.get_lock("held").unwrap().lock().unwrap();
+        for n in 0..SWEEP_FLOOR * 2 {
+            manager.get_lock(&format!("key-{}", n)).unwrap();
+        }
+
+        assert!(manager.locks.lock().unwrap().locks.len() <= SWEEP_FLOOR);
+        assert!(manager.files.lock().unwrap().len() <= 8);
+
+        // The held key kept its lock, so it can still be released
+        let other = FileLockManager::open(&dir).unwrap().with_stripes(8);
+        assert!(!other.get_lock("held").unwrap().try_lock().unwrap());
+        manager.get_lock("held").unwrap().unlock().unwrap();
+        assert!(other.get_lock("held").unwrap().try_lock().unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_blocked_lock_does_not_hold_up_stripe_mates() {
+        let dir = temp_dir("blocked");
+        let a = FileLockManager::open(&dir).unwrap().with_stripes(1);
+        let b = Arc::new(FileLockManager::open(&dir).unwrap().with_stripes(1));
+        let held = a.get_lock("x").unwrap();
+        held.lock().unwrap();
+
+        // A thread of `b` waits for the stripe file `a` holds
+        let waiter = {
+            let b = Arc::clone(&b);
+            thread::spawn(move || {
+                let lock = b.get_lock("y").unwrap();
+                lock.lock().unwrap();
+                lock.unlock().unwrap();
+            })
+        };
+        thread::sleep(Duration::from_millis(20));
+
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(500));
+            held.unlock().unwrap();
+        });
+        let started = Instant::now();
+        assert!(!b.get_lock("z").unwrap().try_lock().unwrap());
+        assert!(started.elapsed() < Duration::from_millis(250));
+
+        release.join().unwrap();
+        waiter.join().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
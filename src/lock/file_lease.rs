@@ -0,0 +1,206 @@
+//! FileLeaseStore - Leases kept in one JSON file, shared by processes on a host.
+//!
+//! Each operation takes an OS file lock on `<path>.lock`, reads the lease
+//! table, and replaces the file atomically (write to a temporary file, then
+//! rename), so concurrent processes see each other's leases and never a
+//! half-written table.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::lease::{Lease, LeaseStore, LeaseTable};
+use super::LockError;
+
+fn io_error(err: std::io::Error) -> LockError {
+    LockError::Other(err.to_string())
+}
+
+/// A [`LeaseStore`] backed by a file, for lease locks across processes.
+pub struct FileLeaseStore {
+    path: PathBuf,
+    lock_path: PathBuf,
+}
+
+impl FileLeaseStore {
+    /// Use the lease file at `path`, creating its directory if needed. The
+    /// file itself is created on the first lease.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, LockError> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
+        Ok(FileLeaseStore {
+            path,
+            lock_path: lock_path.into(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn load(&self) -> Result<LeaseTable, LockError> {
+        match fs::read(&self.path) {
+            Ok(bytes) => {
+                serde_json::from_slice(&bytes).map_err(|e| LockError::Other(e.to_string()))
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(LeaseTable::default()),
+            Err(err) => Err(io_error(err)),
+        }
+    }
+
+    fn save(&self, table: &LeaseTable) -> Result<(), LockError> {
+        let bytes = serde_json::to_vec(table).map_err(|e| LockError::Other(e.to_string()))?;
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        let mut file = File::create(&tmp).map_err(io_error)?;
+        file.write_all(&bytes).map_err(io_error)?;
+        file.sync_all().map_err(io_error)?;
+        drop(file);
+        fs::rename(&tmp, &self.path).map_err(io_error)
+    }
+
+    /// Run `f` on the lease table under the file lock, saving the table
+    /// afterwards if `changed` says `f`'s result changed it. Polls that find
+    /// the lease taken leave the file alone.
+    fn with_table<T>(
+        &self,
+        f: impl FnOnce(&mut LeaseTable) -> T,
+        changed: impl FnOnce(&T) -> bool,
+    ) -> Result<T, LockError> {
+        let guard = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&self.lock_path)
+            .map_err(io_error)?;
+        guard
+            .lock()
+            .map_err(|e| LockError::AcquireFailed(format!("lease file lock: {}", e)))?;
+
+        let mut table = self.load()?;
+        let out = f(&mut table);
+        if changed(&out) {
+            self.save(&table)?;
+        }
+        // Dropping the file releases the OS lock
+        drop(guard);
+        Ok(out)
+    }
+}
+
+impl LeaseStore for FileLeaseStore {
+    fn acquire(&self, key: &str, owner: &str, ttl: Duration) -> Result<Option<Lease>, LockError> {
+        self.with_table(|table| table.acquire(key, owner, ttl), Option::is_some)
+    }
+
+    fn renew(&self, key: &str, token: u64, ttl: Duration) -> Result<Lease, LockError> {
+        self.with_table(|table| table.renew(key, token, ttl), Result::is_ok)?
+    }
+
+    fn release(&self, key: &str, token: u64) -> Result<(), LockError> {
+        self.with_table(|table| table.release(key, token), |released| *released)
+            .map(|_| ())
+    }
+
+    fn current(&self, key: &str) -> Result<Option<Lease>, LockError> {
+        self.with_table(|table| table.current(key), |_| false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock::{LeaseLockManager, Lock, LockManager};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    fn temp_file(name: &str) -> PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "sourced_rust_file_lease_{}_{}_{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        dir.join("leases.json")
+    }
+
+    #[test]
+    fn stores_on_one_file_share_leases_and_tokens() {
+        let path = temp_file("share");
+        let a = FileLeaseStore::open(&path).unwrap();
+        let b = FileLeaseStore::open(&path).unwrap();
+        let ttl = Duration::from_secs(5);
+
+        let lease = a.acquire("k", "a", ttl).unwrap().unwrap();
+        assert!(b.acquire("k", "b", ttl).unwrap().is_none());
+        assert_eq!(b.current("k").unwrap().unwrap(), lease);
+
+        b.release("k", lease.token).unwrap();
+        let next = b.acquire("k", "b", ttl).unwrap().unwrap();
+        assert!(next.token > lease.token);
+        assert!(matches!(a.renew("k", lease.token, ttl), Err(LockError::Expired(_))));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn only_changes_rewrite_the_lease_file() {
+        let path = temp_file("writes");
+        let store = FileLeaseStore::open(&path).unwrap();
+        let ttl = Duration::from_secs(5);
+        let lease = store.acquire("k", "a", ttl).unwrap().unwrap();
+
+        // Reformat the file; a save would write it back compact
+        let table: serde_json::Value = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        let pretty = serde_json::to_vec_pretty(&table).unwrap();
+        fs::write(&path, &pretty).unwrap();
+
+        assert!(store.acquire("k", "b", ttl).unwrap().is_none());
+        assert!(store.renew("k", lease.token + 1, ttl).is_err());
+        store.release("k", lease.token + 1).unwrap();
+        assert_eq!(store.current("k").unwrap().unwrap(), lease);
+        assert_eq!(fs::read(&path).unwrap(), pretty);
+
+        store.release("k", lease.token).unwrap();
+        assert_ne!(fs::read(&path).unwrap(), pretty);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn managers_on_one_file_exclude_each_other() {
+        let path = temp_file("contend");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let counter = path.with_file_name("counter");
+        fs::write(&counter, "0").unwrap();
+
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let (path, counter) = (path.clone(), counter.clone());
+                thread::spawn(move || {
+                    // Each worker opens the file itself, as a separate process would
+                    let store = FileLeaseStore::open(&path).unwrap();
+                    let manager = LeaseLockManager::new(store, Duration::from_secs(5));
+                    let lock = manager.get_lock("counter").unwrap();
+                    for _ in 0..10 {
+                        lock.lock().unwrap();
+                        let value: u32 = fs::read_to_string(&counter).unwrap().parse().unwrap();
+                        fs::write(&counter, (value + 1).to_string()).unwrap();
+                        lock.unlock().unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&counter).unwrap(), "40");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -1,24 +1,339 @@
-use std::collections::HashMap;
-use std::sync::{Arc, Condvar, Mutex};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use super::{Lock, LockError, LockManager};
 
-/// In-memory lock backed by `Mutex<bool>` + `Condvar`.
+/// A held lock, as tracked by an `InMemoryLockManager` in debug mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockHolder {
+    pub key: String,
+    /// The name of the thread that took the lock, or its ID if unnamed.
+    pub thread: String,
+    pub held_for: Duration,
+    /// Whether the lock is held in shared mode, alongside other holders.
+    pub shared: bool,
+}
+
+/// A lock wait that exceeded the threshold of an `InMemoryLockManager` in
+/// debug mode.
+#[derive(Debug, Clone)]
+pub struct LockWaitReport {
+    /// The key being waited for.
+    pub key: String,
+    /// The waiting thread.
+    pub waiter: String,
+    pub waited: Duration,
+    /// Every lock of the manager held at the time, in key order.
+    pub holders: Vec<LockHolder>,
+}
+
+impl fmt::Display for LockWaitReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "thread {} waited {:?} for lock '{}'; held locks:",
+            self.waiter, self.waited, self.key
+        )?;
+        for holder in &self.holders {
+            let mode = if holder.shared { " (shared)" } else { "" };
+            write!(
+                f,
+                "\n  '{}' by thread {} for {:?}{}",
+                holder.key, holder.thread, holder.held_for, mode
+            )?;
+        }
+        Ok(())
+    }
+}
+
+type Reporter = Box<dyn Fn(&LockWaitReport) + Send + Sync>;
+
+/// One holding of a key, as tracked in debug mode.
+struct Holding {
+    thread: String,
+    since: Instant,
+    shared: bool,
+}
+
+/// Holder tracking and slow-wait reporting shared by the locks of a manager.
+struct LockDebug {
+    threshold: Duration,
+    reporter: Reporter,
+    /// The holdings of each held key.
+    holders: Mutex<BTreeMap<String, Vec<Holding>>>,
+}
+
+impl LockDebug {
+    fn acquired(&self, key: &str, shared: bool) {
+        let mut holders = self.holders.lock().unwrap_or_else(PoisonError::into_inner);
+        holders
+            .entry(key.to_string())
+            .or_default()
+            .push(Holding {
+                thread: current_thread(),
+                since: Instant::now(),
+                shared,
+            });
+    }
+
+    fn released(&self, key: &str, shared: bool) {
+        let mut holders = self.holders.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(entries) = holders.get_mut(key) else {
+            return;
+        };
+        // Another thread may release a lock; prefer this thread's holding
+        let thread = current_thread();
+        let index = entries
+            .iter()
+            .position(|holding| holding.shared == shared && holding.thread == thread)
+            .or_else(|| entries.iter().position(|holding| holding.shared == shared));
+        if let Some(index) = index {
+            entries.remove(index);
+        }
+        if entries.is_empty() {
+            holders.remove(key);
+        }
+    }
+
+    fn holders(&self) -> Vec<LockHolder> {
+        let holders = self.holders.lock().unwrap_or_else(PoisonError::into_inner);
+        holders
+            .iter()
+            .flat_map(|(key, entries)| {
+                entries.iter().map(move |holding| LockHolder {
+                    key: key.clone(),
+                    thread: holding.thread.clone(),
+                    held_for: holding.since.elapsed(),
+                    shared: holding.shared,
+                })
+            })
+            .collect()
+    }
+
+    fn wait_report(&self, key: &str, waited: Duration) -> LockWaitReport {
+        LockWaitReport {
+            key: key.to_string(),
+            waiter: current_thread(),
+            waited,
+            holders: self.holders(),
+        }
+    }
+}
+
+fn current_thread() -> String {
+    let thread = thread::current();
+    match thread.name() {
+        Some(name) => name.to_string(),
+        None => format!("{:?}", thread.id()),
+    }
+}
+
+/// How often an `InMemoryLock` was taken and how long waits took.
+#[derive(Debug, Clone, Copy, Default)]
+struct LockCounters {
+    max_queue_depth: usize,
+    acquisitions: u64,
+    contended: u64,
+    total_wait: Duration,
+    max_wait: Duration,
+}
+
+impl LockCounters {
+    fn key_stats(&self, key: &str, queue_depth: usize) -> KeyStats {
+        KeyStats {
+            key: key.to_string(),
+            queue_depth,
+            max_queue_depth: self.max_queue_depth,
+            acquisitions: self.acquisitions,
+            contended: self.contended,
+            total_wait: self.total_wait,
+            max_wait: self.max_wait,
+        }
+    }
+}
+
+/// Who holds an `InMemoryLock`, who waits for it, and how long waits took.
+#[derive(Debug, Default)]
+struct LockState {
+    exclusive: bool,
+    shared: usize,
+    /// Waiters in arrival order: ticket and whether shared. Only the front
+    /// may take the lock, so waiters are served first come, first served.
+    queue: VecDeque<(u64, bool)>,
+    next_ticket: u64,
+    counters: LockCounters,
+}
+
+impl LockState {
+    /// Whether the current holders allow a holding of this mode.
+    fn compatible(&self, shared: bool) -> bool {
+        if shared {
+            !self.exclusive
+        } else {
+            !self.exclusive && self.shared == 0
+        }
+    }
+
+    fn take(&mut self, shared: bool) {
+        if shared {
+            self.shared += 1;
+        } else {
+            self.exclusive = true;
+        }
+        self.counters.acquisitions += 1;
+    }
+
+    fn leave_queue(&mut self, ticket: u64) {
+        if let Some(index) = self.queue.iter().position(|(t, _)| *t == ticket) {
+            self.queue.remove(index);
+        }
+    }
+
+    fn record_wait(&mut self, waited: Duration) {
+        let counters = &mut self.counters;
+        counters.contended += 1;
+        counters.total_wait += waited;
+        counters.max_wait = counters.max_wait.max(waited);
+    }
+
+    fn is_idle(&self) -> bool {
+        !self.exclusive && self.shared == 0 && self.queue.is_empty()
+    }
+
+    fn key_stats(&self, key: &str) -> KeyStats {
+        self.counters.key_stats(key, self.queue.len())
+    }
+}
+
+/// In-memory reader-writer lock backed by `Mutex` + `Condvar`.
 ///
 /// This is the default lock implementation — the same logic that was
 /// previously the concrete `Lock` struct, now behind the `Lock` trait.
+/// Waiters are served in arrival order, so none can starve: shared holders
+/// proceed together, and an exclusive waiter waits for them to finish while
+/// holding back everyone who arrived after it. `try_lock` never jumps the
+/// queue.
 pub struct InMemoryLock {
-    state: Mutex<bool>,
+    state: Mutex<LockState>,
     wake: Condvar,
+    key: String,
+    debug: Option<Arc<LockDebug>>,
 }
 
 impl InMemoryLock {
     pub fn new() -> Self {
         InMemoryLock {
-            state: Mutex::new(false),
+            state: Mutex::new(LockState::default()),
             wake: Condvar::new(),
+            key: String::new(),
+            debug: None,
         }
     }
+
+    fn state(&self) -> Result<MutexGuard<'_, LockState>, LockError> {
+        self.state
+            .lock()
+            .map_err(|e| LockError::Poisoned(e.to_string()))
+    }
+
+    /// Wait for the lock, up to `timeout` if given. In debug mode, a wait
+    /// longer than the threshold is reported once.
+    fn acquire(&self, shared: bool, timeout: Option<Duration>) -> Result<(), LockError> {
+        let mut state = self.state()?;
+        if state.queue.is_empty() && state.compatible(shared) {
+            state.take(shared);
+            drop(state);
+            self.acquired(shared);
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        let mut reported = false;
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        state.queue.push_back((ticket, shared));
+        state.counters.max_queue_depth = state.counters.max_queue_depth.max(state.queue.len());
+
+        loop {
+            let first = state.queue.front().map(|(t, _)| *t) == Some(ticket);
+            if first && state.compatible(shared) {
+                state.queue.pop_front();
+                state.take(shared);
+                state.record_wait(start.elapsed());
+                // The next waiter may be compatible too (a run of shared ones)
+                self.wake.notify_all();
+                drop(state);
+                self.acquired(shared);
+                return Ok(());
+            }
+
+            let now = Instant::now();
+            let mut wait = None;
+            if let Some(timeout) = timeout {
+                let left = (start + timeout).saturating_duration_since(now);
+                if left.is_zero() {
+                    state.leave_queue(ticket);
+                    // Waiters queued behind this one may go now
+                    self.wake.notify_all();
+                    return Err(LockError::AcquireFailed(format!(
+                        "timed out after {:?}",
+                        timeout
+                    )));
+                }
+                wait = Some(left);
+            }
+            if let Some(debug) = self.debug.as_ref().filter(|_| !reported) {
+                let until_report = (start + debug.threshold).saturating_duration_since(now);
+                if until_report.is_zero() {
+                    let report = debug.wait_report(&self.key, now - start);
+                    reported = true;
+                    // The reporter may inspect the manager, so it runs unlocked
+                    drop(state);
+                    (debug.reporter)(&report);
+                    state = self.state()?;
+                    continue;
+                } else {
+                    wait = Some(wait.map_or(until_report, |left| left.min(until_report)));
+                }
+            }
+            state = match wait {
+                Some(wait) => {
+                    self.wake
+                        .wait_timeout(state, wait)
+                        .map_err(|e| LockError::Poisoned(e.to_string()))?
+                        .0
+                }
+                None => self
+                    .wake
+                    .wait(state)
+                    .map_err(|e| LockError::Poisoned(e.to_string()))?,
+            };
+        }
+    }
+
+    fn acquired(&self, shared: bool) {
+        if let Some(debug) = &self.debug {
+            debug.acquired(&self.key, shared);
+        }
+    }
+
+    fn try_acquire(&self, shared: bool) -> Result<bool, LockError> {
+        let mut state = self.state()?;
+        if !state.queue.is_empty() || !state.compatible(shared) {
+            return Ok(false);
+        }
+        state.take(shared);
+        drop(state);
+        self.acquired(shared);
+        Ok(true)
+    }
+
+    fn key_stats(&self) -> Option<KeyStats> {
+        Some(self.state.lock().ok()?.key_stats(&self.key))
+    }
 }
 
 impl Default for InMemoryLock {
@@ -29,59 +344,320 @@ impl Default for InMemoryLock {
 
 impl Lock for InMemoryLock {
     fn lock(&self) -> Result<(), LockError> {
-        let mut locked = self
-            .state
-            .lock()
-            .map_err(|e| LockError::Poisoned(e.to_string()))?;
-        while *locked {
-            locked = self
-                .wake
-                .wait(locked)
-                .map_err(|e| LockError::Poisoned(e.to_string()))?;
-        }
-        *locked = true;
-        Ok(())
+        self.acquire(false, None)
     }
 
     fn try_lock(&self) -> Result<bool, LockError> {
-        let mut locked = self
-            .state
-            .lock()
-            .map_err(|e| LockError::Poisoned(e.to_string()))?;
-        if *locked {
-            Ok(false)
-        } else {
-            *locked = true;
-            Ok(true)
-        }
+        self.try_acquire(false)
+    }
+
+    fn lock_timeout(&self, timeout: Duration) -> Result<(), LockError> {
+        self.acquire(false, Some(timeout))
     }
 
     fn unlock(&self) -> Result<(), LockError> {
-        let mut locked = self
-            .state
-            .lock()
-            .map_err(|e| LockError::Poisoned(e.to_string()))?;
-        if *locked {
-            *locked = false;
-            self.wake.notify_one();
+        let mut state = self.state()?;
+        if state.exclusive {
+            state.exclusive = false;
+            if let Some(debug) = &self.debug {
+                debug.released(&self.key, false);
+            }
+            self.wake.notify_all();
+        }
+        Ok(())
+    }
+
+    fn lock_shared(&self) -> Result<(), LockError> {
+        self.acquire(true, None)
+    }
+
+    fn try_lock_shared(&self) -> Result<bool, LockError> {
+        self.try_acquire(true)
+    }
+
+    fn lock_shared_timeout(&self, timeout: Duration) -> Result<(), LockError> {
+        self.acquire(true, Some(timeout))
+    }
+
+    fn unlock_shared(&self) -> Result<(), LockError> {
+        let mut state = self.state()?;
+        if state.shared > 0 {
+            state.shared -= 1;
+            if let Some(debug) = &self.debug {
+                debug.released(&self.key, true);
+            }
+            if state.shared == 0 {
+                self.wake.notify_all();
+            }
         }
         Ok(())
     }
 }
 
+/// Contention counters of one key of an [`InMemoryLockManager`]. The counters
+/// of a key that ever had to wait outlive the eviction of its lock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyStats {
+    pub key: String,
+    /// Waiters queued now.
+    pub queue_depth: usize,
+    /// The most waiters queued at once.
+    pub max_queue_depth: usize,
+    /// Times the lock was taken, exclusively or shared.
+    pub acquisitions: u64,
+    /// Acquisitions that had to wait.
+    pub contended: u64,
+    /// Time spent waiting, over all contended acquisitions.
+    pub total_wait: Duration,
+    /// The longest wait of a single acquisition.
+    pub max_wait: Duration,
+}
+
+impl KeyStats {
+    /// The mean wait of a contended acquisition.
+    pub fn mean_wait(&self) -> Duration {
+        if self.contended == 0 {
+            return Duration::ZERO;
+        }
+        self.total_wait.div_f64(self.contended as f64)
+    }
+}
+
+/// Counters of an [`InMemoryLockManager`]'s lock table.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LockStats {
+    /// Locks in the table, held or not.
+    pub live: usize,
+    /// Locks held, exclusively or shared, or waited for.
+    pub busy: usize,
+    /// Waiters queued now, over all keys.
+    pub waiting: usize,
+    /// Locks evicted by sweeps since the manager was created.
+    pub evicted: u64,
+    /// Sweeps run since the manager was created, by `get_lock` or `sweep`.
+    pub sweeps: u64,
+}
+
+/// The default table size below which `get_lock` does not sweep.
+const DEFAULT_SWEEP_FLOOR: usize = 1024;
+
+/// The default number of evicted keys whose counters are kept.
+const DEFAULT_RETIRED_LIMIT: usize = 1024;
+
+#[derive(Default)]
+struct LockTable {
+    locks: HashMap<String, Arc<InMemoryLock>>,
+    /// The counters of evicted locks that were contended, so they carry over
+    /// to the next lock of the key.
+    retired: HashMap<String, LockCounters>,
+    /// How many entries `retired` keeps: those that waited longest.
+    retired_limit: usize,
+    /// `get_lock` sweeps once the table reaches this size.
+    next_sweep: usize,
+    evicted: u64,
+    sweeps: u64,
+}
+
+impl LockTable {
+    /// Drop the locks nobody holds, waits for or has a reference to. A lock
+    /// only the table refers to cannot be handed out during the sweep (the
+    /// table is locked), so a later `get_lock` simply creates a fresh one.
+    /// A poisoned lock counts as busy.
+    fn sweep(&mut self) -> usize {
+        let before = self.locks.len();
+        let retired = &mut self.retired;
+        self.locks.retain(|key, lock| {
+            if Arc::strong_count(lock) > 1 {
+                return true;
+            }
+            let Ok(state) = lock.state.lock() else {
+                return true;
+            };
+            if !state.is_idle() {
+                return true;
+            }
+            if state.counters.contended > 0 {
+                retired.insert(key.clone(), state.counters);
+            }
+            false
+        });
+        if self.retired.len() > self.retired_limit {
+            let mut retired: Vec<(String, LockCounters)> = self.retired.drain().collect();
+            retired.sort_by(|(a_key, a), (b_key, b)| {
+                b.total_wait.cmp(&a.total_wait).then_with(|| a_key.cmp(b_key))
+            });
+            retired.truncate(self.retired_limit);
+            self.retired = retired.into_iter().collect();
+        }
+        let evicted = before - self.locks.len();
+        self.evicted += evicted as u64;
+        self.sweeps += 1;
+        evicted
+    }
+}
+
 /// In-memory lock manager backed by a `HashMap<String, Arc<InMemoryLock>>`.
 ///
 /// This is the default `LockManager` — it lazily creates one `InMemoryLock`
-/// per unique key and returns the same `Arc` for repeated lookups.
+/// per unique key and returns the same `Arc` for repeated lookups while the
+/// lock is held or referenced.
+///
+/// Idle locks are evicted so that the table does not grow with every key
+/// ever locked: `get_lock` sweeps whenever the table has doubled since the
+/// last sweep (and holds at least 1024 locks, see
+/// [`with_sweep_floor`](Self::with_sweep_floor)), and [`sweep`](Self::sweep)
+/// can be called on a timer. A lock is idle when it is not held or waited
+/// for and no one outside the table has its `Arc`. The contention counters
+/// of evicted keys that ever had to wait are kept, up to 1024 keys (see
+/// [`with_retired_limit`](Self::with_retired_limit)); past that, sweeps keep
+/// the keys that waited longest.
+///
+/// Locks serve waiters in arrival order. [`key_stats`](Self::key_stats) and
+/// [`hottest`](Self::hottest) report per-key queue depths and wait times, to
+/// find the aggregates under contention.
+///
+/// In debug mode ([`with_debug`](Self::with_debug)) the manager tracks which
+/// thread holds which key, and reports any wait longer than a threshold
+/// together with every held lock, to help find a stuck holder or a deadlock.
 pub struct InMemoryLockManager {
-    locks: Mutex<HashMap<String, Arc<InMemoryLock>>>,
+    locks: Mutex<LockTable>,
+    sweep_floor: usize,
+    debug: Option<Arc<LockDebug>>,
 }
 
 impl InMemoryLockManager {
     pub fn new() -> Self {
         InMemoryLockManager {
-            locks: Mutex::new(HashMap::new()),
+            locks: Mutex::new(LockTable {
+                next_sweep: DEFAULT_SWEEP_FLOOR,
+                retired_limit: DEFAULT_RETIRED_LIMIT,
+                ..LockTable::default()
+            }),
+            sweep_floor: DEFAULT_SWEEP_FLOOR,
+            debug: None,
+        }
+    }
+
+    /// Let `get_lock` sweep once the table holds `floor` locks (and has
+    /// doubled since the last sweep). `usize::MAX` leaves sweeping to
+    /// [`sweep`](Self::sweep).
+    ///
+    /// # Panics
+    ///
+    /// If `floor` is zero, which would sweep on every new key.
+    pub fn with_sweep_floor(mut self, floor: usize) -> Self {
+        assert!(floor > 0, "an InMemoryLockManager needs a sweep floor of at least one");
+        self.sweep_floor = floor;
+        if let Ok(table) = self.locks.get_mut() {
+            table.next_sweep = floor;
+        }
+        self
+    }
+
+    /// Keep the counters of at most `limit` evicted keys, those that waited
+    /// longest. Zero keeps none, so evicted keys start counting afresh.
+    pub fn with_retired_limit(mut self, limit: usize) -> Self {
+        if let Ok(table) = self.locks.get_mut() {
+            table.retired_limit = limit;
         }
+        self
+    }
+
+    /// Turn on debug mode, printing a [`LockWaitReport`] to stderr whenever
+    /// a wait exceeds `threshold`.
+    pub fn with_debug(self, threshold: Duration) -> Self {
+        self.with_debug_reporter(threshold, |report| eprintln!("{}", report))
+    }
+
+    /// Turn on debug mode, passing a [`LockWaitReport`] to `reporter`
+    /// whenever a wait exceeds `threshold`. The reporter runs on the waiting
+    /// thread.
+    pub fn with_debug_reporter(
+        mut self,
+        threshold: Duration,
+        reporter: impl Fn(&LockWaitReport) + Send + Sync + 'static,
+    ) -> Self {
+        self.debug = Some(Arc::new(LockDebug {
+            threshold,
+            reporter: Box::new(reporter),
+            holders: Mutex::new(BTreeMap::new()),
+        }));
+        self
+    }
+
+    /// The locks currently held, in key order. Always empty unless in debug
+    /// mode.
+    pub fn holders(&self) -> Vec<LockHolder> {
+        self.debug.as_ref().map(|debug| debug.holders()).unwrap_or_default()
+    }
+
+    fn table(&self) -> Result<MutexGuard<'_, LockTable>, LockError> {
+        self.locks
+            .lock()
+            .map_err(|_| LockError::Poisoned("lock manager map poisoned".into()))
+    }
+
+    /// Evict every idle lock now, returning how many were evicted.
+    pub fn sweep(&self) -> Result<usize, LockError> {
+        let mut table = self.table()?;
+        let evicted = table.sweep();
+        table.next_sweep = (table.locks.len() * 2).max(self.sweep_floor);
+        Ok(evicted)
+    }
+
+    /// Table counters. A poisoned lock counts as busy.
+    pub fn stats(&self) -> Result<LockStats, LockError> {
+        let table = self.table()?;
+        let mut stats = LockStats {
+            live: table.locks.len(),
+            evicted: table.evicted,
+            sweeps: table.sweeps,
+            ..LockStats::default()
+        };
+        for lock in table.locks.values() {
+            match lock.state.lock() {
+                Ok(state) => {
+                    stats.busy += usize::from(!state.is_idle());
+                    stats.waiting += state.queue.len();
+                }
+                Err(_) => stats.busy += 1,
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Contention counters of `key`, or `None` if it never had a lock, or
+    /// had one that was evicted without ever being contended.
+    pub fn key_stats(&self, key: &str) -> Result<Option<KeyStats>, LockError> {
+        let table = self.table()?;
+        Ok(match table.locks.get(key) {
+            Some(lock) => lock.key_stats(),
+            None => table.retired.get(key).map(|counters| counters.key_stats(key, 0)),
+        })
+    }
+
+    /// The `n` keys that have spent the most time waiting, hottest first.
+    pub fn hottest(&self, n: usize) -> Result<Vec<KeyStats>, LockError> {
+        let table = self.table()?;
+        let retired = table
+            .retired
+            .iter()
+            .map(|(key, counters)| counters.key_stats(key, 0));
+        let mut stats: Vec<KeyStats> = table
+            .locks
+            .values()
+            .filter_map(|lock| lock.key_stats())
+            .filter(|stats| stats.contended > 0 || stats.queue_depth > 0)
+            .chain(retired)
+            .collect();
+        stats.sort_by(|a, b| {
+            b.total_wait
+                .cmp(&a.total_wait)
+                .then(b.queue_depth.cmp(&a.queue_depth))
+                .then_with(|| a.key.cmp(&b.key))
+        });
+        stats.truncate(n);
+        Ok(stats)
     }
 }
 
@@ -95,20 +671,34 @@ impl LockManager for InMemoryLockManager {
     type Lock = InMemoryLock;
 
     fn get_lock(&self, id: &str) -> Result<Arc<InMemoryLock>, LockError> {
-        let mut locks = self
-            .locks
-            .lock()
-            .map_err(|_| LockError::Poisoned("lock manager map poisoned".into()))?;
-        Ok(locks
-            .entry(id.to_string())
-            .or_insert_with(|| Arc::new(InMemoryLock::new()))
-            .clone())
+        let mut table = self.table()?;
+        if let Some(lock) = table.locks.get(id) {
+            return Ok(Arc::clone(lock));
+        }
+
+        if table.locks.len() >= table.next_sweep {
+            table.sweep();
+            table.next_sweep = (table.locks.len() * 2).max(self.sweep_floor);
+        }
+        let counters = table.retired.remove(id).unwrap_or_default();
+        let lock = Arc::new(InMemoryLock {
+            state: Mutex::new(LockState {
+                counters,
+                ..LockState::default()
+            }),
+            key: id.to_string(),
+            debug: self.debug.clone(),
+            ..InMemoryLock::new()
+        });
+        table.locks.insert(id.to_string(), Arc::clone(&lock));
+        Ok(lock)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::{OnceLock, Weak};
 
     // ========================================================================
     // InMemoryLock tests (migrated from old lock/mod.rs)
@@ -148,6 +738,51 @@ mod tests {
         lock.unlock().unwrap();
     }
 
+    #[test]
+    fn lock_timeout_fails_while_held() {
+        let lock = Arc::new(InMemoryLock::new());
+        lock.lock().unwrap();
+        let err = lock.lock_timeout(Duration::from_millis(20)).unwrap_err();
+        assert!(matches!(err, LockError::AcquireFailed(_)));
+
+        let holder = Arc::clone(&lock);
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            holder.unlock().unwrap();
+        });
+        lock.lock_timeout(Duration::from_secs(5)).unwrap();
+        release.join().unwrap();
+        lock.unlock().unwrap();
+    }
+
+    #[test]
+    fn shared_holders_exclude_writers_only() {
+        let lock = Arc::new(InMemoryLock::new());
+        lock.lock_shared().unwrap();
+        assert!(lock.try_lock_shared().unwrap());
+        assert!(!lock.try_lock().unwrap());
+
+        // A waiting writer holds back new readers
+        let writer_lock = Arc::clone(&lock);
+        let writer = thread::spawn(move || {
+            writer_lock.lock().unwrap();
+            writer_lock.unlock().unwrap();
+        });
+        while lock.state().unwrap().queue.is_empty() {
+            thread::yield_now();
+        }
+        assert!(!lock.try_lock_shared().unwrap());
+        assert!(lock.lock_shared_timeout(Duration::from_millis(10)).is_err());
+
+        lock.unlock_shared().unwrap();
+        lock.unlock_shared().unwrap();
+        writer.join().unwrap();
+        assert!(lock.try_lock_shared().unwrap());
+        lock.unlock_shared().unwrap();
+        assert!(lock.try_lock().unwrap());
+        lock.unlock().unwrap();
+    }
+
     // ========================================================================
     // InMemoryLockManager tests
     // ========================================================================
@@ -179,4 +814,229 @@ mod tests {
         assert!(lock.try_lock().unwrap());
         lock.unlock().unwrap();
     }
+
+    #[test]
+    fn debug_mode_reports_slow_waits_with_holders() {
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&reports);
+        let manager = InMemoryLockManager::new()
+            .with_debug_reporter(Duration::from_millis(10), move |report| {
+                sink.lock().unwrap().push(report.clone());
+            });
+
+        let held = manager.get_lock("a").unwrap();
+        thread::Builder::new()
+            .name("holder".into())
+            .spawn(move || held.lock().unwrap())
+            .unwrap()
+            .join()
+            .unwrap();
+        let holders = manager.holders();
+        assert_eq!(holders.len(), 1);
+        assert_eq!((holders[0].key.as_str(), holders[0].thread.as_str()), ("a", "holder"));
+
+        let lock = manager.get_lock("a").unwrap();
+        assert!(lock.lock_timeout(Duration::from_millis(50)).is_err());
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].key, "a");
+        assert_eq!(reports[0].holders.len(), 1);
+        assert_eq!(reports[0].holders[0].thread, "holder");
+        assert!(reports[0].to_string().contains("'a' by thread holder"));
+
+        lock.unlock().unwrap();
+        assert!(manager.holders().is_empty());
+    }
+
+    #[test]
+    fn the_debug_reporter_can_inspect_the_manager() {
+        let manager_cell: Arc<OnceLock<Weak<InMemoryLockManager>>> = Arc::new(OnceLock::new());
+        let waiting = Arc::new(Mutex::new(Vec::new()));
+        let (cell, sink) = (Arc::clone(&manager_cell), Arc::clone(&waiting));
+        let manager = Arc::new(InMemoryLockManager::new().with_debug_reporter(
+            Duration::from_millis(10),
+            move |_| {
+                let manager = cell.get().and_then(Weak::upgrade).unwrap();
+                sink.lock().unwrap().push(manager.stats().unwrap().waiting);
+            },
+        ));
+        manager_cell.set(Arc::downgrade(&manager)).unwrap();
+
+        let lock = manager.get_lock("a").unwrap();
+        lock.lock().unwrap();
+        let waiter = manager.get_lock("a").unwrap();
+        assert!(waiter.lock_timeout(Duration::from_millis(50)).is_err());
+        assert_eq!(*waiting.lock().unwrap(), vec![1]);
+        lock.unlock().unwrap();
+    }
+
+    #[test]
+    fn sweeps_evict_only_idle_unreferenced_locks() {
+        let manager = InMemoryLockManager::new().with_sweep_floor(usize::MAX);
+        let held = manager.get_lock("held").unwrap();
+        held.lock().unwrap();
+        let id_of_held = Arc::as_ptr(&held);
+        drop(held);
+        let referenced = manager.get_lock("referenced").unwrap();
+        manager.get_lock("idle").unwrap().lock().unwrap();
+        manager.get_lock("idle").unwrap().unlock().unwrap();
+
+        assert_eq!(manager.stats().unwrap().live, 3);
+        assert_eq!(manager.stats().unwrap().busy, 1);
+        assert_eq!(manager.sweep().unwrap(), 1);
+
+        // The held lock survived with no outside reference, so it is the same Arc
+        let held = manager.get_lock("held").unwrap();
+        assert_eq!(Arc::as_ptr(&held), id_of_held);
+        assert!(Arc::ptr_eq(&referenced, &manager.get_lock("referenced").unwrap()));
+        held.unlock().unwrap();
+        drop((held, referenced));
+
+        assert_eq!(manager.sweep().unwrap(), 2);
+        let stats = manager.stats().unwrap();
+        assert_eq!((stats.live, stats.evicted, stats.sweeps), (0, 3, 2));
+    }
+
+    #[test]
+    fn get_lock_sweeps_as_the_table_grows() {
+        let manager = InMemoryLockManager::new().with_sweep_floor(8);
+        let pinned = manager.get_lock("pinned").unwrap();
+        for n in 0..1000 {
+            let lock = manager.get_lock(&format!("key-{}", n)).unwrap();
+            lock.lock().unwrap();
+            lock.unlock().unwrap();
+        }
+
+        let stats = manager.stats().unwrap();
+        assert!(stats.live <= 16, "table grew to {}", stats.live);
+        assert!(stats.sweeps > 0);
+        assert!(Arc::ptr_eq(&pinned, &manager.get_lock("pinned").unwrap()));
+    }
+
+    #[test]
+    #[should_panic(expected = "sweep floor")]
+    fn a_zero_sweep_floor_is_rejected() {
+        let _ = InMemoryLockManager::new().with_sweep_floor(0);
+    }
+
+    #[test]
+    fn waiters_are_served_in_arrival_order() {
+        let lock = Arc::new(InMemoryLock::new());
+        let order = Arc::new(Mutex::new(Vec::new()));
+        lock.lock().unwrap();
+
+        let mut waiters = Vec::new();
+        for n in 0..5 {
+            let (waiter_lock, order) = (Arc::clone(&lock), Arc::clone(&order));
+            waiters.push(thread::spawn(move || {
+                waiter_lock.lock().unwrap();
+                order.lock().unwrap().push(n);
+                waiter_lock.unlock().unwrap();
+            }));
+            // Wait until this waiter is queued before starting the next
+            while lock_queue_len(&lock) < n + 1 {
+                thread::yield_now();
+            }
+        }
+        // A free lock with waiters queued is theirs, not try_lock's
+        lock.unlock().unwrap();
+        if lock.try_lock().unwrap() {
+            assert_eq!(order.lock().unwrap().len(), 5);
+            lock.unlock().unwrap();
+        }
+        for waiter in waiters {
+            waiter.join().unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+    }
+
+    fn lock_queue_len(lock: &InMemoryLock) -> usize {
+        lock.state().unwrap().queue.len()
+    }
+
+    #[test]
+    fn key_stats_report_queue_depth_and_waits() {
+        let manager = InMemoryLockManager::new();
+        let lock = manager.get_lock("hot").unwrap();
+        manager.get_lock("cold").unwrap().lock().unwrap();
+        lock.lock().unwrap();
+
+        let waiter_lock = Arc::clone(&lock);
+        let waiter = thread::spawn(move || {
+            waiter_lock.lock().unwrap();
+            waiter_lock.unlock().unwrap();
+        });
+        while lock_queue_len(&lock) == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(manager.key_stats("hot").unwrap().unwrap().queue_depth, 1);
+        assert_eq!(manager.stats().unwrap().waiting, 1);
+
+        thread::sleep(Duration::from_millis(20));
+        lock.unlock().unwrap();
+        waiter.join().unwrap();
+
+        let stats = manager.key_stats("hot").unwrap().unwrap();
+        assert_eq!((stats.queue_depth, stats.max_queue_depth), (0, 1));
+        assert_eq!((stats.acquisitions, stats.contended), (2, 1));
+        assert!(stats.max_wait >= Duration::from_millis(20));
+        assert_eq!(stats.mean_wait(), stats.total_wait);
+
+        let hottest = manager.hottest(10).unwrap();
+        assert_eq!(hottest.len(), 1);
+        assert_eq!(hottest[0].key, "hot");
+        assert!(manager.key_stats("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn contended_counters_survive_eviction() {
+        let manager = InMemoryLockManager::new().with_sweep_floor(usize::MAX);
+        let lock = manager.get_lock("hot").unwrap();
+        lock.lock().unwrap();
+        let waiter_lock = Arc::clone(&lock);
+        let waiter = thread::spawn(move || {
+            waiter_lock.lock().unwrap();
+            waiter_lock.unlock().unwrap();
+        });
+        while lock_queue_len(&lock) == 0 {
+            thread::yield_now();
+        }
+        lock.unlock().unwrap();
+        waiter.join().unwrap();
+        drop(lock);
+        manager.get_lock("cold").unwrap();
+
+        assert_eq!(manager.sweep().unwrap(), 2);
+        assert!(manager.key_stats("cold").unwrap().is_none());
+        let stats = manager.key_stats("hot").unwrap().unwrap();
+        assert_eq!((stats.acquisitions, stats.contended), (2, 1));
+        assert_eq!(manager.hottest(1).unwrap(), vec![stats]);
+
+        // A new lock for the key carries on counting
+        manager.get_lock("hot").unwrap().lock().unwrap();
+        let stats = manager.key_stats("hot").unwrap().unwrap();
+        assert_eq!((stats.acquisitions, stats.contended), (3, 1));
+        assert_eq!(manager.stats().unwrap().busy, 1);
+    }
+
+    #[test]
+    fn sweeps_keep_only_the_hottest_retired_keys() {
+        let manager = InMemoryLockManager::new()
+            .with_sweep_floor(usize::MAX)
+            .with_retired_limit(2);
+        for (key, wait) in [("a", 30), ("b", 10), ("c", 20)] {
+            let lock = manager.get_lock(key).unwrap();
+            lock.state.lock().unwrap().record_wait(Duration::from_millis(wait));
+        }
+
+        assert_eq!(manager.sweep().unwrap(), 3);
+        let hottest: Vec<String> = manager
+            .hottest(10)
+            .unwrap()
+            .into_iter()
+            .map(|stats| stats.key)
+            .collect();
+        assert_eq!(hottest, ["a", "c"]);
+        assert!(manager.key_stats("b").unwrap().is_none());
+    }
 }
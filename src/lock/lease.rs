@@ -0,0 +1,443 @@
+//! Lease locks - Expiring locks with increasing tokens.
+//!
+//! A lease is held for a TTL and must be renewed to be kept. Every lease
+//! gets a token greater than all earlier tokens of its store, so a holder
+//! whose lease expired (and was taken by someone else) can be told apart from
+//! the current one. `QueuedRepository` renews its leases before committing
+//! and rejects the write with `LockError::Expired` if one was lost. The
+//! renewal and the write are separate steps, and the repositories do not
+//! check tokens, so a holder stalled between them can still write late; a
+//! store that must rule this out has to compare [`Lock::fencing_token`]s
+//! itself.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Lock, LockError, LockManager};
+
+/// A lease on one key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    pub key: String,
+    pub owner: String,
+    /// The lease's token, greater than that of every earlier lease of the store.
+    pub token: u64,
+    pub expires_at: SystemTime,
+}
+
+/// Where leases are kept. Each operation must be atomic with respect to every
+/// other user of the store, including other processes for shared stores.
+pub trait LeaseStore: Send + Sync {
+    /// Take the lease on `key` for `ttl` if it is free or expired. `None` if
+    /// a live lease is held, whoever its owner.
+    fn acquire(&self, key: &str, owner: &str, ttl: Duration) -> Result<Option<Lease>, LockError>;
+
+    /// Extend the lease held with `token` to `ttl` from now. Fails with
+    /// `Expired` if the lease expired or was released.
+    fn renew(&self, key: &str, token: u64, ttl: Duration) -> Result<Lease, LockError>;
+
+    /// Release the lease held with `token`. Releasing a lost lease does nothing.
+    fn release(&self, key: &str, token: u64) -> Result<(), LockError>;
+
+    /// The live lease on `key`, if any.
+    fn current(&self, key: &str) -> Result<Option<Lease>, LockError>;
+}
+
+impl<S: LeaseStore> LeaseStore for Arc<S> {
+    fn acquire(&self, key: &str, owner: &str, ttl: Duration) -> Result<Option<Lease>, LockError> {
+        (**self).acquire(key, owner, ttl)
+    }
+
+    fn renew(&self, key: &str, token: u64, ttl: Duration) -> Result<Lease, LockError> {
+        (**self).renew(key, token, ttl)
+    }
+
+    fn release(&self, key: &str, token: u64) -> Result<(), LockError> {
+        (**self).release(key, token)
+    }
+
+    fn current(&self, key: &str) -> Result<Option<Lease>, LockError> {
+        (**self).current(key)
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LeaseRecord {
+    owner: String,
+    token: u64,
+    expires_at_ms: u64,
+}
+
+impl LeaseRecord {
+    fn lease(&self, key: &str) -> Lease {
+        Lease {
+            key: key.to_string(),
+            owner: self.owner.clone(),
+            token: self.token,
+            expires_at: UNIX_EPOCH + Duration::from_millis(self.expires_at_ms),
+        }
+    }
+}
+
+/// The lease state shared by the stores: the leases by key, and the last
+/// token handed out.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(super) struct LeaseTable {
+    last_token: u64,
+    leases: HashMap<String, LeaseRecord>,
+}
+
+impl LeaseTable {
+    fn live(&self, key: &str, now: u64) -> Option<&LeaseRecord> {
+        self.leases.get(key).filter(|record| record.expires_at_ms > now)
+    }
+
+    pub(super) fn acquire(&mut self, key: &str, owner: &str, ttl: Duration) -> Option<Lease> {
+        let now = now_ms();
+        if self.live(key, now).is_some() {
+            return None;
+        }
+        self.last_token += 1;
+        let record = LeaseRecord {
+            owner: owner.to_string(),
+            token: self.last_token,
+            expires_at_ms: now + ttl.as_millis() as u64,
+        };
+        let lease = record.lease(key);
+        self.leases.insert(key.to_string(), record);
+        Some(lease)
+    }
+
+    pub(super) fn renew(
+        &mut self,
+        key: &str,
+        token: u64,
+        ttl: Duration,
+    ) -> Result<Lease, LockError> {
+        let now = now_ms();
+        match self.leases.get_mut(key) {
+            Some(record) if record.token == token && record.expires_at_ms > now => {
+                record.expires_at_ms = now + ttl.as_millis() as u64;
+                Ok(record.lease(key))
+            }
+            _ => Err(LockError::Expired(format!("lease {} on '{}' was lost", token, key))),
+        }
+    }
+
+    /// Whether the lease held with `token` was there to release.
+    pub(super) fn release(&mut self, key: &str, token: u64) -> bool {
+        let held = self.leases.get(key).is_some_and(|record| record.token == token);
+        if held {
+            self.leases.remove(key);
+        }
+        held
+    }
+
+    pub(super) fn current(&self, key: &str) -> Option<Lease> {
+        self.live(key, now_ms()).map(|record| record.lease(key))
+    }
+}
+
+/// An in-process [`LeaseStore`], shared between managers with an `Arc`.
+#[derive(Default)]
+pub struct InMemoryLeaseStore {
+    table: Mutex<LeaseTable>,
+}
+
+impl InMemoryLeaseStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn table(&self) -> Result<std::sync::MutexGuard<'_, LeaseTable>, LockError> {
+        self.table
+            .lock()
+            .map_err(|_| LockError::Poisoned("lease table poisoned".into()))
+    }
+}
+
+impl LeaseStore for InMemoryLeaseStore {
+    fn acquire(&self, key: &str, owner: &str, ttl: Duration) -> Result<Option<Lease>, LockError> {
+        Ok(self.table()?.acquire(key, owner, ttl))
+    }
+
+    fn renew(&self, key: &str, token: u64, ttl: Duration) -> Result<Lease, LockError> {
+        self.table()?.renew(key, token, ttl)
+    }
+
+    fn release(&self, key: &str, token: u64) -> Result<(), LockError> {
+        self.table()?.release(key, token);
+        Ok(())
+    }
+
+    fn current(&self, key: &str) -> Result<Option<Lease>, LockError> {
+        Ok(self.table()?.current(key))
+    }
+}
+
+/// A lock on one key, held as a lease of a [`LeaseLockManager`]'s store.
+///
+/// The handle is shared by the manager's threads, and each thread that takes
+/// the lock keeps its own lease token: a thread whose lease expired and was
+/// taken by another thread cannot renew or release the new lease. A thread
+/// that holds no token, such as one unlocking for another, acts on the
+/// newest lease, the only one that can still be live.
+pub struct LeaseLock<S> {
+    key: String,
+    owner: String,
+    ttl: Duration,
+    store: Arc<S>,
+    tokens: Mutex<HashMap<ThreadId, u64>>,
+}
+
+impl<S: LeaseStore> LeaseLock<S> {
+    fn tokens(&self) -> Result<std::sync::MutexGuard<'_, HashMap<ThreadId, u64>>, LockError> {
+        self.tokens
+            .lock()
+            .map_err(|_| LockError::Poisoned("lease token poisoned".into()))
+    }
+
+    /// The thread holding the lease this thread acts on, and its token.
+    fn holder(tokens: &HashMap<ThreadId, u64>) -> Option<(ThreadId, u64)> {
+        let thread = thread::current().id();
+        match tokens.get(&thread) {
+            Some(token) => Some((thread, *token)),
+            None => tokens
+                .iter()
+                .max_by_key(|(_, token)| **token)
+                .map(|(thread, token)| (*thread, *token)),
+        }
+    }
+
+    /// Extend the held lease by the TTL. Fails with `Expired` if the lease
+    /// was lost, or if the lock is not held.
+    pub fn renew(&self) -> Result<Lease, LockError> {
+        match Self::holder(&*self.tokens()?) {
+            Some((_, token)) => self.store.renew(&self.key, token, self.ttl),
+            None => Err(LockError::Expired(format!("'{}' is not held", self.key))),
+        }
+    }
+}
+
+impl<S: LeaseStore> Lock for LeaseLock<S> {
+    fn lock(&self) -> Result<(), LockError> {
+        let poll = (self.ttl / 10).clamp(Duration::from_millis(1), Duration::from_millis(50));
+        while !self.try_lock()? {
+            thread::sleep(poll);
+        }
+        Ok(())
+    }
+
+    fn try_lock(&self) -> Result<bool, LockError> {
+        let mut tokens = self.tokens()?;
+        match self.store.acquire(&self.key, &self.owner, self.ttl)? {
+            Some(lease) => {
+                tokens.insert(thread::current().id(), lease.token);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn unlock(&self) -> Result<(), LockError> {
+        let mut tokens = self.tokens()?;
+        match Self::holder(&tokens) {
+            Some((thread, token)) => {
+                tokens.remove(&thread);
+                self.store.release(&self.key, token)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Renews the lease, so the write that follows has a full TTL to finish.
+    /// Fails with `Expired` if this handle holds no lease.
+    fn ensure_held(&self) -> Result<(), LockError> {
+        self.renew().map(|_| ())
+    }
+
+    fn fencing_token(&self) -> Option<u64> {
+        let tokens = self.tokens.lock().ok()?;
+        Self::holder(&tokens).map(|(_, token)| token)
+    }
+}
+
+/// A `LockManager` handing out leases from a [`LeaseStore`].
+///
+/// Locks expire `ttl` after they are taken or last renewed (with
+/// [`LeaseLock::renew`]), so a crashed holder cannot block a key for good.
+/// Managers sharing a store contend for the same keys; with a
+/// [`FileLeaseStore`](super::FileLeaseStore), so do processes on one host.
+///
+/// ```ignore
+/// let store = Arc::new(InMemoryLeaseStore::new());
+/// let repo = HashMapRepository::new()
+///     .queued_with(LeaseLockManager::new(store, Duration::from_secs(30)))
+///     .aggregate::<Todo>();
+/// ```
+pub struct LeaseLockManager<S: LeaseStore = InMemoryLeaseStore> {
+    store: Arc<S>,
+    owner: String,
+    ttl: Duration,
+    locks: Mutex<HashMap<String, Arc<LeaseLock<S>>>>,
+}
+
+impl<S: LeaseStore> LeaseLockManager<S> {
+    /// A manager with a generated owner name, unique within the process.
+    pub fn new(store: S, ttl: Duration) -> Self {
+        static NEXT_OWNER: AtomicU64 = AtomicU64::new(1);
+        LeaseLockManager {
+            store: Arc::new(store),
+            owner: format!(
+                "{}-{}",
+                std::process::id(),
+                NEXT_OWNER.fetch_add(1, Ordering::Relaxed)
+            ),
+            ttl,
+            locks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Name the leases' owner, e.g. after the host and worker.
+    pub fn with_owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = owner.into();
+        self
+    }
+
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+}
+
+impl<S: LeaseStore> LockManager for LeaseLockManager<S> {
+    type Lock = LeaseLock<S>;
+
+    fn get_lock(&self, id: &str) -> Result<Arc<LeaseLock<S>>, LockError> {
+        let mut locks = self
+            .locks
+            .lock()
+            .map_err(|_| LockError::Poisoned("lock manager map poisoned".into()))?;
+        Ok(locks
+            .entry(id.to_string())
+            .or_insert_with(|| {
+                Arc::new(LeaseLock {
+                    key: id.to_string(),
+                    owner: self.owner.clone(),
+                    ttl: self.ttl,
+                    store: Arc::clone(&self.store),
+                    tokens: Mutex::new(HashMap::new()),
+                })
+            })
+            .clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    const TTL: Duration = Duration::from_millis(40);
+
+    #[test]
+    fn an_expired_lease_passes_to_the_next_holder_with_a_greater_token() {
+        let store = Arc::new(InMemoryLeaseStore::new());
+        let a = LeaseLockManager::new(Arc::clone(&store), TTL).with_owner("a");
+        let b = LeaseLockManager::new(Arc::clone(&store), TTL).with_owner("b");
+        let (lock_a, lock_b) = (a.get_lock("k").unwrap(), b.get_lock("k").unwrap());
+        assert!(matches!(lock_a.ensure_held(), Err(LockError::Expired(_))));
+
+        assert!(lock_a.try_lock().unwrap());
+        assert!(!lock_b.try_lock().unwrap());
+        assert_eq!(store.current("k").unwrap().unwrap().owner, "a");
+        let first = lock_a.fencing_token().unwrap();
+        lock_a.renew().unwrap();
+
+        thread::sleep(TTL + Duration::from_millis(20));
+        assert!(store.current("k").unwrap().is_none());
+        lock_b.lock().unwrap();
+        assert!(lock_b.fencing_token().unwrap() > first);
+
+        assert!(matches!(lock_a.ensure_held(), Err(LockError::Expired(_))));
+        assert!(matches!(lock_a.renew(), Err(LockError::Expired(_))));
+        // Releasing the lost lease leaves the new holder's alone
+        lock_a.unlock().unwrap();
+        lock_b.ensure_held().unwrap();
+        lock_b.unlock().unwrap();
+        assert!(lock_a.try_lock().unwrap());
+        lock_a.unlock().unwrap();
+    }
+
+    #[test]
+    fn locks_wait_for_release_and_time_out() {
+        let store = Arc::new(InMemoryLeaseStore::new());
+        let manager = LeaseLockManager::new(store, Duration::from_secs(5));
+        let lock = manager.get_lock("k").unwrap();
+        assert!(Arc::ptr_eq(&lock, &manager.get_lock("k").unwrap()));
+
+        lock.lock().unwrap();
+        let err = lock.lock_timeout(Duration::from_millis(20)).unwrap_err();
+        assert!(matches!(err, LockError::AcquireFailed(_)));
+
+        let holder = Arc::clone(&lock);
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            holder.unlock().unwrap();
+        });
+        lock.lock().unwrap();
+        release.join().unwrap();
+        lock.unlock().unwrap();
+    }
+
+    #[test]
+    fn threads_sharing_a_handle_keep_their_own_leases() {
+        let manager = Arc::new(LeaseLockManager::new(InMemoryLeaseStore::new(), TTL));
+        let lock = manager.get_lock("k").unwrap();
+        lock.lock().unwrap();
+        let first = lock.fencing_token().unwrap();
+        thread::sleep(TTL + Duration::from_millis(20));
+
+        // Another thread of the same manager takes the expired lease
+        let (taken_tx, taken_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel::<()>();
+        let other = Arc::clone(&manager);
+        let holder = thread::spawn(move || {
+            let lock = other.get_lock("k").unwrap();
+            lock.lock().unwrap();
+            taken_tx.send(lock.fencing_token().unwrap()).unwrap();
+            done_rx.recv().unwrap();
+            lock.ensure_held().unwrap();
+            lock.unlock().unwrap();
+        });
+        let second = taken_rx.recv().unwrap();
+        assert!(second > first);
+
+        assert_eq!(lock.fencing_token(), Some(first));
+        assert!(matches!(lock.ensure_held(), Err(LockError::Expired(_))));
+        lock.unlock().unwrap();
+        assert_eq!(manager.store().current("k").unwrap().unwrap().token, second);
+        done_tx.send(()).unwrap();
+        holder.join().unwrap();
+        assert!(manager.store().current("k").unwrap().is_none());
+    }
+}
@@ -1,8 +1,13 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
 use super::LockError;
 
 /// Trait for a single lock instance.
 ///
 /// Implementations provide blocking lock, non-blocking try-lock, and unlock.
+/// `lock_timeout` bounds the wait of a blocking lock. Locks may also be taken
+/// in shared mode (reader-writer semantics) with the `*_shared` methods.
 /// In-memory locks use `Mutex` + `Condvar`; distributed locks might use
 /// Redis, Postgres advisory locks, etcd leases, etc.
 pub trait Lock: Send + Sync {
@@ -13,6 +18,89 @@ pub trait Lock: Send + Sync {
     /// Returns `Ok(true)` if acquired, `Ok(false)` if already held.
     fn try_lock(&self) -> Result<bool, LockError>;
 
+    /// Acquire the lock, waiting at most `timeout`. Fails with
+    /// `AcquireFailed` if the lock is still held when the timeout elapses.
+    ///
+    /// The default implementation polls `try_lock`; implementations that can
+    /// wait on a deadline should override it.
+    fn lock_timeout(&self, timeout: Duration) -> Result<(), LockError> {
+        let deadline = Instant::now() + timeout;
+        let mut backoff = Duration::from_millis(1);
+        loop {
+            if self.try_lock()? {
+                return Ok(());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(LockError::AcquireFailed(format!("timed out after {:?}", timeout)));
+            }
+            thread::sleep(backoff.min(deadline - now));
+            backoff = (backoff * 2).min(Duration::from_millis(50));
+        }
+    }
+
     /// Release the lock.
     fn unlock(&self) -> Result<(), LockError>;
+
+    /// Acquire the lock in shared mode, blocking while it is held (or waited
+    /// for) exclusively. Shared holders do not exclude each other, and `lock`
+    /// waits for all of them to release.
+    ///
+    /// The default implementations of the shared methods fall back to the
+    /// exclusive ones, which is correct but lets no readers proceed together.
+    fn lock_shared(&self) -> Result<(), LockError> {
+        self.lock()
+    }
+
+    /// Try to acquire the lock in shared mode without blocking.
+    fn try_lock_shared(&self) -> Result<bool, LockError> {
+        self.try_lock()
+    }
+
+    /// Acquire the lock in shared mode, waiting at most `timeout`.
+    fn lock_shared_timeout(&self, timeout: Duration) -> Result<(), LockError> {
+        self.lock_timeout(timeout)
+    }
+
+    /// Release one shared holding.
+    fn unlock_shared(&self) -> Result<(), LockError> {
+        self.unlock()
+    }
+
+    /// Confirm, before a write, that a lock taken by this handle is still
+    /// held. Locks that can be lost, like expiring leases, fail with
+    /// `Expired`; the default always succeeds.
+    fn ensure_held(&self) -> Result<(), LockError> {
+        Ok(())
+    }
+
+    /// The token of the current holding, for locks that issue increasing
+    /// tokens. Nothing in this crate checks it; a store can use it to fence
+    /// off writes from earlier holders.
+    fn fencing_token(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Take `lock` exclusively or shared, waiting at most `timeout` if given.
+pub(crate) fn acquire<K: Lock + ?Sized>(
+    lock: &K,
+    shared: bool,
+    timeout: Option<Duration>,
+) -> Result<(), LockError> {
+    match (shared, timeout) {
+        (false, None) => lock.lock(),
+        (false, Some(timeout)) => lock.lock_timeout(timeout),
+        (true, None) => lock.lock_shared(),
+        (true, Some(timeout)) => lock.lock_shared_timeout(timeout),
+    }
+}
+
+/// Release a holding taken with [`acquire`].
+pub(crate) fn release<K: Lock + ?Sized>(lock: &K, shared: bool) -> Result<(), LockError> {
+    if shared {
+        lock.unlock_shared()
+    } else {
+        lock.unlock()
+    }
 }
@@ -14,7 +14,7 @@
 //!                            ▼
 //! ┌─────────────────────────────────────────────────────────────┐
 //! │                     Lock Trait                               │
-//! │  lock() / try_lock() / unlock()                              │
+//! │  lock() / lock_timeout() / try_lock() / unlock()             │
 //! └─────────────────────────────────────────────────────────────┘
 //!          │                  │                     │
 //!          ▼                  ▼                     ▼
@@ -23,13 +23,27 @@
 //! │ (included)  │    │ (external)  │    │    (external)       │
 //! └─────────────┘    └─────────────┘    └─────────────────────┘
 //! ```
+//!
+//! `LeaseLockManager` hands out expiring leases with increasing tokens from a
+//! `LeaseStore`: `InMemoryLeaseStore` within a process, `FileLeaseStore`
+//! across processes on one host. `FileLockManager` uses OS advisory file
+//! locks, so pessimistic locking also works across processes on one host.
 
 mod error;
+mod file;
+mod file_lease;
 mod in_memory;
+mod lease;
 mod lock;
 mod lock_manager;
 
 pub use error::LockError;
-pub use in_memory::{InMemoryLock, InMemoryLockManager};
+pub use file::{FileLock, FileLockManager};
+pub use file_lease::FileLeaseStore;
+pub use in_memory::{
+    InMemoryLock, InMemoryLockManager, KeyStats, LockHolder, LockStats, LockWaitReport,
+};
+pub use lease::{InMemoryLeaseStore, Lease, LeaseLock, LeaseLockManager, LeaseStore};
 pub use lock::Lock;
+pub(crate) use lock::{acquire, release};
 pub use lock_manager::LockManager;
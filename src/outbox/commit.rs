@@ -16,8 +16,7 @@ where
 {
     /// Commit the aggregate and outbox message together.
     pub fn commit(self, aggregate: &mut A) -> Result<(), RepositoryError> {
-        let mut entities = [aggregate.entity_mut(), self.event.entity_mut()];
-        self.repo.repo().commit(&mut entities)
+        self.repo.commit_with(aggregate, &mut [self.event.entity_mut()])
     }
 }
 
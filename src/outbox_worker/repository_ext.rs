@@ -3,8 +3,11 @@ use std::time::Duration;
 use crate::aggregate::hydrate;
 use crate::entity::Entity;
 use crate::repository::RepositoryError;
+use crate::file_repo::FileRepository;
 use crate::hashmap_repo::HashMapRepository;
 use crate::outbox::{OutboxMessage, OutboxMessageStatus};
+#[cfg(feature = "sqlite")]
+use crate::sqlite_repo::{self, SqliteRepository};
 
 /// Extension trait for repositories that expose outbox message operations.
 pub trait OutboxRepositoryExt: Send + Sync {
@@ -48,7 +51,7 @@ impl OutboxRepositoryExt for HashMapRepository {
             .map_err(|_| RepositoryError::LockPoisoned("read"))?;
 
         let mut messages = Vec::new();
-        for (id, events) in storage.iter() {
+        for (id, events) in storage.streams().iter() {
             if !id.starts_with(OutboxMessage::ID_PREFIX) {
                 continue;
             }
@@ -77,7 +80,10 @@ impl OutboxRepositoryExt for HashMapRepository {
             .map_err(|_| RepositoryError::LockPoisoned("write"))?;
 
         let mut claimed = Vec::new();
-        for (id, events) in storage.iter_mut() {
+        for (id, events) in storage.streams().iter() {
+            if claimed.len() >= max {
+                break;
+            }
             if !id.starts_with(OutboxMessage::ID_PREFIX) {
                 continue;
             }
@@ -88,39 +94,34 @@ impl OutboxRepositoryExt for HashMapRepository {
 
             if message.is_pending() {
                 message.claim_for(worker_id, lease);
-                *events = message.entity.events().to_vec();
-                message.entity.mark_committed();
                 claimed.push(message);
             }
+        }
 
-            if claimed.len() >= max {
-                break;
-            }
+        for message in &mut claimed {
+            storage.append(&mut message.entity);
+            message.entity.mark_committed();
         }
 
         Ok(claimed)
     }
 
     fn complete_outbox_message(&self, message_id: &str) -> Result<(), RepositoryError> {
-        let normalized_id = if message_id.starts_with(OutboxMessage::ID_PREFIX) {
-            message_id.to_string()
-        } else {
-            format!("{}{}", OutboxMessage::ID_PREFIX, message_id)
-        };
+        let normalized_id = normalize_outbox_id(message_id);
 
         let mut storage = self
             .event_store()
             .write()
             .map_err(|_| RepositoryError::LockPoisoned("write"))?;
 
-        if let Some(events) = storage.get_mut(&normalized_id) {
+        if let Some(events) = storage.stream(&normalized_id) {
             let mut entity = Entity::with_id(normalized_id);
             entity.load_from_history(events.clone());
             let mut message = hydrate::<OutboxMessage>(entity)?;
 
             if message.is_in_flight() {
                 message.complete();
-                *events = message.entity.events().to_vec();
+                storage.append(&mut message.entity);
                 message.entity.mark_committed();
             }
         }
@@ -129,25 +130,21 @@ impl OutboxRepositoryExt for HashMapRepository {
     }
 
     fn release_outbox_message(&self, message_id: &str, error: &str) -> Result<(), RepositoryError> {
-        let normalized_id = if message_id.starts_with(OutboxMessage::ID_PREFIX) {
-            message_id.to_string()
-        } else {
-            format!("{}{}", OutboxMessage::ID_PREFIX, message_id)
-        };
+        let normalized_id = normalize_outbox_id(message_id);
 
         let mut storage = self
             .event_store()
             .write()
             .map_err(|_| RepositoryError::LockPoisoned("write"))?;
 
-        if let Some(events) = storage.get_mut(&normalized_id) {
+        if let Some(events) = storage.stream(&normalized_id) {
             let mut entity = Entity::with_id(normalized_id);
             entity.load_from_history(events.clone());
             let mut message = hydrate::<OutboxMessage>(entity)?;
 
             if message.is_in_flight() {
                 message.release(error.to_string());
-                *events = message.entity.events().to_vec();
+                storage.append(&mut message.entity);
                 message.entity.mark_committed();
             }
         }
@@ -156,27 +153,270 @@ impl OutboxRepositoryExt for HashMapRepository {
     }
 
     fn fail_outbox_message(&self, message_id: &str, error: &str) -> Result<(), RepositoryError> {
-        let normalized_id = if message_id.starts_with(OutboxMessage::ID_PREFIX) {
-            message_id.to_string()
-        } else {
-            format!("{}{}", OutboxMessage::ID_PREFIX, message_id)
-        };
+        let normalized_id = normalize_outbox_id(message_id);
 
         let mut storage = self
             .event_store()
             .write()
             .map_err(|_| RepositoryError::LockPoisoned("write"))?;
 
-        if let Some(events) = storage.get_mut(&normalized_id) {
+        if let Some(events) = storage.stream(&normalized_id) {
             let mut entity = Entity::with_id(normalized_id);
             entity.load_from_history(events.clone());
             let mut message = hydrate::<OutboxMessage>(entity)?;
 
             message.fail(error.to_string());
-            *events = message.entity.events().to_vec();
+            storage.append(&mut message.entity);
             message.entity.mark_committed();
         }
 
         Ok(())
     }
 }
+
+fn normalize_outbox_id(message_id: &str) -> String {
+    if message_id.starts_with(OutboxMessage::ID_PREFIX) {
+        message_id.to_string()
+    } else {
+        format!("{}{}", OutboxMessage::ID_PREFIX, message_id)
+    }
+}
+
+/// State changes are appended as new events on the message stream, so the
+/// full outbox history stays on disk. The store's write lock is held for each
+/// operation, so concurrent workers cannot claim the same message.
+impl OutboxRepositoryExt for FileRepository {
+    fn outbox_messages_by_status(
+        &self,
+        status: OutboxMessageStatus,
+    ) -> Result<Vec<OutboxMessage>, RepositoryError> {
+        let store = self
+            .store()
+            .read()
+            .map_err(|_| RepositoryError::LockPoisoned("read"))?;
+
+        let mut messages = Vec::new();
+        for (id, events) in store.read_streams()? {
+            if !id.starts_with(OutboxMessage::ID_PREFIX) {
+                continue;
+            }
+
+            let mut entity = Entity::with_id(id);
+            entity.load_from_history(events);
+            let message = hydrate::<OutboxMessage>(entity)?;
+
+            if message.status == status {
+                messages.push(message);
+            }
+        }
+
+        Ok(messages)
+    }
+
+    fn claim_outbox_messages(
+        &self,
+        worker_id: &str,
+        max: usize,
+        lease: Duration,
+    ) -> Result<Vec<OutboxMessage>, RepositoryError> {
+        let mut store = self
+            .store()
+            .write()
+            .map_err(|_| RepositoryError::LockPoisoned("write"))?;
+
+        let mut claimed = Vec::new();
+        for (id, events) in store.read_streams()? {
+            if claimed.len() >= max {
+                break;
+            }
+            if !id.starts_with(OutboxMessage::ID_PREFIX) {
+                continue;
+            }
+
+            let mut entity = Entity::with_id(id);
+            entity.load_from_history(events);
+            let mut message = hydrate::<OutboxMessage>(entity)?;
+
+            if message.is_pending() {
+                message.claim_for(worker_id, lease);
+                claimed.push(message);
+            }
+        }
+
+        // All claims are written as one record.
+        store.append(claimed.iter_mut().map(|m| &mut m.entity).collect())?;
+        Ok(claimed)
+    }
+
+    fn complete_outbox_message(&self, message_id: &str) -> Result<(), RepositoryError> {
+        let id = normalize_outbox_id(message_id);
+        let mut store = self
+            .store()
+            .write()
+            .map_err(|_| RepositoryError::LockPoisoned("write"))?;
+
+        if let Some(events) = store.read_stream(&id)? {
+            let mut entity = Entity::with_id(id);
+            entity.load_from_history(events);
+            let mut message = hydrate::<OutboxMessage>(entity)?;
+
+            if message.is_in_flight() {
+                message.complete();
+                store.append(vec![&mut message.entity])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn release_outbox_message(&self, message_id: &str, error: &str) -> Result<(), RepositoryError> {
+        let id = normalize_outbox_id(message_id);
+        let mut store = self
+            .store()
+            .write()
+            .map_err(|_| RepositoryError::LockPoisoned("write"))?;
+
+        if let Some(events) = store.read_stream(&id)? {
+            let mut entity = Entity::with_id(id);
+            entity.load_from_history(events);
+            let mut message = hydrate::<OutboxMessage>(entity)?;
+
+            if message.is_in_flight() {
+                message.release(error.to_string());
+                store.append(vec![&mut message.entity])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fail_outbox_message(&self, message_id: &str, error: &str) -> Result<(), RepositoryError> {
+        let id = normalize_outbox_id(message_id);
+        let mut store = self
+            .store()
+            .write()
+            .map_err(|_| RepositoryError::LockPoisoned("write"))?;
+
+        if let Some(events) = store.read_stream(&id)? {
+            let mut entity = Entity::with_id(id);
+            entity.load_from_history(events);
+            let mut message = hydrate::<OutboxMessage>(entity)?;
+
+            message.fail(error.to_string());
+            store.append(vec![&mut message.entity])?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteRepository {
+    /// Load one outbox message, apply `change` and append the resulting
+    /// events, all in one transaction.
+    fn update_outbox_message<F>(&self, message_id: &str, change: F) -> Result<(), RepositoryError>
+    where
+        F: FnOnce(&mut OutboxMessage),
+    {
+        let id = normalize_outbox_id(message_id);
+        let mut conn = self.connection()?;
+        let tx = conn
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+            .map_err(sqlite_repo::storage_error)?;
+
+        if let Some(events) = sqlite_repo::load_stream(&tx, &id)? {
+            let mut entity = Entity::with_id(id);
+            entity.load_from_history(events);
+            let mut message = hydrate::<OutboxMessage>(entity)?;
+
+            change(&mut message);
+            sqlite_repo::append_entities(&tx, &mut [&mut message.entity])?;
+        }
+
+        tx.commit().map_err(sqlite_repo::storage_error)
+    }
+}
+
+/// Each operation runs in its own immediate transaction, so concurrent
+/// workers (even in other processes) cannot claim the same message.
+#[cfg(feature = "sqlite")]
+impl OutboxRepositoryExt for SqliteRepository {
+    fn outbox_messages_by_status(
+        &self,
+        status: OutboxMessageStatus,
+    ) -> Result<Vec<OutboxMessage>, RepositoryError> {
+        let conn = self.connection()?;
+
+        let mut messages = Vec::new();
+        for (id, events) in sqlite_repo::load_streams(&conn, OutboxMessage::ID_PREFIX)? {
+            let mut entity = Entity::with_id(id);
+            entity.load_from_history(events);
+            let message = hydrate::<OutboxMessage>(entity)?;
+
+            if message.status == status {
+                messages.push(message);
+            }
+        }
+
+        Ok(messages)
+    }
+
+    fn claim_outbox_messages(
+        &self,
+        worker_id: &str,
+        max: usize,
+        lease: Duration,
+    ) -> Result<Vec<OutboxMessage>, RepositoryError> {
+        let mut conn = self.connection()?;
+        let tx = conn
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+            .map_err(sqlite_repo::storage_error)?;
+
+        let mut claimed = Vec::new();
+        for (id, events) in sqlite_repo::load_streams(&tx, OutboxMessage::ID_PREFIX)? {
+            if claimed.len() >= max {
+                break;
+            }
+
+            let mut entity = Entity::with_id(id);
+            entity.load_from_history(events);
+            let mut message = hydrate::<OutboxMessage>(entity)?;
+
+            if message.is_pending() {
+                message.claim_for(worker_id, lease);
+                claimed.push(message);
+            }
+        }
+
+        let mut entities: Vec<&mut Entity> = claimed.iter_mut().map(|m| &mut m.entity).collect();
+        sqlite_repo::append_entities(&tx, &mut entities)?;
+        tx.commit().map_err(sqlite_repo::storage_error)?;
+
+        for message in &mut claimed {
+            message.entity.mark_committed();
+        }
+        Ok(claimed)
+    }
+
+    fn complete_outbox_message(&self, message_id: &str) -> Result<(), RepositoryError> {
+        self.update_outbox_message(message_id, |message| {
+            if message.is_in_flight() {
+                message.complete();
+            }
+        })
+    }
+
+    fn release_outbox_message(&self, message_id: &str, error: &str) -> Result<(), RepositoryError> {
+        self.update_outbox_message(message_id, |message| {
+            if message.is_in_flight() {
+                message.release(error.to_string());
+            }
+        })
+    }
+
+    fn fail_outbox_message(&self, message_id: &str, error: &str) -> Result<(), RepositoryError> {
+        self.update_outbox_message(message_id, |message| {
+            message.fail(error.to_string());
+        })
+    }
+}
@@ -0,0 +1,138 @@
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::read_model::{ReadModel, ReadModelError, ReadModelStore};
+
+use super::projection::ProjectionError;
+
+/// Read model access for a projection while it applies one batch of events.
+///
+/// Writes are staged, not written: the runner commits everything staged for
+/// the batch together with the projection's checkpoint. Reads see staged
+/// writes first, so several events in one batch can build on each other.
+///
+/// During a [`ProjectionRebuild`](super::ProjectionRebuild) the same calls
+/// are redirected to shadow collections, so projection code does not change.
+pub struct ProjectionContext<'a, S> {
+    store: &'a S,
+    shadow: Option<&'a ShadowCollections>,
+    batch: HashMap<String, Vec<u8>>,
+    event: HashMap<String, Vec<u8>>,
+}
+
+impl<'a, S: ReadModelStore> ProjectionContext<'a, S> {
+    pub(crate) fn new(store: &'a S) -> Self {
+        Self {
+            store,
+            shadow: None,
+            batch: HashMap::new(),
+            event: HashMap::new(),
+        }
+    }
+
+    /// A context that reads and writes the shadow copies of each collection.
+    pub(crate) fn shadowed(store: &'a S, shadow: &'a ShadowCollections) -> Self {
+        Self {
+            shadow: Some(shadow),
+            ..Self::new(store)
+        }
+    }
+
+    /// Load a read model by ID, including writes staged earlier in this batch.
+    pub fn get<M: ReadModel>(&self, id: &str) -> Result<Option<M>, ProjectionError> {
+        let key = self.model_key::<M>(id)?;
+        if let Some(bytes) = self.event.get(&key).or_else(|| self.batch.get(&key)) {
+            return decode(bytes).map(Some);
+        }
+        if self.shadow.is_some() {
+            return self.store.get_raw(&key)?.map(|bytes| decode(&bytes)).transpose();
+        }
+        Ok(self.store.get_model::<M>(id)?.map(|v| v.data))
+    }
+
+    /// Stage an insert or replace of a read model.
+    pub fn upsert<M: ReadModel>(&mut self, model: &M) -> Result<(), ProjectionError> {
+        let bytes = serde_json::to_vec(model).map_err(|e| ProjectionError::Failed(e.to_string()))?;
+        let key = self.model_key::<M>(model.id())?;
+        self.event.insert(key, bytes);
+        Ok(())
+    }
+
+    /// Load a read model (or create it with `init`), modify it, and stage the result.
+    pub fn update<M, I, F>(&mut self, id: &str, init: I, f: F) -> Result<(), ProjectionError>
+    where
+        M: ReadModel,
+        I: FnOnce() -> M,
+        F: FnOnce(&mut M),
+    {
+        let mut model = self.get::<M>(id)?.unwrap_or_else(init);
+        f(&mut model);
+        self.upsert(&model)
+    }
+
+    /// Keep the writes staged by the current event.
+    pub(crate) fn accept_event(&mut self) {
+        self.batch.extend(self.event.drain());
+    }
+
+    /// Drop the writes staged by the current event.
+    pub(crate) fn reject_event(&mut self) {
+        self.event.clear();
+    }
+
+    pub(crate) fn into_writes(self) -> Vec<(String, Vec<u8>)> {
+        self.batch.into_iter().collect()
+    }
+
+    fn model_key<M: ReadModel>(&self, id: &str) -> Result<String, ProjectionError> {
+        match self.shadow {
+            Some(shadow) => {
+                let collection = shadow.enter(self.store, M::COLLECTION)?;
+                Ok(format!("{}:{}", collection, id))
+            }
+            None => Ok(format!("{}:{}", M::COLLECTION, id)),
+        }
+    }
+}
+
+fn decode<M: ReadModel>(bytes: &[u8]) -> Result<M, ProjectionError> {
+    serde_json::from_slice(bytes).map_err(|e| ProjectionError::Failed(e.to_string()))
+}
+
+/// The live collections a rebuild has written to so far.
+///
+/// A shadow collection is cleared the first time it is used, so leftovers
+/// from an abandoned rebuild never leak into the new one.
+#[derive(Debug, Default)]
+pub(crate) struct ShadowCollections {
+    touched: RefCell<BTreeSet<String>>,
+}
+
+impl ShadowCollections {
+    pub(crate) fn shadow_name(live: &str) -> String {
+        format!("{}__rebuild", live)
+    }
+
+    /// Mark `live` as rebuilt and return its shadow collection name.
+    pub(crate) fn enter<S: ReadModelStore>(
+        &self,
+        store: &S,
+        live: &str,
+    ) -> Result<String, ReadModelError> {
+        let shadow = Self::shadow_name(live);
+        if !self.touched.borrow().contains(live) {
+            store.delete_collection(&shadow)?;
+            self.touched.borrow_mut().insert(live.to_string());
+        }
+        Ok(shadow)
+    }
+
+    /// `(shadow, live)` pairs for every collection entered so far.
+    pub(crate) fn pairs(&self) -> Vec<(String, String)> {
+        self.touched
+            .borrow()
+            .iter()
+            .map(|live| (Self::shadow_name(live), live.clone()))
+            .collect()
+    }
+}
@@ -0,0 +1,34 @@
+//! Projections - Build read models from the global event log.
+//!
+//! - `Projection` - Maps events to read model writes
+//! - `EventProjection` - A projection made of per-event-name handlers
+//! - `ProjectionContext` - Loads and stages read models while applying a batch
+//! - `ProjectionRunner` - Feeds a projection from `ReadAll` and commits the
+//!   models together with the projection's checkpoint
+//! - `ProjectionRebuild` - Replays a projection from zero into shadow
+//!   collections and atomically swaps them in for the live ones
+//!
+//! ## Example
+//!
+//! ```ignore
+//! use sourced_rust::{EventProjection, ProjectionRunner, SubscriptionThread};
+//!
+//! let projection = EventProjection::new("todo_views")
+//!     .on("Initialized", |event, models| {
+//!         let (id, _user, task): (String, String, String) = event.event.decode()?;
+//!         models.upsert(&TodoView { id, task, completed: false })
+//!     });
+//!
+//! let runner = ProjectionRunner::new(repo.clone(), repo.clone(), projection);
+//! let worker = SubscriptionThread::spawn(runner, Duration::from_millis(50));
+//! ```
+
+mod context;
+mod projection;
+mod rebuild;
+mod runner;
+
+pub use context::ProjectionContext;
+pub use projection::{EventProjection, Projection, ProjectionError};
+pub use rebuild::{ProjectionRebuild, RebuildProgress};
+pub use runner::{ProjectionCheckpoint, ProjectionRunner};
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::entity::PayloadError;
+use crate::read_model::{ReadModelError, ReadModelStore};
+use crate::repository::StoredEvent;
+
+use super::context::ProjectionContext;
+
+/// Error returned by projection handlers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProjectionError {
+    /// Loading a read model failed.
+    Model(ReadModelError),
+    /// The event payload could not be decoded.
+    Payload(String),
+    /// Any other handler failure.
+    Failed(String),
+}
+
+impl fmt::Display for ProjectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectionError::Model(err) => write!(f, "projection model error: {}", err),
+            ProjectionError::Payload(msg) => write!(f, "projection payload error: {}", msg),
+            ProjectionError::Failed(msg) => write!(f, "projection failed: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ProjectionError {}
+
+impl From<ReadModelError> for ProjectionError {
+    fn from(err: ReadModelError) -> Self {
+        ProjectionError::Model(err)
+    }
+}
+
+impl From<PayloadError> for ProjectionError {
+    fn from(err: PayloadError) -> Self {
+        ProjectionError::Payload(err.to_string())
+    }
+}
+
+/// Maps events to read models.
+///
+/// `apply` is called for every event in the global log, in order. It loads
+/// and stages read model writes through the [`ProjectionContext`]; the
+/// [`ProjectionRunner`](super::ProjectionRunner) commits them together with
+/// the projection's checkpoint.
+pub trait Projection<S: ReadModelStore> {
+    /// Unique name, used as the checkpoint ID.
+    fn name(&self) -> &str;
+
+    fn apply(
+        &mut self,
+        event: &StoredEvent,
+        models: &mut ProjectionContext<'_, S>,
+    ) -> Result<(), ProjectionError>;
+}
+
+type Handler<S> = Box<
+    dyn FnMut(&StoredEvent, &mut ProjectionContext<'_, S>) -> Result<(), ProjectionError> + Send,
+>;
+
+/// A [`Projection`] built from per-event-name handlers.
+///
+/// Events without a registered handler are skipped.
+///
+/// ## Example
+///
+/// ```ignore
+/// let projection = EventProjection::new("todo_views")
+///     .on("Initialized", |event, models| {
+///         let (id, _user, task): (String, String, String) = event.event.decode()?;
+///         models.upsert(&TodoView { id, task, completed: false })
+///     })
+///     .on("Completed", |event, models| {
+///         models.update(&event.stream_id, TodoView::default, |view| view.completed = true)
+///     });
+/// ```
+pub struct EventProjection<S> {
+    name: String,
+    handlers: HashMap<String, Handler<S>>,
+}
+
+impl<S: ReadModelStore> EventProjection<S> {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Register the handler for events named `event_name` (replacing any previous one).
+    pub fn on<F>(mut self, event_name: impl Into<String>, handler: F) -> Self
+    where
+        F: FnMut(&StoredEvent, &mut ProjectionContext<'_, S>) -> Result<(), ProjectionError>
+            + Send
+            + 'static,
+    {
+        self.handlers.insert(event_name.into(), Box::new(handler));
+        self
+    }
+
+    /// Whether a handler is registered for `event_name`.
+    pub fn handles(&self, event_name: &str) -> bool {
+        self.handlers.contains_key(event_name)
+    }
+}
+
+impl<S: ReadModelStore> Projection<S> for EventProjection<S> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn apply(
+        &mut self,
+        event: &StoredEvent,
+        models: &mut ProjectionContext<'_, S>,
+    ) -> Result<(), ProjectionError> {
+        match self.handlers.get_mut(&event.event.event_name) {
+            Some(handler) => handler(event, models),
+            None => Ok(()),
+        }
+    }
+}
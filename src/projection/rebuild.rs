@@ -0,0 +1,321 @@
+use crate::read_model::{ReadModel, ReadModelStore};
+use crate::repository::ReadAll;
+use crate::subscription::SubscriptionError;
+
+use super::context::{ProjectionContext, ShadowCollections};
+use super::projection::Projection;
+use super::runner::{apply_batch, ProjectionCheckpoint, ProjectionRunner};
+
+/// How far a [`ProjectionRebuild`] has got.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RebuildProgress {
+    /// Position of the last event applied to the shadow collections.
+    pub position: u64,
+    /// Last position in the event log when the latest batch was read.
+    pub target: u64,
+    /// Events applied since the rebuild started.
+    pub events_applied: usize,
+}
+
+impl RebuildProgress {
+    /// Completion in percent (100 once the log has been replayed).
+    pub fn percent(&self) -> f64 {
+        if self.target == 0 {
+            100.0
+        } else {
+            (self.position as f64 / self.target as f64 * 100.0).min(100.0)
+        }
+    }
+}
+
+type ProgressHandler = Box<dyn FnMut(&RebuildProgress) + Send>;
+
+/// Rebuilds a projection's read models from event zero without serving
+/// half-built data (blue/green).
+///
+/// The projection writes into a shadow copy of every collection it touches
+/// (`"{COLLECTION}__rebuild"`) while the live collections keep serving
+/// reads. [`swap`](Self::swap) then replays the remaining events and, through
+/// [`ReadModelStore::swap_collections`], atomically replaces the live
+/// collections with the shadows and moves the projection's checkpoint to the
+/// rebuilt position.
+///
+/// Stop the live [`ProjectionRunner`] before calling `swap` and start a new
+/// one afterwards (see [`into_runner`](Self::into_runner)); a runner that
+/// keeps polling across the swap would resume from its old position. An
+/// abandoned rebuild is harmless: the live collections are untouched, and a
+/// new rebuild clears the leftover shadows and starts again from zero.
+///
+/// The store must implement the raw collection methods of
+/// [`ReadModelStore`]; the built-in stores do, and others fail with
+/// [`ReadModelError::Unsupported`](crate::ReadModelError::Unsupported).
+///
+/// ## Example
+///
+/// ```ignore
+/// let mut rebuild = ProjectionRebuild::new(repo.clone(), repo.clone(), todo_views_v2())
+///     .on_progress(|p| println!("rebuilding todo_views: {:.0}%", p.percent()));
+/// rebuild.catch_up()?; // live views are still served by the old runner
+///
+/// live_worker.stop();
+/// rebuild.swap()?;
+/// let worker = SubscriptionThread::spawn(rebuild.into_runner(), Duration::from_millis(50));
+/// ```
+pub struct ProjectionRebuild<R, S, P> {
+    events: R,
+    store: S,
+    projection: P,
+    batch_size: usize,
+    shadows: ShadowCollections,
+    collections: Vec<&'static str>,
+    progress: RebuildProgress,
+    on_progress: Option<ProgressHandler>,
+}
+
+impl<R, S, P> ProjectionRebuild<R, S, P> {
+    /// Create a rebuild reading events from `events` and writing models to `store`.
+    pub fn new(events: R, store: S, projection: P) -> Self {
+        Self {
+            events,
+            store,
+            projection,
+            batch_size: 100,
+            shadows: ShadowCollections::default(),
+            collections: Vec::new(),
+            progress: RebuildProgress::default(),
+            on_progress: None,
+        }
+    }
+
+    /// Set the maximum number of events applied per batch.
+    pub fn with_batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    /// Swap `M`'s collection even if the rebuilt projection never writes it,
+    /// so models the old logic produced are removed.
+    pub fn with_collection<M: ReadModel>(mut self) -> Self {
+        self.collections.push(M::COLLECTION);
+        self
+    }
+
+    /// Call `handler` after every applied batch.
+    pub fn on_progress<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(&RebuildProgress) + Send + 'static,
+    {
+        self.on_progress = Some(Box::new(handler));
+        self
+    }
+
+    /// Progress so far.
+    pub fn progress(&self) -> &RebuildProgress {
+        &self.progress
+    }
+
+    /// Get a reference to the projection.
+    pub fn projection(&self) -> &P {
+        &self.projection
+    }
+
+    /// A runner that continues the projection from the swapped-in checkpoint.
+    pub fn into_runner(self) -> ProjectionRunner<R, S, P> {
+        ProjectionRunner::new(self.events, self.store, self.projection)
+            .with_batch_size(self.batch_size)
+    }
+}
+
+impl<R, S, P> ProjectionRebuild<R, S, P>
+where
+    R: ReadAll,
+    S: ReadModelStore,
+    P: Projection<S>,
+{
+    /// Apply and commit one batch to the shadow collections. Returns the
+    /// number of events applied.
+    pub fn poll(&mut self) -> Result<usize, SubscriptionError> {
+        for collection in &self.collections {
+            self.shadows
+                .enter(&self.store, collection)
+                .map_err(|e| SubscriptionError::Repository(e.into()))?;
+        }
+
+        self.progress.target = self.events.last_position()?;
+        let events = self
+            .events
+            .read_all(self.progress.position, self.batch_size)?;
+
+        let mut models = ProjectionContext::shadowed(&self.store, &self.shadows);
+        let (applied, failure) = apply_batch(&mut self.projection, &events, &mut models);
+
+        if applied > 0 {
+            let writes = models.into_writes();
+            if !writes.is_empty() {
+                self.store
+                    .upsert_raw_many(writes)
+                    .map_err(|e| SubscriptionError::Repository(e.into()))?;
+            }
+            self.progress.position = events[applied - 1].position();
+            self.progress.events_applied += applied;
+            if let Some(handler) = self.on_progress.as_mut() {
+                handler(&self.progress);
+            }
+        }
+
+        match failure {
+            Some(err) => Err(err),
+            None => Ok(applied),
+        }
+    }
+
+    /// Replay into the shadow collections until the log is drained. Returns
+    /// the number of events applied. The live collections are not touched.
+    pub fn catch_up(&mut self) -> Result<usize, SubscriptionError> {
+        let mut total = 0;
+        loop {
+            let applied = self.poll()?;
+            total += applied;
+            if applied < self.batch_size {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// Finish the catch-up, then atomically replace the live collections
+    /// with the rebuilt ones and save the projection's checkpoint.
+    pub fn swap(&mut self) -> Result<RebuildProgress, SubscriptionError> {
+        self.catch_up()?;
+
+        let pairs = self.shadows.pairs();
+        let swaps: Vec<(&str, &str)> = pairs
+            .iter()
+            .map(|(shadow, live)| (shadow.as_str(), live.as_str()))
+            .collect();
+        let checkpoint =
+            ProjectionCheckpoint::write(self.projection.name(), self.progress.position);
+
+        self.store
+            .swap_collections(&swaps, vec![checkpoint])
+            .map_err(|e| SubscriptionError::Repository(e.into()))?;
+        Ok(self.progress.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::entity::Entity;
+    use crate::projection::EventProjection;
+    use crate::repository::Commit;
+    use crate::HashMapRepository;
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Tally {
+        id: String,
+        count: u32,
+    }
+
+    impl ReadModel for Tally {
+        const COLLECTION: &'static str = "tallies";
+        fn id(&self) -> &str {
+            &self.id
+        }
+    }
+
+    fn tallies(step: u32) -> EventProjection<HashMapRepository> {
+        EventProjection::new("tallies").on("Counted", move |event, models| {
+            models.update(
+                &event.stream_id,
+                || Tally {
+                    id: event.stream_id.clone(),
+                    count: 0,
+                },
+                |t| t.count += step,
+            )
+        })
+    }
+
+    fn count(repo: &HashMapRepository, id: &str, times: usize) {
+        let mut entity = Entity::with_id(id);
+        for _ in 0..times {
+            entity.digest("Counted", &());
+        }
+        repo.commit(&mut entity).unwrap();
+    }
+
+    fn tally(repo: &HashMapRepository, id: &str) -> Option<u32> {
+        repo.get_model::<Tally>(id).unwrap().map(|v| v.data.count)
+    }
+
+    #[test]
+    fn live_collection_is_served_until_swap() {
+        let repo = HashMapRepository::new();
+        count(&repo, "a", 2);
+        ProjectionRunner::new(repo.clone(), repo.clone(), tallies(1))
+            .catch_up()
+            .unwrap();
+
+        let mut rebuild = ProjectionRebuild::new(repo.clone(), repo.clone(), tallies(10));
+        assert_eq!(rebuild.catch_up().unwrap(), 2);
+        assert_eq!(tally(&repo, "a"), Some(2));
+
+        count(&repo, "b", 1);
+        let progress = rebuild.swap().unwrap();
+        assert_eq!(progress.position, 3);
+        assert_eq!(progress.events_applied, 3);
+        assert_eq!(tally(&repo, "a"), Some(20));
+        assert_eq!(tally(&repo, "b"), Some(10));
+        assert_eq!(
+            repo.get_raw("tallies__rebuild:a").unwrap(),
+            None,
+            "shadow collection should be empty after the swap"
+        );
+
+        let mut runner = rebuild.into_runner();
+        assert_eq!(runner.catch_up().unwrap(), 0);
+        assert_eq!(runner.position(), Some(3));
+    }
+
+    #[test]
+    fn stale_models_and_shadow_leftovers_are_dropped() {
+        let repo = HashMapRepository::new();
+        count(&repo, "a", 1);
+        repo.upsert(&Tally {
+            id: "gone".into(),
+            count: 7,
+        })
+        .unwrap();
+        repo.upsert_raw("tallies__rebuild:leftover", b"{}".to_vec())
+            .unwrap();
+
+        ProjectionRebuild::new(repo.clone(), repo.clone(), tallies(1))
+            .swap()
+            .unwrap();
+
+        assert_eq!(tally(&repo, "a"), Some(1));
+        assert_eq!(tally(&repo, "gone"), None);
+        assert_eq!(tally(&repo, "leftover"), None);
+    }
+
+    #[test]
+    fn reports_progress_per_batch() {
+        let repo = HashMapRepository::new();
+        count(&repo, "a", 3);
+        count(&repo, "b", 2);
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&seen);
+        let mut rebuild = ProjectionRebuild::new(repo.clone(), repo.clone(), tallies(1))
+            .with_batch_size(2)
+            .on_progress(move |p| recorded.lock().unwrap().push((p.position, p.target)));
+        rebuild.swap().unwrap();
+
+        assert_eq!(*seen.lock().unwrap(), vec![(2, 5), (4, 5), (5, 5)]);
+        assert_eq!(rebuild.progress().percent(), 100.0);
+    }
+}
@@ -0,0 +1,316 @@
+use serde::{Deserialize, Serialize};
+
+use crate::read_model::{ReadModel, ReadModelStore};
+use crate::repository::{ReadAll, StoredEvent};
+use crate::subscription::{Pollable, SubscriptionError};
+
+use super::context::ProjectionContext;
+use super::projection::Projection;
+
+/// Stored position of a projection, kept in the read model store itself so it
+/// is committed together with the models it covers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProjectionCheckpoint {
+    pub name: String,
+    pub position: u64,
+}
+
+impl ReadModel for ProjectionCheckpoint {
+    const COLLECTION: &'static str = "projection_checkpoints";
+
+    fn id(&self) -> &str {
+        &self.name
+    }
+}
+
+impl ProjectionCheckpoint {
+    /// The raw `(key, bytes)` write storing `position` for projection `name`.
+    pub(super) fn write(name: &str, position: u64) -> (String, Vec<u8>) {
+        let checkpoint = ProjectionCheckpoint {
+            name: name.to_string(),
+            position,
+        };
+        (
+            format!("{}:{}", Self::COLLECTION, name),
+            serde_json::to_vec(&checkpoint).expect("checkpoint serialization should not fail"),
+        )
+    }
+}
+
+/// Feeds a [`Projection`] from the global event log.
+///
+/// Each poll reads one batch via [`ReadAll`], applies it, and commits the
+/// staged read models together with a [`ProjectionCheckpoint`] through
+/// [`ReadModelStore::upsert_raw_many`]. On stores that write models
+/// atomically (e.g. `SqliteRepository`) an event's effects and the checkpoint
+/// that covers it are never persisted apart, so replay after a crash does not
+/// apply an event twice.
+///
+/// If `apply` fails, the writes of the events before it are still committed
+/// and the next poll retries the failing event.
+///
+/// ## Example
+///
+/// ```ignore
+/// let mut runner = ProjectionRunner::new(repo.clone(), repo.clone(), todo_views());
+/// runner.catch_up()?;
+///
+/// // or in the background:
+/// let worker = SubscriptionThread::spawn(runner, Duration::from_millis(50));
+/// ```
+pub struct ProjectionRunner<R, S, P> {
+    events: R,
+    store: S,
+    projection: P,
+    batch_size: usize,
+    position: Option<u64>,
+}
+
+impl<R, S, P> ProjectionRunner<R, S, P> {
+    /// Create a runner reading events from `events` and writing models to `store`.
+    pub fn new(events: R, store: S, projection: P) -> Self {
+        Self {
+            events,
+            store,
+            projection,
+            batch_size: 100,
+            position: None,
+        }
+    }
+
+    /// Set the maximum number of events applied per poll.
+    pub fn with_batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    /// Position of the last applied event, once the runner has started.
+    pub fn position(&self) -> Option<u64> {
+        self.position
+    }
+
+    /// Get a reference to the projection.
+    pub fn projection(&self) -> &P {
+        &self.projection
+    }
+
+    /// Get a reference to the read model store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+}
+
+impl<R, S, P> ProjectionRunner<R, S, P>
+where
+    R: ReadAll,
+    S: ReadModelStore,
+    P: Projection<S>,
+{
+    /// Apply and commit one batch. Returns the number of events applied.
+    pub fn poll(&mut self) -> Result<usize, SubscriptionError> {
+        let from = self.resolve_position()?;
+        let events = self.events.read_all(from, self.batch_size)?;
+
+        let mut models = ProjectionContext::new(&self.store);
+        let (applied, failure) = apply_batch(&mut self.projection, &events, &mut models);
+
+        if applied > 0 {
+            let position = events[applied - 1].position();
+            let mut writes = models.into_writes();
+            writes.push(ProjectionCheckpoint::write(self.projection.name(), position));
+            self.store
+                .upsert_raw_many(writes)
+                .map_err(|e| SubscriptionError::Repository(e.into()))?;
+            self.position = Some(position);
+        }
+
+        match failure {
+            Some(err) => Err(err),
+            None => Ok(applied),
+        }
+    }
+
+    /// Poll until the log is drained. Returns the number of events applied.
+    pub fn catch_up(&mut self) -> Result<usize, SubscriptionError> {
+        let mut total = 0;
+        loop {
+            let applied = self.poll()?;
+            total += applied;
+            if applied < self.batch_size {
+                return Ok(total);
+            }
+        }
+    }
+
+    fn resolve_position(&mut self) -> Result<u64, SubscriptionError> {
+        if let Some(position) = self.position {
+            return Ok(position);
+        }
+
+        let position = self
+            .store
+            .get_model::<ProjectionCheckpoint>(self.projection.name())
+            .map_err(|e| SubscriptionError::Repository(e.into()))?
+            .map(|v| v.data.position)
+            .unwrap_or(0);
+        self.position = Some(position);
+        Ok(position)
+    }
+}
+
+/// Apply `events` in order until one fails. Returns the number applied and
+/// the failure, if any; only the writes of applied events stay staged.
+pub(super) fn apply_batch<S, P>(
+    projection: &mut P,
+    events: &[StoredEvent],
+    models: &mut ProjectionContext<'_, S>,
+) -> (usize, Option<SubscriptionError>)
+where
+    S: ReadModelStore,
+    P: Projection<S>,
+{
+    for (applied, event) in events.iter().enumerate() {
+        if let Err(err) = projection.apply(event, models) {
+            models.reject_event();
+            let failure = SubscriptionError::Handler {
+                position: event.position(),
+                stream_id: event.stream_id.clone(),
+                message: err.to_string(),
+            };
+            return (applied, Some(failure));
+        }
+        models.accept_event();
+    }
+    (events.len(), None)
+}
+
+impl<R, S, P> Pollable for ProjectionRunner<R, S, P>
+where
+    R: ReadAll + Send,
+    S: ReadModelStore,
+    P: Projection<S> + Send,
+{
+    fn poll(&mut self) -> Result<usize, SubscriptionError> {
+        ProjectionRunner::poll(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::Entity;
+    use crate::projection::{EventProjection, ProjectionError};
+    use crate::repository::{Commit, GetOne};
+    use crate::read_model::InMemoryReadModelStore;
+    use crate::HashMapRepository;
+
+    #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Tally {
+        id: String,
+        count: u32,
+    }
+
+    impl ReadModel for Tally {
+        const COLLECTION: &'static str = "tallies";
+        fn id(&self) -> &str {
+            &self.id
+        }
+    }
+
+    fn tally(id: &str) -> Tally {
+        Tally {
+            id: id.to_string(),
+            count: 0,
+        }
+    }
+
+    fn tallies() -> EventProjection<HashMapRepository> {
+        EventProjection::new("tallies").on("Counted", |event, models| {
+            models.update(&event.stream_id, || tally(&event.stream_id), |t| t.count += 1)
+        })
+    }
+
+    fn count(repo: &HashMapRepository, id: &str, times: usize) {
+        let mut entity = repo.get_one(id).unwrap().unwrap_or_else(|| Entity::with_id(id));
+        for _ in 0..times {
+            entity.digest("Counted", &());
+        }
+        entity.digest("Ignored", &());
+        repo.commit(&mut entity).unwrap();
+    }
+
+    #[test]
+    fn batch_sees_its_own_writes_and_saves_checkpoint() {
+        let repo = HashMapRepository::new();
+        count(&repo, "a", 3);
+
+        let mut runner = ProjectionRunner::new(repo.clone(), repo.clone(), tallies());
+        assert_eq!(runner.catch_up().unwrap(), 4);
+
+        let stored = repo.get_model::<Tally>("a").unwrap().unwrap();
+        assert_eq!(stored.data.count, 3);
+        // One write per batch, not one per event.
+        assert_eq!(stored.version, 1);
+
+        let checkpoint = repo.get_model::<ProjectionCheckpoint>("tallies").unwrap().unwrap();
+        assert_eq!(checkpoint.data.position, 4);
+    }
+
+    #[test]
+    fn new_runner_resumes_from_stored_checkpoint() {
+        let repo = HashMapRepository::new();
+        count(&repo, "a", 2);
+        ProjectionRunner::new(repo.clone(), repo.clone(), tallies())
+            .catch_up()
+            .unwrap();
+
+        count(&repo, "a", 1);
+        let mut runner = ProjectionRunner::new(repo.clone(), repo.clone(), tallies());
+        assert_eq!(runner.catch_up().unwrap(), 2);
+        assert_eq!(repo.get_model::<Tally>("a").unwrap().unwrap().data.count, 3);
+    }
+
+    #[test]
+    fn failed_event_discards_only_its_own_writes() {
+        let repo = HashMapRepository::new();
+        count(&repo, "a", 1);
+        count(&repo, "b", 1);
+
+        let mut fail = true;
+        let projection = EventProjection::new("tallies").on("Counted", move |event, models| {
+            models.update(&event.stream_id, || tally(&event.stream_id), |t| t.count += 1)?;
+            if event.stream_id == "b" && fail {
+                fail = false;
+                return Err(ProjectionError::Failed("boom".into()));
+            }
+            Ok(())
+        });
+        let mut runner = ProjectionRunner::new(repo.clone(), repo.clone(), projection);
+
+        let err = runner.poll().unwrap_err();
+        assert!(matches!(err, SubscriptionError::Handler { position: 3, .. }));
+        assert_eq!(repo.get_model::<Tally>("a").unwrap().unwrap().data.count, 1);
+        assert!(repo.get_model::<Tally>("b").unwrap().is_none());
+        assert_eq!(runner.position(), Some(2));
+
+        assert_eq!(runner.catch_up().unwrap(), 2);
+        assert_eq!(repo.get_model::<Tally>("b").unwrap().unwrap().data.count, 1);
+    }
+
+    #[test]
+    fn runs_against_a_read_model_only_store() {
+        let repo = HashMapRepository::new();
+        count(&repo, "a", 2);
+
+        let projection = EventProjection::new("tallies").on("Counted", |event, models| {
+            models.update(&event.stream_id, || tally(&event.stream_id), |t| t.count += 1)
+        });
+        let mut runner = ProjectionRunner::new(repo, InMemoryReadModelStore::new(), projection);
+        assert_eq!(runner.catch_up().unwrap(), 3);
+
+        let store = runner.store();
+        assert_eq!(store.get_model::<Tally>("a").unwrap().unwrap().data.count, 2);
+        let checkpoint = store.get_model::<ProjectionCheckpoint>("tallies").unwrap().unwrap();
+        assert_eq!(checkpoint.data.position, 3);
+    }
+}
@@ -1,35 +1,61 @@
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::lock::{InMemoryLockManager, Lock, LockManager};
+use crate::lock::{self, InMemoryLockManager, Lock, LockError, LockManager};
 use crate::entity::{Committable, Entity};
 use crate::repository::{
-    Commit, Count, Exists, Find, FindOne, Get, GetMany, GetOne, RepositoryError,
+    Commit, Count, Exists, Find, FindByIndex, FindOne, FindPage, Get, GetMany, GetOne, GetOneFrom,
+    Page, PageRequest, ReadAll, RepositoryError, StoredEvent,
 };
 use crate::snapshot::{SnapshotRecord, SnapshotStore};
+use crate::subscription::CheckpointStore;
 
 /// Options for read operations.
 #[derive(Debug, Clone, Copy)]
 pub struct ReadOpts {
     /// Whether to acquire a lock on the entity/entities.
     pub lock: bool,
+    /// Lock in shared mode, only for the duration of the read.
+    pub shared: bool,
 }
 
 impl Default for ReadOpts {
     fn default() -> Self {
-        Self { lock: true }
+        Self {
+            lock: true,
+            shared: false,
+        }
     }
 }
 
 impl ReadOpts {
     /// Create options that skip locking.
     pub fn no_lock() -> Self {
-        Self { lock: false }
+        Self {
+            lock: false,
+            shared: false,
+        }
+    }
+
+    /// Create options for a consistent read that does not block other
+    /// readers.
+    ///
+    /// The read takes shared locks and releases them before returning, so
+    /// there is nothing to unlock afterwards. It waits for writers holding an
+    /// entity (from a locked read until its commit), and concurrent shared
+    /// reads proceed together.
+    pub fn shared() -> Self {
+        Self {
+            lock: true,
+            shared: true,
+        }
     }
 }
 
 pub struct QueuedRepository<R, L: LockManager = InMemoryLockManager> {
     inner: R,
     lock_manager: Arc<L>,
+    lock_timeout: Option<Duration>,
 }
 
 impl<R: Clone, L: LockManager> Clone for QueuedRepository<R, L> {
@@ -37,6 +63,7 @@ impl<R: Clone, L: LockManager> Clone for QueuedRepository<R, L> {
         QueuedRepository {
             inner: self.inner.clone(),
             lock_manager: Arc::clone(&self.lock_manager),
+            lock_timeout: self.lock_timeout,
         }
     }
 }
@@ -46,6 +73,7 @@ impl<R> QueuedRepository<R> {
         QueuedRepository {
             inner,
             lock_manager: Arc::new(InMemoryLockManager::new()),
+            lock_timeout: None,
         }
     }
 }
@@ -56,9 +84,17 @@ impl<R, L: LockManager> QueuedRepository<R, L> {
         QueuedRepository {
             inner,
             lock_manager: Arc::new(lock_manager),
+            lock_timeout: None,
         }
     }
 
+    /// Fail locked reads with `LockError::AcquireFailed` when a stream's lock
+    /// is not acquired within `timeout`, instead of waiting indefinitely.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
     /// Access the inner repository.
     pub fn inner(&self) -> &R {
         &self.inner
@@ -89,20 +125,64 @@ impl<R, L: LockManager> QueuedRepository<R, L> {
         Ok(self.lock_manager.get_lock(id)?)
     }
 
-    fn lock_ids_in_order(&self, ids: &[&str]) -> Result<Vec<Arc<L::Lock>>, RepositoryError> {
+    /// Wait for the lock on `id`, up to the lock timeout if one is set.
+    fn acquire(&self, id: &str, shared: bool) -> Result<Arc<L::Lock>, RepositoryError> {
+        let lock = self.ensure_lock(id)?;
+        lock::acquire(&*lock, shared, self.lock_timeout).map_err(|e| match e {
+            LockError::AcquireFailed(msg) => {
+                LockError::AcquireFailed(format!("stream '{}': {}", id, msg))
+            }
+            other => other,
+        })?;
+        Ok(lock)
+    }
+
+    fn lock_ids_in_order(
+        &self,
+        ids: &[&str],
+        shared: bool,
+    ) -> Result<Vec<Arc<L::Lock>>, RepositoryError> {
         let mut unique: Vec<&str> = ids.iter().copied().collect();
         unique.sort_unstable();
         unique.dedup();
 
         let mut locks = Vec::with_capacity(unique.len());
         for id in unique {
-            let lock = self.ensure_lock(id)?;
-            lock.lock()?;
-            locks.push(lock);
+            match self.acquire(id, shared) {
+                Ok(lock) => locks.push(lock),
+                Err(e) => {
+                    // A timeout part-way through: release what was taken
+                    for lock in locks {
+                        let _ = lock::release(&*lock, shared);
+                    }
+                    return Err(e);
+                }
+            }
         }
 
         Ok(locks)
     }
+
+    /// Run `read` holding shared locks on `ids`, released before returning.
+    fn read_shared<T>(
+        &self,
+        ids: &[&str],
+        read: impl FnOnce() -> Result<T, RepositoryError>,
+    ) -> Result<T, RepositoryError> {
+        let locks = self.lock_ids_in_order(ids, true)?;
+        let result = read();
+        // Release every lock even if one fails, reporting the read's own
+        // error first
+        let mut released = Ok(());
+        for lock in locks {
+            if let Err(e) = lock.unlock_shared() {
+                released = released.and(Err(e));
+            }
+        }
+        let value = result?;
+        released?;
+        Ok(value)
+    }
 }
 
 // ============================================================================
@@ -111,15 +191,21 @@ impl<R, L: LockManager> QueuedRepository<R, L> {
 
 impl<R: GetOne, L: LockManager> GetOne for QueuedRepository<R, L> {
     fn get_one(&self, id: &str) -> Result<Option<Entity>, RepositoryError> {
-        let lock = self.ensure_lock(id)?;
-        lock.lock()?;
+        self.acquire(id, false)?;
         self.inner.get_one(id)
     }
 }
 
+impl<R: GetOneFrom, L: LockManager> GetOneFrom for QueuedRepository<R, L> {
+    fn get_one_from(&self, id: &str, from_version: u64) -> Result<Option<Entity>, RepositoryError> {
+        self.acquire(id, false)?;
+        self.inner.get_one_from(id, from_version)
+    }
+}
+
 impl<R: GetMany + GetOne, L: LockManager> GetMany for QueuedRepository<R, L> {
     fn get_many(&self, ids: &[&str]) -> Result<Vec<Entity>, RepositoryError> {
-        let _locks = self.lock_ids_in_order(ids)?;
+        let _locks = self.lock_ids_in_order(ids, false)?;
         self.inner.get_many(ids)
     }
 }
@@ -134,7 +220,7 @@ impl<R: Find + GetOne, L: LockManager> Find for QueuedRepository<R, L> {
 
         // Lock all matching entity IDs
         let ids: Vec<&str> = entities.iter().map(|e| e.id()).collect();
-        let _locks = self.lock_ids_in_order(&ids)?;
+        let _locks = self.lock_ids_in_order(&ids, false)?;
 
         // Re-fetch with locks held to ensure consistency
         let mut results = Vec::with_capacity(entities.len());
@@ -149,6 +235,37 @@ impl<R: Find + GetOne, L: LockManager> Find for QueuedRepository<R, L> {
     }
 }
 
+impl<R: FindPage + GetOne, L: LockManager> FindPage for QueuedRepository<R, L> {
+    /// Locks the entities of the page, like [`find`](Find::find). Entities
+    /// that no longer match once locked are released; the caller must unlock
+    /// every returned entity.
+    fn find_page<F>(
+        &self,
+        predicate: F,
+        request: &PageRequest,
+    ) -> Result<Page<Entity>, RepositoryError>
+    where
+        F: Fn(&Entity) -> bool,
+    {
+        let page = self.inner.find_page(&predicate, request)?;
+
+        let ids: Vec<&str> = page.items.iter().map(|e| e.id()).collect();
+        let _locks = self.lock_ids_in_order(&ids, false)?;
+
+        let mut items = Vec::with_capacity(ids.len());
+        for id in ids {
+            match self.inner.get_one(id)? {
+                Some(entity) if predicate(&entity) => items.push(entity),
+                _ => self.unlock(id)?,
+            }
+        }
+        Ok(Page {
+            items,
+            next_cursor: page.next_cursor,
+        })
+    }
+}
+
 impl<R: FindOne + GetOne, L: LockManager> FindOne for QueuedRepository<R, L> {
     fn find_one<F>(&self, predicate: F) -> Result<Option<Entity>, RepositoryError>
     where
@@ -159,8 +276,7 @@ impl<R: FindOne + GetOne, L: LockManager> FindOne for QueuedRepository<R, L> {
 
         if let Some(entity) = entity {
             // Lock the entity
-            let lock = self.ensure_lock(entity.id())?;
-            lock.lock()?;
+            let lock = self.acquire(entity.id(), false)?;
 
             // Re-fetch with lock held to ensure consistency
             if let Some(entity) = self.inner.get_one(entity.id())? {
@@ -195,6 +311,17 @@ impl<R: Count, L: LockManager> Count for QueuedRepository<R, L> {
     }
 }
 
+impl<R: FindByIndex, L: LockManager> FindByIndex for QueuedRepository<R, L> {
+    /// Index lookups do not lock; loading the returned IDs does.
+    fn ids_in(&self, index: &str) -> Result<Vec<String>, RepositoryError> {
+        self.inner.ids_in(index)
+    }
+
+    fn ids_by(&self, index: &str, key: &str) -> Result<Vec<String>, RepositoryError> {
+        self.inner.ids_by(index, key)
+    }
+}
+
 impl<R: Commit, L: LockManager> Commit for QueuedRepository<R, L> {
     fn commit<C: Committable + ?Sized>(&self, committable: &mut C) -> Result<(), RepositoryError> {
         let entities = committable.entities_mut();
@@ -205,6 +332,14 @@ impl<R: Commit, L: LockManager> Commit for QueuedRepository<R, L> {
             locks.push(self.ensure_lock(entity.id())?);
         }
 
+        // Reject the write if a lock was lost meanwhile (an expired lease).
+        // New streams are not locked; the version check stops a second creator.
+        for (lock, entity) in locks.iter().zip(&entities) {
+            if entity.committed_version() > 0 {
+                lock.ensure_held()?;
+            }
+        }
+
         // Delegate to inner repository
         let result = self.inner.commit(committable);
 
@@ -217,6 +352,13 @@ impl<R: Commit, L: LockManager> Commit for QueuedRepository<R, L> {
 
         result
     }
+
+    fn release(&self, stream_ids: &[&str]) -> Result<(), RepositoryError> {
+        for id in stream_ids {
+            self.unlock(id)?;
+        }
+        Ok(())
+    }
 }
 
 // ============================================================================
@@ -253,20 +395,24 @@ pub trait FindOneWithOpts: FindOne {
 
 impl<R: GetOne + GetMany, L: LockManager> GetWithOpts for QueuedRepository<R, L> {
     fn get_with(&self, id: &str, opts: ReadOpts) -> Result<Option<Entity>, RepositoryError> {
-        if opts.lock {
-            self.get_one(id)
-        } else {
+        if !opts.lock {
             self.inner.get_one(id)
+        } else if opts.shared {
+            self.read_shared(&[id], || self.inner.get_one(id))
+        } else {
+            self.get_one(id)
         }
     }
 }
 
 impl<R: GetMany + GetOne, L: LockManager> GetAllWithOpts for QueuedRepository<R, L> {
     fn get_all_with(&self, ids: &[&str], opts: ReadOpts) -> Result<Vec<Entity>, RepositoryError> {
-        if opts.lock {
-            self.get_many(ids)
-        } else {
+        if !opts.lock {
             self.inner.get_many(ids)
+        } else if opts.shared {
+            self.read_shared(ids, || self.inner.get_many(ids))
+        } else {
+            self.get_many(ids)
         }
     }
 }
@@ -276,11 +422,27 @@ impl<R: Find + GetOne, L: LockManager> FindWithOpts for QueuedRepository<R, L> {
     where
         F: Fn(&Entity) -> bool,
     {
-        if opts.lock {
-            self.find(predicate)
-        } else {
-            self.inner.find(predicate)
+        if !opts.lock {
+            return self.inner.find(predicate);
+        }
+        if !opts.shared {
+            return self.find(predicate);
         }
+
+        let entities = self.inner.find(&predicate)?;
+        let ids: Vec<&str> = entities.iter().map(|e| e.id()).collect();
+        self.read_shared(&ids, || {
+            // Re-fetch with the locks held, as `find` does
+            let mut results = Vec::with_capacity(ids.len());
+            for id in &ids {
+                if let Some(entity) = self.inner.get_one(id)? {
+                    if predicate(&entity) {
+                        results.push(entity);
+                    }
+                }
+            }
+            Ok(results)
+        })
     }
 }
 
@@ -293,11 +455,19 @@ impl<R: FindOne + GetOne, L: LockManager> FindOneWithOpts for QueuedRepository<R
     where
         F: Fn(&Entity) -> bool,
     {
-        if opts.lock {
-            self.find_one(predicate)
-        } else {
-            self.inner.find_one(predicate)
+        if !opts.lock {
+            return self.inner.find_one(predicate);
+        }
+        if !opts.shared {
+            return self.find_one(predicate);
         }
+
+        let Some(entity) = self.inner.find_one(&predicate)? else {
+            return Ok(None);
+        };
+        self.read_shared(&[entity.id()], || {
+            Ok(self.inner.get_one(entity.id())?.filter(|entity| predicate(entity)))
+        })
     }
 }
 
@@ -334,6 +504,39 @@ impl<R: SnapshotStore, L: LockManager> SnapshotStore for QueuedRepository<R, L>
     }
 }
 
+// ============================================================================
+// ReadAll delegation
+// ============================================================================
+
+impl<R: ReadAll, L: LockManager> ReadAll for QueuedRepository<R, L> {
+    /// Read the global log (non-locking).
+    fn read_all(&self, from_position: u64, limit: usize) -> Result<Vec<StoredEvent>, RepositoryError> {
+        self.inner.read_all(from_position, limit)
+    }
+
+    fn last_position(&self) -> Result<u64, RepositoryError> {
+        self.inner.last_position()
+    }
+}
+
+// ============================================================================
+// CheckpointStore delegation
+// ============================================================================
+
+impl<R: CheckpointStore, L: LockManager> CheckpointStore for QueuedRepository<R, L> {
+    fn load_checkpoint(&self, name: &str) -> Result<Option<u64>, RepositoryError> {
+        self.inner.load_checkpoint(name)
+    }
+
+    fn save_checkpoint(&self, name: &str, position: u64) -> Result<(), RepositoryError> {
+        self.inner.save_checkpoint(name, position)
+    }
+
+    fn delete_checkpoint(&self, name: &str) -> Result<bool, RepositoryError> {
+        self.inner.delete_checkpoint(name)
+    }
+}
+
 /// Builder trait for wrapping a repository with queue locking.
 pub trait Queueable: Sized {
     fn queued(self) -> QueuedRepository<Self> {
@@ -1,9 +1,14 @@
 //! InMemoryReadModelStore - HashMap-backed read model store for testing and development.
 
-use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::collections::{hash_map, HashMap};
+use std::sync::{Arc, RwLock, RwLockWriteGuard};
 
-use super::{ReadModel, ReadModelError, ReadModelStore, Versioned};
+use serde_json::Value;
+
+use crate::repository::{Page, PageRequest};
+
+use super::index::{check_indexed, ModelIndexes};
+use super::{Query, ReadModel, ReadModelError, ReadModelStore, Versioned};
 
 /// Internal stored representation of a read model.
 struct StoredModel {
@@ -11,12 +16,63 @@ struct StoredModel {
     version: u64,
 }
 
+/// Stored models by key, with the field indexes kept in sync on every write.
+#[derive(Default)]
+struct Models {
+    entries: HashMap<String, StoredModel>,
+    indexes: ModelIndexes,
+}
+
+impl Models {
+    fn get(&self, key: &str) -> Option<&StoredModel> {
+        self.entries.get(key)
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    fn iter(&self) -> hash_map::Iter<'_, String, StoredModel> {
+        self.entries.iter()
+    }
+
+    fn keys(&self) -> hash_map::Keys<'_, String, StoredModel> {
+        self.entries.keys()
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn insert(&mut self, key: String, stored: StoredModel) {
+        self.indexes.insert(&key, &stored.bytes);
+        self.entries.insert(key, stored);
+    }
+
+    fn remove(&mut self, key: &str) -> Option<StoredModel> {
+        let removed = self.entries.remove(key)?;
+        self.indexes.remove(key);
+        Some(removed)
+    }
+
+    fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        let removed: Vec<String> = self.keys().filter(|key| !keep(key)).cloned().collect();
+        for key in removed {
+            self.remove(&key);
+        }
+    }
+}
+
 /// In-memory read model store backed by a HashMap.
 ///
 /// Storage key is `"TABLE:id"`. Clone-friendly via Arc.
+///
+/// Fields listed in `ReadModel::INDEXES` are indexed the first time a
+/// collection is looked up with `find_models_by`, and kept in sync by every
+/// write after that, including raw and `CommitBuilder` writes.
 #[derive(Clone)]
 pub struct InMemoryReadModelStore {
-    storage: Arc<RwLock<HashMap<String, StoredModel>>>,
+    storage: Arc<RwLock<Models>>,
 }
 
 impl Default for InMemoryReadModelStore {
@@ -29,7 +85,7 @@ impl InMemoryReadModelStore {
     /// Create a new empty read model store.
     pub fn new() -> Self {
         Self {
-            storage: Arc::new(RwLock::new(HashMap::new())),
+            storage: Arc::new(RwLock::new(Models::default())),
         }
     }
 
@@ -37,19 +93,75 @@ impl InMemoryReadModelStore {
         format!("{}:{}", table, id)
     }
 
+    /// Start indexing `M::INDEXES` if this collection is not indexed yet.
+    fn ensure_indexes<M: ReadModel>(&self) -> Result<(), ReadModelError> {
+        let registered = self
+            .storage
+            .read()
+            .map_err(|_| ReadModelError::Storage("lock poisoned".into()))?
+            .indexes
+            .is_registered(M::COLLECTION);
+        if registered {
+            return Ok(());
+        }
+
+        let mut storage = self
+            .storage
+            .write()
+            .map_err(|_| ReadModelError::Storage("lock poisoned".into()))?;
+        if storage.indexes.is_registered(M::COLLECTION) {
+            return Ok(());
+        }
+        let prefix = format!("{}:", M::COLLECTION);
+        let Models { entries, indexes } = &mut *storage;
+        let models = entries.iter().filter_map(|(key, stored)| {
+            Some((key.strip_prefix(&prefix)?, stored.bytes.as_slice()))
+        });
+        indexes.register(M::COLLECTION, M::INDEXES, models);
+        Ok(())
+    }
+
     /// Save a raw read model entry (used by CommitBuilder for type-erased writes).
     pub(crate) fn save_raw(&self, key: &str, bytes: Vec<u8>) -> Result<u64, ReadModelError> {
-        let mut storage = self
+        Ok(self.raw_writer()?.upsert(key, bytes))
+    }
+
+    /// Hold the store's write lock for a batch of raw writes, so a caller can
+    /// check other state first and then write without failing halfway.
+    pub(crate) fn raw_writer(&self) -> Result<RawWriter<'_>, ReadModelError> {
+        let storage = self
             .storage
             .write()
             .map_err(|_| ReadModelError::Storage("lock poisoned".into()))?;
+        Ok(RawWriter { storage })
+    }
+}
 
-        let new_version = storage
+/// Exclusive access to an [`InMemoryReadModelStore`] for raw writes.
+pub(crate) struct RawWriter<'a> {
+    storage: RwLockWriteGuard<'a, Models>,
+}
+
+impl RawWriter<'_> {
+    /// Version of the model under `key`, if stored.
+    pub fn version(&self, key: &str) -> Option<u64> {
+        self.storage.get(key).map(|s| s.version)
+    }
+
+    /// Remove the model under `key`. Returns whether it existed.
+    pub fn delete(&mut self, key: &str) -> bool {
+        self.storage.remove(key).is_some()
+    }
+
+    /// Save pre-serialized bytes under `key`. Returns the new version.
+    pub fn upsert(&mut self, key: &str, bytes: Vec<u8>) -> u64 {
+        let new_version = self
+            .storage
             .get(key)
             .map(|s| s.version + 1)
             .unwrap_or(1);
 
-        storage.insert(
+        self.storage.insert(
             key.to_string(),
             StoredModel {
                 bytes,
@@ -57,7 +169,7 @@ impl InMemoryReadModelStore {
             },
         );
 
-        Ok(new_version)
+        new_version
     }
 }
 
@@ -123,7 +235,7 @@ impl ReadModelStore for InMemoryReadModelStore {
                 collection: M::COLLECTION.to_string(),
                 id: model.id().to_string(),
                 expected: 0,
-                actual: storage[&key].version,
+                actual: storage.get(&key).map_or(0, |s| s.version),
             });
         }
 
@@ -225,6 +337,100 @@ impl ReadModelStore for InMemoryReadModelStore {
         Ok(results)
     }
 
+    fn find_models_page<M: ReadModel>(
+        &self,
+        predicate: &dyn Fn(&M) -> bool,
+        request: &PageRequest,
+    ) -> Result<Page<Versioned<M>>, ReadModelError> {
+        let storage = self
+            .storage
+            .read()
+            .map_err(|_| ReadModelError::Storage("lock poisoned".into()))?;
+
+        let prefix = format!("{}:", M::COLLECTION);
+        let mut entries: Vec<(&str, &StoredModel)> = storage
+            .iter()
+            .filter_map(|(key, stored)| Some((key.strip_prefix(&prefix)?, stored)))
+            .filter(|(id, _)| request.includes(id))
+            .collect();
+        entries.sort_unstable_by_key(|(id, _)| *id);
+
+        let candidates = entries.into_iter().filter_map(|(_, stored)| {
+            let data = serde_json::from_slice::<M>(&stored.bytes).ok()?;
+            Some(Ok(Versioned {
+                data,
+                version: stored.version,
+            }))
+        });
+        Page::fill(request, candidates, |m| predicate(&m.data), |m| m.data.id().to_string())
+    }
+
+    fn query_models<M: ReadModel>(
+        &self,
+        query: &Query,
+    ) -> Result<Vec<Versioned<M>>, ReadModelError> {
+        let storage = self
+            .storage
+            .read()
+            .map_err(|_| ReadModelError::Storage("lock poisoned".into()))?;
+
+        let prefix = format!("{}:", M::COLLECTION);
+        let mut entries: Vec<(&str, &StoredModel)> = storage
+            .iter()
+            .filter_map(|(key, stored)| Some((key.strip_prefix(&prefix)?, stored)))
+            .collect();
+        entries.sort_unstable_by_key(|(id, _)| *id);
+
+        // Filter and sort on the stored JSON; only the results are decoded.
+        let docs = entries.into_iter().filter_map(|(_, stored)| {
+            let doc = serde_json::from_slice::<serde_json::Value>(&stored.bytes).ok()?;
+            Some((doc, stored))
+        });
+        query
+            .apply(docs)
+            .into_iter()
+            .map(|stored| {
+                let data = serde_json::from_slice::<M>(&stored.bytes)
+                    .map_err(|e| ReadModelError::Serde(e.to_string()))?;
+                Ok(Versioned {
+                    data,
+                    version: stored.version,
+                })
+            })
+            .collect()
+    }
+
+    fn find_models_by<M: ReadModel>(
+        &self,
+        field: &str,
+        value: &Value,
+    ) -> Result<Vec<Versioned<M>>, ReadModelError> {
+        check_indexed::<M>(field)?;
+        self.ensure_indexes::<M>()?;
+
+        let storage = self
+            .storage
+            .read()
+            .map_err(|_| ReadModelError::Storage("lock poisoned".into()))?;
+        let ids = storage
+            .indexes
+            .ids(M::COLLECTION, field, value)
+            .unwrap_or_default();
+
+        let mut results = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(stored) = storage.get(&Self::make_key(M::COLLECTION, &id)) {
+                let data = serde_json::from_slice::<M>(&stored.bytes)
+                    .map_err(|e| ReadModelError::Serde(e.to_string()))?;
+                results.push(Versioned {
+                    data,
+                    version: stored.version,
+                });
+            }
+        }
+        Ok(results)
+    }
+
     fn find_one_model<M: ReadModel>(
         &self,
         predicate: &dyn Fn(&M) -> bool,
@@ -256,6 +462,69 @@ impl ReadModelStore for InMemoryReadModelStore {
         self.save_raw(key, bytes)?;
         Ok(())
     }
+
+    /// Writes every model under one write lock.
+    fn upsert_raw_many(&self, models: Vec<(String, Vec<u8>)>) -> Result<(), ReadModelError> {
+        let mut writer = self.raw_writer()?;
+        for (key, bytes) in models {
+            writer.upsert(&key, bytes);
+        }
+        Ok(())
+    }
+
+    fn get_raw(&self, key: &str) -> Result<Option<Vec<u8>>, ReadModelError> {
+        let storage = self
+            .storage
+            .read()
+            .map_err(|_| ReadModelError::Storage("lock poisoned".into()))?;
+        Ok(storage.get(key).map(|s| s.bytes.clone()))
+    }
+
+    fn delete_collection(&self, collection: &str) -> Result<usize, ReadModelError> {
+        let mut storage = self
+            .storage
+            .write()
+            .map_err(|_| ReadModelError::Storage("lock poisoned".into()))?;
+
+        let prefix = format!("{}:", collection);
+        let before = storage.len();
+        storage.retain(|key| !key.starts_with(&prefix));
+        Ok(before - storage.len())
+    }
+
+    fn swap_collections(
+        &self,
+        swaps: &[(&str, &str)],
+        models: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), ReadModelError> {
+        let mut storage = self
+            .storage
+            .write()
+            .map_err(|_| ReadModelError::Storage("lock poisoned".into()))?;
+
+        for (shadow, live) in swaps {
+            let shadow_prefix = format!("{}:", shadow);
+            let live_prefix = format!("{}:", live);
+            storage.retain(|key| !key.starts_with(&live_prefix));
+
+            let moved: Vec<String> = storage
+                .keys()
+                .filter(|key| key.starts_with(&shadow_prefix))
+                .cloned()
+                .collect();
+            for key in moved {
+                let stored = storage.remove(&key).expect("key was just listed");
+                let id = &key[shadow_prefix.len()..];
+                storage.insert(Self::make_key(live, id), stored);
+            }
+        }
+
+        for (key, bytes) in models {
+            let version = storage.get(&key).map(|s| s.version + 1).unwrap_or(1);
+            storage.insert(key, StoredModel { bytes, version });
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -415,6 +684,27 @@ mod tests {
         assert_eq!(results.len(), 2);
     }
 
+    #[test]
+    fn swap_collections_replaces_live_models() {
+        let store = InMemoryReadModelStore::new();
+        store.upsert_raw("test_models:old", b"{}".to_vec()).unwrap();
+        store
+            .upsert_raw("test_models__rebuild:1", br#"{"id":"1","value":7}"#.to_vec())
+            .unwrap();
+        store.upsert_raw("other:1", b"{}".to_vec()).unwrap();
+
+        let checkpoint = ("other:1".to_string(), b"[]".to_vec());
+        store
+            .swap_collections(&[("test_models__rebuild", "test_models")], vec![checkpoint])
+            .unwrap();
+
+        assert!(store.get_raw("test_models:old").unwrap().is_none());
+        assert!(store.get_raw("test_models__rebuild:1").unwrap().is_none());
+        assert_eq!(store.get_model::<TestModel>("1").unwrap().unwrap().data.value, 7);
+        assert_eq!(store.get_raw("other:1").unwrap().unwrap(), b"[]");
+        assert_eq!(store.delete_collection("other").unwrap(), 1);
+    }
+
     #[test]
     fn find_one_with_predicate() {
         let store = InMemoryReadModelStore::new();
@@ -459,4 +749,30 @@ mod tests {
         let loaded = clone.get_model::<TestModel>("1").unwrap().unwrap();
         assert_eq!(loaded.data.value, 42);
     }
+
+    #[test]
+    fn query_models_filters_sorts_and_pages() {
+        let store = InMemoryReadModelStore::new();
+        for (id, value) in [("a", 4), ("b", 9), ("c", 1), ("d", 7)] {
+            store
+                .upsert(&TestModel {
+                    id: id.into(),
+                    value,
+                })
+                .unwrap();
+        }
+        store.upsert_raw("test_models:broken", b"not json".to_vec()).unwrap();
+
+        let query = Query::new()
+            .gt("value", 2)
+            .sort_by("value", crate::read_model::SortOrder::Descending)
+            .with_offset(1);
+        let results = store.query_models::<TestModel>(&query).unwrap();
+        let ids: Vec<&str> = results.iter().map(|m| m.data.id.as_str()).collect();
+        assert_eq!(ids, vec!["d", "a"]);
+
+        let all = store.query_models::<TestModel>(&Query::new()).unwrap();
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[0].data.id, "a");
+    }
 }
@@ -0,0 +1,185 @@
+//! Field indexes for read model collections, declared with
+//! `ReadModel::INDEXES` and maintained by `InMemoryReadModelStore` on every
+//! write.
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde_json::Value;
+
+use super::query::field;
+use super::{ReadModel, ReadModelError};
+
+/// Fail with `UnknownIndex` unless `field` is one of `M::INDEXES`.
+pub(crate) fn check_indexed<M: ReadModel>(field: &str) -> Result<(), ReadModelError> {
+    if M::INDEXES.contains(&field) {
+        return Ok(());
+    }
+    Err(ReadModelError::UnknownIndex {
+        collection: M::COLLECTION.to_string(),
+        field: field.to_string(),
+    })
+}
+
+/// The key a field value is indexed under. Numbers are normalized so that
+/// `5` and `5.0` share a key.
+fn index_key(value: &Value) -> String {
+    let Value::Number(n) = value else {
+        return value.to_string();
+    };
+    if let Some(i) = n.as_i64() {
+        return i.to_string();
+    }
+    if let Some(u) = n.as_u64() {
+        return u.to_string();
+    }
+    match n.as_f64() {
+        Some(f) if f.fract() == 0.0 && f.abs() < 9.0e15 => (f as i64).to_string(),
+        _ => n.to_string(),
+    }
+}
+
+/// One indexed field: IDs by value, and the value each ID is filed under.
+#[derive(Debug)]
+struct FieldIndex {
+    field: String,
+    by_value: HashMap<String, BTreeSet<String>>,
+    values: HashMap<String, String>,
+}
+
+impl FieldIndex {
+    fn insert(&mut self, id: &str, doc: &Value) {
+        self.remove(id);
+        let Some(value) = field(doc, &self.field) else {
+            return;
+        };
+        let key = index_key(value);
+        self.by_value.entry(key.clone()).or_default().insert(id.to_string());
+        self.values.insert(id.to_string(), key);
+    }
+
+    fn remove(&mut self, id: &str) {
+        let Some(key) = self.values.remove(id) else {
+            return;
+        };
+        if let Some(ids) = self.by_value.get_mut(&key) {
+            ids.remove(id);
+            if ids.is_empty() {
+                self.by_value.remove(&key);
+            }
+        }
+    }
+}
+
+/// The field indexes of every collection a store has been asked to index.
+#[derive(Debug, Default)]
+pub(crate) struct ModelIndexes {
+    collections: HashMap<String, Vec<FieldIndex>>,
+}
+
+impl ModelIndexes {
+    pub fn is_registered(&self, collection: &str) -> bool {
+        self.collections.contains_key(collection)
+    }
+
+    /// Index `fields` of `collection`, filling the indexes from `models`
+    /// (`(id, bytes)` pairs of the collection).
+    pub fn register<'a>(
+        &mut self,
+        collection: &str,
+        fields: &[&str],
+        models: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+    ) {
+        let mut indexes: Vec<FieldIndex> = fields
+            .iter()
+            .map(|name| FieldIndex {
+                field: name.to_string(),
+                by_value: HashMap::new(),
+                values: HashMap::new(),
+            })
+            .collect();
+        if !indexes.is_empty() {
+            for (id, bytes) in models {
+                if let Ok(doc) = serde_json::from_slice::<Value>(bytes) {
+                    for index in &mut indexes {
+                        index.insert(id, &doc);
+                    }
+                }
+            }
+        }
+        self.collections.insert(collection.to_string(), indexes);
+    }
+
+    /// Update the indexes for a model written under `key` (`"collection:id"`).
+    pub fn insert(&mut self, key: &str, bytes: &[u8]) {
+        let Some((collection, id)) = key.split_once(':') else {
+            return;
+        };
+        let Some(indexes) = self.collections.get_mut(collection) else {
+            return;
+        };
+        if indexes.is_empty() {
+            return;
+        }
+        match serde_json::from_slice::<Value>(bytes) {
+            Ok(doc) => indexes.iter_mut().for_each(|index| index.insert(id, &doc)),
+            Err(_) => indexes.iter_mut().for_each(|index| index.remove(id)),
+        }
+    }
+
+    /// Drop the model stored under `key` from the indexes.
+    pub fn remove(&mut self, key: &str) {
+        let Some((collection, id)) = key.split_once(':') else {
+            return;
+        };
+        if let Some(indexes) = self.collections.get_mut(collection) {
+            indexes.iter_mut().for_each(|index| index.remove(id));
+        }
+    }
+
+    /// IDs of the models whose `field` equals `value`, in ID order, or `None`
+    /// if the field is not indexed.
+    pub fn ids(&self, collection: &str, field: &str, value: &Value) -> Option<Vec<String>> {
+        let index = self
+            .collections
+            .get(collection)?
+            .iter()
+            .find(|index| index.field == field)?;
+        let ids = index
+            .by_value
+            .get(&index_key(value))
+            .map(|ids| ids.iter().cloned().collect())
+            .unwrap_or_default();
+        Some(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn writes_move_models_between_values() {
+        let alice = json!({"id": "g1", "player": {"name": "alice"}, "level": 2}).to_string();
+        let existing = [("g1", alice.as_bytes())];
+
+        let mut indexes = ModelIndexes::default();
+        indexes.register("games", &["player.name", "level"], existing);
+        assert_eq!(indexes.ids("games", "player.name", &json!("alice")).unwrap(), vec!["g1"]);
+        assert_eq!(indexes.ids("games", "level", &json!(2.0)).unwrap(), vec!["g1"]);
+
+        let bob = json!({"id": "g1", "player": {"name": "bob"}, "level": 2}).to_string();
+        indexes.insert("games:g1", bob.as_bytes());
+        indexes.insert("games:g2", bob.as_bytes());
+        assert!(indexes.ids("games", "player.name", &json!("alice")).unwrap().is_empty());
+        assert_eq!(
+            indexes.ids("games", "player.name", &json!("bob")).unwrap(),
+            vec!["g1", "g2"]
+        );
+
+        indexes.remove("games:g1");
+        assert_eq!(indexes.ids("games", "level", &json!(2)).unwrap(), vec!["g2"]);
+        assert_eq!(indexes.ids("games", "score", &json!(2)), None);
+        assert_eq!(indexes.ids("other", "level", &json!(2)), None);
+    }
+}
@@ -0,0 +1,87 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::lock::LockManager;
+
+use super::{QueuedReadModelStore, ReadModel, ReadModelError, ReadModelStore, Versioned};
+
+/// A read model read with
+/// [`get_locked`](QueuedReadModelStore::get_locked), holding its lock.
+///
+/// Derefs to the model. The lock is released when the guard is dropped,
+/// unless the model was written back with [`update`](Self::update) or
+/// [`delete`](Self::delete) (which release it) or taken with
+/// [`into_inner`](Self::into_inner).
+pub struct LockedModel<'a, S, L: LockManager, M: ReadModel> {
+    store: &'a QueuedReadModelStore<S, L>,
+    model: Option<Versioned<M>>,
+}
+
+impl<'a, S, L: LockManager, M: ReadModel> LockedModel<'a, S, L, M> {
+    pub(crate) fn new(store: &'a QueuedReadModelStore<S, L>, model: Versioned<M>) -> Self {
+        Self {
+            store,
+            model: Some(model),
+        }
+    }
+
+    fn versioned(&self) -> &Versioned<M> {
+        self.model.as_ref().expect("model is only taken on consume")
+    }
+
+    /// The version the model was read at.
+    pub fn version(&self) -> u64 {
+        self.versioned().version
+    }
+
+    /// Release the lock now, discarding any changes.
+    pub fn abort(mut self) -> Result<(), ReadModelError> {
+        let model = self.model.take().expect("model is only taken on consume");
+        self.store.unlock::<M>(model.data.id())
+    }
+
+    /// Take the model, keeping the lock. It is then released by a later
+    /// write or `unlock`, as with `get_model`.
+    pub fn into_inner(mut self) -> Versioned<M> {
+        self.model.take().expect("model is only taken on consume")
+    }
+}
+
+impl<S: ReadModelStore, L: LockManager, M: ReadModel> LockedModel<'_, S, L, M> {
+    /// Save the changed model, expecting the version it was read at.
+    pub fn update(mut self) -> Result<Versioned<M>, ReadModelError> {
+        let Versioned { data, version } = self.versioned();
+        let updated = self.store.update(data, *version)?;
+        self.model = None;
+        Ok(updated)
+    }
+
+    /// Delete the model. Returns whether it still existed.
+    pub fn delete(mut self) -> Result<bool, ReadModelError> {
+        let deleted = self.store.delete::<M>(self.versioned().data.id())?;
+        self.model = None;
+        Ok(deleted)
+    }
+}
+
+impl<S, L: LockManager, M: ReadModel> Deref for LockedModel<'_, S, L, M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        &self.versioned().data
+    }
+}
+
+impl<S, L: LockManager, M: ReadModel> DerefMut for LockedModel<'_, S, L, M> {
+    fn deref_mut(&mut self) -> &mut M {
+        &mut self.model.as_mut().expect("model is only taken on consume").data
+    }
+}
+
+impl<S, L: LockManager, M: ReadModel> Drop for LockedModel<'_, S, L, M> {
+    fn drop(&mut self) {
+        // `None` once written back or taken, which own the lock from then on.
+        if let Some(model) = &self.model {
+            let _ = self.store.unlock::<M>(model.data.id());
+        }
+    }
+}
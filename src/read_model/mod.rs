@@ -22,6 +22,9 @@
 //! ```
 
 mod in_memory;
+mod index;
+mod locked;
+mod query;
 mod queued;
 mod repository;
 mod store;
@@ -35,6 +38,11 @@ pub trait ReadModel: Serialize + DeserializeOwned + Clone + Send + Sync {
     /// Maps to a table in SQL, a collection in MongoDB, a key prefix in KV stores, etc.
     const COLLECTION: &'static str;
 
+    /// Fields indexed by stores that support read model indexes, for lookups
+    /// with [`ReadModelStore::find_models_by`]. Names are keys in the
+    /// serialized JSON; dots reach nested fields.
+    const INDEXES: &'static [&'static str] = &[];
+
     /// Returns the unique identifier for this read model instance.
     fn id(&self) -> &str;
 }
@@ -64,6 +72,11 @@ pub enum ReadModelError {
     NotFound { collection: String, id: String },
     /// Lock error.
     Lock(crate::lock::LockError),
+    /// Lookup on a field not listed in `ReadModel::INDEXES`.
+    UnknownIndex { collection: String, field: String },
+    /// The store does not implement an optional operation, e.g. the raw
+    /// collection access projection rebuilds need.
+    Unsupported(&'static str),
 }
 
 impl fmt::Display for ReadModelError {
@@ -85,6 +98,12 @@ impl fmt::Display for ReadModelError {
                 write!(f, "read model not found: {}:{}", collection, id)
             }
             ReadModelError::Lock(err) => write!(f, "read model lock error: {}", err),
+            ReadModelError::UnknownIndex { collection, field } => {
+                write!(f, "no read model index on {}.{}", collection, field)
+            }
+            ReadModelError::Unsupported(operation) => {
+                write!(f, "read model store does not support {}", operation)
+            }
         }
     }
 }
@@ -98,6 +117,8 @@ impl From<crate::lock::LockError> for ReadModelError {
 }
 
 pub use in_memory::InMemoryReadModelStore;
+pub use locked::LockedModel;
+pub use query::{Filter, FilterOp, Query, Sort, SortOrder};
 pub use queued::QueuedReadModelStore;
 pub use repository::{ReadModelRepository, ReadModelsExt};
 pub use store::ReadModelStore;
@@ -0,0 +1,294 @@
+//! Query - Declarative filters, sorting and paging over read models.
+//!
+//! Unlike the predicates taken by `find_models`, a [`Query`] is plain data, so
+//! a backend can translate it into its own query language. Fields are named
+//! by their key in the model's serialized JSON; nested fields use dots
+//! (`"address.city"`).
+
+use std::cmp::Ordering;
+
+use serde_json::Value;
+
+/// A filter on one field.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    pub field: String,
+    pub op: FilterOp,
+}
+
+/// The comparison a [`Filter`] applies.
+///
+/// Numbers compare numerically and strings lexicographically. Values of
+/// different types never match, and a missing field only matches `Eq(Null)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterOp {
+    Eq(Value),
+    Gt(Value),
+    Gte(Value),
+    Lt(Value),
+    Lte(Value),
+    /// The field equals one of the values.
+    In(Vec<Value>),
+    /// The field is a string starting with the prefix.
+    Prefix(String),
+}
+
+/// Sort direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+/// A sort key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sort {
+    pub field: String,
+    pub order: SortOrder,
+}
+
+/// A declarative read model query: filters (all of which must match), sort
+/// keys, and an offset and limit.
+///
+/// Results are in ID order unless sorted, and ties between sort keys keep
+/// ID order.
+///
+/// ## Example
+///
+/// ```ignore
+/// let query = Query::new()
+///     .eq("user_id", "alice")
+///     .gte("score", 100)
+///     .sort_by("score", SortOrder::Descending)
+///     .with_limit(10);
+/// let top = store.read_models::<PlayerView>().query(&query)?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    filters: Vec<Filter>,
+    sort: Vec<Sort>,
+    offset: usize,
+    limit: Option<usize>,
+}
+
+impl Query {
+    /// A query matching every model.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a filter.
+    pub fn filter(mut self, field: impl Into<String>, op: FilterOp) -> Self {
+        self.filters.push(Filter {
+            field: field.into(),
+            op,
+        });
+        self
+    }
+
+    pub fn eq(self, field: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.filter(field, FilterOp::Eq(value.into()))
+    }
+
+    pub fn gt(self, field: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.filter(field, FilterOp::Gt(value.into()))
+    }
+
+    pub fn gte(self, field: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.filter(field, FilterOp::Gte(value.into()))
+    }
+
+    pub fn lt(self, field: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.filter(field, FilterOp::Lt(value.into()))
+    }
+
+    pub fn lte(self, field: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.filter(field, FilterOp::Lte(value.into()))
+    }
+
+    pub fn is_in<V: Into<Value>>(
+        self,
+        field: impl Into<String>,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        let values = values.into_iter().map(Into::into).collect();
+        self.filter(field, FilterOp::In(values))
+    }
+
+    pub fn prefix(self, field: impl Into<String>, prefix: impl Into<String>) -> Self {
+        self.filter(field, FilterOp::Prefix(prefix.into()))
+    }
+
+    /// Add a sort key. Later keys break ties between earlier ones.
+    pub fn sort_by(mut self, field: impl Into<String>, order: SortOrder) -> Self {
+        self.sort.push(Sort {
+            field: field.into(),
+            order,
+        });
+        self
+    }
+
+    /// Skip the first `offset` results.
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Return at most `limit` results.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn filters(&self) -> &[Filter] {
+        &self.filters
+    }
+
+    pub fn sort(&self) -> &[Sort] {
+        &self.sort
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Whether a serialized model matches every filter.
+    pub fn matches(&self, doc: &Value) -> bool {
+        self.filters.iter().all(|filter| filter.matches(doc))
+    }
+
+    /// Filter, sort and page `docs`, which must be in ID order. Each doc is
+    /// paired with the item to return for it.
+    pub fn apply<T>(&self, docs: impl IntoIterator<Item = (Value, T)>) -> Vec<T> {
+        let mut matched: Vec<(Value, T)> = docs
+            .into_iter()
+            .filter(|(doc, _)| self.matches(doc))
+            .collect();
+
+        if !self.sort.is_empty() {
+            matched.sort_by(|(a, _), (b, _)| {
+                self.sort
+                    .iter()
+                    .map(|sort| {
+                        let ordering = sort_cmp(field(a, &sort.field), field(b, &sort.field));
+                        match sort.order {
+                            SortOrder::Ascending => ordering,
+                            SortOrder::Descending => ordering.reverse(),
+                        }
+                    })
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            });
+        }
+
+        let limit = self.limit.unwrap_or(usize::MAX);
+        matched
+            .into_iter()
+            .skip(self.offset)
+            .take(limit)
+            .map(|(_, item)| item)
+            .collect()
+    }
+}
+
+impl Filter {
+    fn matches(&self, doc: &Value) -> bool {
+        let value = field(doc, &self.field).unwrap_or(&Value::Null);
+        match &self.op {
+            FilterOp::Eq(expected) => compare(value, expected) == Some(Ordering::Equal),
+            FilterOp::Gt(bound) => compare(value, bound) == Some(Ordering::Greater),
+            FilterOp::Gte(bound) => compare(value, bound).is_some_and(Ordering::is_ge),
+            FilterOp::Lt(bound) => compare(value, bound) == Some(Ordering::Less),
+            FilterOp::Lte(bound) => compare(value, bound).is_some_and(Ordering::is_le),
+            FilterOp::In(values) => values
+                .iter()
+                .any(|expected| compare(value, expected) == Some(Ordering::Equal)),
+            FilterOp::Prefix(prefix) => value
+                .as_str()
+                .is_some_and(|s| s.starts_with(prefix.as_str())),
+        }
+    }
+}
+
+/// Look up a dotted field path in a JSON object.
+pub(crate) fn field<'a>(doc: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(doc, |value, key| value.get(key))
+}
+
+/// Compare two values of the same type. `None` for mismatched types, and for
+/// arrays and objects unless they are equal.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Number(x), Value::Number(y)) => {
+            if let (Some(x), Some(y)) = (x.as_i64(), y.as_i64()) {
+                Some(x.cmp(&y))
+            } else if let (Some(x), Some(y)) = (x.as_u64(), y.as_u64()) {
+                Some(x.cmp(&y))
+            } else {
+                x.as_f64()?.partial_cmp(&y.as_f64()?)
+            }
+        }
+        _ => (a == b).then_some(Ordering::Equal),
+    }
+}
+
+/// Total order for sorting: missing and null first, then booleans, numbers,
+/// strings, arrays and objects.
+fn sort_cmp(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+    fn rank(value: Option<&Value>) -> u8 {
+        match value {
+            None | Some(Value::Null) => 0,
+            Some(Value::Bool(_)) => 1,
+            Some(Value::Number(_)) => 2,
+            Some(Value::String(_)) => 3,
+            Some(Value::Array(_)) => 4,
+            Some(Value::Object(_)) => 5,
+        }
+    }
+    rank(a).cmp(&rank(b)).then_with(|| match (a, b) {
+        (Some(a), Some(b)) => compare(a, b).unwrap_or(Ordering::Equal),
+        _ => Ordering::Equal,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn docs() -> Vec<(Value, &'static str)> {
+        vec![
+            (json!({"id": "a", "score": 10, "name": "Alpha", "team": {"city": "Oslo"}}), "a"),
+            (json!({"id": "b", "score": 30, "name": "Beta", "team": {"city": "Rome"}}), "b"),
+            (json!({"id": "c", "score": 20.5, "name": "Alpine"}), "c"),
+            (json!({"id": "d", "score": "n/a", "name": "Delta", "team": {"city": "Oslo"}}), "d"),
+        ]
+    }
+
+    #[test]
+    fn filters_compare_by_type() {
+        let run = |query: Query| query.apply(docs());
+        assert_eq!(run(Query::new().gt("score", 15)), vec!["b", "c"]);
+        assert_eq!(run(Query::new().gte("score", 10).lt("score", 30)), vec!["a", "c"]);
+        assert_eq!(run(Query::new().eq("score", 10.0)), vec!["a"]);
+        assert_eq!(run(Query::new().prefix("name", "Alp")), vec!["a", "c"]);
+        assert_eq!(run(Query::new().is_in("team.city", ["Rome", "Paris"])), vec!["b"]);
+        assert_eq!(run(Query::new().eq("team.city", Value::Null)), vec!["c"]);
+    }
+
+    #[test]
+    fn sorts_then_pages() {
+        let query = Query::new()
+            .sort_by("team.city", SortOrder::Ascending)
+            .sort_by("name", SortOrder::Descending);
+        assert_eq!(query.apply(docs()), vec!["c", "d", "a", "b"]);
+        assert_eq!(query.with_offset(1).with_limit(2).apply(docs()), vec!["d", "a"]);
+    }
+}
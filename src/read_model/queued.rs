@@ -5,13 +5,15 @@
 //! `delete`, `upsert_raw`) release it. Callers can also release manually via `unlock`.
 
 use std::sync::Arc;
+use std::time::Duration;
 
+use crate::commit_builder::{AtomicCommit, WriteSet};
 use crate::entity::Committable;
-use crate::lock::{InMemoryLockManager, Lock, LockManager};
+use crate::lock::{self, InMemoryLockManager, Lock, LockError, LockManager};
 use crate::queued_repo::ReadOpts;
-use crate::repository::{Commit, RepositoryError};
+use crate::repository::{Commit, Page, PageRequest, RepositoryError};
 
-use super::{ReadModel, ReadModelError, ReadModelStore, Versioned};
+use super::{LockedModel, Query, ReadModel, ReadModelError, ReadModelStore, Versioned};
 
 /// A `ReadModelStore` wrapper that provides per-instance locking.
 ///
@@ -25,6 +27,7 @@ use super::{ReadModel, ReadModelError, ReadModelStore, Versioned};
 pub struct QueuedReadModelStore<S, L: LockManager = InMemoryLockManager> {
     inner: S,
     lock_manager: L,
+    lock_timeout: Option<Duration>,
 }
 
 impl<S> QueuedReadModelStore<S> {
@@ -32,6 +35,7 @@ impl<S> QueuedReadModelStore<S> {
         QueuedReadModelStore {
             inner,
             lock_manager: InMemoryLockManager::new(),
+            lock_timeout: None,
         }
     }
 }
@@ -42,9 +46,17 @@ impl<S, L: LockManager> QueuedReadModelStore<S, L> {
         QueuedReadModelStore {
             inner,
             lock_manager,
+            lock_timeout: None,
         }
     }
 
+    /// Fail locked reads with `LockError::AcquireFailed` when a model's lock
+    /// is not acquired within `timeout`, instead of waiting indefinitely.
+    pub fn with_lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
     /// Access the inner store.
     pub fn inner(&self) -> &S {
         &self.inner
@@ -58,8 +70,7 @@ impl<S, L: LockManager> QueuedReadModelStore<S, L> {
     /// Manually lock a read model instance.
     pub fn lock<M: ReadModel>(&self, id: &str) -> Result<(), ReadModelError> {
         let key = Self::make_key(M::COLLECTION, id);
-        let lock = self.ensure_lock(&key)?;
-        lock.lock()?;
+        self.acquire(&key, false)?;
         Ok(())
     }
 
@@ -86,21 +97,97 @@ impl<S, L: LockManager> QueuedReadModelStore<S, L> {
         Ok(self.lock_manager.get_lock(key)?)
     }
 
-    fn lock_ids_in_order(&self, keys: &[String]) -> Result<Vec<Arc<L::Lock>>, ReadModelError> {
+    /// Wait for the lock on `key`, up to the lock timeout if one is set.
+    fn acquire(&self, key: &str, shared: bool) -> Result<Arc<L::Lock>, ReadModelError> {
+        let lock = self.ensure_lock(key)?;
+        lock::acquire(&*lock, shared, self.lock_timeout).map_err(|e| match e {
+            LockError::AcquireFailed(msg) => {
+                LockError::AcquireFailed(format!("read model '{}': {}", key, msg))
+            }
+            other => other,
+        })?;
+        Ok(lock)
+    }
+
+    fn lock_ids_in_order(
+        &self,
+        keys: &[String],
+        shared: bool,
+    ) -> Result<Vec<Arc<L::Lock>>, ReadModelError> {
         let mut unique = keys.to_vec();
         unique.sort_unstable();
         unique.dedup();
 
         let mut locks = Vec::with_capacity(unique.len());
         for key in &unique {
-            let lock = self.ensure_lock(key)?;
-            lock.lock()?;
-            locks.push(lock);
+            match self.acquire(key, shared) {
+                Ok(lock) => locks.push(lock),
+                Err(e) => {
+                    // A timeout part-way through: release what was taken
+                    for lock in locks {
+                        let _ = lock::release(&*lock, shared);
+                    }
+                    return Err(e);
+                }
+            }
         }
 
         Ok(locks)
     }
 
+    /// Run `read` holding shared locks on `keys`, released before returning.
+    fn read_shared<T>(
+        &self,
+        keys: &[String],
+        read: impl FnOnce() -> Result<T, ReadModelError>,
+    ) -> Result<T, ReadModelError> {
+        let locks = self.lock_ids_in_order(keys, true)?;
+        let result = read();
+        // Release every lock even if one fails, reporting the read's own
+        // error first
+        let mut released = Ok(());
+        for lock in locks {
+            if let Err(e) = lock.unlock_shared() {
+                released = released.and(Err(e));
+            }
+        }
+        let value = result?;
+        released?;
+        Ok(value)
+    }
+
+    /// Lock `matches` in key order and re-read them, keeping the ones that
+    /// still match `query` and releasing the rest.
+    fn lock_matches<M: ReadModel>(
+        &self,
+        matches: Vec<Versioned<M>>,
+        query: &Query,
+    ) -> Result<Vec<Versioned<M>>, ReadModelError>
+    where
+        S: ReadModelStore,
+    {
+        let keys: Vec<String> = matches
+            .iter()
+            .map(|v| Self::make_key(M::COLLECTION, v.data.id()))
+            .collect();
+        let _locks = self.lock_ids_in_order(&keys, false)?;
+
+        let mut results = Vec::with_capacity(matches.len());
+        for versioned in &matches {
+            let id = versioned.data.id();
+            match self.inner.get_model::<M>(id)? {
+                Some(current)
+                    if serde_json::to_value(&current.data).is_ok_and(|doc| query.matches(&doc)) =>
+                {
+                    results.push(current)
+                }
+                _ => self.release(&Self::make_key(M::COLLECTION, id)),
+            }
+        }
+
+        Ok(results)
+    }
+
     fn make_key(collection: &str, id: &str) -> String {
         format!("{}:{}", collection, id)
     }
@@ -113,8 +200,7 @@ impl<S, L: LockManager> QueuedReadModelStore<S, L> {
 impl<S: ReadModelStore, L: LockManager> ReadModelStore for QueuedReadModelStore<S, L> {
     fn get_model<M: ReadModel>(&self, id: &str) -> Result<Option<Versioned<M>>, ReadModelError> {
         let key = Self::make_key(M::COLLECTION, id);
-        let lock = self.ensure_lock(&key)?;
-        lock.lock()?;
+        self.acquire(&key, false)?;
         self.inner.get_model(id)
     }
 
@@ -170,7 +256,7 @@ impl<S: ReadModelStore, L: LockManager> ReadModelStore for QueuedReadModelStore<
             .iter()
             .map(|v| Self::make_key(M::COLLECTION, v.data.id()))
             .collect();
-        let _locks = self.lock_ids_in_order(&keys)?;
+        let _locks = self.lock_ids_in_order(&keys, false)?;
 
         // Phase 3: re-fetch with locks held to ensure consistency
         let mut results = Vec::new();
@@ -192,6 +278,54 @@ impl<S: ReadModelStore, L: LockManager> ReadModelStore for QueuedReadModelStore<
         Ok(results)
     }
 
+    fn find_models_page<M: ReadModel>(
+        &self,
+        predicate: &dyn Fn(&M) -> bool,
+        request: &PageRequest,
+    ) -> Result<Page<Versioned<M>>, ReadModelError> {
+        // Same phases as `find_models`, for one page
+        let page = self.inner.find_models_page(predicate, request)?;
+
+        let keys: Vec<String> = page
+            .items
+            .iter()
+            .map(|v| Self::make_key(M::COLLECTION, v.data.id()))
+            .collect();
+        let _locks = self.lock_ids_in_order(&keys, false)?;
+
+        let mut items = Vec::with_capacity(page.items.len());
+        for versioned in &page.items {
+            let id = versioned.data.id();
+            match self.inner.get_model::<M>(id)? {
+                Some(current) if predicate(&current.data) => items.push(current),
+                _ => self.release(&Self::make_key(M::COLLECTION, id)),
+            }
+        }
+
+        Ok(Page {
+            items,
+            next_cursor: page.next_cursor,
+        })
+    }
+
+    fn query_models<M: ReadModel>(
+        &self,
+        query: &Query,
+    ) -> Result<Vec<Versioned<M>>, ReadModelError> {
+        // Same phases as `find_models`; the results keep the inner store's order.
+        let matches = self.inner.query_models::<M>(query)?;
+        self.lock_matches(matches, query)
+    }
+
+    fn find_models_by<M: ReadModel>(
+        &self,
+        field: &str,
+        value: &serde_json::Value,
+    ) -> Result<Vec<Versioned<M>>, ReadModelError> {
+        let matches = self.inner.find_models_by::<M>(field, value)?;
+        self.lock_matches(matches, &Query::new().eq(field, value.clone()))
+    }
+
     fn find_one_model<M: ReadModel>(
         &self,
         predicate: &dyn Fn(&M) -> bool,
@@ -202,8 +336,7 @@ impl<S: ReadModelStore, L: LockManager> ReadModelStore for QueuedReadModelStore<
         if let Some(versioned) = found {
             let id = versioned.data.id().to_string();
             let key = Self::make_key(M::COLLECTION, &id);
-            let lock = self.ensure_lock(&key)?;
-            lock.lock()?;
+            let lock = self.acquire(&key, false)?;
 
             // Phase 2: re-fetch with lock held
             if let Some(current) = self.inner.get_model::<M>(&id)? {
@@ -225,6 +358,32 @@ impl<S: ReadModelStore, L: LockManager> ReadModelStore for QueuedReadModelStore<
         }
         result
     }
+
+    fn upsert_raw_many(&self, models: Vec<(String, Vec<u8>)>) -> Result<(), ReadModelError> {
+        let keys: Vec<String> = models.iter().map(|(key, _)| key.clone()).collect();
+        self.inner.upsert_raw_many(models)?;
+        for key in &keys {
+            self.release(key);
+        }
+        Ok(())
+    }
+
+    /// Read raw bytes (non-locking).
+    fn get_raw(&self, key: &str) -> Result<Option<Vec<u8>>, ReadModelError> {
+        self.inner.get_raw(key)
+    }
+
+    fn delete_collection(&self, collection: &str) -> Result<usize, ReadModelError> {
+        self.inner.delete_collection(collection)
+    }
+
+    fn swap_collections(
+        &self,
+        swaps: &[(&str, &str)],
+        models: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), ReadModelError> {
+        self.inner.swap_collections(swaps, models)
+    }
 }
 
 // ============================================================================
@@ -235,6 +394,23 @@ impl<S: Commit, L: LockManager> Commit for QueuedReadModelStore<S, L> {
     fn commit<C: Committable + ?Sized>(&self, committable: &mut C) -> Result<(), RepositoryError> {
         self.inner.commit(committable)
     }
+
+    fn release(&self, stream_ids: &[&str]) -> Result<(), RepositoryError> {
+        self.inner.release(stream_ids)
+    }
+}
+
+impl<S: AtomicCommit, L: LockManager> AtomicCommit for QueuedReadModelStore<S, L> {
+    /// Releases the locks on the written read models once the inner store
+    /// has applied the write set.
+    fn commit_write_set(&self, writes: WriteSet<'_>) -> Result<(), RepositoryError> {
+        let keys: Vec<String> = writes.models.iter().map(|m| m.key.clone()).collect();
+        self.inner.commit_write_set(writes)?;
+        for key in &keys {
+            self.release(key);
+        }
+        Ok(())
+    }
 }
 
 // ============================================================================
@@ -242,16 +418,42 @@ impl<S: Commit, L: LockManager> Commit for QueuedReadModelStore<S, L> {
 // ============================================================================
 
 impl<S: ReadModelStore, L: LockManager> QueuedReadModelStore<S, L> {
-    /// Get a read model with options (opt out of locking with `ReadOpts::no_lock()`).
+    /// Like `get_model`, but the lock is held by the returned [`LockedModel`]
+    /// guard and released when it is dropped unless the model is written
+    /// back. A missing model is unlocked right away.
+    pub fn get_locked<M: ReadModel>(
+        &self,
+        id: &str,
+    ) -> Result<Option<LockedModel<'_, S, L, M>>, ReadModelError> {
+        let key = Self::make_key(M::COLLECTION, id);
+        self.acquire(&key, false)?;
+        match self.inner.get_model::<M>(id) {
+            Ok(Some(model)) => Ok(Some(LockedModel::new(self, model))),
+            Ok(None) => {
+                self.release(&key);
+                Ok(None)
+            }
+            Err(err) => {
+                self.release(&key);
+                Err(err)
+            }
+        }
+    }
+
+    /// Get a read model with options (opt out of locking with `ReadOpts::no_lock()`,
+    /// or read under a shared lock with `ReadOpts::shared()`).
     pub fn get_model_with<M: ReadModel>(
         &self,
         id: &str,
         opts: ReadOpts,
     ) -> Result<Option<Versioned<M>>, ReadModelError> {
-        if opts.lock {
-            self.get_model(id)
-        } else {
+        if !opts.lock {
             self.inner.get_model(id)
+        } else if opts.shared {
+            let key = Self::make_key(M::COLLECTION, id);
+            self.read_shared(&[key], || self.inner.get_model(id))
+        } else {
+            self.get_model(id)
         }
     }
 
@@ -261,11 +463,30 @@ impl<S: ReadModelStore, L: LockManager> QueuedReadModelStore<S, L> {
         predicate: &dyn Fn(&M) -> bool,
         opts: ReadOpts,
     ) -> Result<Vec<Versioned<M>>, ReadModelError> {
-        if opts.lock {
-            self.find_models(predicate)
-        } else {
-            self.inner.find_models(predicate)
+        if !opts.lock {
+            return self.inner.find_models(predicate);
         }
+        if !opts.shared {
+            return self.find_models(predicate);
+        }
+
+        let found = self.inner.find_models(predicate)?;
+        let keys: Vec<String> = found
+            .iter()
+            .map(|v| Self::make_key(M::COLLECTION, v.data.id()))
+            .collect();
+        self.read_shared(&keys, || {
+            // Re-fetch with the locks held, as `find_models` does
+            let mut results = Vec::with_capacity(found.len());
+            for versioned in &found {
+                if let Some(current) = self.inner.get_model::<M>(versioned.data.id())? {
+                    if predicate(&current.data) {
+                        results.push(current);
+                    }
+                }
+            }
+            Ok(results)
+        })
     }
 
     /// Find one read model with options.
@@ -274,11 +495,20 @@ impl<S: ReadModelStore, L: LockManager> QueuedReadModelStore<S, L> {
         predicate: &dyn Fn(&M) -> bool,
         opts: ReadOpts,
     ) -> Result<Option<Versioned<M>>, ReadModelError> {
-        if opts.lock {
-            self.find_one_model(predicate)
-        } else {
-            self.inner.find_one_model(predicate)
+        if !opts.lock {
+            return self.inner.find_one_model(predicate);
+        }
+        if !opts.shared {
+            return self.find_one_model(predicate);
         }
+
+        let Some(found) = self.inner.find_one_model(predicate)? else {
+            return Ok(None);
+        };
+        let id = found.data.id().to_string();
+        self.read_shared(&[Self::make_key(M::COLLECTION, &id)], || {
+            Ok(self.inner.get_model::<M>(&id)?.filter(|current| predicate(&current.data)))
+        })
     }
 }
 
@@ -527,7 +757,7 @@ mod tests {
         // get_model locks
         let _loaded = store.get_model::<TestModel>("1").unwrap();
 
-        // upsert_raw releases (this is what CommitBuilder calls)
+        // upsert_raw releases
         let bytes = serde_json::to_vec(&TestModel {
             id: "1".into(),
             value: 99,
@@ -540,4 +770,144 @@ mod tests {
         assert_eq!(reloaded.data.value, 99);
         store.unlock::<TestModel>("1").unwrap();
     }
+
+    #[test]
+    fn locked_model_releases_on_drop_unless_written() {
+        let store = QueuedReadModelStore::new(InMemoryReadModelStore::new());
+        store.inner().upsert(&TestModel {
+            id: "1".into(),
+            value: 10,
+        }).unwrap();
+        let is_locked = || {
+            let lock = store.lock_manager().get_lock("test_models:1").unwrap();
+            let acquired = lock.try_lock().unwrap();
+            if acquired {
+                lock.unlock().unwrap();
+            }
+            !acquired
+        };
+
+        {
+            let mut model = store.get_locked::<TestModel>("1").unwrap().unwrap();
+            assert!(is_locked());
+            model.value = 11;
+        }
+        assert!(!is_locked());
+
+        let mut model = store.get_locked::<TestModel>("1").unwrap().unwrap();
+        assert_eq!(model.value, 10);
+        model.value = 20;
+        let updated = model.update().unwrap();
+        assert_eq!(updated.version, 2);
+        assert!(!is_locked());
+
+        // A stale write fails and the dropped guard still releases the lock
+        let mut model = store.get_locked::<TestModel>("1").unwrap().unwrap();
+        store.inner().upsert(&TestModel {
+            id: "1".into(),
+            value: 30,
+        }).unwrap();
+        model.value = 21;
+        assert!(matches!(
+            model.update(),
+            Err(ReadModelError::ConcurrencyConflict { .. })
+        ));
+        assert!(!is_locked());
+
+        assert!(store.get_locked::<TestModel>("missing").unwrap().is_none());
+        let model = store.get_locked::<TestModel>("1").unwrap().unwrap();
+        assert!(model.delete().unwrap());
+        assert!(!is_locked());
+    }
+
+    #[test]
+    fn lock_timeout_fails_get_of_held_model() {
+        let store = QueuedReadModelStore::new(InMemoryReadModelStore::new())
+            .with_lock_timeout(Duration::from_millis(30));
+        store.inner().upsert(&TestModel {
+            id: "1".into(),
+            value: 10,
+        }).unwrap();
+
+        store.get_model::<TestModel>("1").unwrap();
+        let err = store.get_model::<TestModel>("1").unwrap_err();
+        assert!(matches!(err, ReadModelError::Lock(LockError::AcquireFailed(_))));
+        assert!(err.to_string().contains("test_models:1"));
+
+        store.unlock::<TestModel>("1").unwrap();
+        assert!(store.get_model::<TestModel>("1").unwrap().is_some());
+        store.unlock::<TestModel>("1").unwrap();
+    }
+
+    /// In-memory locks whose shared release fails for one key.
+    struct FailingRelease {
+        locks: InMemoryLockManager,
+        failing: &'static str,
+    }
+
+    struct FailingLock {
+        inner: Arc<crate::lock::InMemoryLock>,
+        fails: bool,
+    }
+
+    impl Lock for FailingLock {
+        fn lock(&self) -> Result<(), LockError> {
+            self.inner.lock()
+        }
+
+        fn try_lock(&self) -> Result<bool, LockError> {
+            self.inner.try_lock()
+        }
+
+        fn unlock(&self) -> Result<(), LockError> {
+            self.inner.unlock()
+        }
+
+        fn lock_shared(&self) -> Result<(), LockError> {
+            self.inner.lock_shared()
+        }
+
+        fn unlock_shared(&self) -> Result<(), LockError> {
+            if self.fails {
+                return Err(LockError::ReleaseFailed("release refused".into()));
+            }
+            self.inner.unlock_shared()
+        }
+    }
+
+    impl LockManager for FailingRelease {
+        type Lock = FailingLock;
+
+        fn get_lock(&self, id: &str) -> Result<Arc<FailingLock>, LockError> {
+            Ok(Arc::new(FailingLock {
+                inner: self.locks.get_lock(id)?,
+                fails: id == self.failing,
+            }))
+        }
+    }
+
+    #[test]
+    fn a_failed_shared_release_still_releases_the_other_locks() {
+        let locks = FailingRelease {
+            locks: InMemoryLockManager::new(),
+            failing: "test_models:1",
+        };
+        let store = QueuedReadModelStore::with_lock_manager(InMemoryReadModelStore::new(), locks);
+        for id in ["1", "2"] {
+            store.inner().upsert(&TestModel {
+                id: id.into(),
+                value: 10,
+            }).unwrap();
+        }
+
+        let err = store
+            .find_models_with::<TestModel>(&|_| true, ReadOpts::shared())
+            .unwrap_err();
+        assert!(matches!(err, ReadModelError::Lock(LockError::ReleaseFailed(_))));
+
+        // "1" sorts first; "2" was released after it failed
+        let lock = store.lock_manager().get_lock("test_models:2").unwrap();
+        assert!(lock.try_lock().unwrap());
+        lock.unlock().unwrap();
+    }
 }
@@ -2,7 +2,11 @@
 
 use std::marker::PhantomData;
 
-use super::{ReadModel, ReadModelError, ReadModelStore, Versioned};
+use serde_json::Value;
+
+use crate::repository::{Page, PageRequest, Pages};
+
+use super::{Query, ReadModel, ReadModelError, ReadModelStore, Versioned};
 
 /// Typed repository wrapper for accessing read models of a specific type.
 ///
@@ -50,6 +54,42 @@ impl<'a, S: ReadModelStore, M: ReadModel> ReadModelRepository<'a, S, M> {
         self.store.find_models(predicate)
     }
 
+    /// Find one page of read models matching a predicate, in ID order.
+    pub fn find_page(
+        &self,
+        predicate: &dyn Fn(&M) -> bool,
+        request: &PageRequest,
+    ) -> Result<Page<Versioned<M>>, ReadModelError> {
+        self.store.find_models_page(predicate, request)
+    }
+
+    /// Lazily iterate over read models matching a predicate, `batch_size` at a time.
+    pub fn find_iter<F>(
+        &self,
+        predicate: F,
+        batch_size: usize,
+    ) -> Pages<'a, Versioned<M>, ReadModelError>
+    where
+        F: Fn(&M) -> bool + 'a,
+    {
+        self.store.find_models_iter(predicate, batch_size)
+    }
+
+    /// Read models whose indexed `field` equals `value`
+    /// (see [`ReadModel::INDEXES`]).
+    pub fn find_by(
+        &self,
+        field: &str,
+        value: impl Into<Value>,
+    ) -> Result<Vec<Versioned<M>>, ReadModelError> {
+        self.store.find_models_by(field, &value.into())
+    }
+
+    /// Run a declarative query.
+    pub fn query(&self, query: &Query) -> Result<Vec<Versioned<M>>, ReadModelError> {
+        self.store.query_models(query)
+    }
+
     /// Find the first read model matching a predicate.
     pub fn find_one(&self, predicate: &dyn Fn(&M) -> bool) -> Result<Option<Versioned<M>>, ReadModelError> {
         self.store.find_one_model(predicate)
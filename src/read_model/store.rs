@@ -1,6 +1,11 @@
 //! ReadModelStore - Abstract CRUD storage for read models.
 
-use super::{ReadModel, ReadModelError, Versioned};
+use crate::repository::{Page, PageRequest, Pages};
+
+use serde_json::Value;
+
+use super::index::check_indexed;
+use super::{Query, ReadModel, ReadModelError, Versioned};
 
 /// Abstract CRUD storage for read models.
 ///
@@ -39,7 +44,187 @@ pub trait ReadModelStore: Send + Sync {
         predicate: &dyn Fn(&M) -> bool,
     ) -> Result<Option<Versioned<M>>, ReadModelError>;
 
-    /// Save pre-serialized read model bytes by key. Used internally by CommitBuilder
-    /// for type-erased atomic writes.
+    /// Up to `request.limit()` read models matching a predicate, in ID order,
+    /// starting after the request's cursor.
+    ///
+    /// The default sorts the result of [`find_models`](Self::find_models).
+    /// Stores that can read models in key order override it to stop reading
+    /// once the page is full.
+    fn find_models_page<M: ReadModel>(
+        &self,
+        predicate: &dyn Fn(&M) -> bool,
+        request: &PageRequest,
+    ) -> Result<Page<Versioned<M>>, ReadModelError> {
+        let mut models = self.find_models(&|m: &M| request.includes(m.id()) && predicate(m))?;
+        models.sort_by(|a, b| a.data.id().cmp(b.data.id()));
+        Page::fill(request, models.into_iter().map(Ok), |_| true, |m| m.data.id().to_string())
+    }
+
+    /// Run a declarative [`Query`] over a collection.
+    ///
+    /// The default loads the collection and evaluates the query in memory.
+    /// Backends with a query language override it to translate the query.
+    fn query_models<M: ReadModel>(
+        &self,
+        query: &Query,
+    ) -> Result<Vec<Versioned<M>>, ReadModelError> {
+        let mut models = self.find_models::<M>(&|_| true)?;
+        models.sort_by(|a, b| a.data.id().cmp(b.data.id()));
+        let docs = models
+            .into_iter()
+            .map(|model| {
+                let doc = serde_json::to_value(&model.data)
+                    .map_err(|e| ReadModelError::Serde(e.to_string()))?;
+                Ok((doc, model))
+            })
+            .collect::<Result<Vec<_>, ReadModelError>>()?;
+        Ok(query.apply(docs))
+    }
+
+    /// Read models whose indexed `field` equals `value`, in ID order. `field`
+    /// must be listed in [`ReadModel::INDEXES`].
+    ///
+    /// The default evaluates an equality [`Query`]. Stores that maintain
+    /// indexes override it to read only the matching models.
+    fn find_models_by<M: ReadModel>(
+        &self,
+        field: &str,
+        value: &Value,
+    ) -> Result<Vec<Versioned<M>>, ReadModelError> {
+        check_indexed::<M>(field)?;
+        self.query_models(&Query::new().eq(field, value.clone()))
+    }
+
+    /// Lazily iterate over every read model matching a predicate, reading
+    /// `batch_size` models per page.
+    fn find_models_iter<'a, M, F>(
+        &'a self,
+        predicate: F,
+        batch_size: usize,
+    ) -> Pages<'a, Versioned<M>, ReadModelError>
+    where
+        Self: Sized,
+        M: ReadModel,
+        F: Fn(&M) -> bool + 'a,
+    {
+        Pages::new(batch_size, move |request| self.find_models_page(&predicate, request))
+    }
+
+    /// Save pre-serialized read model bytes by key (`"collection:id"`), for
+    /// type-erased writes.
     fn upsert_raw(&self, key: &str, bytes: Vec<u8>) -> Result<(), ReadModelError>;
+
+    /// Save several pre-serialized read models (`(key, bytes)`). Used by
+    /// projections, which write models and their checkpoint together.
+    ///
+    /// The default upserts them one by one, so a failure can leave some of
+    /// them written. Stores that can write them all-or-nothing override it.
+    fn upsert_raw_many(&self, models: Vec<(String, Vec<u8>)>) -> Result<(), ReadModelError> {
+        for (key, bytes) in models {
+            self.upsert_raw(&key, bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Load pre-serialized read model bytes by key (`"collection:id"`).
+    ///
+    /// This and the collection methods below are needed by projection
+    /// rebuilds only. The defaults fail with [`ReadModelError::Unsupported`].
+    fn get_raw(&self, _key: &str) -> Result<Option<Vec<u8>>, ReadModelError> {
+        Err(ReadModelError::Unsupported("get_raw"))
+    }
+
+    /// Delete every read model in a collection. Returns the number deleted.
+    fn delete_collection(&self, _collection: &str) -> Result<usize, ReadModelError> {
+        Err(ReadModelError::Unsupported("delete_collection"))
+    }
+
+    /// Atomically replace collections and upsert pre-serialized models.
+    ///
+    /// For each `(shadow, live)` pair, the models in `live` are deleted and the
+    /// models in `shadow` are moved into `live` (keeping their IDs), leaving
+    /// `shadow` empty. Readers see either the old or the new live collections,
+    /// never a mix. Used by projection rebuilds.
+    fn swap_collections(
+        &self,
+        _swaps: &[(&str, &str)],
+        _models: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), ReadModelError> {
+        Err(ReadModelError::Unsupported("swap_collections"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_model::InMemoryReadModelStore;
+
+    /// A store implementing only the required methods, as an external one might.
+    struct MinimalStore(InMemoryReadModelStore);
+
+    impl ReadModelStore for MinimalStore {
+        fn get_model<M: ReadModel>(
+            &self,
+            id: &str,
+        ) -> Result<Option<Versioned<M>>, ReadModelError> {
+            self.0.get_model(id)
+        }
+
+        fn upsert<M: ReadModel>(&self, model: &M) -> Result<Versioned<M>, ReadModelError> {
+            self.0.upsert(model)
+        }
+
+        fn insert<M: ReadModel>(&self, model: &M) -> Result<Versioned<M>, ReadModelError> {
+            self.0.insert(model)
+        }
+
+        fn update<M: ReadModel>(
+            &self,
+            model: &M,
+            expected_version: u64,
+        ) -> Result<Versioned<M>, ReadModelError> {
+            self.0.update(model, expected_version)
+        }
+
+        fn delete<M: ReadModel>(&self, id: &str) -> Result<bool, ReadModelError> {
+            self.0.delete::<M>(id)
+        }
+
+        fn find_models<M: ReadModel>(
+            &self,
+            predicate: &dyn Fn(&M) -> bool,
+        ) -> Result<Vec<Versioned<M>>, ReadModelError> {
+            self.0.find_models(predicate)
+        }
+
+        fn find_one_model<M: ReadModel>(
+            &self,
+            predicate: &dyn Fn(&M) -> bool,
+        ) -> Result<Option<Versioned<M>>, ReadModelError> {
+            self.0.find_one_model(predicate)
+        }
+
+        fn upsert_raw(&self, key: &str, bytes: Vec<u8>) -> Result<(), ReadModelError> {
+            self.0.upsert_raw(key, bytes)
+        }
+    }
+
+    #[test]
+    fn rebuild_operations_are_optional() {
+        let store = MinimalStore(InMemoryReadModelStore::new());
+        store
+            .upsert_raw_many(vec![("things:1".into(), b"{}".to_vec())])
+            .unwrap();
+
+        assert!(matches!(store.get_raw("things:1"), Err(ReadModelError::Unsupported("get_raw"))));
+        assert!(matches!(
+            store.delete_collection("things"),
+            Err(ReadModelError::Unsupported("delete_collection"))
+        ));
+        assert!(matches!(
+            store.swap_collections(&[("things__rebuild", "things")], Vec::new()),
+            Err(ReadModelError::Unsupported("swap_collections"))
+        ));
+        assert_eq!(store.0.get_raw("things:1").unwrap().unwrap(), b"{}");
+    }
 }
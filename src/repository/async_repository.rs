@@ -0,0 +1,210 @@
+//! Async counterparts of the core repository traits (requires the `async` feature).
+//!
+//! Each trait mirrors its synchronous sibling with an `_async` method suffix, so
+//! a type can implement both families without ambiguity at call sites. Backends
+//! talking to a real database or broker implement these; in-memory stores simply
+//! return ready futures.
+
+use std::future::Future;
+
+use crate::entity::{Committable, Entity};
+use super::error::RepositoryError;
+
+/// Async counterpart of [`GetOne`](super::GetOne).
+pub trait AsyncGetOne {
+    fn get_one_async(
+        &self,
+        id: &str,
+    ) -> impl Future<Output = Result<Option<Entity>, RepositoryError>> + Send;
+}
+
+/// Async counterpart of [`GetOneFrom`](super::GetOneFrom).
+pub trait AsyncGetOneFrom {
+    fn get_one_from_async(
+        &self,
+        id: &str,
+        from_version: u64,
+    ) -> impl Future<Output = Result<Option<Entity>, RepositoryError>> + Send;
+}
+
+/// Async counterpart of [`GetMany`](super::GetMany).
+pub trait AsyncGetMany {
+    fn get_many_async(
+        &self,
+        ids: &[&str],
+    ) -> impl Future<Output = Result<Vec<Entity>, RepositoryError>> + Send;
+}
+
+/// Async counterpart of [`Gettable`](super::Gettable).
+pub trait AsyncGettable {
+    type Output;
+    fn get_from_async<R: AsyncGetOne + AsyncGetMany + Sync>(
+        &self,
+        repo: &R,
+    ) -> impl Future<Output = Result<Self::Output, RepositoryError>> + Send;
+}
+
+impl AsyncGettable for &str {
+    type Output = Option<Entity>;
+
+    fn get_from_async<R: AsyncGetOne + AsyncGetMany + Sync>(
+        &self,
+        repo: &R,
+    ) -> impl Future<Output = Result<Self::Output, RepositoryError>> + Send {
+        repo.get_one_async(self)
+    }
+}
+
+impl AsyncGettable for String {
+    type Output = Option<Entity>;
+
+    fn get_from_async<R: AsyncGetOne + AsyncGetMany + Sync>(
+        &self,
+        repo: &R,
+    ) -> impl Future<Output = Result<Self::Output, RepositoryError>> + Send {
+        repo.get_one_async(self.as_str())
+    }
+}
+
+impl AsyncGettable for &String {
+    type Output = Option<Entity>;
+
+    fn get_from_async<R: AsyncGetOne + AsyncGetMany + Sync>(
+        &self,
+        repo: &R,
+    ) -> impl Future<Output = Result<Self::Output, RepositoryError>> + Send {
+        repo.get_one_async(self.as_str())
+    }
+}
+
+impl AsyncGettable for &[&str] {
+    type Output = Vec<Entity>;
+
+    fn get_from_async<R: AsyncGetOne + AsyncGetMany + Sync>(
+        &self,
+        repo: &R,
+    ) -> impl Future<Output = Result<Self::Output, RepositoryError>> + Send {
+        repo.get_many_async(self)
+    }
+}
+
+impl<const N: usize> AsyncGettable for [&str; N] {
+    type Output = Vec<Entity>;
+
+    fn get_from_async<R: AsyncGetOne + AsyncGetMany + Sync>(
+        &self,
+        repo: &R,
+    ) -> impl Future<Output = Result<Self::Output, RepositoryError>> + Send {
+        repo.get_many_async(self.as_slice())
+    }
+}
+
+impl<const N: usize> AsyncGettable for &[&str; N] {
+    type Output = Vec<Entity>;
+
+    fn get_from_async<R: AsyncGetOne + AsyncGetMany + Sync>(
+        &self,
+        repo: &R,
+    ) -> impl Future<Output = Result<Self::Output, RepositoryError>> + Send {
+        repo.get_many_async(self.as_slice())
+    }
+}
+
+impl AsyncGettable for Vec<&str> {
+    type Output = Vec<Entity>;
+
+    fn get_from_async<R: AsyncGetOne + AsyncGetMany + Sync>(
+        &self,
+        repo: &R,
+    ) -> impl Future<Output = Result<Self::Output, RepositoryError>> + Send {
+        repo.get_many_async(self.as_slice())
+    }
+}
+
+impl AsyncGettable for &Vec<&str> {
+    type Output = Vec<Entity>;
+
+    fn get_from_async<R: AsyncGetOne + AsyncGetMany + Sync>(
+        &self,
+        repo: &R,
+    ) -> impl Future<Output = Result<Self::Output, RepositoryError>> + Send {
+        repo.get_many_async(self.as_slice())
+    }
+}
+
+/// Async counterpart of [`Get`](super::Get).
+pub trait AsyncGet: AsyncGetOne + AsyncGetMany + Sync {
+    fn get_async<G>(
+        &self,
+        gettable: G,
+    ) -> impl Future<Output = Result<G::Output, RepositoryError>> + Send
+    where
+        Self: Sized,
+        G: AsyncGettable + Send + Sync,
+    {
+        async move { gettable.get_from_async(self).await }
+    }
+}
+
+// Blanket implementation: anything implementing AsyncGetOne + AsyncGetMany is AsyncGet
+impl<T: AsyncGetOne + AsyncGetMany + Sync> AsyncGet for T {}
+
+/// Async counterpart of [`Find`](super::Find).
+pub trait AsyncFind {
+    fn find_async<F>(
+        &self,
+        predicate: F,
+    ) -> impl Future<Output = Result<Vec<Entity>, RepositoryError>> + Send
+    where
+        F: Fn(&Entity) -> bool + Send;
+}
+
+/// Async counterpart of [`FindOne`](super::FindOne).
+pub trait AsyncFindOne {
+    fn find_one_async<F>(
+        &self,
+        predicate: F,
+    ) -> impl Future<Output = Result<Option<Entity>, RepositoryError>> + Send
+    where
+        F: Fn(&Entity) -> bool + Send;
+}
+
+/// Async counterpart of [`Exists`](super::Exists).
+pub trait AsyncExists {
+    fn exists_async<F>(
+        &self,
+        predicate: F,
+    ) -> impl Future<Output = Result<bool, RepositoryError>> + Send
+    where
+        F: Fn(&Entity) -> bool + Send;
+}
+
+/// Async counterpart of [`Count`](super::Count).
+pub trait AsyncCount {
+    fn count_async<F>(
+        &self,
+        predicate: F,
+    ) -> impl Future<Output = Result<usize, RepositoryError>> + Send
+    where
+        F: Fn(&Entity) -> bool + Send;
+}
+
+/// Async counterpart of [`Commit`](super::Commit).
+pub trait AsyncCommit {
+    fn commit_async<C: Committable + ?Sized + Send>(
+        &self,
+        committable: &mut C,
+    ) -> impl Future<Output = Result<(), RepositoryError>> + Send;
+}
+
+/// Full async repository trait combining all capabilities.
+pub trait AsyncRepository:
+    AsyncGet + AsyncFind + AsyncFindOne + AsyncExists + AsyncCount + AsyncCommit
+{
+}
+
+// Blanket implementation: anything implementing all async traits is an AsyncRepository
+impl<T> AsyncRepository for T where
+    T: AsyncGet + AsyncFind + AsyncFindOne + AsyncExists + AsyncCount + AsyncCommit
+{
+}
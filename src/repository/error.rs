@@ -3,6 +3,8 @@ use std::fmt;
 use crate::lock::LockError;
 use crate::read_model::ReadModelError;
 
+use super::expected_version::ExpectedVersion;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RepositoryError {
     LockPoisoned(&'static str),
@@ -12,8 +14,34 @@ pub enum RepositoryError {
         expected: u64,
         actual: u64,
     },
+    /// The stream did not match the commit's [`ExpectedVersion`]. `actual` is
+    /// `None` if the stream does not exist.
+    WrongExpectedVersion {
+        id: String,
+        expected: ExpectedVersion,
+        actual: Option<u64>,
+    },
+    /// No [`SecondaryIndex`](super::SecondaryIndex) with this name is declared.
+    UnknownIndex(String),
     Replay(String),
     Model(String),
+    /// A read model in an atomic commit was not at its expected version.
+    /// Holds the [`ReadModelError::ConcurrencyConflict`].
+    ReadModelConflict(ReadModelError),
+    Storage(String),
+    /// An [`update`](crate::AggregateRepository::update) made `attempts`
+    /// attempts and each one conflicted. Holds the last `ConcurrentWrite`.
+    RetriesExhausted {
+        attempts: u32,
+        last: Box<RepositoryError>,
+    },
+    /// A [`UnitOfWork`](crate::UnitOfWork) was asked to track one stream as
+    /// two aggregate types.
+    StreamTypeConflict {
+        stream_id: String,
+        tracked: &'static str,
+        requested: &'static str,
+    },
 }
 
 impl fmt::Display for RepositoryError {
@@ -32,8 +60,39 @@ impl fmt::Display for RepositoryError {
                 "concurrent write detected for entity {} (expected version {}, got {})",
                 id, expected, actual
             ),
+            RepositoryError::WrongExpectedVersion {
+                id,
+                expected,
+                actual,
+            } => match actual {
+                Some(version) => write!(
+                    f,
+                    "wrong expected version for entity {} (expected {}, stream is at version {})",
+                    id, expected, version
+                ),
+                None => write!(
+                    f,
+                    "wrong expected version for entity {} (expected {}, stream does not exist)",
+                    id, expected
+                ),
+            },
+            RepositoryError::UnknownIndex(name) => write!(f, "unknown index: {}", name),
             RepositoryError::Replay(message) => write!(f, "replay error: {}", message),
             RepositoryError::Model(message) => write!(f, "model error: {}", message),
+            RepositoryError::ReadModelConflict(err) => write!(f, "{}", err),
+            RepositoryError::Storage(message) => write!(f, "storage error: {}", message),
+            RepositoryError::RetriesExhausted { attempts, last } => {
+                write!(f, "gave up after {} attempts: {}", attempts, last)
+            }
+            RepositoryError::StreamTypeConflict {
+                stream_id,
+                tracked,
+                requested,
+            } => write!(
+                f,
+                "stream {} is tracked as {} and cannot be loaded as {}",
+                stream_id, tracked, requested
+            ),
         }
     }
 }
@@ -46,6 +105,12 @@ impl From<LockError> for RepositoryError {
     }
 }
 
+impl From<std::io::Error> for RepositoryError {
+    fn from(err: std::io::Error) -> Self {
+        RepositoryError::Storage(err.to_string())
+    }
+}
+
 impl From<ReadModelError> for RepositoryError {
     fn from(err: ReadModelError) -> Self {
         RepositoryError::Model(err.to_string())
@@ -0,0 +1,131 @@
+use std::fmt;
+
+use crate::entity::Entity;
+use super::error::RepositoryError;
+
+/// What a commit expects of the stored stream before appending to it.
+///
+/// Without an expectation, a commit requires the stored version to equal the
+/// entity's `committed_version()` (plain optimistic concurrency). Set one with
+/// [`Entity::expect_version`] or the `commit_expecting` helpers:
+///
+/// ```ignore
+/// // Unique creation: fails if "user-42" already exists
+/// repo.commit_expecting(&mut user, ExpectedVersion::NoStream)?;
+///
+/// // Append from a command handler that carries the version the client saw
+/// repo.commit_expecting(&mut entity, ExpectedVersion::Exact(7))?;
+/// ```
+///
+/// When the expectation holds, the new events are appended after the stored
+/// version even if the entity was loaded at (or created from) another one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpectedVersion {
+    /// Append regardless of the stream's state.
+    Any,
+    /// The stream must not exist yet.
+    NoStream,
+    /// The stream must exist (at any version).
+    StreamExists,
+    /// The stream must be at exactly this version.
+    Exact(u64),
+}
+
+impl ExpectedVersion {
+    /// Whether a stream at `actual` (`None` if it does not exist) satisfies
+    /// this expectation.
+    pub fn matches(&self, actual: Option<u64>) -> bool {
+        match self {
+            ExpectedVersion::Any => true,
+            ExpectedVersion::NoStream => actual.is_none(),
+            ExpectedVersion::StreamExists => actual.is_some(),
+            ExpectedVersion::Exact(version) => actual.unwrap_or(0) == *version,
+        }
+    }
+}
+
+impl fmt::Display for ExpectedVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpectedVersion::Any => write!(f, "any version"),
+            ExpectedVersion::NoStream => write!(f, "no stream"),
+            ExpectedVersion::StreamExists => write!(f, "an existing stream"),
+            ExpectedVersion::Exact(version) => write!(f, "version {}", version),
+        }
+    }
+}
+
+/// Check an entity against its stored stream (`actual` is `None` if the
+/// stream does not exist). Used by every backend's commit validation.
+pub(crate) fn check_expected_version(
+    entity: &Entity,
+    actual: Option<u64>,
+) -> Result<(), RepositoryError> {
+    match entity.expected_version() {
+        None => {
+            let stored = actual.unwrap_or(0);
+            if stored != entity.committed_version() {
+                return Err(RepositoryError::ConcurrentWrite {
+                    id: entity.id().to_string(),
+                    expected: entity.committed_version(),
+                    actual: stored,
+                });
+            }
+        }
+        Some(expected) => {
+            if !expected.matches(actual) {
+                return Err(RepositoryError::WrongExpectedVersion {
+                    id: entity.id().to_string(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_stream_states() {
+        use ExpectedVersion::*;
+        let cases = [
+            (Any, None, true),
+            (Any, Some(3), true),
+            (NoStream, None, true),
+            (NoStream, Some(0), false),
+            (StreamExists, None, false),
+            (StreamExists, Some(0), true),
+            (Exact(0), None, true),
+            (Exact(2), Some(2), true),
+            (Exact(2), Some(3), false),
+        ];
+        for (expected, actual, ok) in cases {
+            assert_eq!(expected.matches(actual), ok, "{:?} vs {:?}", expected, actual);
+        }
+    }
+
+    #[test]
+    fn default_check_compares_committed_version() {
+        let mut entity = Entity::with_id("a");
+        entity.digest("Created", &());
+        assert!(check_expected_version(&entity, None).is_ok());
+        assert!(matches!(
+            check_expected_version(&entity, Some(1)),
+            Err(RepositoryError::ConcurrentWrite { expected: 0, actual: 1, .. })
+        ));
+
+        entity.expect_version(ExpectedVersion::NoStream);
+        assert_eq!(
+            check_expected_version(&entity, Some(1)),
+            Err(RepositoryError::WrongExpectedVersion {
+                id: "a".into(),
+                expected: ExpectedVersion::NoStream,
+                actual: Some(1),
+            })
+        );
+    }
+}
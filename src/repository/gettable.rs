@@ -12,6 +12,17 @@ pub trait GetOne {
     fn get_one(&self, id: &str) -> Result<Option<Entity>, RepositoryError>;
 }
 
+/// Get a single entity with only the events after a given version.
+///
+/// The returned entity has its full version (so it can be committed as usual)
+/// but `events()` holds only the events with `sequence > from_version`, which
+/// saves the I/O and memory of loading history a snapshot already covers.
+/// `from_version` is clamped to the stream's version. Returns `None` if the
+/// stream does not exist.
+pub trait GetOneFrom {
+    fn get_one_from(&self, id: &str, from_version: u64) -> Result<Option<Entity>, RepositoryError>;
+}
+
 /// Internal trait for getting multiple entities.
 pub trait GetMany {
     fn get_many(&self, ids: &[&str]) -> Result<Vec<Entity>, RepositoryError>;
@@ -0,0 +1,253 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::entity::EventRecord;
+
+use super::error::RepositoryError;
+
+type KeyExtractor = Arc<dyn Fn(&EventRecord) -> Option<String> + Send + Sync>;
+
+#[derive(Clone)]
+enum IndexKind {
+    Prefix(String),
+    Event(String),
+    Key(KeyExtractor),
+}
+
+/// A secondary index over streams, maintained by the repository at commit time.
+///
+/// - [`prefix`](Self::prefix) indexes every stream whose ID starts with a prefix.
+/// - [`event`](Self::event) indexes every stream containing an event with a given name.
+/// - [`key`](Self::key) indexes streams under a key extracted from their events.
+///
+/// ## Example
+///
+/// ```ignore
+/// let repo = HashMapRepository::new()
+///     .with_index(SecondaryIndex::prefix("todos", "todo-"))?
+///     .with_index(SecondaryIndex::event("completed", "Completed"))?
+///     .with_index(SecondaryIndex::key("todos_by_user", |event| {
+///         if event.event_name != "Initialized" {
+///             return None;
+///         }
+///         let (_id, user_id, _task): (String, String, String) = event.decode().ok()?;
+///         Some(user_id)
+///     }))?;
+/// ```
+#[derive(Clone)]
+pub struct SecondaryIndex {
+    name: String,
+    kind: IndexKind,
+}
+
+impl SecondaryIndex {
+    /// Index every stream whose ID starts with `prefix`.
+    pub fn prefix(name: impl Into<String>, prefix: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            kind: IndexKind::Prefix(prefix.into()),
+        }
+    }
+
+    /// Index every stream that contains at least one event named `event_name`.
+    pub fn event(name: impl Into<String>, event_name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            kind: IndexKind::Event(event_name.into()),
+        }
+    }
+
+    /// Index streams under the key returned by `extract`, which is called
+    /// for each new event. A stream has at most one key: the most recent
+    /// `Some` replaces the previous one, and `None` leaves it unchanged.
+    pub fn key<F>(name: impl Into<String>, extract: F) -> Self
+    where
+        F: Fn(&EventRecord) -> Option<String> + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            kind: IndexKind::Key(Arc::new(extract)),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The key `events` (appended to stream `id`) file the stream under, if any.
+    fn key_for(&self, id: &str, events: &[EventRecord]) -> Option<String> {
+        match &self.kind {
+            IndexKind::Prefix(prefix) => id.starts_with(prefix.as_str()).then(String::new),
+            IndexKind::Event(name) => events
+                .iter()
+                .any(|e| &e.event_name == name)
+                .then(String::new),
+            IndexKind::Key(extract) => events.iter().rev().find_map(|e| extract(e)),
+        }
+    }
+}
+
+impl fmt::Debug for SecondaryIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match &self.kind {
+            IndexKind::Prefix(prefix) => format!("Prefix({:?})", prefix),
+            IndexKind::Event(name) => format!("Event({:?})", name),
+            IndexKind::Key(_) => "Key(..)".to_string(),
+        };
+        f.debug_struct("SecondaryIndex")
+            .field("name", &self.name)
+            .field("kind", &format_args!("{}", kind))
+            .finish()
+    }
+}
+
+/// Look up stream IDs through a [`SecondaryIndex`] instead of loading every
+/// stream. IDs are returned in sorted order.
+pub trait FindByIndex {
+    /// IDs of every stream in `index` (under any key).
+    fn ids_in(&self, index: &str) -> Result<Vec<String>, RepositoryError>;
+
+    /// IDs of the streams filed under `key` in a [`SecondaryIndex::key`] index.
+    fn ids_by(&self, index: &str, key: &str) -> Result<Vec<String>, RepositoryError>;
+}
+
+/// The entries of one [`SecondaryIndex`]. Prefix and event indexes file
+/// every stream under the empty key.
+#[derive(Debug, Default)]
+struct IndexEntries {
+    by_key: HashMap<String, BTreeSet<String>>,
+    keys: HashMap<String, String>,
+}
+
+impl IndexEntries {
+    fn insert(&mut self, id: &str, key: String) {
+        if let Some(previous) = self.keys.get(id) {
+            if *previous == key {
+                return;
+            }
+            if let Some(ids) = self.by_key.get_mut(previous) {
+                ids.remove(id);
+                if ids.is_empty() {
+                    self.by_key.remove(previous);
+                }
+            }
+        }
+        self.by_key.entry(key.clone()).or_default().insert(id.to_string());
+        self.keys.insert(id.to_string(), key);
+    }
+
+    fn all(&self) -> Vec<String> {
+        let ids: BTreeSet<&String> = self.by_key.values().flatten().collect();
+        ids.into_iter().cloned().collect()
+    }
+
+    fn by(&self, key: &str) -> Vec<String> {
+        self.by_key
+            .get(key)
+            .map(|ids| ids.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// The declared indexes of a store and their entries.
+#[derive(Debug, Default)]
+pub(crate) struct Indexes {
+    indexes: Vec<(SecondaryIndex, IndexEntries)>,
+}
+
+impl Indexes {
+    /// Declare `index` (replacing one with the same name), filling it from
+    /// `streams`.
+    pub fn add<'a>(
+        &mut self,
+        index: SecondaryIndex,
+        streams: impl IntoIterator<Item = (&'a str, &'a [EventRecord])>,
+    ) {
+        self.indexes.retain(|(existing, _)| existing.name != index.name);
+        let mut entries = IndexEntries::default();
+        for (id, events) in streams {
+            if let Some(key) = index.key_for(id, events) {
+                entries.insert(id, key);
+            }
+        }
+        self.indexes.push((index, entries));
+    }
+
+    /// Update every index for `events` newly appended to stream `id`.
+    pub fn record(&mut self, id: &str, events: &[EventRecord]) {
+        for (index, entries) in &mut self.indexes {
+            if let Some(key) = index.key_for(id, events) {
+                entries.insert(id, key);
+            }
+        }
+    }
+
+    fn entries(&self, name: &str) -> Result<&IndexEntries, RepositoryError> {
+        self.indexes
+            .iter()
+            .find(|(index, _)| index.name == name)
+            .map(|(_, entries)| entries)
+            .ok_or_else(|| RepositoryError::UnknownIndex(name.to_string()))
+    }
+
+    pub fn ids_in(&self, name: &str) -> Result<Vec<String>, RepositoryError> {
+        Ok(self.entries(name)?.all())
+    }
+
+    pub fn ids_by(&self, name: &str, key: &str) -> Result<Vec<String>, RepositoryError> {
+        Ok(self.entries(name)?.by(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::Entity;
+
+    fn events(names: &[(&str, &str)]) -> Vec<EventRecord> {
+        let mut entity = Entity::new();
+        for (name, arg) in names {
+            entity.digest(*name, &arg.to_string());
+        }
+        entity.events().to_vec()
+    }
+
+    #[test]
+    fn key_index_moves_stream_to_latest_key() {
+        let status = SecondaryIndex::key("status", |e| e.decode::<String>().ok());
+        let mut indexes = Indexes::default();
+        indexes.add(status, []);
+
+        indexes.record("t1", &events(&[("Created", "open")]));
+        indexes.record("t2", &events(&[("Created", "open")]));
+        assert_eq!(indexes.ids_by("status", "open").unwrap(), vec!["t1", "t2"]);
+
+        indexes.record("t1", &events(&[("Noted", "x"), ("Closed", "done")]));
+        assert_eq!(indexes.ids_by("status", "open").unwrap(), vec!["t2"]);
+        assert_eq!(indexes.ids_by("status", "done").unwrap(), vec!["t1"]);
+        assert_eq!(indexes.ids_in("status").unwrap(), vec!["t1", "t2"]);
+    }
+
+    #[test]
+    fn prefix_and_event_indexes_are_backfilled() {
+        let created = events(&[("Created", "a")]);
+        let completed = events(&[("Created", "b"), ("Completed", "b")]);
+        let streams = [
+            ("todo-1", &created[..]),
+            ("todo-2", &completed[..]),
+            ("outbox:1", &created[..]),
+        ];
+
+        let mut indexes = Indexes::default();
+        indexes.add(SecondaryIndex::prefix("todos", "todo-"), streams);
+        indexes.add(SecondaryIndex::event("completed", "Completed"), streams);
+
+        assert_eq!(indexes.ids_in("todos").unwrap(), vec!["todo-1", "todo-2"]);
+        assert_eq!(indexes.ids_in("completed").unwrap(), vec!["todo-2"]);
+        assert_eq!(
+            indexes.ids_in("missing").unwrap_err(),
+            RepositoryError::UnknownIndex("missing".into())
+        );
+    }
+}
@@ -1,7 +1,25 @@
+#[cfg(feature = "async")]
+mod async_repository;
 mod error;
+mod expected_version;
 mod gettable;
+mod index;
+mod page;
+mod read_all;
 mod repository;
 
 pub use error::RepositoryError;
-pub use gettable::{GetMany, GetOne, Gettable};
-pub use repository::{Commit, Count, Exists, Find, FindOne, Get, Repository};
+pub use expected_version::ExpectedVersion;
+pub(crate) use expected_version::check_expected_version;
+pub use gettable::{GetMany, GetOne, GetOneFrom, Gettable};
+pub use index::{FindByIndex, SecondaryIndex};
+pub(crate) use index::Indexes;
+pub use page::{Page, PageRequest, Pages};
+pub use read_all::{ReadAll, StoredEvent};
+pub use repository::{Commit, Count, Exists, Find, FindOne, FindPage, Get, Repository};
+
+#[cfg(feature = "async")]
+pub use async_repository::{
+    AsyncCommit, AsyncCount, AsyncExists, AsyncFind, AsyncFindOne, AsyncGet, AsyncGetMany,
+    AsyncGetOne, AsyncGetOneFrom, AsyncGettable, AsyncRepository,
+};
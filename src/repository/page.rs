@@ -0,0 +1,171 @@
+use std::vec;
+
+/// Where a page starts and how large it is.
+///
+/// Results are ordered by ID (stream ID for entities, model ID for read
+/// models), and the cursor is the ID of the last item of the previous page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageRequest {
+    limit: usize,
+    after: Option<String>,
+}
+
+impl PageRequest {
+    /// The first page of at most `limit` items (at least one).
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            after: None,
+        }
+    }
+
+    /// Start after `cursor`, usually a previous page's
+    /// [`next_cursor`](Page::next_cursor).
+    pub fn with_after(mut self, cursor: impl Into<String>) -> Self {
+        self.after = Some(cursor.into());
+        self
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn after(&self) -> Option<&str> {
+        self.after.as_deref()
+    }
+
+    /// Whether `id` sorts after the cursor.
+    pub(crate) fn includes(&self, id: &str) -> bool {
+        self.after.as_deref().is_none_or(|after| id > after)
+    }
+}
+
+/// One page of results.
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Cursor for the next page, or `None` if this is the last one. A page
+    /// that stops exactly at the end of the results may still carry a
+    /// cursor, in which case the next page is empty.
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// The request for the page after this one, if any.
+    pub fn next_request(&self, limit: usize) -> Option<PageRequest> {
+        let cursor = self.next_cursor.as_ref()?;
+        Some(PageRequest::new(limit).with_after(cursor.clone()))
+    }
+
+    /// Fill a page from `candidates`, which must be in ID order and all after
+    /// `request`'s cursor. Candidates are only pulled until the page is full.
+    pub(crate) fn fill<E>(
+        request: &PageRequest,
+        candidates: impl IntoIterator<Item = Result<T, E>>,
+        predicate: impl Fn(&T) -> bool,
+        cursor: impl Fn(&T) -> String,
+    ) -> Result<Self, E> {
+        let mut candidates = candidates.into_iter().peekable();
+        let mut items = Vec::new();
+        while let Some(candidate) = candidates.next() {
+            let candidate = candidate?;
+            if !predicate(&candidate) {
+                continue;
+            }
+            items.push(candidate);
+            if items.len() == request.limit {
+                let next_cursor = candidates.peek().and(items.last()).map(&cursor);
+                return Ok(Page { items, next_cursor });
+            }
+        }
+        Ok(Page {
+            items,
+            next_cursor: None,
+        })
+    }
+}
+
+type PageFetch<'a, T, E> = Box<dyn FnMut(&PageRequest) -> Result<Page<T>, E> + 'a>;
+
+/// Lazily iterates over paginated results, fetching one page at a time.
+///
+/// Returned by `find_iter` on repositories and read model stores. After an
+/// error is yielded the iteration ends.
+pub struct Pages<'a, T, E> {
+    fetch: PageFetch<'a, T, E>,
+    request: Option<PageRequest>,
+    buffer: vec::IntoIter<T>,
+}
+
+impl<'a, T, E> Pages<'a, T, E> {
+    /// Iterate from the first page, fetching `batch_size` items at a time.
+    pub fn new<F>(batch_size: usize, fetch: F) -> Self
+    where
+        F: FnMut(&PageRequest) -> Result<Page<T>, E> + 'a,
+    {
+        Self {
+            fetch: Box::new(fetch),
+            request: Some(PageRequest::new(batch_size)),
+            buffer: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl<T, E> Iterator for Pages<'_, T, E> {
+    type Item = Result<T, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.buffer.next() {
+                return Some(Ok(item));
+            }
+            let request = self.request.take()?;
+            match (self.fetch)(&request) {
+                Ok(page) => {
+                    self.request = page.next_request(request.limit);
+                    self.buffer = page.items.into_iter();
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_of(ids: &[&str], request: &PageRequest) -> Page<String> {
+        let candidates = ids
+            .iter()
+            .filter(|id| request.includes(id))
+            .map(|id| Ok::<_, ()>(id.to_string()));
+        Page::fill(request, candidates, |id| !id.ends_with('x'), Clone::clone).unwrap()
+    }
+
+    #[test]
+    fn fill_stops_at_limit_and_skips_non_matches() {
+        let ids = ["a", "bx", "c", "d", "ex"];
+        let first = page_of(&ids, &PageRequest::new(2));
+        assert_eq!(first.items, vec!["a", "c"]);
+        assert_eq!(first.next_cursor.as_deref(), Some("c"));
+
+        let second = page_of(&ids, &first.next_request(2).unwrap());
+        assert_eq!(second.items, vec!["d"]);
+        assert_eq!(second.next_cursor, None);
+    }
+
+    #[test]
+    fn pages_fetches_lazily() {
+        let ids = ["a", "b", "c", "d", "e"];
+        let mut fetched = 0;
+        let mut pages = Pages::new(2, |request: &PageRequest| {
+            fetched += 1;
+            Ok::<_, ()>(page_of(&ids, request))
+        });
+        assert_eq!(pages.next(), Some(Ok("a".to_string())));
+        assert_eq!(pages.by_ref().count(), 4);
+        drop(pages);
+        assert_eq!(fetched, 3);
+    }
+}
@@ -0,0 +1,29 @@
+use crate::entity::EventRecord;
+use super::error::RepositoryError;
+
+/// An event read from the global `$all` log, tagged with the stream it belongs to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredEvent {
+    pub stream_id: String,
+    pub event: EventRecord,
+}
+
+impl StoredEvent {
+    /// Global position of this event (same as `event.position`).
+    pub fn position(&self) -> u64 {
+        self.event.position
+    }
+}
+
+/// Read every committed event across all streams in global commit order.
+///
+/// Positions start at 1 and increase strictly across all streams, so a reader
+/// can checkpoint the last position it handled and resume from there.
+pub trait ReadAll {
+    /// Return up to `limit` events with a position strictly greater than
+    /// `from_position`. Pass 0 to read from the beginning.
+    fn read_all(&self, from_position: u64, limit: usize) -> Result<Vec<StoredEvent>, RepositoryError>;
+
+    /// Position of the most recently committed event (0 if the log is empty).
+    fn last_position(&self) -> Result<u64, RepositoryError>;
+}
@@ -1,6 +1,7 @@
 use crate::entity::Entity;
 use super::error::RepositoryError;
 use super::gettable::{GetMany, GetOne, Gettable};
+use super::page::{Page, PageRequest, Pages};
 
 /// Get one or more entities by ID(s).
 pub trait Get: GetOne + GetMany {
@@ -22,6 +23,33 @@ pub trait Find {
         F: Fn(&Entity) -> bool;
 }
 
+/// Find entities matching a predicate one page at a time, in stream ID order.
+pub trait FindPage {
+    /// Up to `request.limit()` matching entities whose IDs sort after the
+    /// request's cursor. Streams past the end of the page are not loaded.
+    fn find_page<F>(
+        &self,
+        predicate: F,
+        request: &PageRequest,
+    ) -> Result<Page<Entity>, RepositoryError>
+    where
+        F: Fn(&Entity) -> bool;
+
+    /// Lazily iterate over every matching entity, loading `batch_size`
+    /// entities per page.
+    fn find_iter<'a, F>(
+        &'a self,
+        predicate: F,
+        batch_size: usize,
+    ) -> Pages<'a, Entity, RepositoryError>
+    where
+        Self: Sized,
+        F: Fn(&Entity) -> bool + 'a,
+    {
+        Pages::new(batch_size, move |request| self.find_page(&predicate, request))
+    }
+}
+
 /// Find the first entity matching a predicate.
 pub trait FindOne {
     fn find_one<F>(&self, predicate: F) -> Result<Option<Entity>, RepositoryError>
@@ -45,9 +73,35 @@ pub trait Count {
 
 use crate::entity::Committable;
 
+use super::expected_version::ExpectedVersion;
+
 /// Commit one or more entities.
 pub trait Commit {
     fn commit<C: Committable + ?Sized>(&self, committable: &mut C) -> Result<(), RepositoryError>;
+
+    /// Commit one entity, checking the stored stream against `expected`
+    /// instead of the entity's committed version. The expectation applies to
+    /// this attempt only, whether it succeeds or not.
+    fn commit_expecting(
+        &self,
+        entity: &mut Entity,
+        expected: ExpectedVersion,
+    ) -> Result<(), RepositoryError>
+    where
+        Self: Sized,
+    {
+        entity.expect_version(expected);
+        let result = self.commit(entity);
+        entity.clear_expected_version();
+        result
+    }
+
+    /// Release streams that were read for a commit but will not be committed
+    /// through it, such as IDs that turned out not to exist or a command that
+    /// failed. Only locking repositories hold anything to release.
+    fn release(&self, _stream_ids: &[&str]) -> Result<(), RepositoryError> {
+        Ok(())
+    }
 }
 
 /// Full repository trait combining all capabilities.
@@ -55,6 +55,31 @@ impl SnapshotStore for InMemorySnapshotStore {
     }
 }
 
+#[cfg(feature = "async")]
+impl super::store::AsyncSnapshotStore for InMemorySnapshotStore {
+    fn get_snapshot_async(
+        &self,
+        id: &str,
+    ) -> impl std::future::Future<Output = Result<Option<SnapshotRecord>, RepositoryError>> + Send
+    {
+        std::future::ready(self.get_snapshot(id))
+    }
+
+    fn save_snapshot_async(
+        &self,
+        record: SnapshotRecord,
+    ) -> impl std::future::Future<Output = Result<(), RepositoryError>> + Send {
+        std::future::ready(self.save_snapshot(record))
+    }
+
+    fn delete_snapshot_async(
+        &self,
+        id: &str,
+    ) -> impl std::future::Future<Output = Result<bool, RepositoryError>> + Send {
+        std::future::ready(self.delete_snapshot(id))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -7,3 +7,6 @@ pub use in_memory::InMemorySnapshotStore;
 pub use repository::{hydrate_from_snapshot, SnapshotAggregateRepository};
 pub use snapshottable::Snapshottable;
 pub use store::{SnapshotRecord, SnapshotStore};
+
+#[cfg(feature = "async")]
+pub use store::AsyncSnapshotStore;
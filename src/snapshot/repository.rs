@@ -1,10 +1,14 @@
-use crate::aggregate::{hydrate, AggregateRepository};
+use crate::aggregate::{hydrate, AggregateRepository, RetryPolicy, Updated};
 use crate::entity::{Entity, upcast_events};
-use crate::repository::{Commit, Find, Get, RepositoryError};
+use crate::repository::{Commit, Find, GetOne, GetOneFrom, RepositoryError};
 use crate::queued_repo::{GetWithOpts, GetAllWithOpts, ReadOpts, UnlockableRepository};
+#[cfg(feature = "async")]
+use crate::repository::{AsyncCommit, AsyncFind, AsyncGetOne, AsyncGetOneFrom};
 
 use super::snapshottable::Snapshottable;
 use super::store::{SnapshotRecord, SnapshotStore};
+#[cfg(feature = "async")]
+use super::store::AsyncSnapshotStore;
 
 /// Hydrate an aggregate from a snapshot, replaying only events after the snapshot version.
 pub fn hydrate_from_snapshot<A: Snapshottable>(
@@ -22,7 +26,8 @@ pub fn hydrate_from_snapshot<A: Snapshottable>(
         .map_err(|e| RepositoryError::Replay(format!("snapshot deserialize: {e}")))?;
     agg.restore_from_snapshot(snap);
 
-    // Replay only events AFTER the snapshot
+    // Replay only events AFTER the snapshot (the entity may hold the full
+    // stream or, when loaded with `GetOneFrom`, just the tail)
     let post_snapshot: Vec<crate::entity::EventRecord> = agg
         .entity()
         .events()
@@ -67,47 +72,98 @@ impl<R, A> SnapshotAggregateRepository<R, A> {
     }
 }
 
+impl<R, A: Snapshottable> SnapshotAggregateRepository<R, A> {
+    fn hydrate_with_optional_snapshot(
+        &self,
+        entity: Entity,
+        snapshot: Option<SnapshotRecord>,
+    ) -> Result<A, RepositoryError> {
+        match snapshot {
+            Some(snap) if snap.version <= entity.version() => {
+                hydrate_from_snapshot::<A>(entity, snap)
+            }
+            _ => hydrate::<A>(entity),
+        }
+    }
+}
+
 // ============================================================================
 // get / get_all — snapshot-aware hydration
 // ============================================================================
 
 impl<R, A> SnapshotAggregateRepository<R, A>
 where
-    R: Get + SnapshotStore,
+    R: GetOne + GetOneFrom + SnapshotStore,
     A: Snapshottable,
 {
     /// Load an aggregate, using a snapshot if available.
+    ///
+    /// With a snapshot, only the events after the snapshot version are read
+    /// from the store (via [`GetOneFrom`]).
     pub fn get(&self, id: &str) -> Result<Option<A>, RepositoryError> {
-        let entity = self.inner.repo().get(id)?;
-        let Some(entity) = entity else {
-            return Ok(None);
+        let repo = self.inner.repo();
+        let namespace = self.inner.namespace();
+        let stream_id = namespace.stream_id(id);
+        let entity = match repo.get_snapshot(&stream_id)? {
+            Some(snap) => match repo.get_one_from(&stream_id, snap.version)? {
+                Some(entity) if entity.base_version() == snap.version => {
+                    let entity = namespace.load(entity);
+                    return Ok(Some(hydrate_from_snapshot::<A>(entity, snap)?));
+                }
+                // The snapshot is ahead of the stream: ignore it.
+                Some(_) => repo.get_one(&stream_id)?,
+                None => None,
+            },
+            None => repo.get_one(&stream_id)?,
         };
-        let snapshot = self.inner.repo().get_snapshot(id)?;
-        Ok(Some(self.hydrate_with_optional_snapshot(entity, snapshot)?))
+        entity.map(|e| hydrate::<A>(namespace.load(e))).transpose()
     }
 
-    /// Load multiple aggregates by ID.
+    /// Load multiple aggregates by ID. Missing IDs are skipped.
     pub fn get_all(&self, ids: &[&str]) -> Result<Vec<A>, RepositoryError> {
-        let entities = self.inner.repo().get(ids)?;
-        let mut aggregates = Vec::with_capacity(entities.len());
-        for entity in entities {
-            let snapshot = self.inner.repo().get_snapshot(entity.id())?;
-            aggregates.push(self.hydrate_with_optional_snapshot(entity, snapshot)?);
+        let mut aggregates = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(aggregate) = self.get(id)? {
+                aggregates.push(aggregate);
+            }
         }
         Ok(aggregates)
     }
+}
 
-    fn hydrate_with_optional_snapshot(
+impl<R, A> SnapshotAggregateRepository<R, A>
+where
+    R: GetOne + GetOneFrom + Commit + SnapshotStore,
+    A: Snapshottable,
+{
+    /// Snapshot-aware [`AggregateRepository::update`]: each attempt loads
+    /// from the latest snapshot, and a successful commit may take a new one.
+    pub fn update<T, E, F>(&self, id: &str, command: F) -> Result<Option<Updated<A, T>>, E>
+    where
+        F: FnMut(&mut A) -> Result<T, E>,
+        E: From<RepositoryError>,
+    {
+        self.update_with(id, &RetryPolicy::default(), command)
+    }
+
+    /// [`update`](Self::update) with an explicit retry policy.
+    pub fn update_with<T, E, F>(
         &self,
-        entity: Entity,
-        snapshot: Option<SnapshotRecord>,
-    ) -> Result<A, RepositoryError> {
-        match snapshot {
-            Some(snap) if snap.version <= entity.version() => {
-                hydrate_from_snapshot::<A>(entity, snap)
-            }
-            _ => hydrate::<A>(entity),
-        }
+        id: &str,
+        policy: &RetryPolicy,
+        command: F,
+    ) -> Result<Option<Updated<A, T>>, E>
+    where
+        F: FnMut(&mut A) -> Result<T, E>,
+        E: From<RepositoryError>,
+    {
+        let stream_id = self.repo().stream_id(id);
+        policy.run(
+            || self.get(id),
+            |aggregate| self.commit(aggregate),
+            || self.repo().repo().release(&[&stream_id]),
+            command,
+        )
     }
 }
 
@@ -122,18 +178,14 @@ where
 {
     /// Commit the aggregate and create a snapshot if the frequency threshold is met.
     pub fn commit(&self, aggregate: &mut A) -> Result<(), RepositoryError> {
-        self.inner.repo().commit(aggregate.entity_mut())?;
+        self.inner.commit(aggregate)?;
         self.maybe_snapshot(aggregate)?;
         Ok(())
     }
 
     /// Commit multiple aggregates and create snapshots where thresholds are met.
     pub fn commit_all(&self, aggregates: &mut [&mut A]) -> Result<(), RepositoryError> {
-        let mut entities: Vec<&mut Entity> = aggregates
-            .iter_mut()
-            .map(|agg| (*agg).entity_mut())
-            .collect();
-        self.inner.repo().commit(&mut entities[..])?;
+        self.inner.commit_all(aggregates)?;
 
         for agg in aggregates.iter_mut() {
             self.maybe_snapshot(*agg)?;
@@ -151,7 +203,7 @@ where
                 .map_err(|e| RepositoryError::Replay(format!("snapshot serialize: {e}")))?;
 
             self.inner.repo().save_snapshot(SnapshotRecord {
-                aggregate_id: aggregate.entity().id().to_string(),
+                aggregate_id: self.inner.stream_id(aggregate.entity().id()).into_owned(),
                 version,
                 data,
             })?;
@@ -162,6 +214,151 @@ where
     }
 }
 
+// ============================================================================
+// Async counterparts (requires the `async` feature)
+// ============================================================================
+
+#[cfg(feature = "async")]
+impl<R, A> SnapshotAggregateRepository<R, A>
+where
+    R: AsyncGetOne + AsyncGetOneFrom + AsyncSnapshotStore,
+    A: Snapshottable,
+{
+    /// Async counterpart of [`get`](Self::get).
+    pub async fn get_async(&self, id: &str) -> Result<Option<A>, RepositoryError> {
+        let repo = self.inner.repo();
+        let namespace = self.inner.namespace();
+        let stream_id = namespace.stream_id(id);
+        let entity = match repo.get_snapshot_async(&stream_id).await? {
+            Some(snap) => match repo.get_one_from_async(&stream_id, snap.version).await? {
+                Some(entity) if entity.base_version() == snap.version => {
+                    let entity = namespace.load(entity);
+                    return Ok(Some(hydrate_from_snapshot::<A>(entity, snap)?));
+                }
+                Some(_) => repo.get_one_async(&stream_id).await?,
+                None => None,
+            },
+            None => repo.get_one_async(&stream_id).await?,
+        };
+        entity.map(|e| hydrate::<A>(namespace.load(e))).transpose()
+    }
+
+    /// Async counterpart of [`get_all`](Self::get_all).
+    pub async fn get_all_async(&self, ids: &[&str]) -> Result<Vec<A>, RepositoryError> {
+        let mut aggregates = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(aggregate) = self.get_async(id).await? {
+                aggregates.push(aggregate);
+            }
+        }
+        Ok(aggregates)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R, A> SnapshotAggregateRepository<R, A>
+where
+    R: AsyncCommit + AsyncSnapshotStore,
+    A: Snapshottable,
+{
+    /// Async counterpart of [`commit`](Self::commit).
+    pub async fn commit_async(&self, aggregate: &mut A) -> Result<(), RepositoryError> {
+        self.inner.commit_async(aggregate).await?;
+        self.maybe_snapshot_async(aggregate).await
+    }
+
+    /// Async counterpart of [`commit_all`](Self::commit_all).
+    pub async fn commit_all_async(&self, aggregates: &mut [&mut A]) -> Result<(), RepositoryError> {
+        self.inner.commit_all_async(aggregates).await?;
+
+        for agg in aggregates.iter_mut() {
+            self.maybe_snapshot_async(*agg).await?;
+        }
+        Ok(())
+    }
+
+    async fn maybe_snapshot_async(&self, aggregate: &mut A) -> Result<(), RepositoryError> {
+        let version = aggregate.entity().version();
+        let snap_version = aggregate.entity().snapshot_version();
+
+        if version >= snap_version + self.frequency {
+            let snap = aggregate.create_snapshot();
+            let data = bitcode::serialize(&snap)
+                .map_err(|e| RepositoryError::Replay(format!("snapshot serialize: {e}")))?;
+
+            self.inner
+                .repo()
+                .save_snapshot_async(SnapshotRecord {
+                    aggregate_id: self.inner.stream_id(aggregate.entity().id()).into_owned(),
+                    version,
+                    data,
+                })
+                .await?;
+
+            aggregate.entity_mut().set_snapshot_version(version);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl<R, A> SnapshotAggregateRepository<R, A>
+where
+    R: AsyncFind + AsyncSnapshotStore,
+    A: Snapshottable,
+{
+    /// Async counterpart of [`find`](Self::find).
+    pub async fn find_async<F>(&self, predicate: F) -> Result<Vec<A>, RepositoryError>
+    where
+        F: Fn(&A) -> bool,
+    {
+        let namespace = self.inner.namespace();
+        let entities = self.inner.repo().find_async(move |e| namespace.contains(e.id())).await?;
+        let mut results = Vec::new();
+        for entity in entities {
+            let snapshot = self.inner.repo().get_snapshot_async(entity.id()).await?;
+            let agg = self.hydrate_with_optional_snapshot(namespace.load(entity), snapshot)?;
+            if predicate(&agg) {
+                results.push(agg);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Async counterpart of [`find_one`](Self::find_one).
+    pub async fn find_one_async<F>(&self, predicate: F) -> Result<Option<A>, RepositoryError>
+    where
+        F: Fn(&A) -> bool,
+    {
+        let namespace = self.inner.namespace();
+        let entities = self.inner.repo().find_async(move |e| namespace.contains(e.id())).await?;
+        for entity in entities {
+            let snapshot = self.inner.repo().get_snapshot_async(entity.id()).await?;
+            let agg = self.hydrate_with_optional_snapshot(namespace.load(entity), snapshot)?;
+            if predicate(&agg) {
+                return Ok(Some(agg));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Async counterpart of [`exists`](Self::exists).
+    pub async fn exists_async<F>(&self, predicate: F) -> Result<bool, RepositoryError>
+    where
+        F: Fn(&A) -> bool,
+    {
+        Ok(self.find_one_async(predicate).await?.is_some())
+    }
+
+    /// Async counterpart of [`count`](Self::count).
+    pub async fn count_async<F>(&self, predicate: F) -> Result<usize, RepositoryError>
+    where
+        F: Fn(&A) -> bool,
+    {
+        Ok(self.find_async(predicate).await?.len())
+    }
+}
+
 // ============================================================================
 // find / find_one / exists / count — delegate with snapshot-aware hydration
 // ============================================================================
@@ -175,16 +372,12 @@ where
     where
         F: Fn(&A) -> bool,
     {
-        let entities = self.inner.repo().find(|_| true)?;
+        let namespace = self.inner.namespace();
+        let entities = self.inner.repo().find(|e| namespace.contains(e.id()))?;
         let mut results = Vec::new();
         for entity in entities {
             let snapshot = self.inner.repo().get_snapshot(entity.id())?;
-            let agg = match snapshot {
-                Some(snap) if snap.version <= entity.version() => {
-                    hydrate_from_snapshot::<A>(entity, snap)?
-                }
-                _ => hydrate::<A>(entity)?,
-            };
+            let agg = self.hydrate_with_optional_snapshot(namespace.load(entity), snapshot)?;
             if predicate(&agg) {
                 results.push(agg);
             }
@@ -196,15 +389,11 @@ where
     where
         F: Fn(&A) -> bool,
     {
-        let entities = self.inner.repo().find(|_| true)?;
+        let namespace = self.inner.namespace();
+        let entities = self.inner.repo().find(|e| namespace.contains(e.id()))?;
         for entity in entities {
             let snapshot = self.inner.repo().get_snapshot(entity.id())?;
-            let agg = match snapshot {
-                Some(snap) if snap.version <= entity.version() => {
-                    hydrate_from_snapshot::<A>(entity, snap)?
-                }
-                _ => hydrate::<A>(entity)?,
-            };
+            let agg = self.hydrate_with_optional_snapshot(namespace.load(entity), snapshot)?;
             if predicate(&agg) {
                 return Ok(Some(agg));
             }
@@ -237,7 +426,7 @@ where
     A: Snapshottable,
 {
     pub fn abort(&self, aggregate: &A) -> Result<(), RepositoryError> {
-        self.inner.repo().unlock(aggregate.entity().id())
+        self.inner.abort(aggregate)
     }
 }
 
@@ -248,11 +437,14 @@ where
 {
     /// Non-locking read with snapshot-aware hydration.
     pub fn peek(&self, id: &str) -> Result<Option<A>, RepositoryError> {
-        let entity = self.inner.repo().get_with(id, ReadOpts::no_lock())?;
+        let namespace = self.inner.namespace();
+        let stream_id = namespace.stream_id(id);
+        let entity = self.inner.repo().get_with(&stream_id, ReadOpts::no_lock())?;
         let Some(entity) = entity else {
             return Ok(None);
         };
-        let snapshot = self.inner.repo().get_snapshot(id)?;
+        let snapshot = self.inner.repo().get_snapshot(&stream_id)?;
+        let entity = namespace.load(entity);
         match snapshot {
             Some(snap) if snap.version <= entity.version() => {
                 Ok(Some(hydrate_from_snapshot::<A>(entity, snap)?))
@@ -269,16 +461,14 @@ where
 {
     /// Non-locking bulk read with snapshot-aware hydration.
     pub fn peek_all(&self, ids: &[&str]) -> Result<Vec<A>, RepositoryError> {
-        let entities = self.inner.repo().get_all_with(ids, ReadOpts::no_lock())?;
+        let namespace = self.inner.namespace();
+        let stream_ids = namespace.stream_ids(ids);
+        let stream_ids: Vec<&str> = stream_ids.iter().map(|id| id.as_ref()).collect();
+        let entities = self.inner.repo().get_all_with(&stream_ids, ReadOpts::no_lock())?;
         let mut aggregates = Vec::with_capacity(entities.len());
         for entity in entities {
             let snapshot = self.inner.repo().get_snapshot(entity.id())?;
-            let agg = match snapshot {
-                Some(snap) if snap.version <= entity.version() => {
-                    hydrate_from_snapshot::<A>(entity, snap)?
-                }
-                _ => hydrate::<A>(entity)?,
-            };
+            let agg = self.hydrate_with_optional_snapshot(namespace.load(entity), snapshot)?;
             aggregates.push(agg);
         }
         Ok(aggregates)
@@ -318,9 +508,9 @@ where
     A: Snapshottable,
 {
     pub fn commit(self, aggregate: &mut A) -> Result<(), RepositoryError> {
-        self.snap_repo.inner.repo().commit(
-            &mut [aggregate.entity_mut(), self.outbox.entity_mut()][..],
-        )?;
+        self.snap_repo
+            .inner
+            .commit_with(aggregate, &mut [self.outbox.entity_mut()])?;
         self.snap_repo.maybe_snapshot(aggregate)?;
         Ok(())
     }
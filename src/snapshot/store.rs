@@ -19,3 +19,25 @@ pub trait SnapshotStore: Send + Sync {
     /// Delete the snapshot for the given aggregate ID. Returns true if one existed.
     fn delete_snapshot(&self, id: &str) -> Result<bool, RepositoryError>;
 }
+
+/// Async counterpart of [`SnapshotStore`] (requires the `async` feature).
+#[cfg(feature = "async")]
+pub trait AsyncSnapshotStore: Send + Sync {
+    /// Load the latest snapshot for the given aggregate ID.
+    fn get_snapshot_async(
+        &self,
+        id: &str,
+    ) -> impl std::future::Future<Output = Result<Option<SnapshotRecord>, RepositoryError>> + Send;
+
+    /// Save (or overwrite) the snapshot for the given aggregate ID.
+    fn save_snapshot_async(
+        &self,
+        record: SnapshotRecord,
+    ) -> impl std::future::Future<Output = Result<(), RepositoryError>> + Send;
+
+    /// Delete the snapshot for the given aggregate ID. Returns true if one existed.
+    fn delete_snapshot_async(
+        &self,
+        id: &str,
+    ) -> impl std::future::Future<Output = Result<bool, RepositoryError>> + Send;
+}
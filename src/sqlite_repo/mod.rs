@@ -0,0 +1,5 @@
+mod repository;
+mod schema;
+
+pub use repository::SqliteRepository;
+pub(crate) use schema::{append_entities, load_stream, load_streams, storage_error};
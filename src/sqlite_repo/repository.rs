@@ -0,0 +1,869 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior};
+
+use crate::commit_builder::{
+    check_model_versions, AtomicCommit, ModelChange, ModelWrite, WriteSet,
+};
+use crate::entity::{Committable, Entity, EventRecord};
+use crate::read_model::{ReadModel, ReadModelError, ReadModelStore, Versioned};
+use crate::repository::{
+    Commit, Count, Exists, Find, FindOne, FindPage, GetMany, GetOne, GetOneFrom, Page, PageRequest,
+    ReadAll, RepositoryError, StoredEvent,
+};
+use crate::snapshot::{SnapshotRecord, SnapshotStore};
+use crate::subscription::CheckpointStore;
+
+use super::schema::{self, storage_error};
+
+/// Embedded SQLite repository (requires the `sqlite` feature).
+///
+/// Events, read models, snapshots and outbox messages live in one database
+/// file, so `CommitBuilder::commit` writes the aggregate, its outbox messages
+/// and its read models in a single SQL transaction.
+///
+/// File databases use WAL journaling with `synchronous = FULL`, so a commit
+/// is durable once it returns. Cheap to clone: clones share one connection.
+#[derive(Clone)]
+pub struct SqliteRepository {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteRepository {
+    /// Open (or create) the database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RepositoryError> {
+        let conn = Connection::open(path).map_err(storage_error)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))
+            .map_err(storage_error)?;
+        Self::from_connection(conn)
+    }
+
+    /// Open a private in-memory database, for tests.
+    pub fn in_memory() -> Result<Self, RepositoryError> {
+        Self::from_connection(Connection::open_in_memory().map_err(storage_error)?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, RepositoryError> {
+        conn.pragma_update(None, "synchronous", "FULL")
+            .map_err(storage_error)?;
+        conn.pragma_update(None, "foreign_keys", "ON")
+            .map_err(storage_error)?;
+        conn.execute_batch(schema::SCHEMA).map_err(storage_error)?;
+        Ok(SqliteRepository {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    pub(crate) fn connection(&self) -> Result<MutexGuard<'_, Connection>, RepositoryError> {
+        self.conn
+            .lock()
+            .map_err(|_| RepositoryError::LockPoisoned("connection"))
+    }
+
+    fn model_connection(&self) -> Result<MutexGuard<'_, Connection>, ReadModelError> {
+        self.conn
+            .lock()
+            .map_err(|_| ReadModelError::Storage("lock poisoned".into()))
+    }
+
+    /// Append entities and upsert raw read models in one transaction, then
+    /// mark the entities committed.
+    fn write(
+        &self,
+        entities: &mut [&mut Entity],
+        models: Vec<ModelWrite>,
+    ) -> Result<(), RepositoryError> {
+        let mut conn = self.connection()?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(storage_error)?;
+
+        // Every check runs before any entity is rebased, so a failed commit
+        // leaves the entities as they were
+        let stored = schema::check_entities(&tx, entities)?;
+        check_model_versions(&models, |key| {
+            schema::model_version(&tx, key).map_err(storage_error)
+        })?;
+
+        schema::append_checked(&tx, entities, stored)?;
+        for model in &models {
+            match &model.change {
+                ModelChange::Upsert(bytes) => {
+                    schema::upsert_model_row(&tx, &model.key, bytes).map_err(storage_error)?;
+                }
+                ModelChange::Delete => {
+                    tx.execute("DELETE FROM read_models WHERE key = ?1", [&model.key])
+                        .map_err(storage_error)?;
+                }
+            }
+        }
+        tx.commit().map_err(storage_error)?;
+
+        for entity in entities.iter_mut() {
+            entity.mark_committed();
+        }
+        Ok(())
+    }
+
+    fn load_all(&self) -> Result<Vec<Entity>, RepositoryError> {
+        let conn = self.connection()?;
+        Ok(schema::load_streams(&conn, "")?
+            .into_iter()
+            .map(|(id, events)| to_entity(id, events))
+            .collect())
+    }
+}
+
+fn to_entity(id: impl Into<String>, events: Vec<EventRecord>) -> Entity {
+    let mut entity = Entity::with_id(id);
+    entity.load_from_history(events);
+    entity
+}
+
+fn model_key(collection: &str, id: &str) -> String {
+    format!("{}:{}", collection, id)
+}
+
+fn decode_model<M: ReadModel>(bytes: &[u8]) -> Result<M, ReadModelError> {
+    serde_json::from_slice(bytes).map_err(|e| ReadModelError::Serde(e.to_string()))
+}
+
+fn model_storage_error(err: rusqlite::Error) -> ReadModelError {
+    ReadModelError::Storage(err.to_string())
+}
+
+impl GetOne for SqliteRepository {
+    fn get_one(&self, id: &str) -> Result<Option<Entity>, RepositoryError> {
+        let conn = self.connection()?;
+        Ok(schema::load_stream(&conn, id)?.map(|events| to_entity(id, events)))
+    }
+}
+
+impl GetOneFrom for SqliteRepository {
+    fn get_one_from(&self, id: &str, from_version: u64) -> Result<Option<Entity>, RepositoryError> {
+        let conn = self.connection()?;
+        Ok(schema::load_stream_from(&conn, id, from_version)?.map(|(from, events)| {
+            let mut entity = Entity::with_id(id);
+            entity.load_history_after(from, events);
+            entity
+        }))
+    }
+}
+
+impl GetMany for SqliteRepository {
+    fn get_many(&self, ids: &[&str]) -> Result<Vec<Entity>, RepositoryError> {
+        let mut entities = Vec::with_capacity(ids.len());
+        for id in ids {
+            if let Some(entity) = self.get_one(id)? {
+                entities.push(entity);
+            }
+        }
+        Ok(entities)
+    }
+}
+
+impl Find for SqliteRepository {
+    fn find<F>(&self, predicate: F) -> Result<Vec<Entity>, RepositoryError>
+    where
+        F: Fn(&Entity) -> bool,
+    {
+        Ok(self.load_all()?.into_iter().filter(|e| predicate(e)).collect())
+    }
+}
+
+impl FindPage for SqliteRepository {
+    fn find_page<F>(
+        &self,
+        predicate: F,
+        request: &PageRequest,
+    ) -> Result<Page<Entity>, RepositoryError>
+    where
+        F: Fn(&Entity) -> bool,
+    {
+        let conn = self.connection()?;
+        let ids = schema::stream_ids_after(&conn, request.after())?;
+        let candidates = ids.into_iter().map(|id| {
+            let events = schema::load_stream(&conn, &id)?.unwrap_or_default();
+            Ok(to_entity(id, events))
+        });
+        Page::fill(request, candidates, predicate, |e| e.id().to_string())
+    }
+}
+
+impl FindOne for SqliteRepository {
+    fn find_one<F>(&self, predicate: F) -> Result<Option<Entity>, RepositoryError>
+    where
+        F: Fn(&Entity) -> bool,
+    {
+        Ok(self.load_all()?.into_iter().find(|e| predicate(e)))
+    }
+}
+
+impl Exists for SqliteRepository {
+    fn exists<F>(&self, predicate: F) -> Result<bool, RepositoryError>
+    where
+        F: Fn(&Entity) -> bool,
+    {
+        Ok(self.load_all()?.iter().any(predicate))
+    }
+}
+
+impl Count for SqliteRepository {
+    fn count<F>(&self, predicate: F) -> Result<usize, RepositoryError>
+    where
+        F: Fn(&Entity) -> bool,
+    {
+        Ok(self.load_all()?.iter().filter(|e| predicate(e)).count())
+    }
+}
+
+impl Commit for SqliteRepository {
+    fn commit<C: Committable + ?Sized>(&self, committable: &mut C) -> Result<(), RepositoryError> {
+        self.write(&mut committable.entities_mut(), Vec::new())
+    }
+}
+
+impl AtomicCommit for SqliteRepository {
+    /// Writes the events and read models in one transaction.
+    fn commit_write_set(&self, writes: WriteSet<'_>) -> Result<(), RepositoryError> {
+        let WriteSet {
+            mut entities,
+            models,
+        } = writes;
+        self.write(&mut entities, models)
+    }
+}
+
+impl ReadModelStore for SqliteRepository {
+    fn get_model<M: ReadModel>(&self, id: &str) -> Result<Option<Versioned<M>>, ReadModelError> {
+        let conn = self.model_connection()?;
+        let row = conn
+            .query_row(
+                "SELECT version, data FROM read_models WHERE key = ?1",
+                [model_key(M::COLLECTION, id)],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?)),
+            )
+            .optional()
+            .map_err(model_storage_error)?;
+
+        match row {
+            Some((version, bytes)) => Ok(Some(Versioned {
+                data: decode_model(&bytes)?,
+                version: version as u64,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    fn upsert<M: ReadModel>(&self, model: &M) -> Result<Versioned<M>, ReadModelError> {
+        let bytes =
+            serde_json::to_vec(model).map_err(|e| ReadModelError::Serde(e.to_string()))?;
+        let conn = self.model_connection()?;
+        let version = schema::upsert_model_row(&conn, &model_key(M::COLLECTION, model.id()), &bytes)
+            .map_err(model_storage_error)?;
+
+        Ok(Versioned {
+            data: model.clone(),
+            version,
+        })
+    }
+
+    fn insert<M: ReadModel>(&self, model: &M) -> Result<Versioned<M>, ReadModelError> {
+        let key = model_key(M::COLLECTION, model.id());
+        let bytes =
+            serde_json::to_vec(model).map_err(|e| ReadModelError::Serde(e.to_string()))?;
+        let conn = self.model_connection()?;
+
+        let inserted = conn
+            .execute(
+                "INSERT INTO read_models (key, collection, version, data) VALUES (?1, ?2, 1, ?3) \
+                 ON CONFLICT (key) DO NOTHING",
+                params![key, M::COLLECTION, bytes],
+            )
+            .map_err(model_storage_error)?;
+
+        if inserted == 0 {
+            let actual = schema::model_version(&conn, &key)
+                .map_err(model_storage_error)?
+                .unwrap_or(0);
+            return Err(ReadModelError::ConcurrencyConflict {
+                collection: M::COLLECTION.to_string(),
+                id: model.id().to_string(),
+                expected: 0,
+                actual,
+            });
+        }
+
+        Ok(Versioned {
+            data: model.clone(),
+            version: 1,
+        })
+    }
+
+    fn update<M: ReadModel>(
+        &self,
+        model: &M,
+        expected_version: u64,
+    ) -> Result<Versioned<M>, ReadModelError> {
+        let key = model_key(M::COLLECTION, model.id());
+        let bytes =
+            serde_json::to_vec(model).map_err(|e| ReadModelError::Serde(e.to_string()))?;
+        let conn = self.model_connection()?;
+
+        let updated = conn
+            .execute(
+                "UPDATE read_models SET data = ?1, version = version + 1 \
+                 WHERE key = ?2 AND version = ?3",
+                params![bytes, key, expected_version as i64],
+            )
+            .map_err(model_storage_error)?;
+
+        if updated == 0 {
+            return match schema::model_version(&conn, &key).map_err(model_storage_error)? {
+                None => Err(ReadModelError::NotFound {
+                    collection: M::COLLECTION.to_string(),
+                    id: model.id().to_string(),
+                }),
+                Some(actual) => Err(ReadModelError::ConcurrencyConflict {
+                    collection: M::COLLECTION.to_string(),
+                    id: model.id().to_string(),
+                    expected: expected_version,
+                    actual,
+                }),
+            };
+        }
+
+        Ok(Versioned {
+            data: model.clone(),
+            version: expected_version + 1,
+        })
+    }
+
+    fn delete<M: ReadModel>(&self, id: &str) -> Result<bool, ReadModelError> {
+        let conn = self.model_connection()?;
+        let deleted = conn
+            .execute(
+                "DELETE FROM read_models WHERE key = ?1",
+                [model_key(M::COLLECTION, id)],
+            )
+            .map_err(model_storage_error)?;
+        Ok(deleted > 0)
+    }
+
+    fn find_models<M: ReadModel>(
+        &self,
+        predicate: &dyn Fn(&M) -> bool,
+    ) -> Result<Vec<Versioned<M>>, ReadModelError> {
+        let conn = self.model_connection()?;
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT version, data FROM read_models WHERE collection = ?1 ORDER BY key",
+            )
+            .map_err(model_storage_error)?;
+        let rows = stmt
+            .query_map([M::COLLECTION], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(model_storage_error)?;
+
+        let mut results = Vec::new();
+        for row in rows {
+            let (version, bytes) = row.map_err(model_storage_error)?;
+            if let Ok(data) = decode_model::<M>(&bytes) {
+                if predicate(&data) {
+                    results.push(Versioned {
+                        data,
+                        version: version as u64,
+                    });
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    fn find_models_page<M: ReadModel>(
+        &self,
+        predicate: &dyn Fn(&M) -> bool,
+        request: &PageRequest,
+    ) -> Result<Page<Versioned<M>>, ReadModelError> {
+        let conn = self.model_connection()?;
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT version, data FROM read_models \
+                 WHERE collection = ?1 AND (?2 IS NULL OR key > ?2) ORDER BY key",
+            )
+            .map_err(model_storage_error)?;
+        let after = request.after().map(|id| model_key(M::COLLECTION, id));
+        let rows = stmt
+            .query_map(params![M::COLLECTION, after], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(model_storage_error)?;
+
+        let candidates = rows.filter_map(|row| match row {
+            Ok((version, bytes)) => decode_model::<M>(&bytes).ok().map(|data| {
+                Ok(Versioned {
+                    data,
+                    version: version as u64,
+                })
+            }),
+            Err(err) => Some(Err(model_storage_error(err))),
+        });
+        Page::fill(request, candidates, |m| predicate(&m.data), |m| m.data.id().to_string())
+    }
+
+    fn find_one_model<M: ReadModel>(
+        &self,
+        predicate: &dyn Fn(&M) -> bool,
+    ) -> Result<Option<Versioned<M>>, ReadModelError> {
+        Ok(self.find_models(predicate)?.into_iter().next())
+    }
+
+    fn upsert_raw(&self, key: &str, bytes: Vec<u8>) -> Result<(), ReadModelError> {
+        let conn = self.model_connection()?;
+        schema::upsert_model_row(&conn, key, &bytes).map_err(model_storage_error)?;
+        Ok(())
+    }
+
+    /// Writes every model in one transaction.
+    fn upsert_raw_many(&self, models: Vec<(String, Vec<u8>)>) -> Result<(), ReadModelError> {
+        let mut conn = self.model_connection()?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(model_storage_error)?;
+        for (key, bytes) in &models {
+            schema::upsert_model_row(&tx, key, bytes).map_err(model_storage_error)?;
+        }
+        tx.commit().map_err(model_storage_error)
+    }
+
+    fn get_raw(&self, key: &str) -> Result<Option<Vec<u8>>, ReadModelError> {
+        let conn = self.model_connection()?;
+        conn.query_row("SELECT data FROM read_models WHERE key = ?1", [key], |row| {
+            row.get::<_, Vec<u8>>(0)
+        })
+        .optional()
+        .map_err(model_storage_error)
+    }
+
+    fn delete_collection(&self, collection: &str) -> Result<usize, ReadModelError> {
+        let conn = self.model_connection()?;
+        conn.execute("DELETE FROM read_models WHERE collection = ?1", [collection])
+            .map_err(model_storage_error)
+    }
+
+    fn swap_collections(
+        &self,
+        swaps: &[(&str, &str)],
+        models: Vec<(String, Vec<u8>)>,
+    ) -> Result<(), ReadModelError> {
+        let mut conn = self.model_connection()?;
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(model_storage_error)?;
+
+        for (shadow, live) in swaps {
+            tx.execute("DELETE FROM read_models WHERE collection = ?1", [live])
+                .map_err(model_storage_error)?;
+            tx.execute(
+                "UPDATE read_models SET collection = ?2, key = ?2 || substr(key, length(?1) + 1) \
+                 WHERE collection = ?1",
+                params![shadow, live],
+            )
+            .map_err(model_storage_error)?;
+        }
+        for (key, bytes) in &models {
+            schema::upsert_model_row(&tx, key, bytes).map_err(model_storage_error)?;
+        }
+        tx.commit().map_err(model_storage_error)
+    }
+}
+
+impl ReadAll for SqliteRepository {
+    fn read_all(&self, from_position: u64, limit: usize) -> Result<Vec<StoredEvent>, RepositoryError> {
+        let conn = self.connection()?;
+        schema::load_all_from(&conn, from_position, limit)
+    }
+
+    fn last_position(&self) -> Result<u64, RepositoryError> {
+        let conn = self.connection()?;
+        schema::last_position(&conn)
+    }
+}
+
+impl SnapshotStore for SqliteRepository {
+    fn get_snapshot(&self, id: &str) -> Result<Option<SnapshotRecord>, RepositoryError> {
+        let conn = self.connection()?;
+        conn.query_row(
+            "SELECT version, data FROM snapshots WHERE aggregate_id = ?1",
+            [id],
+            |row| {
+                Ok(SnapshotRecord {
+                    aggregate_id: id.to_string(),
+                    version: row.get::<_, i64>(0)? as u64,
+                    data: row.get(1)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(storage_error)
+    }
+
+    fn save_snapshot(&self, record: SnapshotRecord) -> Result<(), RepositoryError> {
+        let conn = self.connection()?;
+        conn.execute(
+            "INSERT INTO snapshots (aggregate_id, version, data) VALUES (?1, ?2, ?3) \
+             ON CONFLICT (aggregate_id) DO UPDATE SET version = excluded.version, data = excluded.data",
+            params![record.aggregate_id, record.version as i64, record.data],
+        )
+        .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn delete_snapshot(&self, id: &str) -> Result<bool, RepositoryError> {
+        let conn = self.connection()?;
+        let deleted = conn
+            .execute("DELETE FROM snapshots WHERE aggregate_id = ?1", [id])
+            .map_err(storage_error)?;
+        Ok(deleted > 0)
+    }
+}
+
+impl CheckpointStore for SqliteRepository {
+    fn load_checkpoint(&self, name: &str) -> Result<Option<u64>, RepositoryError> {
+        let conn = self.connection()?;
+        conn.query_row(
+            "SELECT position FROM checkpoints WHERE name = ?1",
+            [name],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()
+        .map(|position| position.map(|p| p as u64))
+        .map_err(storage_error)
+    }
+
+    fn save_checkpoint(&self, name: &str, position: u64) -> Result<(), RepositoryError> {
+        let conn = self.connection()?;
+        conn.execute(
+            "INSERT INTO checkpoints (name, position) VALUES (?1, ?2) \
+             ON CONFLICT (name) DO UPDATE SET position = excluded.position",
+            params![name, position as i64],
+        )
+        .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn delete_checkpoint(&self, name: &str) -> Result<bool, RepositoryError> {
+        let conn = self.connection()?;
+        let deleted = conn
+            .execute("DELETE FROM checkpoints WHERE name = ?1", [name])
+            .map_err(storage_error)?;
+        Ok(deleted > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::Get;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Counter {
+        id: String,
+        value: i32,
+    }
+
+    impl ReadModel for Counter {
+        const COLLECTION: &'static str = "counters";
+        fn id(&self) -> &str {
+            &self.id
+        }
+    }
+
+    #[test]
+    fn commit_and_get_round_trip() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        let mut entity = Entity::with_id("e1");
+        entity.set_meta("correlation_id", "req-1");
+        entity.digest("Created", &("a", 1));
+        entity.digest_v("Renamed", 2, &"b");
+        repo.commit(&mut entity).unwrap();
+
+        let loaded = repo.get("e1").unwrap().unwrap();
+        assert_eq!(loaded.version(), 2);
+        assert_eq!(loaded.events(), entity.events());
+        assert_eq!(loaded.events()[0].correlation_id(), Some("req-1"));
+        assert_eq!(loaded.events()[1].event_version, 2);
+    }
+
+    #[test]
+    fn empty_entity_creates_stream() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        let mut entity = Entity::with_id("empty");
+        repo.commit(&mut entity).unwrap();
+
+        let loaded = repo.get_one("empty").unwrap().unwrap();
+        assert!(loaded.events().is_empty());
+        assert_eq!(repo.count(|_| true).unwrap(), 1);
+        assert!(repo.get_one("missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn read_all_returns_global_order() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        let mut e1 = Entity::with_id("e1");
+        e1.digest("Created", &"v1");
+        let mut e2 = Entity::with_id("e2");
+        e2.digest("Created", &"v1");
+        repo.commit(&mut [&mut e1, &mut e2]).unwrap();
+        e1.digest("Updated", &"v2");
+        repo.commit(&mut e1).unwrap();
+
+        let all = repo.read_all(0, 10).unwrap();
+        let order: Vec<(&str, u64)> = all
+            .iter()
+            .map(|e| (e.stream_id.as_str(), e.position()))
+            .collect();
+        assert_eq!(order, vec![("e1", 1), ("e2", 2), ("e1", 3)]);
+        assert_eq!(repo.read_all(1, 1).unwrap()[0].stream_id, "e2");
+        assert_eq!(repo.last_position().unwrap(), 3);
+        assert_eq!(repo.get("e1").unwrap().unwrap().events(), e1.events());
+    }
+
+    #[test]
+    fn stale_commit_rolls_back_whole_batch() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        let mut e1 = Entity::with_id("e1");
+        e1.digest("Created", &"v1");
+        let mut e2 = Entity::with_id("e2");
+        e2.digest("Created", &"v1");
+        repo.commit(&mut [&mut e1, &mut e2]).unwrap();
+
+        let mut e1_a = repo.get_one("e1").unwrap().unwrap();
+        let mut e2_a = repo.get_one("e2").unwrap().unwrap();
+        let mut e2_b = repo.get_one("e2").unwrap().unwrap();
+        e2_b.digest("Conflict", &"b");
+        repo.commit(&mut e2_b).unwrap();
+
+        e1_a.digest("Update", &"a");
+        e2_a.digest("Update", &"a");
+        let err = repo.commit(&mut [&mut e1_a, &mut e2_a]).unwrap_err();
+        assert_eq!(
+            err,
+            RepositoryError::ConcurrentWrite {
+                id: "e2".to_string(),
+                expected: 1,
+                actual: 2,
+            }
+        );
+        assert_eq!(repo.get_one("e1").unwrap().unwrap().version(), 1);
+        assert_eq!(e1_a.committed_version(), 1);
+    }
+
+    #[test]
+    fn get_one_from_reads_events_after_version() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        let mut entity = Entity::with_id("e1");
+        for i in 0..3 {
+            entity.digest("Touched", &i);
+        }
+        repo.commit(&mut entity).unwrap();
+
+        let mut tail = repo.get_one_from("e1", 1).unwrap().unwrap();
+        let sequences: Vec<u64> = tail.events().iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, vec![2, 3]);
+        assert_eq!(tail.version(), 3);
+
+        tail.digest("Touched", &3);
+        repo.commit(&mut tail).unwrap();
+        assert_eq!(repo.get_one("e1").unwrap().unwrap().version(), 4);
+        assert_eq!(repo.get_one_from("e1", 9).unwrap().unwrap().base_version(), 4);
+        assert!(repo.get_one_from("missing", 0).unwrap().is_none());
+    }
+
+    #[test]
+    fn expected_version_is_checked_inside_the_transaction() {
+        use crate::repository::ExpectedVersion;
+
+        let repo = SqliteRepository::in_memory().unwrap();
+        let mut entity = Entity::with_id("e1");
+        entity.digest("Created", &1);
+        repo.commit_expecting(&mut entity, ExpectedVersion::NoStream).unwrap();
+
+        let mut duplicate = Entity::with_id("e1");
+        duplicate.digest("Created", &2);
+        let err = repo.commit_expecting(&mut duplicate, ExpectedVersion::NoStream).unwrap_err();
+        assert!(matches!(
+            err,
+            RepositoryError::WrongExpectedVersion { actual: Some(1), .. }
+        ));
+
+        repo.commit_expecting(&mut duplicate, ExpectedVersion::Exact(1)).unwrap();
+        let stored = repo.get_one("e1").unwrap().unwrap();
+        let sequences: Vec<u64> = stored.events().iter().map(|e| e.sequence).collect();
+        assert_eq!(sequences, vec![1, 2]);
+    }
+
+    #[test]
+    fn read_model_versions() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        let counter = Counter {
+            id: "c1".into(),
+            value: 1,
+        };
+
+        assert_eq!(repo.insert(&counter).unwrap().version, 1);
+        assert!(matches!(
+            repo.insert(&counter),
+            Err(ReadModelError::ConcurrencyConflict { actual: 1, .. })
+        ));
+        assert_eq!(repo.upsert(&counter).unwrap().version, 2);
+        assert_eq!(repo.update(&counter, 2).unwrap().version, 3);
+        assert!(matches!(
+            repo.update(&counter, 2),
+            Err(ReadModelError::ConcurrencyConflict {
+                expected: 2,
+                actual: 3,
+                ..
+            })
+        ));
+
+        let missing = Counter {
+            id: "nope".into(),
+            value: 0,
+        };
+        assert!(matches!(
+            repo.update(&missing, 1),
+            Err(ReadModelError::NotFound { .. })
+        ));
+
+        let loaded = repo.get_model::<Counter>("c1").unwrap().unwrap();
+        assert_eq!(loaded.version, 3);
+        assert_eq!(loaded.data, counter);
+
+        assert!(repo.delete::<Counter>("c1").unwrap());
+        assert!(!repo.delete::<Counter>("c1").unwrap());
+    }
+
+    #[test]
+    fn read_model_conflict_rolls_back_the_write_set() {
+        use crate::CommitBuilderExt;
+
+        let repo = SqliteRepository::in_memory().unwrap();
+        let counter = Counter {
+            id: "c1".into(),
+            value: 1,
+        };
+        repo.upsert(&counter).unwrap();
+
+        let mut entity = Entity::with_id("e1");
+        entity.digest("Created", &"v1");
+        let result = repo
+            .delete_readmodel::<Counter>("c1")
+            .readmodel_expecting(&Counter { id: "c2".into(), value: 2 }, 3)
+            .commit_many(&mut [&mut entity]);
+        assert!(matches!(result, Err(RepositoryError::ReadModelConflict(_))));
+        assert!(repo.get_one("e1").unwrap().is_none());
+        assert!(repo.get_model::<Counter>("c1").unwrap().is_some());
+
+        repo.delete_readmodel::<Counter>("c1")
+            .readmodel_expecting(&Counter { id: "c2".into(), value: 2 }, 0)
+            .commit_many(&mut [&mut entity])
+            .unwrap();
+        assert!(repo.get_one("e1").unwrap().is_some());
+        assert!(repo.get_model::<Counter>("c1").unwrap().is_none());
+        assert_eq!(repo.get_model::<Counter>("c2").unwrap().unwrap().version, 1);
+    }
+
+    #[test]
+    fn find_models_filters_by_collection_and_predicate() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        for value in 0..4 {
+            repo.upsert(&Counter {
+                id: format!("c{}", value),
+                value,
+            })
+            .unwrap();
+        }
+        repo.upsert_raw("other:c1", b"{}".to_vec()).unwrap();
+
+        let even = repo.find_models::<Counter>(&|c| c.value % 2 == 0).unwrap();
+        assert_eq!(even.len(), 2);
+        let first = repo.find_one_model::<Counter>(&|c| c.value > 1).unwrap();
+        assert_eq!(first.unwrap().data.id, "c2");
+    }
+
+    #[test]
+    fn pages_read_in_id_order_after_the_cursor() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        for id in ["e3", "e1", "e2"] {
+            let mut entity = Entity::with_id(id);
+            entity.digest("Created", &id);
+            repo.commit(&mut entity).unwrap();
+            repo.upsert(&Counter {
+                id: id.into(),
+                value: 1,
+            })
+            .unwrap();
+        }
+
+        let page = repo.find_page(|_| true, &PageRequest::new(2)).unwrap();
+        let ids: Vec<&str> = page.items.iter().map(|e| e.id()).collect();
+        assert_eq!(ids, vec!["e1", "e2"]);
+        let rest = repo.find_page(|_| true, &page.next_request(2).unwrap()).unwrap();
+        assert_eq!(rest.items[0].id(), "e3");
+        assert_eq!(rest.next_cursor, None);
+
+        let models = repo
+            .find_models_page::<Counter>(&|_| true, &PageRequest::new(5).with_after("e1"))
+            .unwrap();
+        let ids: Vec<&str> = models.items.iter().map(|m| m.data.id.as_str()).collect();
+        assert_eq!(ids, vec!["e2", "e3"]);
+        assert_eq!(models.next_cursor, None);
+    }
+
+    #[test]
+    fn swap_collections_moves_shadow_into_live() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        repo.upsert(&Counter {
+            id: "old".into(),
+            value: 1,
+        })
+        .unwrap();
+        repo.upsert_raw("counters__rebuild:c1", br#"{"id":"c1","value":9}"#.to_vec())
+            .unwrap();
+
+        let checkpoint = ("other:1".to_string(), b"{}".to_vec());
+        repo.swap_collections(&[("counters__rebuild", "counters")], vec![checkpoint])
+            .unwrap();
+
+        assert!(repo.get_model::<Counter>("old").unwrap().is_none());
+        assert_eq!(repo.get_model::<Counter>("c1").unwrap().unwrap().data.value, 9);
+        assert!(repo.get_raw("counters__rebuild:c1").unwrap().is_none());
+        assert_eq!(repo.get_raw("other:1").unwrap().unwrap(), b"{}");
+        assert_eq!(repo.delete_collection("counters").unwrap(), 1);
+    }
+
+    #[test]
+    fn snapshots_overwrite_and_delete() {
+        let repo = SqliteRepository::in_memory().unwrap();
+        assert!(repo.get_snapshot("a1").unwrap().is_none());
+
+        for version in [5, 10] {
+            repo.save_snapshot(SnapshotRecord {
+                aggregate_id: "a1".into(),
+                version,
+                data: vec![version as u8],
+            })
+            .unwrap();
+        }
+
+        let snapshot = repo.get_snapshot("a1").unwrap().unwrap();
+        assert_eq!(snapshot.version, 10);
+        assert_eq!(snapshot.data, vec![10]);
+        assert!(repo.delete_snapshot("a1").unwrap());
+        assert!(!repo.delete_snapshot("a1").unwrap());
+    }
+}
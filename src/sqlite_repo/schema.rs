@@ -0,0 +1,358 @@
+//! Table layout and row mapping for the SQLite backend.
+
+use std::collections::HashMap;
+use std::time::{Duration, UNIX_EPOCH};
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::entity::{Entity, EventRecord};
+use crate::repository::{check_expected_version, RepositoryError, StoredEvent};
+
+pub(super) const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS streams (
+    stream_id TEXT PRIMARY KEY,
+    version   INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS events (
+    position      INTEGER PRIMARY KEY AUTOINCREMENT,
+    stream_id     TEXT NOT NULL REFERENCES streams (stream_id),
+    version       INTEGER NOT NULL,
+    event_name    TEXT NOT NULL,
+    event_version INTEGER NOT NULL,
+    sequence      INTEGER NOT NULL,
+    ts_secs       INTEGER NOT NULL,
+    ts_nanos      INTEGER NOT NULL,
+    payload       BLOB NOT NULL,
+    metadata      TEXT,
+    UNIQUE (stream_id, version)
+);
+
+CREATE TABLE IF NOT EXISTS read_models (
+    key        TEXT PRIMARY KEY,
+    collection TEXT NOT NULL,
+    version    INTEGER NOT NULL,
+    data       BLOB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS read_models_by_collection ON read_models (collection);
+
+CREATE TABLE IF NOT EXISTS snapshots (
+    aggregate_id TEXT PRIMARY KEY,
+    version      INTEGER NOT NULL,
+    data         BLOB NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS checkpoints (
+    name     TEXT PRIMARY KEY,
+    position INTEGER NOT NULL
+);
+";
+
+const EVENT_COLUMNS: &str = "e.event_name, e.payload, e.event_version, e.sequence, \
+     e.ts_secs, e.ts_nanos, e.metadata, e.position";
+
+pub(crate) fn storage_error(err: impl std::fmt::Display) -> RepositoryError {
+    RepositoryError::Storage(err.to_string())
+}
+
+/// Map the event columns starting at `start` back to an `EventRecord`.
+fn event_from_row(row: &Row<'_>, start: usize) -> rusqlite::Result<EventRecord> {
+    let secs: i64 = row.get(start + 4)?;
+    let nanos: i64 = row.get(start + 5)?;
+    let metadata: Option<String> = row.get(start + 6)?;
+    let metadata = match metadata {
+        Some(json) => serde_json::from_str(&json).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(
+                start + 6,
+                rusqlite::types::Type::Text,
+                Box::new(e),
+            )
+        })?,
+        None => HashMap::new(),
+    };
+
+    Ok(EventRecord {
+        event_name: row.get(start)?,
+        payload: row.get(start + 1)?,
+        event_version: row.get::<_, i64>(start + 2)? as u64,
+        sequence: row.get::<_, i64>(start + 3)? as u64,
+        timestamp: UNIX_EPOCH + Duration::new(secs as u64, nanos as u32),
+        metadata,
+        position: row.get::<_, i64>(start + 7)? as u64,
+    })
+}
+
+/// Load one stream, or `None` if it has never been committed.
+pub(crate) fn load_stream(
+    conn: &Connection,
+    id: &str,
+) -> Result<Option<Vec<EventRecord>>, RepositoryError> {
+    let exists = conn
+        .query_row("SELECT 1 FROM streams WHERE stream_id = ?1", [id], |_| Ok(()))
+        .optional()
+        .map_err(storage_error)?;
+    if exists.is_none() {
+        return Ok(None);
+    }
+
+    let mut stmt = conn
+        .prepare_cached(&format!(
+            "SELECT {} FROM events e WHERE e.stream_id = ?1 ORDER BY e.version",
+            EVENT_COLUMNS
+        ))
+        .map_err(storage_error)?;
+    let events = stmt
+        .query_map([id], |row| event_from_row(row, 0))
+        .map_err(storage_error)?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(storage_error)?;
+    Ok(Some(events))
+}
+
+/// IDs of the streams that sort after `after` (all streams for `None`), in
+/// order.
+pub(crate) fn stream_ids_after(
+    conn: &Connection,
+    after: Option<&str>,
+) -> Result<Vec<String>, RepositoryError> {
+    let mut stmt = conn
+        .prepare_cached(
+            "SELECT stream_id FROM streams WHERE ?1 IS NULL OR stream_id > ?1 ORDER BY stream_id",
+        )
+        .map_err(storage_error)?;
+    let ids = stmt
+        .query_map([after], |row| row.get(0))
+        .map_err(storage_error)?
+        .collect::<rusqlite::Result<Vec<String>>>()
+        .map_err(storage_error)?;
+    Ok(ids)
+}
+
+/// Load the events of one stream after `from_version` (clamped to the stream's
+/// version), returning the clamped version with them.
+pub(crate) fn load_stream_from(
+    conn: &Connection,
+    id: &str,
+    from_version: u64,
+) -> Result<Option<(u64, Vec<EventRecord>)>, RepositoryError> {
+    let version = conn
+        .query_row("SELECT version FROM streams WHERE stream_id = ?1", [id], |row| {
+            row.get::<_, i64>(0)
+        })
+        .optional()
+        .map_err(storage_error)?;
+    let Some(version) = version else {
+        return Ok(None);
+    };
+    let from = from_version.min(version as u64);
+
+    let mut stmt = conn
+        .prepare_cached(&format!(
+            "SELECT {} FROM events e WHERE e.stream_id = ?1 AND e.version > ?2 ORDER BY e.version",
+            EVENT_COLUMNS
+        ))
+        .map_err(storage_error)?;
+    let events = stmt
+        .query_map(params![id, from as i64], |row| event_from_row(row, 0))
+        .map_err(storage_error)?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(storage_error)?;
+    Ok(Some((from, events)))
+}
+
+/// Load every stream whose ID starts with `prefix` (all streams for `""`),
+/// in one query.
+pub(crate) fn load_streams(
+    conn: &Connection,
+    prefix: &str,
+) -> Result<Vec<(String, Vec<EventRecord>)>, RepositoryError> {
+    let mut stmt = conn
+        .prepare_cached(&format!(
+            "SELECT s.stream_id, e.position, {} FROM streams s \
+             LEFT JOIN events e ON e.stream_id = s.stream_id \
+             WHERE substr(s.stream_id, 1, length(?1)) = ?1 \
+             ORDER BY s.stream_id, e.version",
+            EVENT_COLUMNS
+        ))
+        .map_err(storage_error)?;
+
+    let mut rows = stmt.query([prefix]).map_err(storage_error)?;
+    let mut streams: Vec<(String, Vec<EventRecord>)> = Vec::new();
+    while let Some(row) = rows.next().map_err(storage_error)? {
+        let id: String = row.get(0).map_err(storage_error)?;
+        if streams.last().map(|(last, _)| last != &id).unwrap_or(true) {
+            streams.push((id, Vec::new()));
+        }
+        // Streams without events come back as a single row of NULLs.
+        let position: Option<i64> = row.get(1).map_err(storage_error)?;
+        if position.is_some() {
+            let event = event_from_row(row, 2).map_err(storage_error)?;
+            streams.last_mut().unwrap().1.push(event);
+        }
+    }
+    Ok(streams)
+}
+
+/// Up to `limit` events with a position greater than `from_position`, in
+/// position order.
+pub(crate) fn load_all_from(
+    conn: &Connection,
+    from_position: u64,
+    limit: usize,
+) -> Result<Vec<StoredEvent>, RepositoryError> {
+    let mut stmt = conn
+        .prepare_cached(&format!(
+            "SELECT e.stream_id, {} FROM events e \
+             WHERE e.position > ?1 ORDER BY e.position LIMIT ?2",
+            EVENT_COLUMNS
+        ))
+        .map_err(storage_error)?;
+    let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+    let events = stmt
+        .query_map(params![from_position as i64, limit], |row| {
+            Ok(StoredEvent {
+                stream_id: row.get(0)?,
+                event: event_from_row(row, 1)?,
+            })
+        })
+        .map_err(storage_error)?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(storage_error)?;
+    Ok(events)
+}
+
+/// Position of the most recently appended event (0 if there are none).
+pub(crate) fn last_position(conn: &Connection) -> Result<u64, RepositoryError> {
+    conn.query_row("SELECT COALESCE(MAX(position), 0) FROM events", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .map(|p| p as u64)
+    .map_err(storage_error)
+}
+
+/// Check every entity's committed version against the stored one, then append
+/// their new events with consecutive global positions. Must run inside a
+/// transaction; does not mark entities committed.
+pub(crate) fn append_entities(
+    conn: &Connection,
+    entities: &mut [&mut Entity],
+) -> Result<(), RepositoryError> {
+    let stored = check_entities(conn, entities)?;
+    append_checked(conn, entities, stored)
+}
+
+/// Phase 1: Validate (optimistic concurrency / expected version check).
+/// Returns each entity's stored version, leaving the entities untouched.
+pub(crate) fn check_entities(
+    conn: &Connection,
+    entities: &[&mut Entity],
+) -> Result<Vec<u64>, RepositoryError> {
+    let mut stored = Vec::with_capacity(entities.len());
+    for entity in entities.iter() {
+        let actual = conn
+            .query_row(
+                "SELECT version FROM streams WHERE stream_id = ?1",
+                [entity.id()],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()
+            .map_err(storage_error)?
+            .map(|version| version as u64);
+        check_expected_version(entity, actual)?;
+        stored.push(actual.unwrap_or(0));
+    }
+    Ok(stored)
+}
+
+/// Append the entities' new events onto the `stored` versions returned by
+/// [`check_entities`], rebasing the entities first.
+pub(crate) fn append_checked(
+    conn: &Connection,
+    entities: &mut [&mut Entity],
+    stored: Vec<u64>,
+) -> Result<(), RepositoryError> {
+    for (entity, stored) in entities.iter_mut().zip(stored) {
+        entity.rebase(stored);
+    }
+
+    // Phase 2: Append new events
+    let mut insert_event = conn
+        .prepare_cached(
+            "INSERT INTO events \
+             (position, stream_id, version, event_name, event_version, sequence, ts_secs, ts_nanos, payload, metadata) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        )
+        .map_err(storage_error)?;
+    let mut upsert_stream = conn
+        .prepare_cached(
+            "INSERT INTO streams (stream_id, version) VALUES (?1, ?2) \
+             ON CONFLICT (stream_id) DO UPDATE SET version = excluded.version",
+        )
+        .map_err(storage_error)?;
+
+    let mut next_position = last_position(conn)? + 1;
+    for entity in entities.iter_mut() {
+        entity.assign_positions(next_position);
+        next_position += entity.new_events().len() as u64;
+
+        let base = entity.committed_version();
+        let new_version = base + entity.new_events().len() as u64;
+        upsert_stream
+            .execute(params![entity.id(), new_version as i64])
+            .map_err(storage_error)?;
+
+        for (i, event) in entity.new_events().iter().enumerate() {
+            let since_epoch = event
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let metadata = if event.metadata.is_empty() {
+                None
+            } else {
+                Some(serde_json::to_string(&event.metadata).map_err(storage_error)?)
+            };
+            insert_event
+                .execute(params![
+                    event.position as i64,
+                    entity.id(),
+                    (base + i as u64 + 1) as i64,
+                    event.event_name,
+                    event.event_version as i64,
+                    event.sequence as i64,
+                    since_epoch.as_secs() as i64,
+                    since_epoch.subsec_nanos() as i64,
+                    event.payload,
+                    metadata,
+                ])
+                .map_err(storage_error)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Insert or overwrite a read model row, bumping its version. Returns the new version.
+pub(crate) fn upsert_model_row(
+    conn: &Connection,
+    key: &str,
+    bytes: &[u8],
+) -> rusqlite::Result<u64> {
+    let collection = key.split_once(':').map(|(c, _)| c).unwrap_or(key);
+    conn.prepare_cached(
+        "INSERT INTO read_models (key, collection, version, data) VALUES (?1, ?2, 1, ?3) \
+         ON CONFLICT (key) DO UPDATE SET version = version + 1, data = excluded.data \
+         RETURNING version",
+    )?
+    .query_row(params![key, collection, bytes], |row| row.get::<_, i64>(0))
+    .map(|v| v as u64)
+}
+
+/// Current version of a read model row, if present.
+pub(crate) fn model_version(conn: &Connection, key: &str) -> rusqlite::Result<Option<u64>> {
+    conn.query_row("SELECT version FROM read_models WHERE key = ?1", [key], |row| {
+        row.get::<_, i64>(0)
+    })
+    .optional()
+    .map(|v| v.map(|v| v as u64))
+}
@@ -0,0 +1,14 @@
+use crate::repository::RepositoryError;
+
+/// Trait for persisting subscription checkpoints: the global position of the
+/// last event a named subscription has handled.
+pub trait CheckpointStore: Send + Sync {
+    /// Load the checkpoint for the given subscription name.
+    fn load_checkpoint(&self, name: &str) -> Result<Option<u64>, RepositoryError>;
+
+    /// Save (or overwrite) the checkpoint for the given subscription name.
+    fn save_checkpoint(&self, name: &str, position: u64) -> Result<(), RepositoryError>;
+
+    /// Delete the checkpoint for the given subscription name. Returns true if one existed.
+    fn delete_checkpoint(&self, name: &str) -> Result<bool, RepositoryError>;
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::repository::RepositoryError;
+
+use super::checkpoint::CheckpointStore;
+
+/// In-memory checkpoint store backed by `Arc<RwLock<HashMap>>`.
+///
+/// Clone-friendly (cloning shares the same underlying storage).
+#[derive(Clone, Default)]
+pub struct InMemoryCheckpointStore {
+    storage: Arc<RwLock<HashMap<String, u64>>>,
+}
+
+impl InMemoryCheckpointStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CheckpointStore for InMemoryCheckpointStore {
+    fn load_checkpoint(&self, name: &str) -> Result<Option<u64>, RepositoryError> {
+        let storage = self
+            .storage
+            .read()
+            .map_err(|_| RepositoryError::LockPoisoned("checkpoint read"))?;
+        Ok(storage.get(name).copied())
+    }
+
+    fn save_checkpoint(&self, name: &str, position: u64) -> Result<(), RepositoryError> {
+        let mut storage = self
+            .storage
+            .write()
+            .map_err(|_| RepositoryError::LockPoisoned("checkpoint write"))?;
+        storage.insert(name.to_string(), position);
+        Ok(())
+    }
+
+    fn delete_checkpoint(&self, name: &str) -> Result<bool, RepositoryError> {
+        let mut storage = self
+            .storage
+            .write()
+            .map_err(|_| RepositoryError::LockPoisoned("checkpoint write"))?;
+        Ok(storage.remove(name).is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_load_and_delete() {
+        let store = InMemoryCheckpointStore::new();
+        assert_eq!(store.load_checkpoint("projector").unwrap(), None);
+
+        store.save_checkpoint("projector", 3).unwrap();
+        store.save_checkpoint("projector", 7).unwrap();
+        assert_eq!(store.load_checkpoint("projector").unwrap(), Some(7));
+
+        assert!(store.delete_checkpoint("projector").unwrap());
+        assert!(!store.delete_checkpoint("projector").unwrap());
+        assert_eq!(store.load_checkpoint("projector").unwrap(), None);
+    }
+
+    #[test]
+    fn clone_shares_storage() {
+        let store = InMemoryCheckpointStore::new();
+        let clone = store.clone();
+        store.save_checkpoint("projector", 5).unwrap();
+        assert_eq!(clone.load_checkpoint("projector").unwrap(), Some(5));
+    }
+}
@@ -0,0 +1,37 @@
+//! Subscriptions - Checkpointed consumers of the global event log.
+//!
+//! A subscription has a name and a starting position. It first replays the
+//! historical events from the `$all` log (see [`ReadAll`](crate::ReadAll)),
+//! then keeps handling events as they are committed, saving the position of
+//! the last handled event to a [`CheckpointStore`] so it resumes after a restart.
+//!
+//! - `Subscription` - Pull-based catch-up and live polling
+//! - `SubscriptionThread` - Background runner for a subscription
+//! - `CheckpointStore` - Checkpoint persistence (`InMemoryCheckpointStore`,
+//!   and the `HashMapRepository`, `FileRepository` and `SqliteRepository` backends)
+//!
+//! ## Example
+//!
+//! ```ignore
+//! use sourced_rust::{StartFrom, StoredEvent, Subscription, SubscriptionThread};
+//!
+//! let subscription = Subscription::new("emailer", repo.clone(), repo.clone(), |e: &StoredEvent| {
+//!     send_email(&e.event)?;
+//!     Ok::<_, EmailError>(())
+//! })
+//! .start_from(StartFrom::Beginning);
+//!
+//! let worker = SubscriptionThread::spawn(subscription, Duration::from_millis(50));
+//! ```
+
+mod checkpoint;
+mod in_memory;
+mod subscription;
+mod thread;
+
+pub use checkpoint::CheckpointStore;
+pub use in_memory::InMemoryCheckpointStore;
+pub use subscription::{
+    Pollable, StartFrom, Subscription, SubscriptionError, SubscriptionHandler,
+};
+pub use thread::{SubscriptionStats, SubscriptionThread};
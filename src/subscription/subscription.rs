@@ -0,0 +1,333 @@
+use std::fmt;
+
+use crate::repository::{ReadAll, RepositoryError, StoredEvent};
+
+use super::checkpoint::CheckpointStore;
+
+/// Where a subscription starts when it has no stored checkpoint yet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StartFrom {
+    /// Replay the whole log.
+    #[default]
+    Beginning,
+    /// Handle only events after the given global position.
+    Position(u64),
+    /// Skip history and handle only events committed after the subscription starts.
+    End,
+}
+
+/// Handler invoked for every event a subscription delivers.
+///
+/// Delivery is at-least-once: after a crash, events since the last saved
+/// checkpoint are delivered again, so handlers should be idempotent.
+pub trait SubscriptionHandler {
+    type Error: fmt::Display;
+
+    fn handle(&mut self, event: &StoredEvent) -> Result<(), Self::Error>;
+}
+
+impl<F, E> SubscriptionHandler for F
+where
+    F: FnMut(&StoredEvent) -> Result<(), E>,
+    E: fmt::Display,
+{
+    type Error = E;
+
+    fn handle(&mut self, event: &StoredEvent) -> Result<(), E> {
+        self(event)
+    }
+}
+
+/// Error type for subscription operations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubscriptionError {
+    /// Reading the log or the checkpoint store failed.
+    Repository(RepositoryError),
+    /// The handler rejected an event. The checkpoint stops just before it, so
+    /// the next poll retries the same event.
+    Handler {
+        position: u64,
+        stream_id: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for SubscriptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubscriptionError::Repository(err) => write!(f, "subscription repository error: {}", err),
+            SubscriptionError::Handler {
+                position,
+                stream_id,
+                message,
+            } => write!(
+                f,
+                "subscription handler failed at position {} (stream {}): {}",
+                position, stream_id, message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SubscriptionError {}
+
+impl From<RepositoryError> for SubscriptionError {
+    fn from(err: RepositoryError) -> Self {
+        SubscriptionError::Repository(err)
+    }
+}
+
+/// A consumer that a [`SubscriptionThread`](super::SubscriptionThread) can drive.
+pub trait Pollable: Send {
+    /// Read and handle one batch. Returns the number of events handled.
+    fn poll(&mut self) -> Result<usize, SubscriptionError>;
+}
+
+/// A named, checkpointed consumer of the global event log.
+///
+/// On the first poll the subscription resumes from its stored checkpoint, or
+/// from [`StartFrom`] if it has none. Each poll reads one batch via
+/// [`ReadAll`], hands the events to the handler in order, and saves the
+/// position of the last handled event. [`catch_up`](Self::catch_up) polls
+/// until the log is drained; after that, further polls pick up live commits.
+///
+/// ## Example
+///
+/// ```ignore
+/// use sourced_rust::{InMemoryCheckpointStore, StoredEvent, Subscription};
+///
+/// let mut sub = Subscription::new("emailer", repo.clone(), checkpoints, |e: &StoredEvent| {
+///     println!("{} {}", e.position(), e.event.event_name);
+///     Ok::<_, String>(())
+/// });
+/// sub.catch_up()?;   // replay history
+/// sub.poll()?;       // later: handle anything committed since
+/// ```
+pub struct Subscription<R, C, H> {
+    name: String,
+    repo: R,
+    checkpoints: C,
+    handler: H,
+    start_from: StartFrom,
+    batch_size: usize,
+    position: Option<u64>,
+}
+
+impl<R, C, H> Subscription<R, C, H> {
+    /// Create a subscription that starts from the beginning of the log.
+    pub fn new(name: impl Into<String>, repo: R, checkpoints: C, handler: H) -> Self {
+        Self {
+            name: name.into(),
+            repo,
+            checkpoints,
+            handler,
+            start_from: StartFrom::Beginning,
+            batch_size: 100,
+            position: None,
+        }
+    }
+
+    /// Set where to start when no checkpoint is stored.
+    pub fn start_from(mut self, start_from: StartFrom) -> Self {
+        self.start_from = start_from;
+        self
+    }
+
+    /// Set the maximum number of events read per poll.
+    pub fn with_batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    /// The subscription name (the checkpoint key).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Position of the last handled event, once the subscription has started.
+    pub fn position(&self) -> Option<u64> {
+        self.position
+    }
+
+    /// Get a reference to the handler.
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Get a mutable reference to the handler.
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+}
+
+impl<R, C, H> Subscription<R, C, H>
+where
+    R: ReadAll,
+    C: CheckpointStore,
+    H: SubscriptionHandler,
+{
+    /// Read and handle one batch. Returns the number of events handled.
+    pub fn poll(&mut self) -> Result<usize, SubscriptionError> {
+        let from = self.resolve_position()?;
+        let events = self.repo.read_all(from, self.batch_size)?;
+
+        let mut handled = 0;
+        let mut failure = None;
+        for event in &events {
+            if let Err(err) = self.handler.handle(event) {
+                failure = Some(SubscriptionError::Handler {
+                    position: event.position(),
+                    stream_id: event.stream_id.clone(),
+                    message: err.to_string(),
+                });
+                break;
+            }
+            self.position = Some(event.position());
+            handled += 1;
+        }
+
+        if handled > 0 {
+            self.checkpoints
+                .save_checkpoint(&self.name, events[handled - 1].position())?;
+        }
+
+        match failure {
+            Some(err) => Err(err),
+            None => Ok(handled),
+        }
+    }
+
+    /// Poll until the log is drained. Returns the number of events handled.
+    pub fn catch_up(&mut self) -> Result<usize, SubscriptionError> {
+        let mut total = 0;
+        loop {
+            let handled = self.poll()?;
+            total += handled;
+            if handled < self.batch_size {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// Whether every committed event has been handled.
+    pub fn is_caught_up(&mut self) -> Result<bool, SubscriptionError> {
+        let position = self.resolve_position()?;
+        Ok(position >= self.repo.last_position()?)
+    }
+
+    fn resolve_position(&mut self) -> Result<u64, SubscriptionError> {
+        if let Some(position) = self.position {
+            return Ok(position);
+        }
+
+        let position = match self.checkpoints.load_checkpoint(&self.name)? {
+            Some(position) => position,
+            None => match self.start_from {
+                StartFrom::Beginning => 0,
+                StartFrom::Position(position) => position,
+                StartFrom::End => self.repo.last_position()?,
+            },
+        };
+        self.position = Some(position);
+        Ok(position)
+    }
+}
+
+impl<R, C, H> Pollable for Subscription<R, C, H>
+where
+    R: ReadAll + Send,
+    C: CheckpointStore,
+    H: SubscriptionHandler + Send,
+{
+    fn poll(&mut self) -> Result<usize, SubscriptionError> {
+        Subscription::poll(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::Entity;
+    use crate::repository::Commit;
+    use crate::subscription::InMemoryCheckpointStore;
+    use crate::HashMapRepository;
+
+    fn commit_events(repo: &HashMapRepository, id: &str, count: usize) {
+        let mut entity = Entity::with_id(id);
+        for i in 0..count {
+            entity.digest("Happened", &i);
+        }
+        repo.commit(&mut entity).unwrap();
+    }
+
+    #[test]
+    fn catch_up_then_live() {
+        let repo = HashMapRepository::new();
+        commit_events(&repo, "a", 3);
+
+        let mut seen = Vec::new();
+        let mut sub = Subscription::new(
+            "test",
+            repo.clone(),
+            InMemoryCheckpointStore::new(),
+            |e: &StoredEvent| {
+                seen.push(e.position());
+                Ok::<_, String>(())
+            },
+        )
+        .with_batch_size(2);
+
+        assert_eq!(sub.catch_up().unwrap(), 3);
+        assert!(sub.is_caught_up().unwrap());
+        assert_eq!(sub.poll().unwrap(), 0);
+
+        commit_events(&repo, "b", 1);
+        assert!(!sub.is_caught_up().unwrap());
+        assert_eq!(sub.poll().unwrap(), 1);
+        assert_eq!(sub.position(), Some(4));
+
+        drop(sub);
+        assert_eq!(seen, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn handler_error_keeps_checkpoint_before_failed_event() {
+        let repo = HashMapRepository::new();
+        commit_events(&repo, "a", 3);
+        let checkpoints = InMemoryCheckpointStore::new();
+
+        let mut fail_at = Some(2);
+        let mut sub = Subscription::new("test", repo, checkpoints.clone(), |e: &StoredEvent| {
+            if Some(e.position()) == fail_at {
+                fail_at = None;
+                return Err("boom");
+            }
+            Ok(())
+        });
+
+        let err = sub.poll().unwrap_err();
+        assert!(matches!(err, SubscriptionError::Handler { position: 2, .. }));
+        assert_eq!(checkpoints.load_checkpoint("test").unwrap(), Some(1));
+
+        assert_eq!(sub.poll().unwrap(), 2);
+        assert_eq!(checkpoints.load_checkpoint("test").unwrap(), Some(3));
+    }
+
+    #[test]
+    fn start_from_end_skips_history() {
+        let repo = HashMapRepository::new();
+        commit_events(&repo, "a", 2);
+
+        let mut sub = Subscription::new(
+            "test",
+            repo.clone(),
+            InMemoryCheckpointStore::new(),
+            |_: &StoredEvent| Ok::<_, String>(()),
+        )
+        .start_from(StartFrom::End);
+
+        assert_eq!(sub.catch_up().unwrap(), 0);
+        commit_events(&repo, "b", 1);
+        assert_eq!(sub.catch_up().unwrap(), 1);
+    }
+}
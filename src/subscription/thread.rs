@@ -0,0 +1,107 @@
+//! Threaded subscription runner for background event handling.
+
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use super::subscription::{Pollable, SubscriptionError};
+
+/// Statistics from a subscription thread.
+#[derive(Debug, Default, Clone)]
+pub struct SubscriptionStats {
+    pub events_handled: usize,
+    pub errors: usize,
+    pub polls: usize,
+    /// The most recent error, if any poll failed.
+    pub last_error: Option<SubscriptionError>,
+}
+
+/// A background thread that catches a subscription (or any other
+/// [`Pollable`], such as a `ProjectionRunner`) up and then keeps polling for
+/// live events.
+///
+/// Each poll drains the log in batches before sleeping for `poll_interval`,
+/// so a backlog is handled at full speed. A failed poll is counted and
+/// retried on the next tick, starting from the event that failed.
+///
+/// ## Example
+///
+/// ```ignore
+/// use sourced_rust::{Subscription, SubscriptionThread};
+/// use std::time::Duration;
+///
+/// let subscription = Subscription::new("emailer", repo.clone(), repo.clone(), handler);
+/// let thread = SubscriptionThread::spawn(subscription, Duration::from_millis(50));
+///
+/// // ... commit aggregates ...
+///
+/// let stats = thread.stop();
+/// println!("Handled {} events", stats.events_handled);
+/// ```
+pub struct SubscriptionThread {
+    stop_tx: Sender<()>,
+    handle: Option<JoinHandle<SubscriptionStats>>,
+}
+
+impl SubscriptionThread {
+    /// Spawn a thread running the given subscription.
+    pub fn spawn<S>(mut subscription: S, poll_interval: Duration) -> Self
+    where
+        S: Pollable + 'static,
+    {
+        let (stop_tx, stop_rx) = channel();
+
+        let handle = thread::spawn(move || {
+            let mut stats = SubscriptionStats::default();
+
+            loop {
+                stats.polls += 1;
+                loop {
+                    match subscription.poll() {
+                        Ok(0) => break,
+                        Ok(handled) => stats.events_handled += handled,
+                        Err(err) => {
+                            stats.errors += 1;
+                            stats.last_error = Some(err);
+                            break;
+                        }
+                    }
+                }
+
+                match stop_rx.recv_timeout(poll_interval) {
+                    Ok(()) | Err(RecvTimeoutError::Disconnected) => break,
+                    Err(RecvTimeoutError::Timeout) => {}
+                }
+            }
+
+            stats
+        });
+
+        Self {
+            stop_tx,
+            handle: Some(handle),
+        }
+    }
+
+    /// Signal the thread to stop and wait for it to finish.
+    /// Returns the subscription statistics.
+    pub fn stop(mut self) -> SubscriptionStats {
+        let _ = self.stop_tx.send(());
+        if let Some(handle) = self.handle.take() {
+            handle.join().unwrap_or_default()
+        } else {
+            SubscriptionStats::default()
+        }
+    }
+
+    /// Signal the thread to stop without waiting.
+    pub fn signal_stop(&self) {
+        let _ = self.stop_tx.send(());
+    }
+}
+
+impl Drop for SubscriptionThread {
+    fn drop(&mut self) {
+        let _ = self.stop_tx.send(());
+    }
+}
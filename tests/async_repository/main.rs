@@ -0,0 +1,173 @@
+//! Async repository integration tests (requires the `async` feature).
+#![cfg(feature = "async")]
+
+mod aggregate;
+
+use aggregate::Todo;
+use serde::{Deserialize, Serialize};
+use sourced_rust::{
+    AggregateBuilder, AsyncCommit, AsyncCount, AsyncExists, AsyncFind, AsyncGet, AsyncGetOne,
+    AsyncSnapshotStore, CommitBuilderExt, Entity, HashMapRepository, ReadModel, ReadModelsExt,
+    RepositoryError,
+};
+
+fn todo(id: &str, user: &str) -> Todo {
+    let mut todo = Todo::new();
+    todo.initialize(id.into(), user.into(), format!("task for {user}"));
+    todo
+}
+
+// --- Core traits ---
+
+#[tokio::test]
+async fn commit_and_get_entities() {
+    let repo = HashMapRepository::new();
+
+    let mut entity = Entity::with_id("e1");
+    entity.digest("Created", &"v1");
+    repo.commit_async(&mut entity).await.unwrap();
+
+    let loaded = repo.get_one_async("e1").await.unwrap().unwrap();
+    assert_eq!(loaded.events().len(), 1);
+
+    let mut e2 = Entity::with_id("e2");
+    e2.digest("Created", &"v1");
+    repo.commit_async(&mut [&mut e2]).await.unwrap();
+
+    let all = repo.get_async(&["e1", "e2", "missing"]).await.unwrap();
+    assert_eq!(all.len(), 2);
+}
+
+#[tokio::test]
+async fn predicates_match_sync_behaviour() {
+    let repo = HashMapRepository::new();
+
+    let mut a = Entity::with_id("todo-1");
+    a.digest("Created", &"a");
+    let mut b = Entity::with_id("todo-2");
+    b.digest("Created", &"b");
+    let mut c = Entity::with_id("user-1");
+    c.digest("Created", &"c");
+    repo.commit_async(&mut [&mut a, &mut b, &mut c]).await.unwrap();
+
+    let todos = repo.find_async(|e| e.id().starts_with("todo-")).await.unwrap();
+    assert_eq!(todos.len(), 2);
+    assert_eq!(repo.count_async(|_| true).await.unwrap(), 3);
+    assert!(repo.exists_async(|e| e.id() == "user-1").await.unwrap());
+    assert!(!repo.exists_async(|e| e.id() == "user-2").await.unwrap());
+}
+
+#[tokio::test]
+async fn concurrent_write_is_detected() {
+    let repo = HashMapRepository::new();
+
+    let mut entity = Entity::with_id("e1");
+    entity.digest("Created", &"v1");
+    repo.commit_async(&mut entity).await.unwrap();
+
+    let mut reader1 = repo.get_one_async("e1").await.unwrap().unwrap();
+    let mut reader2 = repo.get_one_async("e1").await.unwrap().unwrap();
+    reader1.digest("Updated", &"r1");
+    reader2.digest("Updated", &"r2");
+
+    repo.commit_async(&mut reader1).await.unwrap();
+    let err = repo.commit_async(&mut reader2).await.unwrap_err();
+    assert!(matches!(err, RepositoryError::ConcurrentWrite { .. }));
+}
+
+// --- AggregateRepository ---
+
+#[tokio::test]
+async fn aggregate_repository_roundtrip() {
+    let repo = HashMapRepository::new().aggregate::<Todo>();
+
+    let mut t1 = todo("t1", "alice");
+    let mut t2 = todo("t2", "bob");
+    repo.commit_async(&mut t1).await.unwrap();
+    repo.commit_all_async(&mut [&mut t2]).await.unwrap();
+
+    let mut loaded = repo.get_async("t1").await.unwrap().unwrap();
+    assert_eq!(loaded.user_id, "alice");
+    loaded.complete();
+    repo.commit_async(&mut loaded).await.unwrap();
+
+    let completed = repo.find_async(|t| t.completed).await.unwrap();
+    assert_eq!(completed.len(), 1);
+    assert_eq!(repo.count_async(|_| true).await.unwrap(), 2);
+    assert!(repo.exists_async(|t| t.user_id == "bob").await.unwrap());
+    assert_eq!(repo.get_all_async(&["t1", "t2"]).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn aggregate_repository_futures_are_send() {
+    let repo = HashMapRepository::new().aggregate::<Todo>();
+    let handle = tokio::spawn(async move {
+        let mut t = todo("t1", "alice");
+        repo.commit_async(&mut t).await.unwrap();
+        repo.get_async("t1").await.unwrap().is_some()
+    });
+    assert!(handle.await.unwrap());
+}
+
+#[tokio::test]
+async fn namespaced_aggregate_repository_roundtrip() {
+    let repo = HashMapRepository::new();
+    let todos = repo.clone().aggregate::<Todo>().namespaced();
+
+    let mut todo = todo("t1", "alice");
+    todos.commit_async(&mut todo).await.unwrap();
+    assert_eq!(todo.entity.id(), "t1");
+    assert!(repo.get_one_async("Todo:t1").await.unwrap().is_some());
+
+    let mut other = Entity::with_id("t1");
+    other.digest("Unrelated", &"x");
+    repo.commit_async(&mut other).await.unwrap();
+
+    let loaded = todos.get_async("t1").await.unwrap().unwrap();
+    assert_eq!(loaded.entity.id(), "t1");
+    assert_eq!(todos.count_async(|_| true).await.unwrap(), 1);
+}
+
+// --- SnapshotAggregateRepository ---
+
+#[tokio::test]
+async fn snapshot_repository_creates_and_uses_snapshots() {
+    let repo = HashMapRepository::new().aggregate::<Todo>().with_snapshots(2);
+
+    let mut t = todo("t1", "alice");
+    t.complete();
+    repo.commit_async(&mut t).await.unwrap();
+
+    let snap = repo.repo().repo().get_snapshot_async("t1").await.unwrap();
+    assert_eq!(snap.unwrap().version, 2);
+
+    let loaded = repo.get_async("t1").await.unwrap().unwrap();
+    assert!(loaded.completed);
+    assert_eq!(loaded.entity.snapshot_version(), 2);
+    assert_eq!(repo.find_async(|t| t.completed).await.unwrap().len(), 1);
+}
+
+// --- CommitBuilder ---
+
+#[derive(Clone, Debug, Serialize, Deserialize, ReadModel)]
+#[readmodel(collection = "todo_views")]
+struct TodoView {
+    id: String,
+    user_id: String,
+}
+
+#[tokio::test]
+async fn commit_builder_commits_aggregate_and_read_model() {
+    let repo = HashMapRepository::new();
+
+    let mut t = todo("t1", "alice");
+    let view = TodoView {
+        id: "t1".into(),
+        user_id: "alice".into(),
+    };
+    repo.readmodel(&view).commit_async(&mut t).await.unwrap();
+
+    assert!(repo.get_one_async("t1").await.unwrap().is_some());
+    let stored = repo.read_models::<TodoView>().get("t1").unwrap().unwrap();
+    assert_eq!(stored.data.user_id, "alice");
+}
@@ -1,6 +1,5 @@
-use sourced_rust::read_model::ReadModelStore;
 use sourced_rust::{
-    Aggregate, Commit, CommitBuilderExt, Find, Get, GetAggregate, OutboxMessage, hydrate,
+    Aggregate, AtomicCommit, CommitBuilderExt, Find, Get, GetAggregate, OutboxMessage, hydrate,
 };
 
 use crate::domain::bomb::Bomb;
@@ -35,7 +34,7 @@ where
 
 // ── Game commands ──
 
-pub fn create_game<R: Commit + ReadModelStore>(
+pub fn create_game<R: AtomicCommit>(
     repo: &R,
     game_id: &str,
     ascii_map: &str,
@@ -57,7 +56,7 @@ pub fn create_game<R: Commit + ReadModelStore>(
     Ok(map)
 }
 
-pub fn tick<R: Commit + ReadModelStore + Get + sourced_rust::Find>(
+pub fn tick<R: AtomicCommit + Get + sourced_rust::Find>(
     repo: &R,
     game_id: &str,
 ) -> Result<TickSaga, GameError> {
@@ -280,7 +279,7 @@ fn apply_damage(
 
 // ── Player commands ──
 
-pub fn join_game<R: Commit + ReadModelStore + Get + sourced_rust::Find>(
+pub fn join_game<R: AtomicCommit + Get + sourced_rust::Find>(
     repo: &R,
     player_id: &str,
     name: &str,
@@ -319,7 +318,7 @@ pub fn join_game<R: Commit + ReadModelStore + Get + sourced_rust::Find>(
     Ok(())
 }
 
-pub fn move_player<R: Commit + ReadModelStore + Get + sourced_rust::Find>(
+pub fn move_player<R: AtomicCommit + Get + sourced_rust::Find>(
     repo: &R,
     player_id: &str,
     direction: Direction,
@@ -375,7 +374,7 @@ pub fn move_player<R: Commit + ReadModelStore + Get + sourced_rust::Find>(
     Ok(())
 }
 
-pub fn place_bomb<R: Commit + ReadModelStore + Get + sourced_rust::Find>(
+pub fn place_bomb<R: AtomicCommit + Get + sourced_rust::Find>(
     repo: &R,
     player_id: &str,
     game_id: &str,
@@ -268,7 +268,7 @@ fn chain_reaction() {
 }
 
 /// Helper to move a player step by step to a target position via simple pathfinding.
-fn bob_move_to_position<R: sourced_rust::Commit + sourced_rust::read_model::ReadModelStore + sourced_rust::Get + sourced_rust::Find>(
+fn bob_move_to_position<R: sourced_rust::AtomicCommit + sourced_rust::Get + sourced_rust::Find>(
     game: &Game<'_, R>,
     id: &str,
     _name: &str,
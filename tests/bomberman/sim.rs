@@ -1,5 +1,4 @@
-use sourced_rust::read_model::ReadModelStore;
-use sourced_rust::{Commit, Get, ReadModelsExt, Versioned};
+use sourced_rust::{AtomicCommit, Get, ReadModelsExt, Versioned};
 
 use crate::commands;
 use crate::domain::player::Player;
@@ -15,7 +14,7 @@ pub struct Game<'a, R> {
 
 impl<'a, R> Game<'a, R>
 where
-    R: Commit + ReadModelStore + Get + sourced_rust::Find,
+    R: AtomicCommit + Get + sourced_rust::Find,
 {
     pub fn new(repo: &'a R, game_id: &str, ascii: &str) -> Result<Self, GameError> {
         commands::create_game(repo, game_id, ascii)?;
@@ -54,7 +53,7 @@ pub struct PlayerSim<'a, R> {
 
 impl<'a, R> PlayerSim<'a, R>
 where
-    R: Commit + ReadModelStore + Get + sourced_rust::Find,
+    R: AtomicCommit + Get + sourced_rust::Find,
 {
     pub fn join(&self, spawn_index: usize) -> Result<(), GameError> {
         commands::join_game(self.game.repo, &self.id, &self.name, &self.game.game_id, spawn_index)
@@ -1,4 +1,7 @@
-use sourced_rust::{Commit, Entity, GetOne, HashMapRepository};
+use sourced_rust::{
+    Commit, Entity, ExpectedVersion, GetOne, HashMapRepository, Queueable, ReadAll,
+    RepositoryError,
+};
 
 // --- Event Accumulation ---
 
@@ -184,6 +187,137 @@ fn partial_conflict_rolls_back_entire_commit() {
     assert_eq!(e2_loaded.events().len(), 2);
 }
 
+// --- Expected Version ---
+
+#[test]
+fn no_stream_rejects_duplicate_creation() {
+    let repo = HashMapRepository::new();
+
+    let mut first = Entity::with_id("user-42");
+    first.digest("Registered", &"alice");
+    repo.commit_expecting(&mut first, ExpectedVersion::NoStream).unwrap();
+
+    let mut second = Entity::with_id("user-42");
+    second.digest("Registered", &"bob");
+    let err = repo.commit_expecting(&mut second, ExpectedVersion::NoStream).unwrap_err();
+    assert_eq!(
+        err,
+        RepositoryError::WrongExpectedVersion {
+            id: "user-42".into(),
+            expected: ExpectedVersion::NoStream,
+            actual: Some(1),
+        }
+    );
+    assert_eq!(repo.get_one("user-42").unwrap().unwrap().version(), 1);
+}
+
+#[test]
+fn exact_version_appends_without_loading() {
+    let repo = HashMapRepository::new();
+    let mut entity = Entity::with_id("e1");
+    entity.digest("Created", &"v1");
+    entity.digest("Updated", &"v2");
+    repo.commit(&mut entity).unwrap();
+
+    // A command handler that only knows the version the client saw
+    let mut command = Entity::with_id("e1");
+    command.digest("Renamed", &"v3");
+    let err = repo.commit_expecting(&mut command, ExpectedVersion::Exact(1)).unwrap_err();
+    assert!(matches!(
+        err,
+        RepositoryError::WrongExpectedVersion { actual: Some(2), .. }
+    ));
+
+    repo.commit_expecting(&mut command, ExpectedVersion::Exact(2)).unwrap();
+    assert_eq!(command.version(), 3);
+    assert_eq!(command.committed_version(), 3);
+    assert_eq!(command.expected_version(), None);
+
+    let stored = repo.get_one("e1").unwrap().unwrap();
+    let sequences: Vec<u64> = stored.events().iter().map(|e| e.sequence).collect();
+    assert_eq!(sequences, vec![1, 2, 3]);
+    assert_eq!(stored.events()[2].event_name, "Renamed");
+}
+
+#[test]
+fn any_appends_after_concurrent_writes() {
+    let repo = HashMapRepository::new();
+    let mut entity = Entity::with_id("log");
+    entity.digest("Line", &1);
+    repo.commit(&mut entity).unwrap();
+
+    let mut stale = repo.get_one("log").unwrap().unwrap();
+    entity.digest("Line", &2);
+    repo.commit(&mut entity).unwrap();
+
+    stale.digest("Line", &3);
+    assert!(repo.commit(&mut stale.clone()).is_err());
+    repo.commit_expecting(&mut stale, ExpectedVersion::Any).unwrap();
+    assert_eq!(stale.version(), 3);
+
+    // The entity keeps working after being rebased onto the stored stream
+    stale.digest("Line", &4);
+    repo.commit(&mut stale).unwrap();
+    assert_eq!(repo.get_one("log").unwrap().unwrap().version(), 4);
+}
+
+#[test]
+fn failed_expectation_does_not_carry_over_to_the_next_commit() {
+    let repo = HashMapRepository::new();
+    let mut entity = Entity::with_id("e1");
+    entity.digest("Created", &"v1");
+    repo.commit(&mut entity).unwrap();
+
+    let mut stale = repo.get_one("e1").unwrap().unwrap();
+    entity.digest("Updated", &"v2");
+    repo.commit(&mut entity).unwrap();
+
+    stale.digest("Updated", &"stale");
+    assert!(repo.commit_expecting(&mut stale, ExpectedVersion::Exact(5)).is_err());
+    assert_eq!(stale.expected_version(), None);
+    assert!(matches!(
+        repo.commit(&mut stale),
+        Err(RepositoryError::ConcurrentWrite { expected: 1, actual: 2, .. })
+    ));
+}
+
+#[test]
+fn stream_exists_requires_existing_stream() {
+    let repo = HashMapRepository::new();
+
+    let mut entity = Entity::with_id("e1");
+    entity.digest("Updated", &"v1");
+    let err = repo.commit_expecting(&mut entity, ExpectedVersion::StreamExists).unwrap_err();
+    assert!(matches!(
+        err,
+        RepositoryError::WrongExpectedVersion { actual: None, .. }
+    ));
+
+    let mut created = Entity::with_id("e1");
+    repo.commit(&mut created).unwrap();
+    repo.commit_expecting(&mut entity, ExpectedVersion::StreamExists).unwrap();
+    assert_eq!(repo.get_one("e1").unwrap().unwrap().version(), 1);
+}
+
+#[test]
+fn expected_versions_are_checked_per_entity() {
+    let repo = HashMapRepository::new();
+    let mut existing = Entity::with_id("b");
+    existing.digest("Created", &"b");
+    repo.commit(&mut existing).unwrap();
+
+    let mut a = Entity::with_id("a");
+    a.digest("Created", &"a");
+    a.expect_version(ExpectedVersion::NoStream);
+    let mut b = Entity::with_id("b");
+    b.digest("Created", &"b");
+    b.expect_version(ExpectedVersion::NoStream);
+
+    let err = repo.commit(&mut [&mut a, &mut b]).unwrap_err();
+    assert!(matches!(err, RepositoryError::WrongExpectedVersion { ref id, .. } if id == "b"));
+    assert!(repo.get_one("a").unwrap().is_none());
+}
+
 // --- Version Tracking ---
 
 #[test]
@@ -249,3 +383,73 @@ fn new_events_returns_only_uncommitted() {
     assert_eq!(entity.new_events().len(), 1);
     assert_eq!(entity.new_events()[0].event_name, "e3");
 }
+
+// --- Global Log ---
+
+#[test]
+fn positions_are_assigned_on_commit() {
+    let repo = HashMapRepository::new();
+
+    let mut entity = Entity::with_id("e1");
+    entity.digest("Created", &"v1");
+    assert_eq!(entity.events()[0].position, 0);
+
+    repo.commit(&mut entity).unwrap();
+    assert_eq!(entity.events()[0].position, 1);
+}
+
+#[test]
+fn read_all_pages_through_every_stream() {
+    let repo = HashMapRepository::new();
+    for i in 0..5 {
+        let mut entity = Entity::with_id(format!("e{}", i));
+        entity.digest("Created", &i);
+        repo.commit(&mut entity).unwrap();
+    }
+
+    let mut seen = Vec::new();
+    let mut checkpoint = 0;
+    loop {
+        let page = repo.read_all(checkpoint, 2).unwrap();
+        if page.is_empty() {
+            break;
+        }
+        checkpoint = page.last().unwrap().position();
+        seen.extend(page.into_iter().map(|e| e.stream_id));
+    }
+
+    assert_eq!(seen, vec!["e0", "e1", "e2", "e3", "e4"]);
+    assert_eq!(checkpoint, repo.last_position().unwrap());
+}
+
+#[test]
+fn failed_commit_does_not_consume_positions() {
+    let repo = HashMapRepository::new();
+
+    let mut entity = Entity::with_id("e1");
+    entity.digest("Created", &"v1");
+    repo.commit(&mut entity).unwrap();
+
+    let mut stale = Entity::with_id("e1");
+    stale.digest("Created", &"again");
+    assert!(repo.commit(&mut stale).is_err());
+
+    let mut other = Entity::with_id("e2");
+    other.digest("Created", &"v1");
+    repo.commit(&mut other).unwrap();
+
+    assert_eq!(other.events()[0].position, 2);
+    assert_eq!(repo.read_all(0, 10).unwrap().len(), 2);
+}
+
+#[test]
+fn queued_repository_reads_the_global_log() {
+    let repo = HashMapRepository::new().queued();
+
+    let mut entity = Entity::with_id("e1");
+    entity.digest("Created", &"v1");
+    repo.commit(&mut entity).unwrap();
+
+    assert_eq!(repo.last_position().unwrap(), 1);
+    assert_eq!(repo.read_all(0, 10).unwrap()[0].stream_id, "e1");
+}
@@ -0,0 +1,356 @@
+mod aggregate;
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use aggregate::Todo;
+use sourced_rust::{
+    AggregateBuilder, Commit, FileLockManager, FileRepository, GetAggregate, LogPublisher,
+    OutboxMessage, OutboxMessageStatus, OutboxRepositoryExt, OutboxWorker, Queueable,
+    RepositoryError,
+};
+
+static NEXT_DIR: AtomicU64 = AtomicU64::new(1);
+
+/// A fresh store directory, removed when dropped.
+struct TempStore(PathBuf);
+
+impl TempStore {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "sourced_rust_file_repo_it_{}_{}_{}",
+            name,
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        TempStore(dir)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+
+    fn last_segment(&self) -> PathBuf {
+        let mut files: Vec<PathBuf> = fs::read_dir(&self.0)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        files.sort();
+        files.pop().unwrap()
+    }
+}
+
+impl Drop for TempStore {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn aggregate_survives_restart() {
+    let store = TempStore::new("restart");
+    {
+        let repo = FileRepository::open(store.path()).unwrap().aggregate::<Todo>();
+        let mut todo = Todo::new();
+        todo.initialize("t1".into(), "alice".into(), "Buy milk".into());
+        repo.commit(&mut todo).unwrap();
+
+        let mut todo = repo.get("t1").unwrap().unwrap();
+        todo.complete();
+        repo.commit(&mut todo).unwrap();
+    }
+
+    let repo = FileRepository::open(store.path()).unwrap().aggregate::<Todo>();
+    let todo = repo.get("t1").unwrap().unwrap();
+    let snapshot = todo.snapshot();
+    assert_eq!(snapshot.user_id, "alice");
+    assert_eq!(snapshot.task, "Buy milk");
+    assert!(snapshot.completed);
+    assert_eq!(todo.entity.version(), 2);
+}
+
+#[test]
+fn find_and_count_across_restart() {
+    let store = TempStore::new("find");
+    {
+        let repo = FileRepository::open(store.path()).unwrap().aggregate::<Todo>();
+        for i in 0..5 {
+            let mut todo = Todo::new();
+            todo.initialize(format!("t{}", i), "alice".into(), format!("Task {}", i));
+            if i % 2 == 0 {
+                todo.complete();
+            }
+            repo.commit(&mut todo).unwrap();
+        }
+    }
+
+    let repo = FileRepository::open(store.path()).unwrap().aggregate::<Todo>();
+    assert_eq!(repo.count(|t| t.snapshot().completed).unwrap(), 3);
+    assert!(repo.exists(|t| t.snapshot().task == "Task 4").unwrap());
+    assert_eq!(repo.find(|t| !t.snapshot().completed).unwrap().len(), 2);
+}
+
+#[test]
+fn a_directory_can_only_be_opened_once_at_a_time() {
+    let store = TempStore::new("exclusive");
+    let repo = FileRepository::open(store.path()).unwrap();
+
+    let err = FileRepository::open(store.path()).err().unwrap();
+    assert!(matches!(err, RepositoryError::Storage(_)));
+    assert!(err.to_string().contains("already open"));
+
+    // Clones share the lock; it is released once the last one is dropped
+    let clone = repo.clone();
+    drop(repo);
+    assert!(FileRepository::open(store.path()).is_err());
+    drop(clone);
+    FileRepository::open(store.path()).unwrap();
+}
+
+#[test]
+fn stale_aggregate_is_rejected() {
+    let store = TempStore::new("stale");
+    let repo = FileRepository::open(store.path()).unwrap().aggregate::<Todo>();
+
+    let mut todo = Todo::new();
+    todo.initialize("t1".into(), "alice".into(), "Buy milk".into());
+    repo.commit(&mut todo).unwrap();
+
+    let mut first = repo.get("t1").unwrap().unwrap();
+    let mut second = repo.get("t1").unwrap().unwrap();
+    first.complete();
+    second.complete();
+    repo.commit(&mut first).unwrap();
+
+    match repo.commit(&mut second).unwrap_err() {
+        RepositoryError::ConcurrentWrite { id, expected, actual } => {
+            assert_eq!(id, "t1");
+            assert_eq!(expected, 1);
+            assert_eq!(actual, 2);
+        }
+        other => panic!("expected ConcurrentWrite, got: {:?}", other),
+    }
+}
+
+#[test]
+fn recovers_from_torn_final_record() {
+    let store = TempStore::new("torn");
+    {
+        let repo = FileRepository::open(store.path()).unwrap().aggregate::<Todo>();
+        let mut todo = Todo::new();
+        todo.initialize("t1".into(), "alice".into(), "Buy milk".into());
+        repo.commit(&mut todo).unwrap();
+
+        let mut todo = repo.get("t1").unwrap().unwrap();
+        todo.complete();
+        repo.commit(&mut todo).unwrap();
+    }
+
+    // Simulate a crash halfway through writing the second commit.
+    let segment = store.last_segment();
+    let bytes = fs::read(&segment).unwrap();
+    let first_record_len = 8 + u32::from_le_bytes(bytes[0..4].try_into().unwrap()) as usize;
+    let torn_len = first_record_len + (bytes.len() - first_record_len) / 2;
+    OpenOptions::new()
+        .write(true)
+        .open(&segment)
+        .unwrap()
+        .set_len(torn_len as u64)
+        .unwrap();
+
+    let repo = FileRepository::open(store.path()).unwrap().aggregate::<Todo>();
+    let mut todo = repo.get("t1").unwrap().unwrap();
+    assert_eq!(todo.entity.version(), 1);
+    assert!(!todo.snapshot().completed);
+
+    // The store is writable again and the retried commit is durable.
+    todo.complete();
+    repo.commit(&mut todo).unwrap();
+    drop(repo);
+
+    let repo = FileRepository::open(store.path()).unwrap().aggregate::<Todo>();
+    assert!(repo.get("t1").unwrap().unwrap().snapshot().completed);
+}
+
+#[test]
+fn trailing_garbage_is_discarded() {
+    let store = TempStore::new("garbage");
+    {
+        let repo = FileRepository::open(store.path()).unwrap().aggregate::<Todo>();
+        let mut todo = Todo::new();
+        todo.initialize("t1".into(), "alice".into(), "Buy milk".into());
+        repo.commit(&mut todo).unwrap();
+    }
+
+    let segment = store.last_segment();
+    let valid_len = fs::metadata(&segment).unwrap().len();
+    let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+    file.write_all(&[0u8; 64]).unwrap();
+    drop(file);
+
+    let repo = FileRepository::open(store.path()).unwrap().aggregate::<Todo>();
+    assert!(repo.get("t1").unwrap().is_some());
+    assert_eq!(fs::metadata(&segment).unwrap().len(), valid_len);
+}
+
+#[test]
+fn queued_repository_over_file_store() {
+    let store = TempStore::new("queued");
+    let repo = FileRepository::open(store.path())
+        .unwrap()
+        .queued()
+        .aggregate::<Todo>();
+
+    let mut todo = Todo::new();
+    todo.initialize("t1".into(), "alice".into(), "Buy milk".into());
+    repo.commit(&mut todo).unwrap();
+
+    let mut todo = repo.get("t1").unwrap().unwrap();
+    todo.complete();
+    repo.commit(&mut todo).unwrap();
+
+    assert!(repo.peek("t1").unwrap().unwrap().snapshot().completed);
+}
+
+#[test]
+fn file_lock_managers_serialize_workers_sharing_a_lock_directory() {
+    let store = TempStore::new("file_locks");
+    let locks = TempStore::new("file_locks_dir");
+    let events = FileRepository::open(store.path()).unwrap();
+    // Each worker has its own lock manager, as separate processes would. The
+    // repository itself is shared: a directory can only be open once.
+    let worker = || {
+        events
+            .clone()
+            .queued_with(FileLockManager::open(locks.path()).unwrap())
+            .aggregate::<Todo>()
+    };
+    let (repo_a, repo_b) = (worker(), worker());
+
+    let mut todo = Todo::new();
+    todo.initialize("t1".into(), "alice".into(), "Buy milk".into());
+    repo_a.commit(&mut todo).unwrap();
+
+    let mut todo = repo_a.get("t1").unwrap().unwrap();
+    let (tx, rx) = mpsc::channel();
+    let waiter = thread::spawn(move || {
+        let todo = repo_b.get("t1").unwrap().unwrap();
+        tx.send(todo.snapshot().completed).unwrap();
+        repo_b.abort(&todo).unwrap();
+    });
+
+    assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+    todo.complete();
+    repo_a.commit(&mut todo).unwrap();
+    assert!(rx.recv_timeout(Duration::from_secs(2)).unwrap());
+    waiter.join().unwrap();
+}
+
+#[test]
+fn concurrent_commits_from_threads() {
+    let store = TempStore::new("threads");
+    let repo = FileRepository::open(store.path()).unwrap();
+
+    let handles: Vec<_> = (0..4)
+        .map(|n| {
+            let repo = repo.clone().aggregate::<Todo>();
+            thread::spawn(move || {
+                for i in 0..5 {
+                    let mut todo = Todo::new();
+                    todo.initialize(format!("t{}-{}", n, i), "alice".into(), "Task".into());
+                    repo.commit(&mut todo).unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    drop(repo);
+
+    let repo = FileRepository::open(store.path()).unwrap().aggregate::<Todo>();
+    assert_eq!(repo.count(|_| true).unwrap(), 20);
+}
+
+#[test]
+fn outbox_drains_after_restart() {
+    let store = TempStore::new("outbox");
+    let message_id;
+    {
+        let repo = FileRepository::open(store.path()).unwrap();
+        let mut todo = Todo::new();
+        todo.initialize("t1".into(), "alice".into(), "Buy milk".into());
+        let mut message =
+            OutboxMessage::encode("t1:init", "TodoInitialized", &todo.snapshot()).unwrap();
+        message_id = message.id().to_string();
+        repo.commit(&mut [&mut todo.entity, &mut message.entity])
+            .unwrap();
+    }
+
+    // Pending message is still there after a restart.
+    let repo = FileRepository::open(store.path()).unwrap();
+    assert_eq!(repo.outbox_messages_pending().unwrap().len(), 1);
+
+    let buffer = Arc::new(Mutex::new(Vec::new()));
+    let mut worker = OutboxWorker::new(LogPublisher::with_buffer(Arc::clone(&buffer)))
+        .with_worker_id("worker-1");
+
+    let mut claimed = repo
+        .claim_outbox_messages("worker-1", 10, Duration::from_secs(30))
+        .unwrap();
+    assert_eq!(claimed.len(), 1);
+
+    // A second worker cannot claim the same message.
+    assert!(repo
+        .claim_outbox_messages("worker-2", 10, Duration::from_secs(30))
+        .unwrap()
+        .is_empty());
+
+    let result = worker.process_batch(&mut claimed);
+    assert_eq!(result.completed, 1);
+    for message in &mut claimed {
+        repo.commit(&mut message.entity).unwrap();
+    }
+    assert!(buffer.lock().unwrap()[0].contains("TodoInitialized"));
+    drop(repo);
+
+    let repo = FileRepository::open(store.path()).unwrap();
+    let published = repo
+        .get_aggregate::<OutboxMessage>(&message_id)
+        .unwrap()
+        .unwrap();
+    assert!(published.is_published());
+    assert!(repo.outbox_messages_pending().unwrap().is_empty());
+}
+
+#[test]
+fn outbox_release_and_fail_are_durable() {
+    let store = TempStore::new("outbox_fail");
+    {
+        let repo = FileRepository::open(store.path()).unwrap();
+        let mut first = OutboxMessage::create("m1", "Event", vec![1]);
+        let mut second = OutboxMessage::create("m2", "Event", vec![2]);
+        repo.commit(&mut [&mut first.entity, &mut second.entity])
+            .unwrap();
+
+        repo.claim_outbox_messages("worker-1", 10, Duration::from_secs(30))
+            .unwrap();
+        repo.release_outbox_message("m1", "broker down").unwrap();
+        repo.fail_outbox_message("m2", "bad payload").unwrap();
+    }
+
+    let repo = FileRepository::open(store.path()).unwrap();
+    assert_eq!(repo.outbox_messages_pending().unwrap().len(), 1);
+    assert_eq!(
+        repo.outbox_messages_by_status(OutboxMessageStatus::Failed)
+            .unwrap()
+            .len(),
+        1
+    );
+}
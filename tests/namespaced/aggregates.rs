@@ -0,0 +1,45 @@
+use sourced_rust::{digest, sourced, Entity, Snapshot};
+
+#[derive(Default, Snapshot)]
+pub struct Order {
+    pub entity: Entity,
+    pub customer: String,
+    pub lines: u32,
+}
+
+impl Order {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[digest("OrderPlaced")]
+    pub fn place(&mut self, id: String, customer: String) {
+        self.entity.set_id(&id);
+        self.customer = customer;
+    }
+
+    #[digest("LineAdded")]
+    pub fn add_line(&mut self) {
+        self.lines += 1;
+    }
+}
+
+sourced_rust::aggregate!(Order, entity {
+    "OrderPlaced"(id, customer) => place,
+    "LineAdded"() => add_line(),
+});
+
+#[derive(Default)]
+pub struct Inventory {
+    pub entity: Entity,
+    pub on_hand: i64,
+}
+
+#[sourced(entity)]
+impl Inventory {
+    #[event("Stocked")]
+    pub fn stock(&mut self, sku: String, quantity: i64) {
+        self.entity.set_id(&sku);
+        self.on_hand += quantity;
+    }
+}
@@ -0,0 +1,226 @@
+mod aggregates;
+
+use aggregates::{Inventory, Order};
+use serde::{Deserialize, Serialize};
+use sourced_rust::{
+    Aggregate, AggregateBuilder, CommitBuilderExt, GetOne, HashMapRepository, OutboxCommitExt,
+    OutboxMessage, PageRequest, Queueable, ReadModel, ReadModelsExt, SnapshotStore,
+};
+
+fn place_order(id: &str, customer: &str) -> Order {
+    let mut order = Order::new();
+    order.place(id.into(), customer.into());
+    order
+}
+
+fn stock(sku: &str, quantity: i64) -> Inventory {
+    let mut inventory = Inventory::default();
+    inventory.stock(sku.into(), quantity);
+    inventory
+}
+
+#[test]
+fn type_names_are_generated() {
+    assert_eq!(Order::TYPE_NAME, "Order");
+    assert_eq!(Inventory::TYPE_NAME, "Inventory");
+}
+
+mod ledger {
+    use sourced_rust::{Entity, EventRecord};
+
+    #[derive(Default)]
+    pub struct Ledger {
+        pub entity: Entity,
+    }
+
+    impl Ledger {
+        pub fn replay(&mut self, _event: &EventRecord) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    pub struct Journal {
+        pub entity: Entity,
+    }
+
+    impl Journal {
+        pub fn replay(&mut self, _event: &EventRecord) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    #[derive(Default)]
+    pub struct Unnamed {
+        pub entity: Entity,
+    }
+
+    impl sourced_rust::Aggregate for Unnamed {
+        type ReplayError = String;
+
+        fn entity(&self) -> &Entity {
+            &self.entity
+        }
+
+        fn entity_mut(&mut self) -> &mut Entity {
+            &mut self.entity
+        }
+
+        fn replay_event(&mut self, _event: &EventRecord) -> Result<(), String> {
+            Ok(())
+        }
+    }
+}
+
+sourced_rust::impl_aggregate!(self::ledger::Ledger, entity, replay);
+sourced_rust::impl_aggregate!(ledger::Journal, entity, replay, name = "Book");
+
+#[test]
+fn impl_aggregate_names_drop_the_module_path() {
+    assert_eq!(ledger::Ledger::TYPE_NAME, "Ledger");
+    assert_eq!(ledger::Journal::TYPE_NAME, "Book");
+    assert_eq!(ledger::Unnamed::TYPE_NAME, "");
+}
+
+#[test]
+#[should_panic(expected = "TYPE_NAME")]
+fn namespacing_needs_a_type_name() {
+    let _ = HashMapRepository::new()
+        .aggregate::<ledger::Unnamed>()
+        .namespaced();
+}
+
+#[test]
+fn same_id_in_two_aggregate_types_does_not_collide() {
+    let repo = HashMapRepository::new();
+    let orders = repo.clone().aggregate::<Order>().namespaced();
+    let inventory = repo.clone().aggregate::<Inventory>().namespaced();
+
+    let mut order = place_order("42", "alice");
+    orders.commit(&mut order).unwrap();
+    assert_eq!(order.entity.id(), "42");
+
+    let mut item = stock("42", 10);
+    inventory.commit(&mut item).unwrap();
+
+    assert_eq!(orders.get("42").unwrap().unwrap().customer, "alice");
+    let loaded = inventory.get("42").unwrap().unwrap();
+    assert_eq!(loaded.on_hand, 10);
+    assert_eq!(loaded.entity.id(), "42");
+
+    // The store holds one stream per type.
+    assert_eq!(orders.stream_id("42"), "Order:42");
+    assert_eq!(repo.get_one("Order:42").unwrap().unwrap().version(), 1);
+    assert_eq!(repo.get_one("Inventory:42").unwrap().unwrap().version(), 1);
+    assert!(repo.get_one("42").unwrap().is_none());
+}
+
+#[test]
+fn find_and_count_only_touch_their_category() {
+    let repo = HashMapRepository::new();
+    let orders = repo.clone().aggregate::<Order>().namespaced();
+    let inventory = repo.clone().aggregate::<Inventory>().namespaced();
+
+    let mut message = OutboxMessage::create("msg-1", "OrderPlaced", b"{}".to_vec());
+    orders
+        .outbox(&mut message)
+        .commit(&mut place_order("1", "alice"))
+        .unwrap();
+    orders.commit(&mut place_order("2", "bob")).unwrap();
+    inventory.commit(&mut stock("sku-1", 3)).unwrap();
+
+    // Without namespacing, the Inventory and outbox streams would fail to
+    // replay as orders.
+    let found = orders.find(|o| o.customer == "alice").unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].entity.id(), "1");
+    assert_eq!(orders.count(|_| true).unwrap(), 2);
+    assert_eq!(inventory.count(|_| true).unwrap(), 1);
+    assert!(repo.get_one(message.entity.id()).unwrap().is_some());
+}
+
+#[test]
+fn pages_are_keyed_by_aggregate_id() {
+    let repo = HashMapRepository::new();
+    let orders = repo.clone().aggregate::<Order>().namespaced();
+    let inventory = repo.clone().aggregate::<Inventory>().namespaced();
+    for id in ["1", "2", "3"] {
+        orders.commit(&mut place_order(id, "alice")).unwrap();
+    }
+    inventory.commit(&mut stock("0", 1)).unwrap();
+
+    let first = orders.find_page(|_| true, &PageRequest::new(2)).unwrap();
+    assert_eq!(first.next_cursor.as_deref(), Some("2"));
+    let rest = orders.find_page(|_| true, &PageRequest::new(2).with_after("2")).unwrap();
+    let ids: Vec<&str> = rest.items.iter().map(|o| o.entity.id()).collect();
+    assert_eq!(ids, vec!["3"]);
+    assert_eq!(rest.next_cursor, None);
+}
+
+#[test]
+fn queued_namespaced_repository_locks_stream_ids() {
+    let repo = HashMapRepository::new().queued();
+    let orders = repo.clone().aggregate::<Order>().namespaced();
+    let inventory = repo.aggregate::<Inventory>().namespaced();
+    orders.commit(&mut place_order("42", "alice")).unwrap();
+    inventory.commit(&mut stock("42", 1)).unwrap();
+
+    // Holding the order's lock does not block the inventory item with the same ID.
+    let mut order = orders.get("42").unwrap().unwrap();
+    let item = inventory.get("42").unwrap().unwrap();
+    inventory.abort(&item).unwrap();
+
+    order.add_line();
+    orders.commit(&mut order).unwrap();
+    assert_eq!(orders.peek("42").unwrap().unwrap().lines, 1);
+}
+
+#[test]
+fn snapshots_are_keyed_by_stream() {
+    let repo = HashMapRepository::new();
+    let orders = repo.clone().aggregate::<Order>().namespaced().with_snapshots(2);
+
+    let mut order = place_order("42", "alice");
+    order.add_line();
+    orders.commit(&mut order).unwrap();
+
+    assert!(repo.get_snapshot("Order:42").unwrap().is_some());
+    assert!(repo.get_snapshot("42").unwrap().is_none());
+
+    let loaded = orders.get("42").unwrap().unwrap();
+    assert_eq!(loaded.lines, 1);
+    assert_eq!(loaded.entity.id(), "42");
+    assert_eq!(loaded.entity.snapshot_version(), 2);
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct OrderView {
+    id: String,
+    customer: String,
+}
+
+impl ReadModel for OrderView {
+    const COLLECTION: &'static str = "order_views";
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+#[test]
+fn commit_builder_writes_to_namespaced_stream() {
+    let repo = HashMapRepository::new();
+    let orders = repo.clone().aggregate::<Order>().namespaced();
+
+    let mut order = place_order("42", "alice");
+    let view = OrderView {
+        id: "42".into(),
+        customer: "alice".into(),
+    };
+    repo.readmodel(&view).namespaced().commit(&mut order).unwrap();
+
+    assert_eq!(order.entity.id(), "42");
+    assert_eq!(orders.get("42").unwrap().unwrap().customer, "alice");
+    let stored = repo.read_models::<OrderView>().get("42").unwrap().unwrap();
+    assert_eq!(stored.data.customer, "alice");
+}
@@ -1,6 +1,7 @@
 //! Integration tests for read models (ReadModel + ReadModelStore).
 
 mod aggregate;
+mod projections;
 mod views;
 
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -10,8 +11,9 @@ use std::time::Duration;
 
 use aggregate::Counter;
 use sourced_rust::{
-    AggregateBuilder, CommitBuilderExt, HashMapRepository, OutboxMessage, QueuedReadModelStore,
-    ReadModelsExt, ReadOpts,
+    AggregateBuilder, CommitBuilderExt, ExpectedVersion, HashMapRepository, OutboxMessage,
+    PageRequest, Query, QueuedReadModelStore, ReadModel, ReadModelError, ReadModelsExt, ReadOpts,
+    RepositoryError, SortOrder,
 };
 use views::{CounterView, UserCountersIndexView};
 
@@ -53,6 +55,30 @@ fn readmodel_commits_with_aggregate() {
     assert_eq!(stored_view.data.name, "Page Views");
 }
 
+#[test]
+fn unique_creation_rejects_readmodel_and_aggregate() {
+    let repo = HashMapRepository::new();
+    let counters = repo.clone().aggregate::<Counter>();
+
+    let mut counter = Counter::new();
+    counter.create("counter-1".into(), "Page Views".into(), "user-1".into());
+    counter.increment(3);
+    counters.commit_expecting(&mut counter, ExpectedVersion::NoStream).unwrap();
+
+    let mut duplicate = Counter::new();
+    duplicate.create("counter-1".into(), "Duplicate".into(), "user-2".into());
+    let view = CounterView::new("counter-1", "Duplicate", "user-2");
+    let err = repo
+        .readmodel(&view)
+        .commit_expecting(&mut duplicate, ExpectedVersion::NoStream)
+        .unwrap_err();
+    assert!(matches!(err, RepositoryError::WrongExpectedVersion { .. }));
+
+    assert!(repo.read_models::<CounterView>().get("counter-1").unwrap().is_none());
+    let stored = counters.get("counter-1").unwrap().unwrap();
+    assert_eq!(stored.value(), 3);
+}
+
 #[test]
 fn multiple_readmodels_commit_together() {
     let repo = HashMapRepository::new();
@@ -375,6 +401,31 @@ fn queued_readmodel_upsert_releases_lock() {
     store.unlock::<CounterView>("q-2").unwrap();
 }
 
+#[test]
+fn queued_readmodel_query_locks_matches() {
+    let store = QueuedReadModelStore::new(HashMapRepository::new());
+    let views = store.read_models::<CounterView>();
+    for (id, value) in [("q-4", 3), ("q-5", 9), ("q-6", 6)] {
+        let mut view = CounterView::new(id, "Query", "user-q");
+        view.set_value(value);
+        views.upsert(&view).unwrap();
+    }
+
+    let query = Query::new()
+        .gte("value", 5)
+        .sort_by("value", SortOrder::Descending);
+    let found = views.query(&query).unwrap();
+    let ids: Vec<&str> = found.iter().map(|v| v.data.id.as_str()).collect();
+    assert_eq!(ids, vec!["q-5", "q-6"]);
+
+    // The matches stay locked until written; the non-match was never locked.
+    assert!(views.get("q-4").unwrap().is_some());
+    for view in found {
+        views.upsert(&view.data).unwrap();
+    }
+    assert_eq!(views.query(&query.with_limit(1)).unwrap().len(), 1);
+}
+
 #[test]
 fn queued_readmodel_abort_releases_lock() {
     let store = QueuedReadModelStore::new(HashMapRepository::new());
@@ -631,3 +682,67 @@ fn queued_readmodel_full_lifecycle_with_aggregate_and_outbox() {
     let stored_counter = agg_repo.get("life-1").unwrap().unwrap();
     assert_eq!(stored_counter.value(), 15);
 }
+
+#[test]
+fn readmodels_are_listed_page_by_page() {
+    let repo = HashMapRepository::new();
+    let views = repo.read_models::<CounterView>();
+    let models = [("c-3", "user-1"), ("c-1", "user-1"), ("c-4", "user-2"), ("c-2", "user-1")];
+    for (id, user_id) in models {
+        views.upsert(&CounterView::new(id, id, user_id)).unwrap();
+    }
+    let mine = |v: &CounterView| v.user_id == "user-1";
+
+    let first = views.find_page(&mine, &PageRequest::new(2)).unwrap();
+    let ids: Vec<&str> = first.items.iter().map(|v| v.data.id.as_str()).collect();
+    assert_eq!(ids, vec!["c-1", "c-2"]);
+
+    let second = views.find_page(&mine, &first.next_request(2).unwrap()).unwrap();
+    let ids: Vec<&str> = second.items.iter().map(|v| v.data.id.as_str()).collect();
+    assert_eq!(ids, vec!["c-3"]);
+    assert_eq!(second.next_cursor, None);
+
+    let all: Vec<String> = views
+        .find_iter(|_| true, 3)
+        .map(|v| v.unwrap().data.id)
+        .collect();
+    assert_eq!(all, vec!["c-1", "c-2", "c-3", "c-4"]);
+}
+
+#[test]
+fn indexed_lookups_follow_every_write() {
+    assert_eq!(CounterView::INDEXES, ["name", "user_id"]);
+    let repo = HashMapRepository::new();
+    let views = repo.read_models::<CounterView>();
+    views.upsert(&CounterView::new("c-1", "Likes", "user-1")).unwrap();
+
+    // Committed through the CommitBuilder's raw writes.
+    let mut counter = Counter::new();
+    counter.create("c-2".into(), "Views".into(), "user-1".into());
+    repo.readmodel(&CounterView::new("c-2", "Views", "user-1"))
+        .commit(&mut counter)
+        .unwrap();
+
+    let ids = |user: &str| -> Vec<String> {
+        let found = views.find_by("user_id", user).unwrap();
+        found.into_iter().map(|v| v.data.id).collect()
+    };
+    assert_eq!(ids("user-1"), vec!["c-1", "c-2"]);
+
+    let moved = views.get("c-1").unwrap().unwrap();
+    let mut view = moved.data.clone();
+    view.user_id = "user-2".into();
+    views.update(&view, moved.version).unwrap();
+    views.delete("c-2").unwrap();
+    assert!(ids("user-1").is_empty());
+    assert_eq!(ids("user-2"), vec!["c-1"]);
+    assert_eq!(views.find_by("name", "Likes").unwrap().len(), 1);
+
+    assert_eq!(
+        views.find_by("value", 0).unwrap_err(),
+        ReadModelError::UnknownIndex {
+            collection: "counter_views".into(),
+            field: "value".into(),
+        }
+    );
+}
@@ -0,0 +1,168 @@
+//! Eventually consistent read models built by a projection.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sourced_rust::{
+    CommitAggregate, EventProjection, HashMapRepository, ProjectionRebuild, ProjectionRunner,
+    ReadModelsExt, SubscriptionThread,
+};
+
+use super::aggregate::Counter;
+use super::views::{CounterView, UserCountersIndexView};
+
+fn counter_views() -> EventProjection<HashMapRepository> {
+    EventProjection::new("counter_views")
+        .on("CounterCreated", |event, models| {
+            let (id, name, user_id): (String, String, String) = event.event.decode()?;
+            models.upsert(&CounterView::new(&id, &name, &user_id))?;
+            models.update(
+                &user_id,
+                || UserCountersIndexView::new(&user_id),
+                |index| index.add_counter(&id, 0),
+            )
+        })
+        .on("CounterIncremented", |event, models| {
+            let (amount,): (i32,) = event.event.decode()?;
+            let Some(mut view) = models.get::<CounterView>(&event.stream_id)? else {
+                return Ok(());
+            };
+            view.value += amount;
+            models.upsert(&view)?;
+            models.update(
+                &view.user_id,
+                || UserCountersIndexView::new(&view.user_id),
+                |index| index.total_value += amount,
+            )
+        })
+}
+
+fn create_counter(repo: &HashMapRepository, id: &str, user_id: &str, increments: &[i32]) {
+    let mut counter = Counter::new();
+    counter.create(id.into(), id.into(), user_id.into());
+    for amount in increments {
+        counter.increment(*amount);
+    }
+    repo.commit_aggregate(&mut counter).unwrap();
+}
+
+#[test]
+fn projection_builds_views_from_events() {
+    let repo = HashMapRepository::new();
+    create_counter(&repo, "counter-1", "user-1", &[5, 2]);
+    create_counter(&repo, "counter-2", "user-1", &[1]);
+
+    let mut runner = ProjectionRunner::new(repo.clone(), repo.clone(), counter_views());
+    assert_eq!(runner.catch_up().unwrap(), 5);
+
+    let view = repo.read_models::<CounterView>().get("counter-1").unwrap().unwrap();
+    assert_eq!(view.data.value, 7);
+
+    let index = repo
+        .read_models::<UserCountersIndexView>()
+        .get("user-1")
+        .unwrap()
+        .unwrap();
+    assert_eq!(index.data.counter_ids, vec!["counter-1", "counter-2"]);
+    assert_eq!(index.data.total_value, 8);
+}
+
+#[test]
+fn replay_does_not_apply_events_twice() {
+    let repo = HashMapRepository::new();
+    create_counter(&repo, "counter-1", "user-1", &[5]);
+
+    ProjectionRunner::new(repo.clone(), repo.clone(), counter_views())
+        .with_batch_size(1)
+        .catch_up()
+        .unwrap();
+
+    // A restarted runner resumes from its checkpoint instead of replaying.
+    let mut restarted = ProjectionRunner::new(repo.clone(), repo.clone(), counter_views());
+    assert_eq!(restarted.catch_up().unwrap(), 0);
+    assert_eq!(restarted.position(), Some(2));
+
+    let view = repo.read_models::<CounterView>().get("counter-1").unwrap().unwrap();
+    assert_eq!(view.data.value, 5);
+}
+
+#[test]
+fn projection_follows_live_commits_in_background() {
+    let repo = HashMapRepository::new();
+    create_counter(&repo, "counter-1", "user-1", &[]);
+
+    let runner = ProjectionRunner::new(repo.clone(), repo.clone(), counter_views());
+    let worker = SubscriptionThread::spawn(runner, Duration::from_millis(5));
+
+    create_counter(&repo, "counter-2", "user-1", &[3]);
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let index = repo.read_models::<UserCountersIndexView>().get("user-1").unwrap();
+        if index.map(|i| i.data.total_value) == Some(3) {
+            break;
+        }
+        assert!(Instant::now() < deadline, "projection did not catch up");
+        thread::sleep(Duration::from_millis(5));
+    }
+
+    let stats = worker.stop();
+    assert_eq!(stats.events_handled, 3);
+    assert_eq!(stats.errors, 0);
+}
+
+#[test]
+fn rebuild_swaps_in_new_views_without_serving_partial_data() {
+    let repo = HashMapRepository::new();
+    create_counter(&repo, "counter-1", "user-1", &[5, 2]);
+    create_counter(&repo, "counter-2", "user-2", &[1]);
+
+    let mut live = ProjectionRunner::new(repo.clone(), repo.clone(), counter_views());
+    live.catch_up().unwrap();
+    let worker = SubscriptionThread::spawn(live, Duration::from_millis(5));
+
+    // v2 of the projection counts increments instead of summing them, and no
+    // longer maintains the per-user index.
+    let counting = EventProjection::new("counter_views")
+        .on("CounterCreated", |event, models| {
+            let (id, name, user_id): (String, String, String) = event.event.decode()?;
+            models.upsert(&CounterView::new(&id, &name, &user_id))
+        })
+        .on("CounterIncremented", |event, models| {
+            models.update(
+                &event.stream_id,
+                || CounterView::new(&event.stream_id, "", ""),
+                |view| view.value += 1,
+            )
+        });
+
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let reported = Arc::clone(&progress);
+    let mut rebuild = ProjectionRebuild::new(repo.clone(), repo.clone(), counting)
+        .with_batch_size(2)
+        .with_collection::<UserCountersIndexView>()
+        .on_progress(move |p| reported.lock().unwrap().push(p.events_applied));
+    assert_eq!(rebuild.catch_up().unwrap(), 5);
+
+    // Until the swap, readers still get the old views.
+    let view = repo.read_models::<CounterView>().get("counter-1").unwrap().unwrap();
+    assert_eq!(view.data.value, 7);
+    assert!(repo.read_models::<UserCountersIndexView>().get("user-1").unwrap().is_some());
+
+    worker.stop();
+    let done = rebuild.swap().unwrap();
+    assert_eq!(done.position, 5);
+    assert_eq!(*progress.lock().unwrap(), vec![2, 4, 5]);
+
+    let view = repo.read_models::<CounterView>().get("counter-1").unwrap().unwrap();
+    assert_eq!(view.data.value, 2);
+    assert!(repo.read_models::<UserCountersIndexView>().get("user-1").unwrap().is_none());
+
+    // The rebuilt projection carries on from the swapped-in checkpoint.
+    create_counter(&repo, "counter-3", "user-1", &[4]);
+    let mut runner = rebuild.into_runner();
+    assert_eq!(runner.catch_up().unwrap(), 2);
+    let view = repo.read_models::<CounterView>().get("counter-3").unwrap().unwrap();
+    assert_eq!(view.data.value, 1);
+}
@@ -5,11 +5,12 @@ use sourced_rust::ReadModel;
 
 /// A read-optimized view of a single counter.
 #[derive(Clone, Debug, Serialize, Deserialize, ReadModel)]
-#[readmodel(collection = "counter_views")]
+#[readmodel(collection = "counter_views", index = "name")]
 pub struct CounterView {
     #[readmodel(id)]
     pub id: String,
     pub name: String,
+    #[readmodel(index)]
     pub user_id: String,
     pub value: i32,
 }
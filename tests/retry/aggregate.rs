@@ -0,0 +1,32 @@
+use sourced_rust::{digest, Entity};
+
+#[derive(Debug, Default)]
+pub struct Account {
+    pub entity: Entity,
+    balance: i64,
+}
+
+impl Account {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[digest("Opened")]
+    pub fn open(&mut self, id: String) {
+        self.entity.set_id(&id);
+    }
+
+    #[digest("Deposited")]
+    pub fn deposit(&mut self, amount: i64) {
+        self.balance += amount;
+    }
+
+    pub fn balance(&self) -> i64 {
+        self.balance
+    }
+}
+
+sourced_rust::aggregate!(Account, entity {
+    "Opened"(id) => open,
+    "Deposited"(amount) => deposit,
+});
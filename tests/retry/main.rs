@@ -0,0 +1,175 @@
+mod aggregate;
+
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::Duration;
+
+use aggregate::Account;
+use sourced_rust::microsvc::HandlerError;
+use sourced_rust::{
+    AggregateBuilder, HashMapRepository, Queueable, RepositoryError, RetryPolicy,
+};
+
+fn open_account(repo: &HashMapRepository, id: &str) {
+    let mut account = Account::new();
+    account.open(id.into());
+    repo.clone().aggregate::<Account>().commit(&mut account).unwrap();
+}
+
+#[test]
+fn update_reloads_and_reruns_after_a_conflict() {
+    let repo = HashMapRepository::new();
+    open_account(&repo, "acc-1");
+    let accounts = repo.clone().aggregate::<Account>();
+
+    let mut runs = 0;
+    let updated = accounts
+        .update("acc-1", |account| {
+            runs += 1;
+            if runs == 1 {
+                // Another writer gets in between our load and commit.
+                let mut other = accounts.get("acc-1")?.unwrap();
+                other.deposit(100);
+                accounts.commit(&mut other)?;
+            }
+            account.deposit(5);
+            Ok::<_, RepositoryError>(account.balance())
+        })
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(updated.attempts, 2);
+    assert_eq!(updated.value, 105);
+    assert_eq!(updated.aggregate.entity.version(), 3);
+    assert_eq!(accounts.get("acc-1").unwrap().unwrap().balance(), 105);
+}
+
+#[test]
+fn concurrent_updates_all_land() {
+    let repo = HashMapRepository::new();
+    open_account(&repo, "acc-1");
+
+    let writers = 8;
+    let barrier = Arc::new(Barrier::new(writers));
+    let handles: Vec<_> = (0..writers)
+        .map(|_| {
+            let accounts = repo.clone().aggregate::<Account>();
+            let barrier = Arc::clone(&barrier);
+            thread::spawn(move || {
+                barrier.wait();
+                let policy = RetryPolicy::new(50);
+                accounts
+                    .update_with("acc-1", &policy, |account| {
+                        account.deposit(1);
+                        Ok::<_, RepositoryError>(())
+                    })
+                    .unwrap()
+                    .unwrap()
+                    .attempts
+            })
+        })
+        .collect();
+    let attempts: u32 = handles.into_iter().map(|h| h.join().unwrap()).sum();
+
+    let accounts = repo.aggregate::<Account>();
+    assert_eq!(accounts.get("acc-1").unwrap().unwrap().balance(), writers as i64);
+    assert!(attempts >= writers as u32);
+}
+
+#[test]
+fn exhausted_retries_report_the_attempts_and_last_conflict() {
+    let repo = HashMapRepository::new();
+    open_account(&repo, "acc-1");
+    let accounts = repo.clone().aggregate::<Account>();
+
+    let mut runs = 0;
+    let err = accounts
+        .update_with("acc-1", &RetryPolicy::new(2).without_backoff(), |account| {
+            runs += 1;
+            let mut other = accounts.get("acc-1")?.unwrap();
+            other.deposit(1);
+            accounts.commit(&mut other)?;
+            account.deposit(5);
+            Ok::<_, RepositoryError>(())
+        })
+        .unwrap_err();
+
+    let RepositoryError::RetriesExhausted { attempts, last } = err else {
+        panic!("expected RetriesExhausted, got {:?}", err);
+    };
+    assert_eq!(attempts, 2);
+    assert!(matches!(*last, RepositoryError::ConcurrentWrite { .. }));
+    assert_eq!(runs, 2);
+    assert_eq!(accounts.get("acc-1").unwrap().unwrap().balance(), 2);
+}
+
+#[test]
+fn command_errors_are_not_retried() {
+    let repo = HashMapRepository::new();
+    open_account(&repo, "acc-1");
+    let accounts = repo.aggregate::<Account>();
+
+    let mut runs = 0;
+    let err = accounts
+        .update("acc-1", |account| {
+            runs += 1;
+            if account.balance() < 10 {
+                return Err(HandlerError::Rejected("insufficient funds".into()));
+            }
+            Ok(())
+        })
+        .unwrap_err();
+
+    assert!(matches!(err, HandlerError::Rejected(_)));
+    assert_eq!(runs, 1);
+    assert!(accounts
+        .update("missing", |_| Ok::<_, HandlerError>(()))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn queued_updates_release_the_stream_when_they_do_not_commit() {
+    let repo = HashMapRepository::new();
+    open_account(&repo, "acc-1");
+    let accounts = repo
+        .clone()
+        .queued()
+        .with_lock_timeout(Duration::from_millis(100))
+        .aggregate::<Account>();
+
+    // A command error
+    let err = accounts
+        .update("acc-1", |_| Err::<(), _>(HandlerError::Rejected("no".into())))
+        .unwrap_err();
+    assert!(matches!(err, HandlerError::Rejected(_)));
+
+    // A missing aggregate
+    let missing = accounts.update("missing", |_| Ok::<_, RepositoryError>(()));
+    assert!(missing.unwrap().is_none());
+
+    // A conflict: the retry loads the stream again under the same lock manager
+    let unlocked = repo.aggregate::<Account>();
+    let mut runs = 0;
+    let updated = accounts
+        .update_with("acc-1", &RetryPolicy::new(3).without_backoff(), |account| {
+            runs += 1;
+            if runs == 1 {
+                let mut other = unlocked.get("acc-1")?.unwrap();
+                other.deposit(100);
+                unlocked.commit(&mut other)?;
+            }
+            account.deposit(5);
+            Ok::<_, RepositoryError>(())
+        })
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.attempts, 2);
+
+    for id in ["acc-1", "missing"] {
+        let account = accounts.get(id).unwrap();
+        accounts.repo().unlock(id).unwrap();
+        assert_eq!(account.is_some(), id == "acc-1");
+    }
+    assert_eq!(accounts.peek("acc-1").unwrap().unwrap().balance(), 105);
+}
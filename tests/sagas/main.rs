@@ -10,3 +10,4 @@ mod order;
 mod orchestration;
 mod distributed;
 mod microsvc_saga;
+mod unit_of_work;
@@ -0,0 +1,205 @@
+//! Unit of Work Tests
+//!
+//! One saga step touches the saga, the order, the inventory and the payment.
+//! A `UnitOfWork` loads them together and commits them together, so a step
+//! either happens completely or not at all.
+
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use sourced_rust::{AggregateRef, HashMapRepository, Queueable, RepositoryError, UnitOfWork};
+
+use super::order::{
+    Inventory, Order, OrderFulfillmentSaga, OrderItem, OrderStatus, Payment, SagaStatus,
+};
+
+fn items() -> Vec<OrderItem> {
+    vec![OrderItem {
+        sku: "WIDGET-001".to_string(),
+        quantity: 5,
+        price_cents: 1000,
+    }]
+}
+
+fn setup(repo: &HashMapRepository, order_ids: &[&str]) {
+    let mut uow = UnitOfWork::new(repo).namespaced();
+    let mut inventory = Inventory::new();
+    inventory.initialize("WIDGET-001".to_string(), 100);
+    uow.add(inventory);
+    for order_id in order_ids {
+        let mut order = Order::new();
+        order.create(order_id.to_string(), "customer-456".to_string(), items());
+        uow.add(order);
+    }
+    uow.commit().unwrap();
+}
+
+#[test]
+fn saga_step_commits_every_aggregate_together() {
+    let repo = HashMapRepository::new();
+    setup(&repo, &["order-123"]);
+
+    let mut uow = UnitOfWork::new(&repo).namespaced();
+    let mut saga = OrderFulfillmentSaga::new();
+    saga.start(
+        "saga-123".to_string(),
+        "order-123".to_string(),
+        "customer-456".to_string(),
+        items(),
+        5000,
+    );
+    uow.add(saga);
+    uow.load(&[
+        AggregateRef::of::<Order>("order-123"),
+        AggregateRef::of::<Inventory>("WIDGET-001"),
+    ])
+    .unwrap();
+
+    uow.get_mut::<Inventory>("WIDGET-001")
+        .unwrap()
+        .reserve("order-123".to_string(), 5);
+    uow.get_mut::<Order>("order-123").unwrap().mark_inventory_reserved();
+    let mut payment = Payment::new();
+    payment.initiate("payment-789".to_string(), "order-123".to_string(), 5000);
+    payment.authorize("txn-abc123".to_string());
+    payment.capture();
+    uow.add(payment);
+    let saga = uow.get_mut::<OrderFulfillmentSaga>("saga-123").unwrap();
+    saga.inventory_reserved();
+    saga.payment_succeeded();
+    uow.commit().unwrap();
+
+    let mut check = UnitOfWork::new(&repo).namespaced();
+    let saga = check.get::<OrderFulfillmentSaga>("saga-123").unwrap().unwrap();
+    assert_eq!(saga.status(), SagaStatus::PaymentProcessed);
+    let order = check.get::<Order>("order-123").unwrap().unwrap();
+    assert_eq!(order.status(), OrderStatus::InventoryReserved);
+    let inventory = check.get::<Inventory>("WIDGET-001").unwrap().unwrap();
+    assert_eq!(inventory.available(), 95);
+    assert!(check.get::<Payment>("payment-789").unwrap().unwrap().is_successful());
+}
+
+#[test]
+fn a_conflict_on_one_aggregate_rolls_back_the_step() {
+    let repo = HashMapRepository::new();
+    setup(&repo, &["order-123"]);
+
+    let mut uow = UnitOfWork::new(&repo).namespaced();
+    uow.get::<Order>("order-123")
+        .unwrap()
+        .unwrap()
+        .mark_inventory_reserved();
+    uow.get::<Inventory>("WIDGET-001")
+        .unwrap()
+        .unwrap()
+        .reserve("order-123".to_string(), 5);
+
+    // Another process reserves stock first
+    let mut other = UnitOfWork::new(&repo).namespaced();
+    other
+        .get::<Inventory>("WIDGET-001")
+        .unwrap()
+        .unwrap()
+        .reserve("order-999".to_string(), 10);
+    other.commit().unwrap();
+
+    let err = uow.commit().unwrap_err();
+    assert!(matches!(err, RepositoryError::ConcurrentWrite { .. }));
+
+    let mut check = UnitOfWork::new(&repo).namespaced();
+    let order = check.get::<Order>("order-123").unwrap().unwrap();
+    assert_eq!(order.status(), OrderStatus::Pending);
+    let inventory = check.get::<Inventory>("WIDGET-001").unwrap().unwrap();
+    assert_eq!(inventory.available(), 90);
+}
+
+#[test]
+fn queued_units_of_work_take_turns_on_shared_aggregates() {
+    let store = HashMapRepository::new();
+    setup(&store, &["order-1", "order-2"]);
+    let repo = Arc::new(store.queued());
+
+    let workers: Vec<_> = ["order-1", "order-2"]
+        .into_iter()
+        .map(|order_id| {
+            let repo = Arc::clone(&repo);
+            thread::spawn(move || {
+                let mut uow = UnitOfWork::new(repo.as_ref()).namespaced();
+                // Both units lock the same inventory stream, in stream ID order
+                uow.load(&[
+                    AggregateRef::of::<Order>(order_id),
+                    AggregateRef::of::<Inventory>("WIDGET-001"),
+                ])
+                .unwrap();
+                uow.get_mut::<Inventory>("WIDGET-001")
+                    .unwrap()
+                    .reserve(order_id.to_string(), 5);
+                uow.get_mut::<Order>(order_id).unwrap().mark_inventory_reserved();
+                uow.commit().unwrap();
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    let mut check = UnitOfWork::new(repo.inner()).namespaced();
+    let inventory = check.get::<Inventory>("WIDGET-001").unwrap().unwrap();
+    assert_eq!(inventory.available(), 90);
+    assert_eq!(inventory.reservation_for_order("order-1"), Some(5));
+    assert_eq!(inventory.reservation_for_order("order-2"), Some(5));
+}
+
+#[test]
+fn commit_releases_missing_and_taken_streams() {
+    let store = HashMapRepository::new();
+    setup(&store, &["order-1", "order-2"]);
+    let repo = store.queued().with_lock_timeout(Duration::from_millis(30));
+
+    let mut uow = UnitOfWork::new(&repo).namespaced();
+    uow.load(&[
+        AggregateRef::of::<Order>("order-1"),
+        AggregateRef::of::<Order>("order-2"),
+        AggregateRef::of::<Payment>("payment-missing"),
+    ])
+    .unwrap();
+    uow.take::<Order>("order-2").unwrap();
+    uow.get_mut::<Order>("order-1").unwrap().mark_inventory_reserved();
+    uow.commit().unwrap();
+
+    // Every stream the unit of work locked can be locked again
+    let mut next = UnitOfWork::new(&repo).namespaced();
+    next.load(&[
+        AggregateRef::of::<Order>("order-1"),
+        AggregateRef::of::<Order>("order-2"),
+        AggregateRef::of::<Payment>("payment-missing"),
+    ])
+    .unwrap();
+    next.abort().unwrap();
+}
+
+#[test]
+fn dropping_an_uncommitted_unit_of_work_releases_its_streams() {
+    let store = HashMapRepository::new();
+    setup(&store, &["order-1"]);
+    let repo = store.queued().with_lock_timeout(Duration::from_millis(30));
+
+    let mut uow = UnitOfWork::new(&repo).namespaced();
+    uow.load(&[
+        AggregateRef::of::<Order>("order-1"),
+        AggregateRef::of::<Order>("order-1"),
+        AggregateRef::of::<Payment>("payment-missing"),
+    ])
+    .unwrap();
+    // Asking again for a missing stream it already holds does not wait on it
+    assert!(uow.get::<Payment>("payment-missing").unwrap().is_none());
+    drop(uow);
+
+    let mut next = UnitOfWork::new(&repo).namespaced();
+    next.load(&[
+        AggregateRef::of::<Order>("order-1"),
+        AggregateRef::of::<Payment>("payment-missing"),
+    ])
+    .unwrap();
+}
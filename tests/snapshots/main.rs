@@ -2,7 +2,7 @@ mod aggregate;
 
 use aggregate::Todo;
 use sourced_rust::{
-    AggregateBuilder, HashMapRepository, Queueable, SnapshotStore,
+    AggregateBuilder, GetOne, HashMapRepository, Queueable, RepositoryError, SnapshotStore,
 };
 
 #[test]
@@ -105,6 +105,54 @@ fn snapshot_plus_newer_events() {
     assert_eq!(loaded.entity.snapshot_version(), 2);
 }
 
+#[test]
+fn get_reads_only_events_after_snapshot() {
+    let store = HashMapRepository::new();
+    let snapshots = store.clone().aggregate::<Todo>().with_snapshots(1);
+    let plain = store.clone().aggregate::<Todo>();
+
+    let mut todo = Todo::new();
+    todo.initialize("t1".into(), "alice".into(), "Buy milk".into());
+    snapshots.commit(&mut todo).unwrap();
+
+    // Add an event without taking a new snapshot
+    let mut todo = plain.get("t1").unwrap().unwrap();
+    todo.complete();
+    plain.commit(&mut todo).unwrap();
+
+    let mut loaded = snapshots.get("t1").unwrap().unwrap();
+    assert!(loaded.completed);
+    assert_eq!(loaded.entity.version(), 2);
+    assert_eq!(loaded.entity.base_version(), 1);
+    assert_eq!(loaded.entity.events().len(), 1);
+    assert_eq!(loaded.entity.events()[0].event_name, "Completed");
+
+    // A tail-loaded aggregate commits like a fully loaded one
+    loaded.entity.digest("Reopened", &());
+    snapshots.commit(&mut loaded).unwrap();
+    assert_eq!(store.get_one("t1").unwrap().unwrap().version(), 3);
+}
+
+#[test]
+fn snapshot_ahead_of_stream_is_ignored() {
+    let repo = HashMapRepository::new()
+        .aggregate::<Todo>()
+        .with_snapshots(2);
+
+    let mut todo = Todo::new();
+    todo.initialize("t1".into(), "alice".into(), "Buy milk".into());
+    todo.complete();
+    repo.commit(&mut todo).unwrap();
+
+    let mut stale = repo.repo().repo().get_snapshot("t1").unwrap().unwrap();
+    stale.version = 9;
+    repo.repo().repo().save_snapshot(stale).unwrap();
+
+    let loaded = repo.get("t1").unwrap().unwrap();
+    assert!(loaded.completed);
+    assert_eq!(loaded.entity.events().len(), 2);
+}
+
 #[test]
 fn no_snapshot_falls_back_to_full_replay() {
     let repo = HashMapRepository::new()
@@ -235,3 +283,28 @@ fn commit_all_with_snapshots() {
     let snap2 = repo.repo().repo().get_snapshot("t2").unwrap().unwrap();
     assert_eq!(snap2.version, 2);
 }
+
+#[test]
+fn update_loads_from_snapshot_and_snapshots_on_commit() {
+    let repo = HashMapRepository::new()
+        .aggregate::<Todo>()
+        .with_snapshots(2);
+
+    let mut todo = Todo::new();
+    todo.initialize("t1".into(), "alice".into(), "Buy milk".into());
+    repo.commit(&mut todo).unwrap();
+
+    let updated = repo
+        .update("t1", |todo| {
+            todo.complete();
+            Ok::<_, RepositoryError>(())
+        })
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.attempts, 1);
+    assert!(updated.aggregate.completed);
+
+    let snap = repo.repo().repo().get_snapshot("t1").unwrap().unwrap();
+    assert_eq!(snap.version, 2);
+    assert!(repo.get("t1").unwrap().unwrap().completed);
+}
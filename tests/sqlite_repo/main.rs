@@ -0,0 +1,368 @@
+//! SQLite backend integration tests (requires the `sqlite` feature).
+#![cfg(feature = "sqlite")]
+
+mod aggregate;
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use aggregate::Todo;
+use serde::{Deserialize, Serialize};
+use sourced_rust::{
+    AggregateBuilder, CheckpointStore, CommitAggregate, CommitBuilderExt, EventProjection,
+    ExpectedVersion, GetAggregate, OutboxMessage, OutboxRepositoryExt, ProjectionCheckpoint,
+    ProjectionRebuild, ProjectionRunner, ReadModel, ReadModelStore, ReadModelsExt,
+    RepositoryError, SnapshotStore, SqliteRepository, StoredEvent, Subscription,
+    SubscriptionError,
+};
+
+static NEXT_DB: AtomicU64 = AtomicU64::new(1);
+
+/// A fresh database file, removed (with its WAL files) when dropped.
+struct TempDb(PathBuf);
+
+impl TempDb {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "sourced_rust_sqlite_{}_{}_{}.db",
+            name,
+            std::process::id(),
+            NEXT_DB.fetch_add(1, Ordering::SeqCst)
+        ));
+        let db = TempDb(path);
+        db.cleanup();
+        db
+    }
+
+    fn cleanup(&self) {
+        for suffix in ["", "-wal", "-shm"] {
+            let mut path = self.0.clone().into_os_string();
+            path.push(suffix);
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        self.cleanup();
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ReadModel)]
+#[readmodel(collection = "todo_views")]
+struct TodoView {
+    #[readmodel(id)]
+    id: String,
+    task: String,
+    completed: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ReadModel)]
+#[readmodel(collection = "rejected_views")]
+struct RejectedView {
+    #[readmodel(id)]
+    id: String,
+}
+
+fn todo(id: &str, task: &str) -> Todo {
+    let mut todo = Todo::new();
+    todo.initialize(id.into(), "alice".into(), task.into());
+    todo
+}
+
+fn view(todo: &Todo) -> TodoView {
+    TodoView {
+        id: todo.entity.id().to_string(),
+        task: todo.task.clone(),
+        completed: todo.completed,
+    }
+}
+
+#[test]
+fn data_survives_reopen() {
+    let db = TempDb::new("reopen");
+    {
+        let repo = SqliteRepository::open(&db.0).unwrap();
+        let mut t1 = todo("t1", "Buy milk");
+        let message = OutboxMessage::create("t1:init", "TodoInitialized", vec![1]);
+        repo.readmodel(&view(&t1))
+            .outbox(message)
+            .commit(&mut t1)
+            .unwrap();
+    }
+
+    let repo = SqliteRepository::open(&db.0).unwrap();
+    let loaded = repo.get_aggregate::<Todo>("t1").unwrap().unwrap();
+    assert_eq!(loaded.task, "Buy milk");
+    assert_eq!(loaded.entity.version(), 1);
+
+    let stored = repo.read_models::<TodoView>().get("t1").unwrap().unwrap();
+    assert_eq!(stored.data.task, "Buy milk");
+    assert_eq!(repo.outbox_messages_pending().unwrap().len(), 1);
+}
+
+#[test]
+fn aggregate_repository_round_trip() {
+    let repo = SqliteRepository::in_memory().unwrap().aggregate::<Todo>();
+
+    let mut t1 = todo("t1", "Buy milk");
+    let mut t2 = todo("t2", "Walk dog");
+    t2.complete();
+    repo.commit_all(&mut [&mut t1, &mut t2]).unwrap();
+
+    assert_eq!(repo.count(|t| t.completed).unwrap(), 1);
+    assert!(repo.exists(|t| t.task == "Buy milk").unwrap());
+    assert_eq!(repo.find(|_| true).unwrap().len(), 2);
+
+    let mut stale = repo.get("t1").unwrap().unwrap();
+    let mut fresh = repo.get("t1").unwrap().unwrap();
+    fresh.complete();
+    repo.commit(&mut fresh).unwrap();
+
+    stale.complete();
+    assert!(matches!(
+        repo.commit(&mut stale),
+        Err(RepositoryError::ConcurrentWrite { expected: 1, actual: 2, .. })
+    ));
+}
+
+#[test]
+fn commit_builder_is_one_transaction() {
+    let db = TempDb::new("atomic");
+    let repo = SqliteRepository::open(&db.0).unwrap();
+
+    // Make read model writes to one collection fail inside the database, so
+    // the failure happens after the events were inserted in the same transaction.
+    let conn = rusqlite::Connection::open(&db.0).unwrap();
+    conn.execute_batch(
+        "CREATE TRIGGER reject_views BEFORE INSERT ON read_models \
+         WHEN NEW.collection = 'rejected_views' \
+         BEGIN SELECT RAISE(ABORT, 'rejected'); END;",
+    )
+    .unwrap();
+    drop(conn);
+
+    let mut t1 = todo("t1", "Buy milk");
+    let message = OutboxMessage::create("t1:init", "TodoInitialized", vec![1]);
+    let err = repo
+        .readmodel(&view(&t1))
+        .readmodel(&RejectedView { id: "t1".into() })
+        .outbox(message)
+        .commit(&mut t1)
+        .unwrap_err();
+    assert!(matches!(err, RepositoryError::Storage(_)));
+
+    // Nothing from the failed commit is visible.
+    assert!(repo.get_aggregate::<Todo>("t1").unwrap().is_none());
+    assert!(repo.get_model::<TodoView>("t1").unwrap().is_none());
+    assert!(repo.outbox_messages_pending().unwrap().is_empty());
+    assert_eq!(t1.entity.committed_version(), 0);
+
+    // The same aggregate commits fine without the rejected view.
+    repo.readmodel(&view(&t1)).commit(&mut t1).unwrap();
+    assert!(repo.get_aggregate::<Todo>("t1").unwrap().is_some());
+    assert_eq!(repo.get_model::<TodoView>("t1").unwrap().unwrap().version, 1);
+}
+
+#[test]
+fn conflicting_commit_leaves_read_models_untouched() {
+    let repo = SqliteRepository::in_memory().unwrap();
+    let mut t1 = todo("t1", "Buy milk");
+    repo.readmodel(&view(&t1)).commit(&mut t1).unwrap();
+
+    let mut stale = repo.get_aggregate::<Todo>("t1").unwrap().unwrap();
+    let mut fresh = repo.get_aggregate::<Todo>("t1").unwrap().unwrap();
+    fresh.complete();
+    repo.readmodel(&view(&fresh)).commit(&mut fresh).unwrap();
+
+    stale.complete();
+    let mut stale_view = view(&stale);
+    stale_view.task = "stale".into();
+    assert!(repo.readmodel(&stale_view).commit(&mut stale).is_err());
+
+    let stored = repo.get_model::<TodoView>("t1").unwrap().unwrap();
+    assert_eq!(stored.version, 2);
+    assert_eq!(stored.data.task, "Buy milk");
+}
+
+#[test]
+fn model_conflict_leaves_entities_as_they_were() {
+    let repo = SqliteRepository::in_memory().unwrap();
+    let mut t1 = todo("t1", "Buy milk");
+    repo.readmodel(&view(&t1)).commit(&mut t1).unwrap();
+
+    // A stale copy, committed over a newer stream with ExpectedVersion::Any
+    let mut stale = repo.get_aggregate::<Todo>("t1").unwrap().unwrap();
+    let mut fresh = repo.get_aggregate::<Todo>("t1").unwrap().unwrap();
+    fresh.complete();
+    repo.commit_aggregate(&mut fresh).unwrap();
+
+    stale.complete();
+    let err = repo
+        .readmodel_expecting(&view(&stale), 5)
+        .commit_expecting(&mut stale, ExpectedVersion::Any)
+        .unwrap_err();
+    assert!(matches!(err, RepositoryError::ReadModelConflict(_)));
+    assert_eq!(stale.entity.committed_version(), 1);
+    assert_eq!(stale.entity.base_version(), 0);
+    assert_eq!(stale.entity.new_events().len(), 1);
+
+    repo.readmodel_expecting(&view(&stale), 1)
+        .commit_expecting(&mut stale, ExpectedVersion::Any)
+        .unwrap();
+    let loaded = repo.get_aggregate::<Todo>("t1").unwrap().unwrap();
+    assert_eq!(loaded.entity.version(), 3);
+    assert!(loaded.completed);
+}
+
+#[test]
+fn snapshots_through_aggregate_repository() {
+    let repo = SqliteRepository::in_memory()
+        .unwrap()
+        .aggregate::<Todo>()
+        .with_snapshots(1);
+
+    let mut t1 = todo("t1", "Buy milk");
+    repo.commit(&mut t1).unwrap();
+
+    let snapshot = repo.repo().repo().get_snapshot("t1").unwrap().unwrap();
+    assert_eq!(snapshot.version, 1);
+
+    let loaded = repo.get("t1").unwrap().unwrap();
+    assert_eq!(loaded.task, "Buy milk");
+    assert_eq!(loaded.entity.snapshot_version(), 1);
+}
+
+#[test]
+fn outbox_claim_complete_release_fail() {
+    let repo = SqliteRepository::in_memory().unwrap();
+    let mut t1 = todo("t1", "Buy milk");
+    let first = OutboxMessage::create("m1", "Event", vec![1]);
+    let second = OutboxMessage::create("m2", "Event", vec![2]);
+    let third = OutboxMessage::create("m3", "Event", vec![3]);
+    repo.outbox(first)
+        .outbox(second)
+        .outbox(third)
+        .commit(&mut t1)
+        .unwrap();
+
+    let claimed = repo
+        .claim_outbox_messages("worker-1", 10, Duration::from_secs(30))
+        .unwrap();
+    assert_eq!(claimed.len(), 3);
+    assert!(repo
+        .claim_outbox_messages("worker-2", 10, Duration::from_secs(30))
+        .unwrap()
+        .is_empty());
+
+    repo.complete_outbox_message("m1").unwrap();
+    repo.release_outbox_message("outbox:m2", "broker down").unwrap();
+    repo.fail_outbox_message("m3", "bad payload").unwrap();
+
+    let published = repo
+        .get_aggregate::<OutboxMessage>("outbox:m1")
+        .unwrap()
+        .unwrap();
+    assert!(published.is_published());
+    assert_eq!(repo.outbox_messages_pending().unwrap().len(), 1);
+    let failed = repo
+        .get_aggregate::<OutboxMessage>("outbox:m3")
+        .unwrap()
+        .unwrap();
+    assert!(failed.is_failed());
+}
+
+#[test]
+fn subscription_resumes_after_reopen() {
+    let db = TempDb::new("subscription");
+    let mut handled = Vec::new();
+    {
+        let repo = SqliteRepository::open(&db.0).unwrap();
+        let mut t1 = todo("t1", "Buy milk");
+        repo.commit_aggregate(&mut t1).unwrap();
+
+        let mut subscription = Subscription::new("views", repo.clone(), repo, |e: &StoredEvent| {
+            handled.push(e.position());
+            Ok::<_, String>(())
+        });
+        assert_eq!(subscription.catch_up().unwrap(), 1);
+    }
+
+    let repo = SqliteRepository::open(&db.0).unwrap();
+    assert_eq!(repo.load_checkpoint("views").unwrap(), Some(1));
+    let mut t2 = todo("t2", "Walk dog");
+    repo.commit_aggregate(&mut t2).unwrap();
+
+    let mut subscription = Subscription::new("views", repo.clone(), repo, |e: &StoredEvent| {
+        handled.push(e.position());
+        Ok::<_, String>(())
+    });
+    assert_eq!(subscription.catch_up().unwrap(), 1);
+    drop(subscription);
+    assert_eq!(handled, vec![1, 2]);
+}
+
+#[test]
+fn projection_models_and_checkpoint_commit_together() {
+    let db = TempDb::new("projection");
+    let repo = SqliteRepository::open(&db.0).unwrap();
+    let mut t1 = todo("t1", "Buy milk");
+    repo.commit_aggregate(&mut t1).unwrap();
+
+    let conn = rusqlite::Connection::open(&db.0).unwrap();
+    conn.execute_batch(
+        "CREATE TRIGGER reject_views BEFORE INSERT ON read_models \
+         WHEN NEW.collection = 'rejected_views' \
+         BEGIN SELECT RAISE(ABORT, 'rejected'); END;",
+    )
+    .unwrap();
+
+    let projection = EventProjection::new("todo_views").on("Initialized", |event, models| {
+        let (id, _user, task): (String, String, String) = event.event.decode()?;
+        models.upsert(&TodoView { id: id.clone(), task, completed: false })?;
+        models.upsert(&RejectedView { id })
+    });
+    let mut runner = ProjectionRunner::new(repo.clone(), repo.clone(), projection);
+
+    assert!(matches!(runner.poll(), Err(SubscriptionError::Repository(_))));
+    assert!(repo.get_model::<TodoView>("t1").unwrap().is_none());
+    assert!(repo.get_model::<ProjectionCheckpoint>("todo_views").unwrap().is_none());
+
+    conn.execute_batch("DROP TRIGGER reject_views;").unwrap();
+    assert_eq!(runner.catch_up().unwrap(), 1);
+    assert_eq!(repo.get_model::<TodoView>("t1").unwrap().unwrap().data.task, "Buy milk");
+    let checkpoint = repo.get_model::<ProjectionCheckpoint>("todo_views").unwrap().unwrap();
+    assert_eq!(checkpoint.data.position, 1);
+}
+
+#[test]
+fn rebuild_swap_survives_reopen() {
+    let db = TempDb::new("rebuild");
+    {
+        let repo = SqliteRepository::open(&db.0).unwrap();
+        for (id, task) in [("t1", "Buy milk"), ("t2", "Walk dog")] {
+            repo.commit_aggregate(&mut todo(id, task)).unwrap();
+        }
+        let shouting = EventProjection::new("todo_views").on("Initialized", |event, models| {
+            let (id, _user, task): (String, String, String) = event.event.decode()?;
+            models.upsert(&TodoView {
+                id,
+                task: task.to_uppercase(),
+                completed: false,
+            })
+        });
+
+        let progress = ProjectionRebuild::new(repo.clone(), repo.clone(), shouting)
+            .swap()
+            .unwrap();
+        assert_eq!(progress.position, 2);
+    }
+
+    let repo = SqliteRepository::open(&db.0).unwrap();
+    assert_eq!(repo.get_model::<TodoView>("t2").unwrap().unwrap().data.task, "WALK DOG");
+    let checkpoint = repo.get_model::<ProjectionCheckpoint>("todo_views").unwrap().unwrap();
+    assert_eq!(checkpoint.data.position, 2);
+}
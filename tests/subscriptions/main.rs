@@ -0,0 +1,188 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use sourced_rust::{
+    CheckpointStore, Commit, Entity, FileRepository, HashMapRepository, InMemoryCheckpointStore,
+    StartFrom, StoredEvent, Subscription, SubscriptionError, SubscriptionThread,
+};
+
+static NEXT_DIR: AtomicU64 = AtomicU64::new(1);
+
+/// A fresh store directory, removed when dropped.
+struct TempStore(PathBuf);
+
+impl TempStore {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "sourced_rust_subscriptions_it_{}_{}_{}",
+            name,
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        TempStore(dir)
+    }
+}
+
+impl Drop for TempStore {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn commit_event<R: Commit>(repo: &R, id: &str, name: &str) {
+    let mut entity = Entity::with_id(id);
+    entity.digest(name, &id);
+    repo.commit(&mut entity).unwrap();
+}
+
+/// Handler that records `stream_id:event_name` for every event it sees.
+fn recorder(seen: &Arc<Mutex<Vec<String>>>) -> impl FnMut(&StoredEvent) -> Result<(), String> {
+    let seen = Arc::clone(seen);
+    move |e: &StoredEvent| {
+        seen.lock()
+            .unwrap()
+            .push(format!("{}:{}", e.stream_id, e.event.event_name));
+        Ok(())
+    }
+}
+
+fn wait_until(condition: impl Fn() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !condition() {
+        assert!(Instant::now() < deadline, "timed out waiting for subscription");
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn thread_replays_history_then_handles_live_events() {
+    let repo = HashMapRepository::new();
+    commit_event(&repo, "order-1", "OrderPlaced");
+    commit_event(&repo, "order-2", "OrderPlaced");
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let subscription = Subscription::new("audit", repo.clone(), repo.clone(), recorder(&seen));
+    let worker = SubscriptionThread::spawn(subscription, Duration::from_millis(5));
+
+    wait_until(|| seen.lock().unwrap().len() == 2);
+    commit_event(&repo, "order-3", "OrderPlaced");
+    wait_until(|| seen.lock().unwrap().len() == 3);
+
+    let stats = worker.stop();
+    assert_eq!(stats.events_handled, 3);
+    assert_eq!(stats.errors, 0);
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec![
+            "order-1:OrderPlaced",
+            "order-2:OrderPlaced",
+            "order-3:OrderPlaced"
+        ]
+    );
+    assert_eq!(repo.load_checkpoint("audit").unwrap(), Some(3));
+}
+
+#[test]
+fn resumes_from_checkpoint_after_restart() {
+    let store = TempStore::new("resume");
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    {
+        let repo = FileRepository::open(&store.0).unwrap();
+        commit_event(&repo, "a", "Created");
+        commit_event(&repo, "b", "Created");
+
+        let mut subscription = Subscription::new("audit", repo.clone(), repo, recorder(&seen));
+        assert_eq!(subscription.catch_up().unwrap(), 2);
+    }
+
+    let repo = FileRepository::open(&store.0).unwrap();
+    assert_eq!(repo.load_checkpoint("audit").unwrap(), Some(2));
+    commit_event(&repo, "c", "Created");
+
+    let mut subscription = Subscription::new("audit", repo.clone(), repo, recorder(&seen));
+    assert_eq!(subscription.catch_up().unwrap(), 1);
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec!["a:Created", "b:Created", "c:Created"]
+    );
+}
+
+#[test]
+fn stored_checkpoint_wins_over_start_position() {
+    let repo = HashMapRepository::new();
+    for id in ["a", "b", "c", "d"] {
+        commit_event(&repo, id, "Created");
+    }
+    let checkpoints = InMemoryCheckpointStore::new();
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut fresh = Subscription::new("audit", repo.clone(), checkpoints.clone(), recorder(&seen))
+        .start_from(StartFrom::Position(2));
+    assert_eq!(fresh.catch_up().unwrap(), 2);
+
+    checkpoints.save_checkpoint("audit", 1).unwrap();
+    let mut resumed = Subscription::new("audit", repo, checkpoints, recorder(&seen))
+        .start_from(StartFrom::End);
+    assert_eq!(resumed.catch_up().unwrap(), 3);
+
+    assert_eq!(
+        *seen.lock().unwrap(),
+        vec!["c:Created", "d:Created", "b:Created", "c:Created", "d:Created"]
+    );
+}
+
+#[test]
+fn subscriptions_keep_independent_checkpoints() {
+    let repo = HashMapRepository::new();
+    commit_event(&repo, "a", "Created");
+
+    let ok = |_: &StoredEvent| Ok::<_, String>(());
+    let mut first = Subscription::new("first", repo.clone(), repo.clone(), ok);
+    first.catch_up().unwrap();
+    commit_event(&repo, "b", "Created");
+
+    let mut second = Subscription::new("second", repo.clone(), repo.clone(), ok);
+    assert_eq!(second.catch_up().unwrap(), 2);
+    assert_eq!(first.catch_up().unwrap(), 1);
+    assert_eq!(repo.load_checkpoint("first").unwrap(), Some(2));
+    assert_eq!(repo.load_checkpoint("second").unwrap(), Some(2));
+}
+
+#[test]
+fn thread_retries_failed_event() {
+    let repo = HashMapRepository::new();
+    commit_event(&repo, "a", "Created");
+
+    let attempts = Arc::new(Mutex::new(0));
+    let counter = Arc::clone(&attempts);
+    let subscription = Subscription::new(
+        "flaky",
+        repo.clone(),
+        repo.clone(),
+        move |_: &StoredEvent| {
+            let mut attempts = counter.lock().unwrap();
+            *attempts += 1;
+            if *attempts == 1 {
+                Err("broker down".to_string())
+            } else {
+                Ok(())
+            }
+        },
+    );
+    let worker = SubscriptionThread::spawn(subscription, Duration::from_millis(5));
+
+    wait_until(|| repo.load_checkpoint("flaky").unwrap() == Some(1));
+    let stats = worker.stop();
+    assert_eq!(stats.events_handled, 1);
+    assert_eq!(stats.errors, 1);
+    assert!(matches!(
+        stats.last_error,
+        Some(SubscriptionError::Handler { position: 1, .. })
+    ));
+    assert_eq!(*attempts.lock().unwrap(), 2);
+}
@@ -2,8 +2,10 @@ mod aggregate;
 
 use bitcode;
 use sourced_rust::{
-    AggregateBuilder, Commit, EventEmitter, GetAggregate, HashMapRepository, LocalEmitterPublisher,
-    LogPublisher, OutboxCommitExt, OutboxMessage, OutboxRepositoryExt, OutboxWorker, Queueable,
+    AggregateBuilder, Commit, EventEmitter, GetAggregate, HashMapRepository, InMemoryLeaseStore,
+    LeaseLockManager, LocalEmitterPublisher, Lock, LockError, LockManager, LogPublisher,
+    OutboxCommitExt, OutboxMessage, OutboxRepositoryExt, OutboxWorker, PageRequest, Queueable,
+    ReadOpts, RepositoryError, SecondaryIndex,
 };
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{mpsc, Arc, Mutex};
@@ -350,6 +352,172 @@ fn abort_releases_lock_after_get_all() {
     assert!(rx_got.recv_timeout(Duration::from_millis(500)).is_ok());
 }
 
+#[test]
+fn locked_guard_releases_unless_committed() {
+    let repo = HashMapRepository::new().queued().aggregate::<Todo>();
+    let mut todo = Todo::new();
+    let id = next_id();
+    todo.initialize(id.clone(), "user1".to_string(), "Guarded".to_string());
+    repo.commit(&mut todo).unwrap();
+
+    let is_locked = |id: &str| {
+        let lock = repo.repo().lock_manager().get_lock(id).unwrap();
+        let acquired = lock.try_lock().unwrap();
+        if acquired {
+            lock.unlock().unwrap();
+        }
+        !acquired
+    };
+
+    // A handler bailing out with `?` drops the guard and releases the lock
+    let handler = || -> Result<(), RepositoryError> {
+        let mut todo = repo.get_locked(&id)?.unwrap();
+        assert!(is_locked(&id));
+        todo.complete();
+        Err(RepositoryError::Storage("handler failed".into()))
+    };
+    assert!(handler().is_err());
+    assert!(!is_locked(&id));
+    assert!(!repo.peek(&id).unwrap().unwrap().snapshot().completed);
+
+    let mut todo = repo.get_locked(&id).unwrap().unwrap();
+    todo.complete();
+    let todo = todo.commit().unwrap();
+    assert!(todo.snapshot().completed);
+    assert!(!is_locked(&id));
+
+    assert!(repo.get_locked("missing").unwrap().is_none());
+    assert!(!is_locked("missing"));
+
+    // `into_inner` keeps the lock until an explicit abort
+    let todo = repo.get_locked(&id).unwrap().unwrap().into_inner();
+    assert!(is_locked(&id));
+    repo.abort(&todo).unwrap();
+    assert!(!is_locked(&id));
+}
+
+#[test]
+fn lock_timeout_fails_reads_of_held_streams() {
+    let repo = HashMapRepository::new()
+        .queued()
+        .with_lock_timeout(Duration::from_millis(30))
+        .aggregate::<Todo>();
+    let (id1, id2) = (next_id(), next_id());
+    for id in [&id1, &id2] {
+        let mut todo = Todo::new();
+        todo.initialize(id.clone(), "user1".to_string(), "Timeout".to_string());
+        repo.commit(&mut todo).unwrap();
+    }
+
+    let held = repo.get(&id1).unwrap().unwrap();
+    let err = repo.get(&id1).err().unwrap();
+    assert!(matches!(err, RepositoryError::Lock(LockError::AcquireFailed(_))));
+    assert!(err.to_string().contains(&id1));
+
+    // A batch read that times out releases the locks it already took
+    assert!(repo.get_all(&[&id1, &id2]).is_err());
+    let todo = repo.get(&id2).unwrap().unwrap();
+    repo.abort(&todo).unwrap();
+
+    repo.abort(&held).unwrap();
+    let todo = repo.get(&id1).unwrap().unwrap();
+    repo.abort(&todo).unwrap();
+}
+
+#[test]
+fn lost_lease_rejects_the_commit() {
+    let store = Arc::new(InMemoryLeaseStore::new());
+    let events = HashMapRepository::new();
+    let ttl = Duration::from_millis(40);
+    let repo_a = events
+        .clone()
+        .queued_with(LeaseLockManager::new(Arc::clone(&store), ttl))
+        .aggregate::<Todo>();
+    let repo_b = events
+        .clone()
+        .queued_with(LeaseLockManager::new(Arc::clone(&store), ttl))
+        .aggregate::<Todo>();
+
+    let id = next_id();
+    let mut todo = Todo::new();
+    todo.initialize(id.clone(), "user1".to_string(), "Leased".to_string());
+    repo_a.commit(&mut todo).unwrap();
+
+    // A holds the lease past its TTL, and B takes it over
+    let mut stale = repo_a.get(&id).unwrap().unwrap();
+    thread::sleep(ttl + Duration::from_millis(20));
+    let current = repo_b.get(&id).unwrap().unwrap();
+
+    stale.complete();
+    let err = repo_a.commit(&mut stale).unwrap_err();
+    assert!(matches!(err, RepositoryError::Lock(LockError::Expired(_))));
+    assert!(!repo_b.peek(&id).unwrap().unwrap().snapshot().completed);
+
+    repo_b.abort(&current).unwrap();
+}
+
+#[test]
+fn writing_a_stream_without_its_lease_is_rejected() {
+    let store = Arc::new(InMemoryLeaseStore::new());
+    let ttl = Duration::from_secs(5);
+    let events = HashMapRepository::new();
+    let repo_a = events
+        .clone()
+        .queued_with(LeaseLockManager::new(Arc::clone(&store), ttl))
+        .aggregate::<Todo>();
+    let repo_b = events
+        .queued_with(LeaseLockManager::new(Arc::clone(&store), ttl))
+        .aggregate::<Todo>();
+
+    let id = next_id();
+    let mut todo = Todo::new();
+    todo.initialize(id.clone(), "user1".to_string(), "Leased".to_string());
+    repo_a.commit(&mut todo).unwrap();
+
+    // A reads without taking the lease while B holds it
+    let current = repo_b.get(&id).unwrap().unwrap();
+    let mut unleased = repo_a.get_with(&id, ReadOpts::no_lock()).unwrap().unwrap();
+    unleased.complete();
+    let err = repo_a.commit(&mut unleased).unwrap_err();
+    assert!(matches!(err, RepositoryError::Lock(LockError::Expired(_))));
+    assert!(!repo_b.peek(&id).unwrap().unwrap().snapshot().completed);
+
+    repo_b.abort(&current).unwrap();
+}
+
+#[test]
+fn shared_reads_wait_for_writers_but_not_each_other() {
+    let repo = Arc::new(HashMapRepository::new().queued().aggregate::<Todo>());
+    let id = next_id();
+    let mut todo = Todo::new();
+    todo.initialize(id.clone(), "user1".to_string(), "Shared".to_string());
+    repo.commit(&mut todo).unwrap();
+
+    // Shared reads hold nothing afterwards and do not block each other
+    let lock = repo.repo().lock_manager().get_lock(&id).unwrap();
+    lock.lock_shared().unwrap();
+    let read = repo.get_with(&id, ReadOpts::shared()).unwrap().unwrap();
+    assert!(!read.snapshot().completed);
+    lock.unlock_shared().unwrap();
+    assert!(lock.try_lock().unwrap());
+    lock.unlock().unwrap();
+
+    // A writer between get and commit holds shared readers back
+    let mut todo = repo.get(&id).unwrap().unwrap();
+    let (tx, rx) = mpsc::channel();
+    let reader_repo = Arc::clone(&repo);
+    let reader_id = id.clone();
+    thread::spawn(move || {
+        let todo = reader_repo.get_with(&reader_id, ReadOpts::shared()).unwrap().unwrap();
+        tx.send(todo.snapshot().completed).unwrap();
+    });
+    assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+
+    todo.complete();
+    repo.commit(&mut todo).unwrap();
+    assert!(rx.recv_timeout(Duration::from_millis(500)).unwrap());
+}
+
 #[test]
 fn queued_repo_blocks_get_until_commit() {
     let repo = Arc::new(HashMapRepository::new().queued().aggregate::<Todo>());
@@ -634,6 +802,76 @@ fn find_by_completed_status() {
     assert_eq!(pending[0].snapshot().task, "Pending task");
 }
 
+fn indexed_repo() -> HashMapRepository {
+    HashMapRepository::new()
+        .with_index(SecondaryIndex::prefix("todos", "todo-"))
+        .unwrap()
+        .with_index(SecondaryIndex::event("completed", "Completed"))
+        .unwrap()
+        .with_index(SecondaryIndex::key("todos_by_user", |event| {
+            if event.event_name != "Initialized" {
+                return None;
+            }
+            let (_id, user_id, _task): (String, String, String) = event.decode().ok()?;
+            Some(user_id)
+        }))
+        .unwrap()
+}
+
+#[test]
+fn indexed_queries_skip_unrelated_streams() {
+    let repo = indexed_repo().aggregate::<Todo>();
+
+    let mut todo1 = Todo::new();
+    todo1.initialize(next_id(), "alice".to_string(), "Task 1".to_string());
+    let mut todo2 = Todo::new();
+    todo2.initialize(next_id(), "alice".to_string(), "Task 2".to_string());
+    let mut todo3 = Todo::new();
+    todo3.initialize(next_id(), "bob".to_string(), "Task 3".to_string());
+    let mut message = OutboxMessage::create("msg-1", "TodoCreated", b"{}".to_vec());
+    repo.outbox(&mut message).commit(&mut todo1).unwrap();
+    repo.commit_all(&mut [&mut todo2, &mut todo3]).unwrap();
+
+    assert_eq!(repo.count_in("todos").unwrap(), 3);
+    let alice = repo.find_by("todos_by_user", "alice").unwrap();
+    let tasks: Vec<String> = alice.iter().map(|t| t.snapshot().task).collect();
+    assert_eq!(tasks.len(), 2);
+    assert!(tasks.contains(&"Task 1".to_string()) && tasks.contains(&"Task 2".to_string()));
+    assert_eq!(repo.count_by("todos_by_user", "carol").unwrap(), 0);
+
+    // Indexes are maintained by later commits.
+    let mut todo3 = repo.get(todo3.entity.id()).unwrap().unwrap();
+    todo3.complete();
+    repo.commit(&mut todo3).unwrap();
+    let completed = repo.find_in("completed").unwrap();
+    assert_eq!(completed.len(), 1);
+    assert_eq!(completed[0].snapshot().task, "Task 3");
+
+    assert!(matches!(
+        repo.find_in("by_priority"),
+        Err(RepositoryError::UnknownIndex(_))
+    ));
+}
+
+#[test]
+fn index_declared_later_is_backfilled() {
+    let repo = HashMapRepository::new();
+    let todos = repo.clone().aggregate::<Todo>();
+    let mut todo = Todo::new();
+    todo.initialize(next_id(), "alice".to_string(), "Existing".to_string());
+    todo.complete();
+    todos.commit(&mut todo).unwrap();
+
+    let todos = repo
+        .with_index(SecondaryIndex::event("completed", "Completed"))
+        .unwrap()
+        .queued()
+        .aggregate::<Todo>();
+    let completed = todos.find_in("completed").unwrap();
+    assert_eq!(completed.len(), 1);
+    todos.abort(&completed[0]).unwrap();
+}
+
 /// Full metadata chain: Entity → EventRecord → OutboxMessage → OutboxWorker → publisher
 #[test]
 fn metadata_flows_from_entity_through_outbox_to_publisher() {
@@ -690,3 +928,58 @@ fn metadata_flows_from_entity_through_outbox_to_publisher() {
     assert!(lines[0].contains("cmd-create-todo"));
     assert!(lines[0].contains("u-42"));
 }
+
+#[test]
+fn find_page_walks_matching_aggregates_by_cursor() {
+    let repo = HashMapRepository::new().aggregate::<Todo>();
+    for (id, user) in [("p-1", "alice"), ("p-2", "bob"), ("p-3", "alice"), ("p-4", "alice")] {
+        let mut todo = Todo::new();
+        todo.initialize(id.to_string(), user.to_string(), format!("Task {}", id));
+        repo.commit(&mut todo).unwrap();
+    }
+    let alice = |t: &Todo| t.snapshot().user_id == "alice";
+
+    let first = repo.find_page(alice, &PageRequest::new(2)).unwrap();
+    let ids: Vec<&str> = first.items.iter().map(|t| t.entity.id()).collect();
+    assert_eq!(ids, vec!["p-1", "p-3"]);
+    assert_eq!(first.next_cursor.as_deref(), Some("p-3"));
+
+    let second = repo.find_page(alice, &first.next_request(2).unwrap()).unwrap();
+    assert_eq!(second.items.len(), 1);
+    assert_eq!(second.items[0].entity.id(), "p-4");
+    assert_eq!(second.next_cursor, None);
+
+    let tasks: Vec<String> = repo
+        .find_iter(|_| true, 3)
+        .map(|t| t.unwrap().snapshot().task)
+        .collect();
+    assert_eq!(tasks, vec!["Task p-1", "Task p-2", "Task p-3", "Task p-4"]);
+}
+
+#[test]
+fn queued_find_iter_only_keeps_returned_aggregates_locked() {
+    let repo = HashMapRepository::new()
+        .queued()
+        .with_lock_timeout(Duration::from_millis(30))
+        .aggregate::<Todo>();
+    for (id, user) in [("q-1", "alice"), ("q-2", "bob"), ("q-3", "alice")] {
+        let mut todo = Todo::new();
+        todo.initialize(id.to_string(), user.to_string(), format!("Task {}", id));
+        repo.commit(&mut todo).unwrap();
+    }
+
+    let found: Vec<Todo> = repo
+        .find_iter(|t| t.snapshot().user_id == "alice", 2)
+        .map(|t| t.unwrap())
+        .collect();
+    let ids: Vec<&str> = found.iter().map(|t| t.entity.id()).collect();
+    assert_eq!(ids, vec!["q-1", "q-3"]);
+
+    // The rejected stream was released; the returned ones are still held
+    let bob = repo.get("q-2").unwrap().unwrap();
+    repo.abort(&bob).unwrap();
+    assert!(repo.get("q-1").is_err());
+    for todo in &found {
+        repo.abort(todo).unwrap();
+    }
+}